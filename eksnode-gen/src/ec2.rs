@@ -6,16 +6,14 @@ use std::{
 
 use anyhow::Result;
 use aws_sdk_ec2::types::InstanceTypeInfo;
-use aws_types::region::Region;
 use eksnode::{ec2::Instance, resource::calculate_eni_max_pods};
 use handlebars::Handlebars;
 use serde_json::json;
 
 /// Collects all instances and their details from the region provided
-async fn get_instances(region: Region) -> Result<Vec<InstanceTypeInfo>> {
+async fn get_instances(region: &str) -> Result<Vec<InstanceTypeInfo>> {
   // Using region specific client to pull instance data for that region
-  let config = aws_config::from_env().region(region).load().await;
-  let client = crate::get_client(config, 3).await.unwrap();
+  let client = eksnode::aws::get_regional_ec2_client(region).await;
 
   let results = client
     .describe_instance_types()
@@ -34,48 +32,117 @@ async fn get_instances(region: Region) -> Result<Vec<InstanceTypeInfo>> {
 fn get_manual_instances() -> Result<BTreeMap<String, Instance>> {
   let mut result = BTreeMap::new();
   for inst in vec![
-    ("cr1.8xlarge", 32, "unknown", true, 30, 8, "None"),
-    ("hs1.8xlarge", 16, "unknown", true, 30, 8, "None"),
-    ("u-12tb1.metal", 448, "unknown", false, 30, 5, "None"),
-    ("u-18tb1.metal", 448, "unknown", false, 50, 15, "None"),
-    ("u-24tb1.metal", 448, "unknown", false, 50, 15, "None"),
-    ("u-6tb1.metal", 448, "unknown", false, 30, 5, "None"),
-    ("u-9tb1.metal", 448, "unknown", false, 30, 5, "None"),
-    ("c5a.metal", 96, "unknown", false, 50, 15, "None"),
-    ("c5ad.metal", 96, "unknown", true, 50, 15, "None"),
-    ("p4de.24xlarge", 96, "nitro", true, 50, 15, "NVIDIA"),
-    ("bmn-sf1.metal", 1, "unknown", false, 50, 15, "None"),
+    ("cr1.8xlarge", 32, "unknown", true, 30, 8, "None", None),
+    ("hs1.8xlarge", 16, "unknown", true, 30, 8, "None", None),
+    ("u-12tb1.metal", 448, "unknown", false, 30, 5, "None", None),
+    ("u-18tb1.metal", 448, "unknown", false, 50, 15, "None", None),
+    ("u-24tb1.metal", 448, "unknown", false, 50, 15, "None", None),
+    ("u-6tb1.metal", 448, "unknown", false, 30, 5, "None", None),
+    ("u-9tb1.metal", 448, "unknown", false, 30, 5, "None", None),
+    ("c5a.metal", 96, "unknown", false, 50, 15, "None", None),
+    ("c5ad.metal", 96, "unknown", true, 50, 15, "None", None),
+    ("p4de.24xlarge", 96, "nitro", true, 50, 15, "NVIDIA", Some(8)),
+    ("bmn-sf1.metal", 1, "unknown", false, 50, 15, "None", None),
   ] {
     let instance_type = inst.0.to_string();
     let instance = Instance {
       default_vcpus: inst.1,
       gpu_manufacturer: inst.6.to_string(),
+      gpu_count: inst.7,
       eni_maximum_pods: calculate_eni_max_pods(inst.5, inst.4, false),
       hypervisor: inst.2.to_string(),
       instance_storage_supported: inst.3,
       ipv4_addresses_per_interface: inst.4,
       maximum_network_interfaces: inst.5,
+      network_baseline_bandwidth_gbps: None,
+      network_burst_bandwidth_gbps: None,
+      ebs_baseline_throughput_mbps: None,
+      ebs_maximum_throughput_mbps: None,
     };
     result.insert(instance_type, instance);
   }
   Ok(result)
 }
 
+/// Converts the instance type details returned by the EC2 API into the instance data used by eksnode
+fn get_instance(instance: &InstanceTypeInfo) -> Instance {
+  let net_info = instance.network_info.as_ref().unwrap();
+  let ipv4_addresses = net_info.ipv4_addresses_per_interface.unwrap();
+
+  // only one network card is supported, so use the maximum_network_interfaces from the default card
+  let def_net_card_idx = net_info.default_network_card_index.unwrap();
+  let def_net_card = net_info
+    .network_cards
+    .as_ref()
+    .unwrap()
+    .get(def_net_card_idx as usize)
+    .unwrap();
+  let network_interfaces = def_net_card.maximum_network_interfaces().unwrap();
+  let ebs_optimized_info = instance
+    .ebs_info
+    .as_ref()
+    .and_then(|ebs_info| ebs_info.ebs_optimized_info.as_ref());
+
+  let gpu_manufacturer = match instance.gpu_info.as_ref() {
+    Some(gpu_info) => gpu_info
+      .gpus
+      .as_ref()
+      .unwrap()
+      .first()
+      .unwrap()
+      .manufacturer
+      .as_ref()
+      .unwrap()
+      .to_string(),
+    None => "none".to_string(),
+  };
+
+  let gpu_count = instance.gpu_info.as_ref().map(|gpu_info| {
+    gpu_info
+      .gpus()
+      .iter()
+      .map(|gpu| gpu.count().unwrap_or_default())
+      .sum::<i32>()
+  });
+
+  Instance {
+    default_vcpus: instance.v_cpu_info.as_ref().unwrap().default_v_cpus().unwrap(),
+    eni_maximum_pods: calculate_eni_max_pods(network_interfaces, ipv4_addresses, false),
+    gpu_manufacturer,
+    gpu_count,
+    hypervisor: match &instance.hypervisor {
+      Some(hypervisor) => hypervisor.as_str().to_owned(),
+      None => "unknown".to_string(),
+    },
+    instance_storage_supported: instance.instance_storage_supported.unwrap(),
+    ipv4_addresses_per_interface: ipv4_addresses,
+    maximum_network_interfaces: network_interfaces,
+    network_baseline_bandwidth_gbps: def_net_card.baseline_bandwidth_in_gbps(),
+    network_burst_bandwidth_gbps: def_net_card.peak_bandwidth_in_gbps(),
+    ebs_baseline_throughput_mbps: ebs_optimized_info.and_then(|ebs| ebs.baseline_throughput_in_m_bps()),
+    ebs_maximum_throughput_mbps: ebs_optimized_info.and_then(|ebs| ebs.maximum_throughput_in_m_bps()),
+  }
+}
+
 /// Writes the EC2 instance details collected to a rust file
 ///
 /// This generates a static map that will be used by eksnode to lookup instance details without the need to re-query the
 /// EC2 API
 fn write_ec2(instances: &BTreeMap<String, Instance>, cur_dir: &Path) -> Result<()> {
+  let rendered = render_ec2(instances, cur_dir)?;
+  let dest_path = cur_dir.join("eksnode").join("files").join("ec2-instances.yaml");
+  fs::write(dest_path, rendered)?;
+
+  Ok(())
+}
+
+fn render_ec2(instances: &BTreeMap<String, Instance>, cur_dir: &Path) -> Result<String> {
   let mut handlebars = Handlebars::new();
   let template = cur_dir.join("eksnode-gen").join("templates").join("ec2-instances.tpl");
   handlebars.register_template_file("tpl", template)?;
 
   let data = json!({"instances": instances});
-  let rendered = handlebars.render("tpl", &data)?;
-  let dest_path = cur_dir.join("eksnode").join("files").join("ec2-instances.yaml");
-  fs::write(dest_path, rendered)?;
-
-  Ok(())
+  Ok(handlebars.render("tpl", &data)?)
 }
 
 pub async fn write_files(cur_dir: &Path) -> Result<()> {
@@ -85,7 +152,7 @@ pub async fn write_files(cur_dir: &Path) -> Result<()> {
   let mut instances = get_manual_instances()?;
 
   for region in &regions {
-    let results = get_instances(Region::new(region.to_owned())).await?;
+    let results = get_instances(region).await?;
     let _ = results
       .into_iter()
       .map(|instance| {
@@ -93,47 +160,7 @@ pub async fn write_files(cur_dir: &Path) -> Result<()> {
         let instance_type = instance_type.as_str().to_string();
 
         if let btree_map::Entry::Vacant(e) = instances.entry(instance_type) {
-          let net_info = instance.network_info.as_ref().unwrap();
-          let ipv4_addresses = net_info.ipv4_addresses_per_interface.unwrap();
-
-          // only one network card is supported, so use the maximum_network_interfaces from the default card
-          let def_net_card_idx = net_info.default_network_card_index.unwrap();
-          let network_interfaces = net_info
-            .network_cards
-            .as_ref()
-            .unwrap()
-            .get(def_net_card_idx as usize)
-            .unwrap()
-            .maximum_network_interfaces()
-            .unwrap();
-
-          let gpu_manufacturer = match instance.gpu_info.as_ref() {
-            Some(gpu_info) => gpu_info
-              .gpus
-              .as_ref()
-              .unwrap()
-              .first()
-              .unwrap()
-              .manufacturer
-              .as_ref()
-              .unwrap()
-              .to_string(),
-            None => "none".to_string(),
-          };
-
-          let inst = Instance {
-            default_vcpus: instance.v_cpu_info.unwrap().default_v_cpus().unwrap(),
-            eni_maximum_pods: calculate_eni_max_pods(network_interfaces, ipv4_addresses, false),
-            gpu_manufacturer,
-            hypervisor: match instance.hypervisor {
-              Some(hypervisor) => hypervisor.as_str().to_owned(),
-              None => "unknown".to_string(),
-            },
-            instance_storage_supported: instance.instance_storage_supported.unwrap(),
-            ipv4_addresses_per_interface: ipv4_addresses,
-            maximum_network_interfaces: network_interfaces,
-          };
-          e.insert(inst);
+          e.insert(get_instance(&instance));
         }
       })
       .collect::<Vec<_>>();
@@ -141,3 +168,62 @@ pub async fn write_files(cur_dir: &Path) -> Result<()> {
 
   write_ec2(&instances, cur_dir)
 }
+
+#[cfg(test)]
+mod tests {
+  use aws_sdk_ec2::types::{
+    EbsInfo, EbsOptimizedInfo, InstanceType, InstanceTypeHypervisor, NetworkCardInfo, NetworkInfo, VCpuInfo,
+  };
+
+  use super::*;
+
+  /// Details of m5.large as returned by DescribeInstanceTypes
+  fn m5_large() -> InstanceTypeInfo {
+    InstanceTypeInfo::builder()
+      .instance_type(InstanceType::M5Large)
+      .v_cpu_info(VCpuInfo::builder().default_v_cpus(2).build())
+      .hypervisor(InstanceTypeHypervisor::Nitro)
+      .instance_storage_supported(false)
+      .network_info(
+        NetworkInfo::builder()
+          .ipv4_addresses_per_interface(10)
+          .default_network_card_index(0)
+          .network_cards(
+            NetworkCardInfo::builder()
+              .network_card_index(0)
+              .maximum_network_interfaces(3)
+              .baseline_bandwidth_in_gbps(0.75)
+              .peak_bandwidth_in_gbps(10.0)
+              .build(),
+          )
+          .build(),
+      )
+      .ebs_info(
+        EbsInfo::builder()
+          .ebs_optimized_info(
+            EbsOptimizedInfo::builder()
+              .baseline_throughput_in_m_bps(81.25)
+              .maximum_throughput_in_m_bps(593.75)
+              .build(),
+          )
+          .build(),
+      )
+      .build()
+  }
+
+  #[test]
+  fn it_renders_network_performance() {
+    let instance = get_instance(&m5_large());
+    assert_eq!(instance.eni_maximum_pods, 29);
+    assert_eq!(instance.network_baseline_bandwidth_gbps, Some(0.75));
+    assert_eq!(instance.network_burst_bandwidth_gbps, Some(10.0));
+    assert_eq!(instance.ebs_baseline_throughput_mbps, Some(81.25));
+    assert_eq!(instance.ebs_maximum_throughput_mbps, Some(593.75));
+
+    let cur_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let rendered = render_ec2(&BTreeMap::from([("m5.large".to_owned(), instance)]), cur_dir).unwrap();
+    let parsed: BTreeMap<String, Instance> = serde_yaml::from_str(&rendered).unwrap();
+    assert_eq!(parsed["m5.large"].network_burst_bandwidth_gbps, Some(10.0));
+    assert_eq!(parsed["m5.large"].ebs_maximum_throughput_mbps, Some(593.75));
+  }
+}
@@ -0,0 +1,148 @@
+//! Generates `kubernetes-versions.yaml`
+//!
+//! The supported minor versions and their latest patch versions are fetched from the EKS artifacts in S3. Upstream
+//! does not publish the pause tag, credential provider API version, or removed kubelet flags in a form that can be
+//! fetched, so these are derived from the tables below. When a new Kubernetes version changes any of them, update the
+//! table with the upstream reference and rerun `update-k8s-matrix`
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use eksnode::kubelet::KubernetesVersion;
+use handlebars::Handlebars;
+use serde_json::json;
+
+use crate::versions;
+
+/// Tag of the default sandbox (pause) image, by the first minor version it is used with
+static PAUSE_TAGS: &[(&str, &str)] = &[("1.24", "3.8")];
+
+/// First minor version where ecr-credential-provider implements the v1 credential provider API
+///
+/// https://github.com/kubernetes/cloud-provider-aws/pull/597
+static CREDENTIAL_PROVIDER_V1_SINCE: &str = "1.27";
+
+/// Kubelet flags and the minor version they were removed from kubelet
+///
+/// https://kubernetes.io/docs/reference/command-line-tools-reference/kubelet/
+static REMOVED_KUBELET_FLAGS: &[(&str, &str)] = &[
+  // dockershim removal
+  ("--cni-bin-dir", "1.24"),
+  ("--cni-cache-dir", "1.24"),
+  ("--cni-conf-dir", "1.24"),
+  ("--docker-endpoint", "1.24"),
+  ("--dynamic-config-dir", "1.24"),
+  ("--image-pull-progress-deadline", "1.24"),
+  ("--network-plugin", "1.24"),
+  ("--container-runtime", "1.27"),
+  // in-tree Azure credential provider removal
+  ("--azure-container-registry-config", "1.30"),
+];
+
+/// Parse the major and minor of a version (i.e. - `1.27` or `1.27.4`)
+fn parse_minor(version: &str) -> Result<(u64, u64)> {
+  let mut parts = version.split('.');
+  let (Some(major), Some(minor)) = (parts.next(), parts.next()) else {
+    bail!("Kubernetes version {version} is not in the form <major>.<minor>");
+  };
+  let major = major
+    .parse()
+    .with_context(|| format!("Invalid major version in Kubernetes version {version}"))?;
+  let minor = minor
+    .parse()
+    .with_context(|| format!("Invalid minor version in Kubernetes version {version}"))?;
+
+  Ok((major, minor))
+}
+
+/// Get the details of the Kubernetes minor version from the rules above
+fn get_kubernetes_version(minor: &str, kubernetes_version: &str) -> Result<KubernetesVersion> {
+  let version = parse_minor(minor)?;
+
+  let mut pause_tag = PAUSE_TAGS.first().map(|(_, tag)| tag.to_string()).unwrap_or_default();
+  for (since, tag) in PAUSE_TAGS {
+    if parse_minor(since)? <= version {
+      pause_tag = tag.to_string();
+    }
+  }
+
+  let credential_provider_api_version = match version >= parse_minor(CREDENTIAL_PROVIDER_V1_SINCE)? {
+    true => "v1",
+    false => "v1alpha1",
+  };
+
+  let mut removed_kubelet_flags = Vec::new();
+  for (flag, removed) in REMOVED_KUBELET_FLAGS {
+    if parse_minor(removed)? <= version {
+      removed_kubelet_flags.push(flag.to_string());
+    }
+  }
+  removed_kubelet_flags.sort();
+
+  Ok(KubernetesVersion {
+    kubernetes_version: kubernetes_version.to_owned(),
+    pause_tag,
+    credential_provider_api_version: credential_provider_api_version.to_owned(),
+    removed_kubelet_flags,
+  })
+}
+
+/// Writes the Kubernetes version details consumed by the version-aware builders in `eksnode`
+fn write_kubernetes_versions(versions: &BTreeMap<String, KubernetesVersion>, cur_dir: &Path) -> Result<()> {
+  let mut handlebars = Handlebars::new();
+  let template = cur_dir
+    .join("eksnode-gen")
+    .join("templates")
+    .join("kubernetes-versions.tpl");
+  handlebars.register_template_file("tpl", template)?;
+
+  let data = json!({"versions": versions});
+  let rendered = handlebars.render("tpl", &data)?;
+  let dest_path = cur_dir.join("eksnode").join("files").join("kubernetes-versions.yaml");
+  fs::write(dest_path, rendered)?;
+
+  Ok(())
+}
+
+pub async fn update_k8s_matrix(cur_dir: &Path) -> Result<()> {
+  let versions = versions::get_latest_kubernetes_versions()
+    .await?
+    .iter()
+    .map(|(minor, version)| Ok((minor.to_owned(), get_kubernetes_version(minor, version)?)))
+    .collect::<Result<BTreeMap<_, _>>>()?;
+
+  write_kubernetes_versions(&versions, cur_dir)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_parses_minor_version() {
+    assert_eq!(parse_minor("1.27").unwrap(), (1, 27));
+    assert_eq!(parse_minor("1.27.4").unwrap(), (1, 27));
+
+    assert_eq!(
+      parse_minor("1").unwrap_err().to_string(),
+      "Kubernetes version 1 is not in the form <major>.<minor>"
+    );
+    assert_eq!(
+      parse_minor("1.x").unwrap_err().to_string(),
+      "Invalid minor version in Kubernetes version 1.x"
+    );
+    assert!(get_kubernetes_version("v1.27", "1.27.4").is_err());
+  }
+
+  #[test]
+  fn it_gets_kubernetes_version() {
+    let version = get_kubernetes_version("1.27", "1.27.4").unwrap();
+    assert_eq!(version.pause_tag, "3.8");
+    assert_eq!(version.credential_provider_api_version, "v1");
+    assert!(version
+      .removed_kubelet_flags
+      .contains(&"--container-runtime".to_owned()));
+    assert!(!version
+      .removed_kubelet_flags
+      .contains(&"--azure-container-registry-config".to_owned()));
+  }
+}
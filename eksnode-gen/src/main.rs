@@ -3,7 +3,7 @@ use std::env;
 
 use anyhow::Result;
 use clap::Parser;
-use eksnode_gen::{ec2, versions, Cli, Commands};
+use eksnode_gen::{ec2, kubernetes, versions, Cli, Commands};
 use tracing_log::AsTrace;
 use tracing_subscriber::FmtSubscriber;
 
@@ -21,6 +21,7 @@ async fn main() -> Result<()> {
     .without_time()
     .finish();
   tracing::subscriber::set_global_default(subscriber).expect("Setting default subscriber failed");
+  eksnode::aws::configure(cli.aws.clone())?;
 
   let cur_exe = env::current_exe()?;
   let cur_dir = cur_exe.parent().unwrap().parent().unwrap().parent().unwrap();
@@ -35,5 +36,10 @@ async fn main() -> Result<()> {
     // and are not available via a public API. This file is used to map the Kubernetes version to the
     // correct artifact version.
     Commands::UpdateArtifactVersions => versions::update_artifact_versions(cur_dir).await,
+
+    // Updates the `kubernetes-versions.yaml` which embeds the supported Kubernetes versions and the details
+    // that vary by version (pause image tag, credential provider API version, removed kubelet flags) into the
+    // `eksnode` binary. The latest versions are sourced from the EKS artifacts in S3
+    Commands::UpdateK8sMatrix => kubernetes::update_k8s_matrix(cur_dir).await,
   }
 }
@@ -5,8 +5,7 @@ use std::{
   path::Path,
 };
 
-use anyhow::Result;
-use aws_sdk_s3::{config::Region, Client};
+use anyhow::{Context, Result};
 use handlebars::Handlebars;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -45,6 +44,22 @@ struct Version {
   /// The version of nerdctl - this is not pulled from S3, but statically set in `versions.yaml`
   /// nerdctl is used in place of ctr
   nerdctl_version: String,
+
+  /// The latest build date under `kubernetes_version` that contains the aws-iam-authenticator artifact
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  aws_iam_authenticator_build_date: Option<String>,
+
+  /// The latest build date under `kubernetes_version` that contains the ecr-credential-provider artifact
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  ecr_credential_provider_build_date: Option<String>,
+
+  /// The latest build date under `kubernetes_version` that contains the CNI plugins artifact
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  cni_plugins_build_date: Option<String>,
+
+  /// The version of the CNI plugins, as provided in the artifact name `cni-plugins-linux-<arch>-<version>.tgz`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  cni_plugins_version: Option<String>,
 }
 
 impl Versions {
@@ -75,24 +90,98 @@ pub async fn update_artifact_versions(cur_dir: &Path) -> Result<()> {
   // Open existing file in project
   let mut versions = Versions::read(&dest_path)?;
 
-  let build_date_versions = get_build_date_versions().await?;
+  let keys = list_artifact_keys().await?;
+  let build_date_versions = get_build_date_versions(&keys);
   for (k, v) in &mut versions.versions {
-    v.kubernetes_build_date = build_date_versions.get(k).unwrap().kubernetes_build_date.to_owned();
-    v.kubernetes_version = build_date_versions.get(k).unwrap().kubernetes_version.to_owned();
+    let build_date_version = build_date_versions
+      .get(k)
+      .with_context(|| format!("Kubernetes version {k} not found in the {S3_BUCKET_NAME} artifacts"))?;
+    v.kubernetes_build_date = build_date_version.kubernetes_build_date.to_owned();
+    v.kubernetes_version = build_date_version.kubernetes_version.to_owned();
+
+    v.aws_iam_authenticator_build_date = get_artifact_build_date(&keys, &v.kubernetes_version, "aws-iam-authenticator");
+    v.ecr_credential_provider_build_date =
+      get_artifact_build_date(&keys, &v.kubernetes_version, "ecr-credential-provider");
+    match get_cni_plugins_version(&keys, &v.kubernetes_version) {
+      Some((build_date, version)) => {
+        v.cni_plugins_build_date = Some(build_date);
+        v.cni_plugins_version = Some(version);
+      }
+      None => {
+        v.cni_plugins_build_date = None;
+        v.cni_plugins_version = None;
+      }
+    }
   }
 
   versions.write(&dest_path, cur_dir)
 }
 
+/// Get the latest <major>.<minor>.<patch> version provided by EKS for each supported minor version
+pub(crate) async fn get_latest_kubernetes_versions() -> Result<BTreeMap<String, String>> {
+  let keys = list_artifact_keys().await?;
+
+  Ok(
+    get_build_date_versions(&keys)
+      .into_iter()
+      .map(|(minor, v)| (minor, v.kubernetes_version))
+      .collect(),
+  )
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 struct BuildDateVersion {
   kubernetes_build_date: String,
   kubernetes_version: String,
 }
 
-async fn get_build_date_versions() -> Result<BTreeMap<String, BuildDateVersion>> {
-  let config = aws_config::from_env().region(Region::new("us-west-2")).load().await;
-  let client = Client::new(&config);
+/// Artifact key within the S3 bucket
+///
+/// `<kubernetes-ver>/<build-date>/<artifact-type>/<os-type>/<os-arch>/<name>`
+struct ArtifactKey {
+  kubernetes_version: String,
+  /// The <major>.<minor> version of `kubernetes_version`
+  minor_version: String,
+  /// The minor number of `kubernetes_version`
+  minor: i32,
+  build_date: String,
+  /// The artifact name, only set for `bin/linux/amd64/<name>` artifacts
+  name: Option<String>,
+}
+
+impl ArtifactKey {
+  fn new(key: &str) -> Result<Self> {
+    let (kubernetes_version, path) = key
+      .split_once('/')
+      .with_context(|| format!("Artifact key {key} is not under a <kubernetes-ver>/<build-date>/ prefix"))?;
+    let (build_date, path) = path.split_once('/').unwrap_or((path, ""));
+
+    let (minor_version, minor) = kubernetes_version
+      .split_once('.')
+      .and_then(|(major, rest)| {
+        let minor = rest.split_once('.').map_or(rest, |(minor, _)| minor);
+        Some((format!("{major}.{minor}"), minor.parse().ok()?))
+      })
+      .with_context(|| format!("Artifact key {key} has an invalid Kubernetes version {kubernetes_version}"))?;
+
+    let name = match path.split('/').collect::<Vec<&str>>().as_slice() {
+      ["bin", "linux", "amd64", name] => Some(name.to_string()),
+      _ => None,
+    };
+
+    Ok(ArtifactKey {
+      kubernetes_version: kubernetes_version.to_owned(),
+      minor_version,
+      minor,
+      build_date: build_date.to_owned(),
+      name,
+    })
+  }
+}
+
+/// List the keys of all artifacts stored in S3
+async fn list_artifact_keys() -> Result<Vec<ArtifactKey>> {
+  let client = eksnode::aws::get_s3_client("us-west-2").await;
 
   let mut object_paginator = client
     .list_objects_v2()
@@ -101,41 +190,76 @@ async fn get_build_date_versions() -> Result<BTreeMap<String, BuildDateVersion>>
     .into_paginator()
     .send();
 
-  let mut build_dates = HashSet::new();
-
-  // Reduces list of files down to unique version/build-date
+  let mut keys = Vec::new();
   while let Some(page) = object_paginator.next().await {
-    for obj in page?.contents.unwrap_or_default().iter() {
-      let key = obj.key().unwrap();
-      // <kubernetes-ver>/<build-date>/<artifact-type>/<os-type>/<os-arch>/<name>
-      let split = key.split('/').collect::<Vec<&str>>();
-      build_dates.insert((split[0].to_owned(), split[1].to_owned()));
+    for key in page?.contents.unwrap_or_default().iter().filter_map(|obj| obj.key()) {
+      keys.push(ArtifactKey::new(key)?);
     }
   }
 
+  Ok(keys)
+}
+
+fn get_build_date_versions(keys: &[ArtifactKey]) -> BTreeMap<String, BuildDateVersion> {
+  // Reduces list of files down to unique version/build-date
+  let build_dates = keys
+    .iter()
+    .map(|k| {
+      (
+        k.minor_version.as_str(),
+        k.minor,
+        k.kubernetes_version.as_str(),
+        k.build_date.as_str(),
+      )
+    })
+    .collect::<HashSet<_>>();
+
   let mut max_versions = BTreeMap::new();
 
-  for (kubernetes_version, build_date) in build_dates.iter() {
-    let version_split = kubernetes_version.split('.').collect::<Vec<&str>>();
-    let minor_version = version_split[..2].join(".");
+  for (minor_version, minor, kubernetes_version, build_date) in build_dates {
     let entry = BuildDateVersion {
       kubernetes_build_date: build_date.to_owned(),
       kubernetes_version: kubernetes_version.to_owned(),
     };
 
-    if version_split[1].parse::<i32>().unwrap() >= MIN_SUPPORTED_KUBERNETES_VERSION {
-      match max_versions.get(&minor_version) {
+    if minor >= MIN_SUPPORTED_KUBERNETES_VERSION {
+      match max_versions.get(minor_version) {
         Some(max_build_date) => {
           if &entry > max_build_date {
-            max_versions.insert(minor_version, entry);
+            max_versions.insert(minor_version.to_owned(), entry);
           }
         }
         None => {
-          max_versions.insert(minor_version, entry);
+          max_versions.insert(minor_version.to_owned(), entry);
         }
       }
     }
   }
 
-  Ok(max_versions)
+  max_versions
+}
+
+/// Get the latest build date under the Kubernetes version that contains the named artifact
+fn get_artifact_build_date(keys: &[ArtifactKey], kubernetes_version: &str, name: &str) -> Option<String> {
+  keys
+    .iter()
+    .filter(|k| k.kubernetes_version == kubernetes_version && k.name.as_deref() == Some(name))
+    .map(|k| k.build_date.to_owned())
+    .max()
+}
+
+/// Get the latest build date and version of the CNI plugins under the Kubernetes version
+fn get_cni_plugins_version(keys: &[ArtifactKey], kubernetes_version: &str) -> Option<(String, String)> {
+  keys
+    .iter()
+    .filter(|k| k.kubernetes_version == kubernetes_version)
+    .filter_map(|k| {
+      let version = k
+        .name
+        .as_deref()?
+        .strip_prefix("cni-plugins-linux-amd64-")?
+        .strip_suffix(".tgz")?;
+      Some((k.build_date.to_owned(), version.to_owned()))
+    })
+    .max()
 }
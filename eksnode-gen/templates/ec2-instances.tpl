@@ -9,8 +9,23 @@
   default_vcpus: {{ instance.default_vcpus }}
   eni_maximum_pods: {{ instance.eni_maximum_pods }}
   gpu_manufacturer: {{ instance.gpu_manufacturer }}
+{{ #if instance.gpu_count }}
+  gpu_count: {{ instance.gpu_count }}
+{{ /if }}
   hypervisor: {{ instance.hypervisor }}
   instance_storage_supported: {{ instance.instance_storage_supported }}
   ipv4_addresses_per_interface: {{ instance.ipv4_addresses_per_interface }}
   maximum_network_interfaces: {{ instance.maximum_network_interfaces }}
+{{ #if instance.network_baseline_bandwidth_gbps }}
+  network_baseline_bandwidth_gbps: {{ instance.network_baseline_bandwidth_gbps }}
+{{ /if }}
+{{ #if instance.network_burst_bandwidth_gbps }}
+  network_burst_bandwidth_gbps: {{ instance.network_burst_bandwidth_gbps }}
+{{ /if }}
+{{ #if instance.ebs_baseline_throughput_mbps }}
+  ebs_baseline_throughput_mbps: {{ instance.ebs_baseline_throughput_mbps }}
+{{ /if }}
+{{ #if instance.ebs_maximum_throughput_mbps }}
+  ebs_maximum_throughput_mbps: {{ instance.ebs_maximum_throughput_mbps }}
+{{ /if }}
 {{ /each }}
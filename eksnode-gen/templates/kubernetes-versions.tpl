@@ -0,0 +1,17 @@
+# Do not manually edit - this file is automatically generated with:
+# cargo run --bin eksnode-gen update-k8s-matrix
+#
+# Kubernetes versions supported by `eksnode` and the details used when joining a node of that version
+
+{{ #each versions as |version| }}
+'{{ @key }}':
+  kubernetes_version: {{ version.kubernetes_version }}
+  pause_tag: '{{ version.pause_tag }}'
+  credential_provider_api_version: {{ version.credential_provider_api_version }}
+{{ #if version.removed_kubelet_flags }}
+  removed_kubelet_flags:
+{{ #each version.removed_kubelet_flags as |flag| }}
+  - {{ flag }}
+{{ /each }}
+{{ /if }}
+{{ /each }}
@@ -1,6 +1,7 @@
 ---
 # This file is automatically generated by running `cargo run --bin eksnode-gen update-artifact-versions`
-# The `kubernetes_` fields are managed by the `eksnode-gen` CLI, the rest are intended to be provided by users
+# The `kubernetes_` and `*_build_date` fields (and `cni_plugins_version`) are managed by the `eksnode-gen` CLI,
+# the rest are intended to be provided by users
 # Note: Comments are not retained in the generated file
 #
 # Look up the respective versions based on the `version` provided
@@ -14,6 +15,20 @@ versions: {
       runc_version: '{{ version.runc_version }}',
       containerd_version: '{{ version.containerd_version }}',
       nerdctl_version: '{{ version.nerdctl_version }}',
+{{ #if version.aws_iam_authenticator_build_date }}
+      # Generated by `cargo run --bin eksnode-gen update-artifact-versions`
+      aws_iam_authenticator_build_date: '{{ version.aws_iam_authenticator_build_date }}',
+{{ /if }}
+{{ #if version.ecr_credential_provider_build_date }}
+      # Generated by `cargo run --bin eksnode-gen update-artifact-versions`
+      ecr_credential_provider_build_date: '{{ version.ecr_credential_provider_build_date }}',
+{{ /if }}
+{{ #if version.cni_plugins_version }}
+      # Generated by `cargo run --bin eksnode-gen update-artifact-versions`
+      cni_plugins_build_date: '{{ version.cni_plugins_build_date }}',
+      # Generated by `cargo run --bin eksnode-gen update-artifact-versions`
+      cni_plugins_version: '{{ version.cni_plugins_version }}',
+{{ /if }}
   },
 {{ /each }}
 }
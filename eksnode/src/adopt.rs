@@ -0,0 +1,227 @@
+//! Detection of nodes already configured by other bootstrap tooling
+//!
+//! Running eksnode over a node joined by the EKS AMI `bootstrap.sh` or by `nodeadm` leaves both tools fighting over
+//! the kubelet configuration; the node is either refused or adopted by moving the configuration of the other tool
+//! aside before eksnode regenerates it
+
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use tracing::info;
+
+use crate::{
+  events::{self, EventKind},
+  kubelet, utils,
+};
+
+/// Directory the configuration of the other tool is moved to when the node is adopted, in a tree mirroring `/`
+pub const ADOPTED_DIR: &str = "/var/lib/eksnode/adopted";
+
+/// Bootstrap script of the EKS optimized Amazon Linux 2 AMI
+const BOOTSTRAP_SH_PATH: &str = "/etc/eks/bootstrap.sh";
+
+/// Bootstrap tooling that may have configured the node before eksnode
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BootstrapTool {
+  /// `/etc/eks/bootstrap.sh` of the EKS optimized Amazon Linux 2 AMI
+  BootstrapSh,
+  /// `nodeadm` of the EKS optimized Amazon Linux 2023 AMI
+  Nodeadm,
+}
+
+impl fmt::Display for BootstrapTool {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::BootstrapSh => write!(f, "bootstrap.sh"),
+      Self::Nodeadm => write!(f, "nodeadm"),
+    }
+  }
+}
+
+impl BootstrapTool {
+  /// Files and directories the tool writes when it configures the node
+  ///
+  /// `bootstrap.sh` writes the same kubelet drop-ins as eksnode, so they are only its artifacts when
+  /// [`is_bootstrap_sh_node`] identifies the node. The kubelet config and kubeconfig it writes are overwritten by
+  /// eksnode and are left in place
+  fn artifacts(&self) -> &'static [&'static str] {
+    match self {
+      Self::BootstrapSh => &[kubelet::ARGS_PATH, kubelet::EXTRA_ARGS_PATH],
+      Self::Nodeadm => &[
+        "/etc/kubernetes/kubelet/config.json",
+        "/etc/kubernetes/kubelet/config.json.d",
+        "/etc/eks/kubelet/environment",
+      ],
+    }
+  }
+}
+
+/// File or directory written by another bootstrap tool
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Artifact {
+  pub tool: BootstrapTool,
+  /// Absolute path on the node (i.e. - `/etc/eks/kubelet/environment`)
+  pub path: &'static str,
+}
+
+impl fmt::Display for Artifact {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} ({})", self.path, self.tool)
+  }
+}
+
+/// Whether `bootstrap.sh` configured the node under the root directory
+///
+/// The script is only present on the EKS optimized Amazon Linux 2 AMI, and it writes the kubelet arguments on a single
+/// line where eksnode writes one argument per line
+fn is_bootstrap_sh_node(root: &Path) -> bool {
+  if !utils::rooted(root, BOOTSTRAP_SH_PATH).is_file() {
+    return false;
+  }
+
+  std::fs::read_to_string(utils::rooted(root, kubelet::ARGS_PATH))
+    .is_ok_and(|contents| contents.contains("KUBELET_ARGS=") && !contents.contains(" \\\n"))
+}
+
+/// Find the artifacts of other bootstrap tooling on the node under the root directory
+pub fn detect<P: AsRef<Path>>(root: P) -> Vec<Artifact> {
+  let root = root.as_ref();
+
+  let mut tools = vec![BootstrapTool::Nodeadm];
+  if is_bootstrap_sh_node(root) {
+    tools.push(BootstrapTool::BootstrapSh);
+  }
+
+  tools
+    .into_iter()
+    .flat_map(|tool| tool.artifacts().iter().map(move |&path| Artifact { tool, path }))
+    .filter(|artifact| utils::rooted(root, artifact.path).exists())
+    .collect()
+}
+
+/// Take over the node by moving the artifacts into the adopted directory, returning the paths they were moved to
+///
+/// Each move is recorded in the event log when `record_events` is set
+pub fn adopt<P: AsRef<Path>>(root: P, artifacts: &[Artifact], record_events: bool) -> Result<Vec<PathBuf>> {
+  let root = root.as_ref();
+
+  let mut moved = Vec::new();
+  for artifact in artifacts {
+    let source = utils::rooted(root, artifact.path);
+    let destination = utils::rooted(root, ADOPTED_DIR).join(artifact.path.trim_start_matches('/'));
+    if let Some(parent) = destination.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(&source, &destination)
+      .with_context(|| format!("Unable to move {} to {}", source.display(), destination.display()))?;
+
+    info!("Moved {artifact} to {}", destination.display());
+    if record_events {
+      events::record(
+        EventKind::FileWritten,
+        &destination.to_string_lossy(),
+        Some(format!("Adopted from {}", artifact.tool)),
+      );
+    }
+    moved.push(destination);
+  }
+
+  Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn touch(root: &Path, path: &str) {
+    write(root, path, "");
+  }
+
+  fn write(root: &Path, path: &str, contents: &str) {
+    let path = utils::rooted(root, path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+  }
+
+  #[test]
+  fn it_detects_bootstrap_tooling() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(detect(dir.path()).is_empty());
+
+    touch(dir.path(), BOOTSTRAP_SH_PATH);
+    write(
+      dir.path(),
+      kubelet::ARGS_PATH,
+      "[Service]\nEnvironment='KUBELET_ARGS=--node-ip=10.0.1.23 --pod-infra-container-image=pause:3.5 --v=2'\n",
+    );
+    touch(dir.path(), "/etc/eks/kubelet/environment");
+    assert_eq!(
+      detect(dir.path()),
+      vec![
+        Artifact {
+          tool: BootstrapTool::Nodeadm,
+          path: "/etc/eks/kubelet/environment",
+        },
+        Artifact {
+          tool: BootstrapTool::BootstrapSh,
+          path: kubelet::ARGS_PATH,
+        },
+      ]
+    );
+
+    // The paths shared with bootstrap.sh belong to eksnode once it has written them
+    write(
+      dir.path(),
+      kubelet::ARGS_PATH,
+      "[Service]\nEnvironment='KUBELET_ARGS=--v=2 \\\n\t--node-ip=10.0.1.23 \\\n\t--cloud-provider=aws'\n",
+    );
+    assert_eq!(detect(dir.path()).len(), 1);
+  }
+
+  #[test]
+  fn it_ignores_eksnode_dropins_without_state() {
+    let dir = tempfile::tempdir().unwrap();
+    write(
+      dir.path(),
+      kubelet::ARGS_PATH,
+      "[Service]\nEnvironment='KUBELET_ARGS=--v=2 \\\n\t--node-ip=10.0.1.23 \\\n\t--cloud-provider=aws'\n",
+    );
+    write(
+      dir.path(),
+      kubelet::EXTRA_ARGS_PATH,
+      "[Service]\nEnvironment='KUBELET_EXTRA_ARGS='\n",
+    );
+    assert!(detect(dir.path()).is_empty());
+
+    // Even on the Amazon Linux 2 AMI, where bootstrap.sh is present but was never run
+    touch(dir.path(), BOOTSTRAP_SH_PATH);
+    assert!(detect(dir.path()).is_empty());
+  }
+
+  #[test]
+  fn it_adopts_node() {
+    let dir = tempfile::tempdir().unwrap();
+    touch(dir.path(), "/etc/kubernetes/kubelet/config.json.d/40-nodeadm.conf");
+    touch(dir.path(), "/etc/kubernetes/kubelet/config.json");
+
+    let artifacts = detect(dir.path());
+    let moved = adopt(dir.path(), &artifacts, false).unwrap();
+
+    assert!(detect(dir.path()).is_empty());
+    assert_eq!(
+      moved,
+      vec![
+        dir
+          .path()
+          .join("var/lib/eksnode/adopted/etc/kubernetes/kubelet/config.json"),
+        dir
+          .path()
+          .join("var/lib/eksnode/adopted/etc/kubernetes/kubelet/config.json.d"),
+      ]
+    );
+    assert!(moved[1].join("40-nodeadm.conf").exists());
+  }
+}
@@ -0,0 +1,479 @@
+use std::{
+  collections::BTreeMap,
+  fmt,
+  path::PathBuf,
+  sync::{Arc, Mutex, OnceLock, RwLock},
+  time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use aws_config::{retry::RetryConfig, sts::AssumeRoleProvider, timeout::TimeoutConfig, BehaviorVersion, SdkConfig};
+use aws_runtime::env_config::file::{EnvConfigFileKind, EnvConfigFiles};
+use aws_sdk_eks::config::{
+  interceptors::{BeforeSerializationInterceptorContextRef, FinalizerInterceptorContextRef},
+  ConfigBag, Intercept, RuntimeComponents,
+};
+use aws_types::{region::Region, sdk_config::SharedCredentialsProvider};
+use clap::Args;
+use tracing::warn;
+
+/// Default maximum number of attempts for AWS API calls, including the initial request
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Default timeout in seconds of each attempt of an AWS API call
+const DEFAULT_ATTEMPT_TIMEOUT: u64 = 5;
+
+/// Session name of the role assumed with --aws-role-arn, recorded in CloudTrail
+const ROLE_SESSION_NAME: &str = "eksnode";
+
+/// Number of consecutive failed calls to a service after which further calls fail without being sent
+const CIRCUIT_BREAKER_THRESHOLD: u32 = 5;
+
+/// Duration the circuit stays open before a call is allowed through to probe the service
+const CIRCUIT_BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Response of an AWS API or IMDS call without a field required by eksnode, or with a field that cannot be parsed
+///
+/// Names the call and the field so that failed joins are actionable from the logs
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResponseError {
+  MissingField {
+    /// API call (i.e. - `eks:DescribeCluster`) or IMDS path
+    api: String,
+    field: &'static str,
+  },
+  InvalidField {
+    api: String,
+    field: &'static str,
+    value: String,
+  },
+}
+
+impl ResponseError {
+  pub fn missing(api: impl Into<String>, field: &'static str) -> Self {
+    Self::MissingField { api: api.into(), field }
+  }
+
+  pub fn invalid(api: impl Into<String>, field: &'static str, value: impl Into<String>) -> Self {
+    Self::InvalidField {
+      api: api.into(),
+      field,
+      value: value.into(),
+    }
+  }
+}
+
+impl fmt::Display for ResponseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::MissingField { api, field } => write!(f, "{api} response is missing {field}"),
+      Self::InvalidField { api, field, value } => write!(f, "{api} response has an invalid {field}: {value:?}"),
+    }
+  }
+}
+
+impl std::error::Error for ResponseError {}
+
+/// Retry and timeout settings shared by the AWS SDK clients
+#[derive(Args, Clone, Debug)]
+pub struct ClientConfig {
+  /// Maximum number of attempts for AWS API calls, including the initial request
+  ///
+  /// Retries use adaptive rate limiting to back off when the service is throttling requests
+  #[arg(
+    long,
+    env = "EKSNODE_AWS_MAX_ATTEMPTS",
+    global = true,
+    default_value_t = DEFAULT_MAX_ATTEMPTS,
+    value_parser = clap::value_parser!(u32).range(1..)
+  )]
+  pub aws_max_attempts: u32,
+
+  /// Timeout in seconds of each attempt of an AWS API call
+  #[arg(
+    long,
+    env = "EKSNODE_AWS_TIMEOUT",
+    global = true,
+    default_value_t = DEFAULT_ATTEMPT_TIMEOUT,
+    value_parser = clap::value_parser!(u64).range(1..)
+  )]
+  pub aws_timeout: u64,
+
+  /// Region of the AWS API calls (i.e. - `us-west-2`); defaults to the region of the environment
+  ///
+  /// Calls to resources in a fixed region, such as the EKS artifacts bucket, are not affected
+  #[arg(long, env = "EKSNODE_AWS_REGION", global = true)]
+  pub aws_region: Option<String>,
+
+  /// Named profile of the shared AWS config and credentials files used for the AWS API calls
+  #[arg(long, env = "EKSNODE_AWS_PROFILE", global = true)]
+  pub aws_profile: Option<String>,
+
+  /// IAM role assumed for the AWS API calls, using the credentials of the environment or --aws-profile
+  #[arg(long, env = "EKSNODE_AWS_ROLE_ARN", global = true)]
+  pub aws_role_arn: Option<String>,
+}
+
+impl Default for ClientConfig {
+  fn default() -> Self {
+    Self {
+      aws_max_attempts: DEFAULT_MAX_ATTEMPTS,
+      aws_timeout: DEFAULT_ATTEMPT_TIMEOUT,
+      aws_region: None,
+      aws_profile: None,
+      aws_role_arn: None,
+    }
+  }
+}
+
+static CLIENT_CONFIG: OnceLock<ClientConfig> = OnceLock::new();
+
+/// Set the retry and timeout settings used by all AWS SDK clients created afterwards
+///
+/// May only be called once, before the first client is created; the defaults are used otherwise
+pub fn configure(config: ClientConfig) -> Result<()> {
+  CLIENT_CONFIG
+    .set(config)
+    .map_err(|_| anyhow!("AWS client configuration has already been set"))
+}
+
+/// Region and shared config files of credentials sourced from outside of the environment (i.e. - hybrid nodes)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeCredentials {
+  /// Region of the AWS API calls when --aws-region is not set
+  pub region: Option<String>,
+  /// Shared config file used in place of `~/.aws/config`
+  pub config_file: Option<PathBuf>,
+  /// Shared credentials file used in place of `~/.aws/credentials`
+  pub credentials_file: Option<PathBuf>,
+}
+
+impl NodeCredentials {
+  /// Shared config and credentials files, falling back to the defaults of the environment for those not set
+  fn profile_files(&self) -> EnvConfigFiles {
+    let mut files = EnvConfigFiles::builder()
+      .include_default_config_file(self.config_file.is_none())
+      .include_default_credentials_file(self.credentials_file.is_none());
+    if let Some(path) = &self.config_file {
+      files = files.with_file(EnvConfigFileKind::Config, path);
+    }
+    if let Some(path) = &self.credentials_file {
+      files = files.with_file(EnvConfigFileKind::Credentials, path);
+    }
+
+    files.build()
+  }
+}
+
+static NODE_CREDENTIALS: RwLock<Option<NodeCredentials>> = RwLock::new(None);
+
+/// Set the node credentials used by all AWS SDK clients created afterwards
+///
+/// Unlike the retry and timeout settings, these may be updated as the files are written (i.e. - join, then reconcile)
+pub fn set_node_credentials(credentials: NodeCredentials) {
+  *NODE_CREDENTIALS.write().unwrap_or_else(|e| e.into_inner()) = Some(credentials);
+}
+
+/// Load the shared environment configuration with the configured region, credentials, retry, and timeout settings
+///
+/// All AWS SDK clients are created from this configuration
+pub async fn get_sdk_config() -> SdkConfig {
+  let config = CLIENT_CONFIG.get_or_init(ClientConfig::default);
+  let node = NODE_CREDENTIALS.read().unwrap_or_else(|e| e.into_inner()).clone();
+
+  let mut loader = aws_config::defaults(BehaviorVersion::latest())
+    .retry_config(RetryConfig::adaptive().with_max_attempts(config.aws_max_attempts))
+    .timeout_config(
+      TimeoutConfig::builder()
+        .operation_attempt_timeout(Duration::from_secs(config.aws_timeout))
+        .build(),
+    );
+  let node_region = node.as_ref().and_then(|node| node.region.as_ref());
+  if let Some(region) = config.aws_region.as_ref().or(node_region) {
+    loader = loader.region(Region::new(region.to_owned()));
+  }
+  if let Some(node) = &node {
+    loader = loader.profile_files(node.profile_files());
+  }
+  if let Some(profile) = &config.aws_profile {
+    loader = loader.profile_name(profile);
+  }
+  let sdk_config = loader.load().await;
+
+  let Some(role_arn) = &config.aws_role_arn else {
+    return sdk_config;
+  };
+  let provider = AssumeRoleProvider::builder(role_arn)
+    .session_name(ROLE_SESSION_NAME)
+    .configure(&sdk_config)
+    .build()
+    .await;
+
+  sdk_config
+    .into_builder()
+    .credentials_provider(SharedCredentialsProvider::new(provider))
+    .build()
+}
+
+/// Get the EC2 client
+pub async fn get_ec2_client() -> aws_sdk_ec2::Client {
+  let sdk_config = get_sdk_config().await;
+  let config = aws_sdk_ec2::config::Builder::from(&sdk_config)
+    .interceptor(CircuitBreaker::for_service("ec2"))
+    .build();
+
+  aws_sdk_ec2::Client::from_conf(config)
+}
+
+/// Get the EC2 client for the region, rather than that of the environment
+pub async fn get_regional_ec2_client(region: &str) -> aws_sdk_ec2::Client {
+  let sdk_config = get_sdk_config().await;
+  let config = aws_sdk_ec2::config::Builder::from(&sdk_config)
+    .region(aws_sdk_ec2::config::Region::new(region.to_owned()))
+    .interceptor(CircuitBreaker::for_service("ec2"))
+    .build();
+
+  aws_sdk_ec2::Client::from_conf(config)
+}
+
+/// Get the ECR client
+pub async fn get_ecr_client() -> aws_sdk_ecr::Client {
+  let sdk_config = get_sdk_config().await;
+  let config = aws_sdk_ecr::config::Builder::from(&sdk_config)
+    .interceptor(CircuitBreaker::for_service("ecr"))
+    .build();
+
+  aws_sdk_ecr::Client::from_conf(config)
+}
+
+/// Get the EKS client
+pub async fn get_eks_client() -> aws_sdk_eks::Client {
+  let sdk_config = get_sdk_config().await;
+  let config = aws_sdk_eks::config::Builder::from(&sdk_config)
+    .interceptor(CircuitBreaker::for_service("eks"))
+    .build();
+
+  aws_sdk_eks::Client::from_conf(config)
+}
+
+/// Get the CloudWatch Logs client, in the region when one is given
+pub async fn get_logs_client(region: Option<&str>) -> aws_sdk_cloudwatchlogs::Client {
+  let sdk_config = get_sdk_config().await;
+  let mut builder =
+    aws_sdk_cloudwatchlogs::config::Builder::from(&sdk_config).interceptor(CircuitBreaker::for_service("logs"));
+  if let Some(region) = region {
+    builder = builder.region(aws_sdk_cloudwatchlogs::config::Region::new(region.to_owned()));
+  }
+
+  aws_sdk_cloudwatchlogs::Client::from_conf(builder.build())
+}
+
+/// Get the Security Hub client for the region
+pub async fn get_securityhub_client(region: &str) -> aws_sdk_securityhub::Client {
+  let sdk_config = get_sdk_config().await;
+  let config = aws_sdk_securityhub::config::Builder::from(&sdk_config)
+    .region(aws_sdk_securityhub::config::Region::new(region.to_owned()))
+    .interceptor(CircuitBreaker::for_service("securityhub"))
+    .build();
+
+  aws_sdk_securityhub::Client::from_conf(config)
+}
+
+/// Get the SSM client for the region
+pub async fn get_ssm_client(region: &str) -> aws_sdk_ssm::Client {
+  let sdk_config = get_sdk_config().await;
+  let config = aws_sdk_ssm::config::Builder::from(&sdk_config)
+    .region(aws_sdk_ssm::config::Region::new(region.to_owned()))
+    .interceptor(CircuitBreaker::for_service("ssm"))
+    .build();
+
+  aws_sdk_ssm::Client::from_conf(config)
+}
+
+/// Get the S3 client for the region
+pub async fn get_s3_client(region: &'static str) -> aws_sdk_s3::Client {
+  let sdk_config = get_sdk_config().await;
+  let config = aws_sdk_s3::config::Builder::from(&sdk_config)
+    .region(aws_sdk_s3::config::Region::new(region))
+    .interceptor(CircuitBreaker::for_service("s3"))
+    .build();
+
+  aws_sdk_s3::Client::from_conf(config)
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+  consecutive_failures: u32,
+  opened_at: Option<Instant>,
+}
+
+impl CircuitState {
+  /// Whether calls are rejected; once the cooldown elapses a call is allowed through to probe the service
+  fn is_open(&self, now: Instant) -> bool {
+    matches!(self.opened_at, Some(opened_at) if now.duration_since(opened_at) < CIRCUIT_BREAKER_COOLDOWN)
+  }
+
+  fn record_success(&mut self) {
+    self.consecutive_failures = 0;
+    self.opened_at = None;
+  }
+
+  /// Record a failed call, returning true when the failure opens the circuit
+  fn record_failure(&mut self, now: Instant) -> bool {
+    self.consecutive_failures += 1;
+    if self.consecutive_failures < CIRCUIT_BREAKER_THRESHOLD {
+      return false;
+    }
+
+    self.opened_at = Some(now);
+    true
+  }
+}
+
+/// Fails calls to a service without sending them after repeated failures once retries are exhausted
+///
+/// Only failures that indicate the service is unavailable (timeouts, connection errors, throttling, and 5xx
+/// responses) are counted; other errors such as access denied show that the service is reachable
+#[derive(Clone, Debug)]
+struct CircuitBreaker {
+  service: &'static str,
+  state: Arc<Mutex<CircuitState>>,
+}
+
+impl CircuitBreaker {
+  /// The circuit breaker shared by all clients of the service
+  fn for_service(service: &'static str) -> Self {
+    static BREAKERS: Mutex<BTreeMap<&str, CircuitBreaker>> = Mutex::new(BTreeMap::new());
+
+    let mut breakers = BREAKERS.lock().unwrap_or_else(|e| e.into_inner());
+    breakers
+      .entry(service)
+      .or_insert_with(|| Self {
+        service,
+        state: Arc::default(),
+      })
+      .clone()
+  }
+
+  fn state(&self) -> std::sync::MutexGuard<'_, CircuitState> {
+    self.state.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+impl Intercept for CircuitBreaker {
+  fn name(&self) -> &'static str {
+    "CircuitBreaker"
+  }
+
+  fn read_before_execution(
+    &self,
+    _context: &BeforeSerializationInterceptorContextRef<'_>,
+    _cfg: &mut ConfigBag,
+  ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if self.state().is_open(Instant::now()) {
+      return Err(
+        format!(
+          "{} calls are failing; skipping call for up to {}s",
+          self.service,
+          CIRCUIT_BREAKER_COOLDOWN.as_secs()
+        )
+        .into(),
+      );
+    }
+
+    Ok(())
+  }
+
+  fn read_after_execution(
+    &self,
+    context: &FinalizerInterceptorContextRef<'_>,
+    _runtime_components: &RuntimeComponents,
+    _cfg: &mut ConfigBag,
+  ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let unavailable = match (context.output_or_error(), context.response()) {
+      (Some(Err(_)), Some(response)) => {
+        let status = response.status();
+        status.is_server_error() || status.as_u16() == 429
+      }
+      (Some(Err(_)), None) => true,
+      _ => false,
+    };
+
+    let now = Instant::now();
+    let mut state = self.state();
+    // Calls rejected while the circuit is open are not counted
+    if state.is_open(now) {
+      return Ok(());
+    }
+    match unavailable {
+      true => {
+        if state.record_failure(now) {
+          warn!(
+            "{} calls failed {} times in a row; skipping calls for {}s",
+            self.service,
+            state.consecutive_failures,
+            CIRCUIT_BREAKER_COOLDOWN.as_secs()
+          );
+        }
+      }
+      false => state.record_success(),
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_gets_node_credentials_profile_files() {
+    let credentials = NodeCredentials {
+      config_file: Some(PathBuf::from("/etc/eksnode/aws/config")),
+      ..NodeCredentials::default()
+    };
+
+    let files = format!("{:?}", credentials.profile_files());
+    assert!(files.contains("/etc/eksnode/aws/config"));
+    assert!(files.contains("Default(Credentials)"));
+    assert!(!files.contains("Default(Config)"));
+  }
+
+  #[test]
+  fn it_opens_circuit_after_consecutive_failures() {
+    let now = Instant::now();
+    let mut state = CircuitState::default();
+
+    for _ in 1..CIRCUIT_BREAKER_THRESHOLD {
+      assert!(!state.record_failure(now));
+    }
+    assert!(!state.is_open(now));
+
+    assert!(state.record_failure(now));
+    assert!(state.is_open(now));
+    assert!(!state.is_open(now + CIRCUIT_BREAKER_COOLDOWN));
+  }
+
+  #[test]
+  fn it_closes_circuit_on_success() {
+    let now = Instant::now();
+    let mut state = CircuitState::default();
+
+    for _ in 0..CIRCUIT_BREAKER_THRESHOLD {
+      state.record_failure(now);
+    }
+    state.record_success();
+
+    assert!(!state.is_open(now));
+    assert_eq!(state.consecutive_failures, 0);
+  }
+
+  #[test]
+  fn it_shares_circuit_breaker_across_clients() {
+    let breaker = CircuitBreaker::for_service("test");
+    breaker.state().consecutive_failures = 2;
+
+    assert_eq!(CircuitBreaker::for_service("test").state().consecutive_failures, 2);
+  }
+}
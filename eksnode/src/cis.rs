@@ -0,0 +1,363 @@
+//! CIS Amazon EKS Benchmark checks of the worker node configuration
+//!
+//! The kubelet and containerd settings are collected once into a [`NodeSnapshot`] that each [`Check`] queries
+
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::Result;
+use serde_json::Value as JsonValue;
+use tracing::debug;
+
+use crate::{containerd, kubelet, utils};
+
+/// Kubelet and containerd configuration of the running node
+#[derive(Debug, Default)]
+pub struct NodeSnapshot {
+  /// Command line flags of the running kubelet without the leading dashes (i.e. - `node-ip` -> `10.0.0.1`)
+  ///
+  /// Empty when kubelet is not running
+  pub kubelet_flags: BTreeMap<String, String>,
+  /// Kubelet config file passed with `--config`, or the file written by `join-cluster` when kubelet is not running
+  pub kubelet_config: Option<JsonValue>,
+  /// containerd config file
+  pub containerd_config: Option<toml::Value>,
+}
+
+impl NodeSnapshot {
+  /// Collect the configuration of the node under the root directory (`/` on the host)
+  pub fn collect(root: &Path) -> Result<Self> {
+    let kubelet_flags = match get_kubelet_cmdline(root)? {
+      Some(cmdline) => parse_flags(&cmdline),
+      None => {
+        debug!("kubelet is not running; only the config files are collected");
+        BTreeMap::new()
+      }
+    };
+
+    let config_path = kubelet_flags
+      .get("config")
+      .map_or(kubelet::KUBELET_CONFIG_PATH, String::as_str);
+    let kubelet_config = match std::fs::read(utils::rooted(root, config_path)) {
+      Ok(contents) => Some(serde_json::from_slice(&contents)?),
+      Err(_) => None,
+    };
+    let containerd_config = match std::fs::read_to_string(utils::rooted(root, containerd::CONTAINERD_CONFIG_PATH)) {
+      Ok(contents) => Some(toml::from_str(&contents)?),
+      Err(_) => None,
+    };
+
+    Ok(Self {
+      kubelet_flags,
+      kubelet_config,
+      containerd_config,
+    })
+  }
+
+  /// Get the value of a kubelet config field by its dotted path (i.e. - `authentication.anonymous.enabled`)
+  pub fn kubelet_config_value(&self, path: &str) -> Option<String> {
+    let value = path
+      .split('.')
+      .try_fold(self.kubelet_config.as_ref()?, |value, key| value.get(key))?;
+
+    match value {
+      JsonValue::String(s) => Some(s.to_owned()),
+      JsonValue::Null => None,
+      value => Some(value.to_string()),
+    }
+  }
+
+  /// Get the effective kubelet setting, where the command line flag takes precedence over the config file
+  pub fn kubelet_setting(&self, flag: &str, config_path: &str) -> Option<String> {
+    self
+      .kubelet_flags
+      .get(flag)
+      .cloned()
+      .or_else(|| self.kubelet_config_value(config_path))
+  }
+}
+
+/// Get the command line arguments of the running kubelet from /proc
+fn get_kubelet_cmdline(root: &Path) -> Result<Option<Vec<String>>> {
+  for entry in std::fs::read_dir(utils::rooted(root, "/proc"))? {
+    let path = entry?.path();
+    if !path
+      .file_name()
+      .is_some_and(|n| n.to_string_lossy().chars().all(|c| c.is_ascii_digit()))
+    {
+      continue;
+    }
+    // Processes may exit while being read
+    let Ok(comm) = std::fs::read_to_string(path.join("comm")) else {
+      continue;
+    };
+    if comm.trim() != "kubelet" {
+      continue;
+    }
+
+    let cmdline = std::fs::read(path.join("cmdline"))?;
+    let args = cmdline
+      .split(|b| *b == 0)
+      .filter(|arg| !arg.is_empty())
+      .map(|arg| String::from_utf8_lossy(arg).into_owned())
+      .collect();
+    return Ok(Some(args));
+  }
+
+  Ok(None)
+}
+
+/// Parse `--flag=value`, `--flag value`, and boolean `--flag` arguments, skipping the program name
+fn parse_flags(args: &[String]) -> BTreeMap<String, String> {
+  let mut flags = BTreeMap::new();
+  let mut args = args.iter().skip(1).peekable();
+
+  while let Some(arg) = args.next() {
+    let Some(flag) = arg.strip_prefix("--").or_else(|| arg.strip_prefix('-')) else {
+      continue;
+    };
+    let (name, value) = match flag.split_once('=') {
+      Some((name, value)) => (name, value.to_owned()),
+      None => match args.next_if(|next| !next.starts_with('-')) {
+        Some(value) => (flag, value.to_owned()),
+        None => (flag, "true".to_owned()),
+      },
+    };
+    flags.insert(name.to_owned(), value);
+  }
+
+  flags
+}
+
+/// Recommendation of the benchmark evaluated against the node snapshot
+pub trait Check {
+  /// Recommendation number in the benchmark (i.e. - `3.2.1`)
+  fn id(&self) -> &'static str;
+
+  /// Recommendation title
+  fn title(&self) -> &'static str;
+
+  /// Get the reason the node does not follow the recommendation
+  fn evaluate(&self, snapshot: &NodeSnapshot) -> Option<String>;
+}
+
+/// Value a kubelet setting is expected to have
+enum Expected {
+  /// Set to the value
+  Equals(&'static str),
+  /// Set to the value, or unset where the value is the kubelet default
+  EqualsOrUnset(&'static str),
+  /// Not set to the value; unset passes
+  NotEquals(&'static str),
+  /// Set to any value
+  Set,
+}
+
+/// Check of a kubelet setting configured through a command line flag or the config file
+struct KubeletSetting {
+  id: &'static str,
+  title: &'static str,
+  flag: &'static str,
+  config_path: &'static str,
+  expected: Expected,
+}
+
+impl Check for KubeletSetting {
+  fn id(&self) -> &'static str {
+    self.id
+  }
+
+  fn title(&self) -> &'static str {
+    self.title
+  }
+
+  fn evaluate(&self, snapshot: &NodeSnapshot) -> Option<String> {
+    let value = snapshot.kubelet_setting(self.flag, self.config_path);
+    let passed = match (&self.expected, value.as_deref()) {
+      (Expected::Equals(expected), value) => value == Some(*expected),
+      (Expected::EqualsOrUnset(expected), value) => value.is_none_or(|v| v == *expected),
+      (Expected::NotEquals(unexpected), value) => value != Some(*unexpected),
+      (Expected::Set, value) => value.is_some_and(|v| !v.is_empty()),
+    };
+    if passed {
+      return None;
+    }
+
+    let setting = format!("--{} (`{}`)", self.flag, self.config_path);
+    Some(match (&self.expected, value) {
+      (Expected::Equals(expected) | Expected::EqualsOrUnset(expected), Some(value)) => {
+        format!("{setting} is {value}; expected {expected}")
+      }
+      (Expected::Equals(expected) | Expected::EqualsOrUnset(expected), None) => {
+        format!("{setting} is not set; expected {expected}")
+      }
+      (Expected::NotEquals(unexpected), _) => format!("{setting} must not be {unexpected}"),
+      (Expected::Set, _) => format!("{setting} is not set"),
+    })
+  }
+}
+
+/// Kubelet recommendations of the CIS Amazon EKS Benchmark (section 3.2)
+const KUBELET_CHECKS: &[KubeletSetting] = &[
+  KubeletSetting {
+    id: "3.2.1",
+    title: "Ensure that the Anonymous Auth is Not Enabled",
+    flag: "anonymous-auth",
+    config_path: "authentication.anonymous.enabled",
+    expected: Expected::Equals("false"),
+  },
+  KubeletSetting {
+    id: "3.2.2",
+    title: "Ensure that the --authorization-mode argument is not set to AlwaysAllow",
+    flag: "authorization-mode",
+    config_path: "authorization.mode",
+    expected: Expected::NotEquals("AlwaysAllow"),
+  },
+  KubeletSetting {
+    id: "3.2.3",
+    title: "Ensure that a Client CA File is Configured",
+    flag: "client-ca-file",
+    config_path: "authentication.x509.clientCAFile",
+    expected: Expected::Set,
+  },
+  KubeletSetting {
+    id: "3.2.4",
+    title: "Ensure that the --read-only-port is disabled",
+    flag: "read-only-port",
+    config_path: "readOnlyPort",
+    expected: Expected::EqualsOrUnset("0"),
+  },
+  KubeletSetting {
+    id: "3.2.5",
+    title: "Ensure that the --streaming-connection-idle-timeout argument is not set to 0",
+    flag: "streaming-connection-idle-timeout",
+    config_path: "streamingConnectionIdleTimeout",
+    expected: Expected::NotEquals("0"),
+  },
+  KubeletSetting {
+    id: "3.2.6",
+    title: "Ensure that the --protect-kernel-defaults argument is set to true",
+    flag: "protect-kernel-defaults",
+    config_path: "protectKernelDefaults",
+    expected: Expected::Equals("true"),
+  },
+  KubeletSetting {
+    id: "3.2.7",
+    title: "Ensure that the --make-iptables-util-chains argument is set to true",
+    flag: "make-iptables-util-chains",
+    config_path: "makeIPTablesUtilChains",
+    expected: Expected::EqualsOrUnset("true"),
+  },
+  KubeletSetting {
+    id: "3.2.10",
+    title: "Ensure that the --rotate-certificates argument is not set to false",
+    flag: "rotate-certificates",
+    config_path: "rotateCertificates",
+    expected: Expected::NotEquals("false"),
+  },
+];
+
+/// Get the checks of the benchmark
+pub fn get_checks() -> Vec<&'static dyn Check> {
+  KUBELET_CHECKS.iter().map(|check| check as &dyn Check).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn it_parses_flags() {
+    let args = [
+      "/usr/bin/kubelet",
+      "--config",
+      "/etc/kubernetes/kubelet/kubelet-config.json",
+      "--node-ip=10.0.0.1",
+      "--anonymous-auth",
+      "-v=2",
+    ]
+    .map(String::from);
+
+    assert_eq!(
+      parse_flags(&args),
+      BTreeMap::from([
+        ("anonymous-auth".to_owned(), "true".to_owned()),
+        (
+          "config".to_owned(),
+          "/etc/kubernetes/kubelet/kubelet-config.json".to_owned()
+        ),
+        ("node-ip".to_owned(), "10.0.0.1".to_owned()),
+        ("v".to_owned(), "2".to_owned()),
+      ])
+    );
+  }
+
+  #[test]
+  fn it_collects_snapshot() {
+    let root = tempfile::tempdir().unwrap();
+    let write = |path: &str, contents: &[u8]| {
+      let path = utils::rooted(root.path(), path);
+      std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+      std::fs::write(path, contents).unwrap();
+    };
+    write("/proc/1/comm", b"systemd\n");
+    write("/proc/1/cmdline", b"/sbin/init\0");
+    write("/proc/1234/comm", b"kubelet\n");
+    write(
+      "/proc/1234/cmdline",
+      b"/usr/bin/kubelet\0--config\0/etc/kubelet.json\0--read-only-port=10255\0",
+    );
+    write(
+      "/etc/kubelet.json",
+      br#"{"authentication":{"anonymous":{"enabled":false}},"readOnlyPort":0}"#,
+    );
+
+    let snapshot = NodeSnapshot::collect(root.path()).unwrap();
+    assert_eq!(
+      snapshot
+        .kubelet_config_value("authentication.anonymous.enabled")
+        .as_deref(),
+      Some("false")
+    );
+    assert_eq!(
+      snapshot.kubelet_setting("read-only-port", "readOnlyPort").as_deref(),
+      Some("10255")
+    );
+    assert!(snapshot.containerd_config.is_none());
+  }
+
+  #[test]
+  fn it_evaluates_kubelet_checks() {
+    let snapshot = NodeSnapshot {
+      kubelet_config: Some(json!({
+        "authentication": {
+          "anonymous": { "enabled": false },
+          "x509": { "clientCAFile": "/etc/kubernetes/pki/ca.crt" }
+        },
+        "authorization": { "mode": "Webhook" },
+        "readOnlyPort": 10255,
+        "rotateCertificates": true,
+      })),
+      ..Default::default()
+    };
+
+    let issues = get_checks()
+      .iter()
+      .filter_map(|check| check.evaluate(&snapshot).map(|issue| (check.id(), issue)))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      issues,
+      vec![
+        (
+          "3.2.4",
+          "--read-only-port (`readOnlyPort`) is 10255; expected 0".to_owned()
+        ),
+        (
+          "3.2.6",
+          "--protect-kernel-defaults (`protectKernelDefaults`) is not set; expected true".to_owned()
+        ),
+      ]
+    );
+  }
+}
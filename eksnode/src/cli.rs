@@ -1,11 +1,16 @@
 use anstyle::{AnsiColor, Color, Style};
-use clap::{builder::Styles, Parser, Subcommand};
+use clap::{builder::Styles, parser::ValueSource, ArgMatches, Parser, Subcommand, ValueEnum};
 use clap_verbosity_flag::Verbosity;
 
-use crate::commands;
+use crate::{aws, commands, telemetry, utils};
 
-/// Styles for CLI
-fn get_styles() -> Styles {
+/// Describes the environment variables that arguments are read from, such as through a systemd `EnvironmentFile=`
+const ENV_HELP: &str = "Arguments can also be set through environment variables named after the flag with an EKSNODE_ \
+                        prefix (i.e. - --cluster-name as EKSNODE_CLUSTER_NAME). Flags on the command line take \
+                        precedence, and repeatable flags take a single value from the environment.";
+
+/// Styles for CLI, shared with `eksnode-gen`
+pub fn get_styles() -> Styles {
   Styles::styled()
     .header(
       Style::new()
@@ -24,10 +29,25 @@ fn get_styles() -> Styles {
     .error(Style::new().bold().fg_color(Some(Color::Ansi(AnsiColor::BrightRed))))
 }
 
+/// Get the IDs of the arguments set on the command line or through the environment rather than by default
+pub fn get_explicit_args(matches: &ArgMatches) -> Vec<String> {
+  matches
+    .ids()
+    .filter(|id| {
+      matches!(
+        matches.value_source(id.as_str()),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+      )
+    })
+    .map(|id| id.to_string())
+    .collect()
+}
+
 #[derive(Debug, Parser)]
 #[command(author, about, version)]
 #[command(propagate_version = true)]
 #[command(styles=get_styles())]
+#[command(after_help = ENV_HELP)]
 pub struct Cli {
   #[command(subcommand)]
   pub command: Commands,
@@ -36,8 +56,37 @@ pub struct Cli {
   pub verbose: Verbosity,
 
   /// Disable colors on logged output
-  #[arg(long, global = true, default_value = "false")]
+  #[arg(long, env = "EKSNODE_NO_COLOR", global = true, default_value = "false")]
   pub no_color: bool,
+
+  /// Destination for logged output
+  #[arg(long, env = "EKSNODE_LOG_TARGET", global = true, value_enum, default_value_t)]
+  pub log_target: LogTarget,
+
+  /// Writes the files eksnode generates under a directory to a writable directory instead, for AMIs with an
+  /// immutable root filesystem (i.e. - `/etc/kubernetes=/var/lib/eksnode/etc/kubernetes`)
+  ///
+  /// `provision-ami` redirects the directory to the writable directory with a symlink or bind mount
+  #[arg(long, env = "EKSNODE_PATH_MAP", global = true, value_parser = utils::parse_path_map)]
+  pub path_map: Vec<utils::PathMap>,
+
+  #[clap(flatten)]
+  pub aws: aws::ClientConfig,
+
+  #[clap(flatten)]
+  pub telemetry: telemetry::TelemetryConfig,
+}
+
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+pub enum LogTarget {
+  /// Plain text output written to stderr
+  #[default]
+  Stderr,
+  /// Structured entries written to the systemd journal
+  ///
+  /// Span fields such as the cluster name and instance ID, along with the join phase,
+  /// are emitted as journal fields for consumption by journal based log collectors
+  Journald,
 }
 
 #[derive(Debug, Subcommand)]
@@ -55,15 +104,154 @@ pub enum Commands {
   /// Expose and collect details about the node for debugging purposes
   Debug(commands::debug::DebugInput),
 
+  /// Query the event log of the actions eksnode has taken on the node
+  ///
+  /// Files written, systemd unit actions, and failed AWS API calls are recorded to /var/lib/eksnode/events.jsonl,
+  /// which outlives the journald retention
+  Events(commands::events::EventsInput),
+
   /// Pull images from a registry
   ///
   /// Supports pulling one image as specified or for pulling commonly used images
   /// to be cached on the host/AMI
   PullImage(commands::pull::PullImageInput),
 
+  /// Keep ECR auth entries in a Docker config file valid by refreshing the 12 hour ECR token
+  ///
+  /// Covers host level pulls (i.e. - nerdctl, ctr, or a registry mirror that requires basic auth) that bypass the
+  /// kubelet credential provider
+  EcrCredentialRefresh(commands::credential::EcrCredentialRefreshInput),
+
   /// Join an instance to the cluster
-  JoinCluster(commands::join::JoinClusterInput),
+  JoinCluster(Box<commands::join::JoinClusterInput>),
+
+  /// Provision the AMI at build time
+  ///
+  /// Creates the directories, system configuration (sysctl, logrotate, networkd), and service units used by the node
+  ProvisionAmi(commands::provision::ProvisionAmiInput),
+
+  /// Render the node configuration from a join-cluster config file and reapply it when it drifts
+  ///
+  /// With --watch, keeps running to re-render when the config file changes and restore generated files that have
+  /// been modified, restarting containerd and kubelet as needed
+  Reconcile(commands::reconcile::ReconcileInput),
+
+  /// Render an embedded template with the values provided
+  ///
+  /// Lets AMI pipelines build custom variants of the sandbox-image service, sysctl, logrotate, and unit files
+  /// without duplicating them
+  Render(commands::render::RenderInput),
+
+  /// Show the kubeReserved, hard eviction thresholds, and allocatable estimates computed for an instance type
+  ///
+  /// Uses the same formulas as `join-cluster` so capacity planners can audit them without launching nodes
+  Reservations(commands::reservations::ReservationsInput),
+
+  /// Show the node name and provider ID of the instance
+  ///
+  /// Derived from the instance metadata the same way as the values `join-cluster` writes into the kubelet config,
+  /// for consumption by external tooling such as custom cloud controller manager setups
+  Status(commands::status::StatusInput),
+
+  /// Switch kubelet to another cluster in its kubeconfig and restart it
+  ///
+  /// Eases blue/green cluster migrations of stateful nodes joined with --additional-cluster
+  SwitchCluster(commands::switch::SwitchClusterInput),
+
+  /// Validate a join-cluster config file offline
+  ///
+  /// Checks types, mutually exclusive fields, and CIDR/IP syntax without calling AWS
+  ValidateConfig(commands::validate::ValidateConfigInput),
 
   /// Validate the node configuration
   ValidateNode(commands::validate::ValidateNodeInput),
+
+  /// Verify the checksums of the installed binaries against their expected checksums
+  ///
+  /// Produces a report for AMI release gates covering kubelet, containerd, runc, and the CNI plugins
+  VerifyArtifacts(commands::artifacts::VerifyArtifactsInput),
+
+  /// Verify the node advertises the expected GPU or Neuron device capacity
+  ///
+  /// Intended to be run after kubelet and the device plugin have started on accelerator nodes
+  VerifyAccelerators(commands::accelerators::VerifyAcceleratorsInput),
+}
+
+impl Commands {
+  /// Name of the command as invoked (i.e. - `join-cluster`)
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::CalculateMaxPods(_) => "calculate-max-pods",
+      Self::GetVersions(_) => "get-versions",
+      Self::Debug(_) => "debug",
+      Self::Events(_) => "events",
+      Self::PullImage(_) => "pull-image",
+      Self::EcrCredentialRefresh(_) => "ecr-credential-refresh",
+      Self::JoinCluster(_) => "join-cluster",
+      Self::ProvisionAmi(_) => "provision-ami",
+      Self::Reconcile(_) => "reconcile",
+      Self::Render(_) => "render",
+      Self::Reservations(_) => "reservations",
+      Self::Status(_) => "status",
+      Self::SwitchCluster(_) => "switch-cluster",
+      Self::ValidateConfig(_) => "validate-config",
+      Self::ValidateNode(_) => "validate-node",
+      Self::VerifyArtifacts(_) => "verify-artifacts",
+      Self::VerifyAccelerators(_) => "verify-accelerators",
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_reads_arguments_from_env() {
+    use clap::CommandFactory;
+
+    // The environment is not modified since it is shared by the tests running in parallel
+    let cli = Cli::command();
+    let env = |command: &str, id: &str| {
+      cli
+        .find_subcommand(command)
+        .unwrap()
+        .get_arguments()
+        .find(|arg| arg.get_id() == id)
+        .and_then(|arg| arg.get_env())
+        .and_then(|env| env.to_str())
+        .map(str::to_owned)
+    };
+    assert_eq!(
+      env("calculate-max-pods", "instance_type").as_deref(),
+      Some("EKSNODE_INSTANCE_TYPE")
+    );
+    assert_eq!(
+      env("calculate-max-pods", "cni_version").as_deref(),
+      Some("EKSNODE_CNI_VERSION")
+    );
+
+    // Flags with the same name but a different meaning per command are scoped to the command
+    assert_eq!(env("render", "output").as_deref(), Some("EKSNODE_RENDER_OUTPUT"));
+    assert_eq!(env("status", "output").as_deref(), Some("EKSNODE_STATUS_OUTPUT"));
+    assert_eq!(env("events", "path").as_deref(), Some("EKSNODE_EVENTS_PATH"));
+  }
+
+  #[test]
+  fn it_parses_arguments() {
+    let cli = Cli::try_parse_from([
+      "eksnode",
+      "calculate-max-pods",
+      "--instance-type",
+      "m5.large",
+      "--cni-version",
+      "1.18.0",
+    ]);
+
+    let Commands::CalculateMaxPods(input) = cli.unwrap().command else {
+      panic!("Expected calculate-max-pods");
+    };
+    assert_eq!(input.instance_type.as_deref(), Some("m5.large"));
+    assert_eq!(input.cni_version.as_deref(), Some("1.18.0"));
+  }
 }
@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{info, warn};
+
+use crate::{aws, ec2, gpu, kubelet};
+
+/// Input arguments for `verify-accelerators` command
+#[derive(Args, Debug, Default, Serialize, Deserialize)]
+pub struct VerifyAcceleratorsInput {
+  /// Maximum time in seconds to wait for the device plugin to advertise the devices
+  #[arg(long, env = "EKSNODE_ACCELERATORS_TIMEOUT", default_value = "0")]
+  pub timeout: u64,
+
+  /// Source of the node name, matching the --node-name-strategy the node was joined with
+  #[arg(long, env = "EKSNODE_NODE_NAME_STRATEGY", value_enum, default_value_t)]
+  pub node_name_strategy: ec2::NodeNameStrategy,
+
+  /// The kubeconfig used to read the Node object; defaults to the kubeconfig of kubelet
+  #[arg(long, env = "EKSNODE_ACCELERATORS_KUBECONFIG", default_value = kubelet::KUBECONFIG_PATH)]
+  pub kubeconfig: PathBuf,
+}
+
+/// Accelerator resource expected to be advertised by the node
+struct ExpectedResource {
+  resource: &'static str,
+  count: usize,
+}
+
+impl VerifyAcceleratorsInput {
+  /// Verify the accelerators on the node are advertised by the Node object
+  ///
+  /// The capacity of the Node object is compared against the GPU count from the instance data (or the devices
+  /// detected by the driver), flagging driver and device plugin issues early
+  pub async fn verify(&self) -> Result<()> {
+    let Some(expected) = get_expected_resource().await? else {
+      info!("No accelerators found on the node, nothing to verify");
+      return Ok(());
+    };
+
+    let imds = ec2::get_imds_data().await?;
+    let identity = ec2::get_node_identity(&imds, &aws::get_ec2_client().await, self.node_name_strategy).await?;
+    let client = kubelet::ApiClient::from_kubeconfig(&self.kubeconfig)?;
+    let path = format!("/api/v1/nodes/{}", identity.node_name);
+
+    let deadline = Instant::now() + Duration::from_secs(self.timeout);
+    loop {
+      let result = match client.get(&path).await {
+        Ok(node) => {
+          gpu::verify_advertised_devices(&gpu::get_advertised_devices(&node), expected.resource, expected.count)
+        }
+        Err(e) => Err(e),
+      };
+
+      match result {
+        Ok(()) => return Ok(()),
+        Err(e) if Instant::now() >= deadline => return Err(e),
+        Err(e) => {
+          warn!("{e}; retrying");
+          sleep(Duration::from_secs(5)).await;
+        }
+      }
+    }
+  }
+}
+
+/// Get the accelerator resource and device count expected on the node
+async fn get_expected_resource() -> Result<Option<ExpectedResource>> {
+  let instance_type = ec2::get_instance_type().await?;
+  let instance = ec2::get_instance(&instance_type)?;
+
+  if let Some(instance) = instance.as_ref().filter(|i| i.gpu_manufacturer == "NVIDIA") {
+    let count = match instance.gpu_count {
+      Some(count) => count as usize,
+      None => gpu::get_nvidia_gpu_count()?,
+    };
+    if count == 0 {
+      bail!("{instance_type} is an NVIDIA GPU instance but no GPUs were detected by the driver");
+    }
+
+    return Ok(Some(ExpectedResource {
+      resource: gpu::NVIDIA_GPU_RESOURCE,
+      count,
+    }));
+  }
+
+  if instance_type.starts_with("inf") || instance_type.starts_with("trn") {
+    let count = gpu::get_neuron_device_count("/dev")?;
+    if count == 0 {
+      bail!("{instance_type} is a Neuron instance but no Neuron devices were found; is the Neuron driver loaded?");
+    }
+
+    return Ok(Some(ExpectedResource {
+      resource: gpu::NEURON_RESOURCE,
+      count,
+    }));
+  }
+
+  Ok(None)
+}
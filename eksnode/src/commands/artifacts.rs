@@ -0,0 +1,275 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{bail, Result};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use tabled::{Table, Tabled};
+use tracing::{info, warn};
+
+use crate::{aws, kubelet, utils, Architecture};
+
+/// S3 bucket where EKS publishes the Kubernetes binaries and their checksums
+const EKS_BINARY_BUCKET: &str = "amazon-eks";
+
+/// Region of the EKS binary bucket
+const EKS_BINARY_BUCKET_REGION: &str = "us-west-2";
+
+/// Directory containing the CNI plugin binaries
+const CNI_BIN_DIR: &str = "/opt/cni/bin";
+
+/// Binaries installed on the AMI and the paths they are installed to
+const ARTIFACTS: &[(&str, &str)] = &[
+  ("kubelet", "/usr/bin/kubelet"),
+  ("aws-iam-authenticator", "/usr/bin/aws-iam-authenticator"),
+  (
+    "ecr-credential-provider",
+    "/etc/eks/image-credential-provider/ecr-credential-provider",
+  ),
+  ("containerd", "/usr/local/bin/containerd"),
+  ("containerd-shim-runc-v2", "/usr/local/bin/containerd-shim-runc-v2"),
+  ("ctr", "/usr/local/bin/ctr"),
+  ("runc", "/usr/local/sbin/runc"),
+];
+
+/// Binaries published by EKS to S3 alongside a `.sha256` checksum
+const EKS_S3_ARTIFACTS: &[&str] = &["kubelet", "aws-iam-authenticator", "ecr-credential-provider"];
+
+/// Input arguments for `verify-artifacts` command
+#[derive(Args, Debug, Default, Serialize, Deserialize)]
+pub struct VerifyArtifactsInput {
+  /// Path to a checksum manifest in `sha256sum` format (`<sha256>  <name or path>`)
+  ///
+  /// Typically recorded at AMI build time from the upstream release checksums (runc, containerd, CNI plugins)
+  #[arg(long, env = "EKSNODE_ARTIFACTS_CHECKSUMS")]
+  pub checksums: Option<PathBuf>,
+
+  /// The build date of the EKS Kubernetes binaries in S3 (i.e. - `2024-11-15` from `kubernetes_build_date`)
+  ///
+  /// When provided, the checksums of kubelet, aws-iam-authenticator, and ecr-credential-provider are retrieved
+  /// from the EKS S3 bucket for the installed kubelet version
+  #[arg(long, env = "EKSNODE_S3_BUILD_DATE")]
+  pub s3_build_date: Option<String>,
+
+  /// Fail if an installed artifact does not have an expected checksum to verify against
+  #[arg(long, env = "EKSNODE_ARTIFACTS_REQUIRE_ALL")]
+  pub require_all: bool,
+
+  /// Output report in JSON format
+  #[arg(long, env = "EKSNODE_ARTIFACTS_OUTPUT_JSON")]
+  pub output_json: bool,
+}
+
+/// Result of verifying an artifact's checksum
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+  Verified,
+  Mismatch,
+  Unverified,
+}
+
+impl std::fmt::Display for Status {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Verified => write!(f, "verified"),
+      Self::Mismatch => write!(f, "mismatch"),
+      Self::Unverified => write!(f, "unverified"),
+    }
+  }
+}
+
+/// Checksum verification report entry for an installed artifact
+#[derive(Debug, Serialize, Deserialize, Tabled)]
+pub struct ArtifactReport {
+  name: String,
+  path: String,
+  sha256: String,
+  #[tabled(display_with = "display_expected")]
+  expected: Option<String>,
+  status: Status,
+}
+
+fn display_expected(expected: &Option<String>) -> String {
+  expected.clone().unwrap_or_else(|| "-".to_owned())
+}
+
+impl VerifyArtifactsInput {
+  pub async fn verify(&self) -> Result<()> {
+    let mut expected = BTreeMap::new();
+    if let Some(build_date) = &self.s3_build_date {
+      expected.extend(get_eks_s3_checksums(build_date).await?);
+    }
+    if let Some(path) = &self.checksums {
+      expected.extend(parse_checksums(&tokio::fs::read_to_string(path).await?)?);
+    }
+
+    let reports = get_installed_artifacts()?
+      .into_iter()
+      .map(|(name, path)| {
+        let sha256 = utils::sha256_file(&path)?;
+        Ok(verify_artifact(&name, &path.to_string_lossy(), &sha256, &expected))
+      })
+      .collect::<Result<Vec<_>>>()?;
+
+    match self.output_json {
+      true => println!("{}", serde_json::to_string_pretty(&reports)?),
+      false => println!("{}", Table::new(&reports)),
+    }
+
+    let mismatched = reports.iter().filter(|r| r.status == Status::Mismatch).count();
+    let unverified = reports.iter().filter(|r| r.status == Status::Unverified).count();
+    if mismatched > 0 {
+      bail!("{mismatched} artifact(s) do not match their expected checksum");
+    }
+    if unverified > 0 {
+      if self.require_all {
+        bail!("{unverified} artifact(s) do not have an expected checksum to verify against");
+      }
+      warn!("{unverified} artifact(s) do not have an expected checksum to verify against");
+    }
+
+    info!("Verified {} artifact(s)", reports.len() - unverified);
+    Ok(())
+  }
+}
+
+/// Parse a checksum manifest in `sha256sum` format
+///
+/// Entries are keyed by both the path and the file name so that manifests generated from either upstream
+/// release checksums (file name only) or the installed binaries (full path) can be used
+pub fn parse_checksums(contents: &str) -> Result<BTreeMap<String, String>> {
+  let mut checksums = BTreeMap::new();
+
+  for line in contents
+    .lines()
+    .map(str::trim)
+    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+  {
+    let Some((sha256, path)) = line.split_once(char::is_whitespace) else {
+      bail!("Invalid checksum manifest entry: {line}");
+    };
+    let sha256 = sha256.to_lowercase();
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+      bail!("Invalid SHA256 checksum in manifest entry: {line}");
+    }
+
+    // `sha256sum` prefixes the path with `*` when computed in binary mode
+    let path = path.trim().trim_start_matches('*');
+    if let Some(name) = path.rsplit('/').next() {
+      checksums.insert(name.to_owned(), sha256.clone());
+    }
+    checksums.insert(path.to_owned(), sha256);
+  }
+
+  Ok(checksums)
+}
+
+/// Compare the computed checksum of an artifact against the expected checksum, looked up by path then name
+pub fn verify_artifact(name: &str, path: &str, sha256: &str, expected: &BTreeMap<String, String>) -> ArtifactReport {
+  let expected = expected.get(path).or_else(|| expected.get(name)).cloned();
+  let status = match &expected {
+    Some(e) if e == sha256 => Status::Verified,
+    Some(_) => Status::Mismatch,
+    None => Status::Unverified,
+  };
+
+  ArtifactReport {
+    name: name.to_owned(),
+    path: path.to_owned(),
+    sha256: sha256.to_owned(),
+    expected,
+    status,
+  }
+}
+
+/// Get the artifacts installed on the node, including the CNI plugins
+fn get_installed_artifacts() -> Result<Vec<(String, PathBuf)>> {
+  let mut artifacts = ARTIFACTS
+    .iter()
+    .map(|(name, path)| (name.to_string(), PathBuf::from(path)))
+    .filter(|(_, path)| path.exists())
+    .collect::<Vec<_>>();
+
+  if let Ok(entries) = std::fs::read_dir(CNI_BIN_DIR) {
+    let mut plugins = entries
+      .filter_map(|entry| entry.ok())
+      .map(|entry| entry.path())
+      .filter(|path| path.is_file())
+      .map(|path| (path.file_name().unwrap_or_default().to_string_lossy().to_string(), path))
+      .collect::<Vec<_>>();
+    plugins.sort();
+    artifacts.extend(plugins);
+  }
+
+  Ok(artifacts)
+}
+
+/// Get the checksums published by EKS in S3 for the installed kubelet version
+async fn get_eks_s3_checksums(build_date: &str) -> Result<BTreeMap<String, String>> {
+  let kubelet_version = kubelet::get_kubelet_version().await?;
+  let arch = Architecture::detect()?.oci_arch();
+  let prefix = format!("{kubelet_version}/{build_date}/bin/linux/{arch}");
+
+  let client = aws::get_s3_client(EKS_BINARY_BUCKET_REGION).await;
+
+  let mut checksums = BTreeMap::new();
+  for name in EKS_S3_ARTIFACTS {
+    let key = format!("{prefix}/{name}.sha256");
+    let object = match client.get_object().bucket(EKS_BINARY_BUCKET).key(&key).send().await {
+      Ok(object) => object,
+      Err(e) => {
+        warn!("Unable to retrieve s3://{EKS_BINARY_BUCKET}/{key}: {e}");
+        continue;
+      }
+    };
+    let body = object.body.collect().await?.into_bytes();
+    checksums.extend(
+      parse_checksums(&String::from_utf8_lossy(&body))?
+        .into_iter()
+        .filter(|(k, _)| k == name),
+    );
+  }
+
+  Ok(checksums)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const KUBELET_SHA256: &str = "0f0be138463a248b7f256afeccea397ab8c4b405c90eb1b84a68be9ce7bca2d7";
+  const RUNC_SHA256: &str = "a1c8e0bbd4a2d1a3f0e1a7c6e4b1a9d3c2b8f7e6d5c4b3a291807f6e5d4c3b2a";
+
+  #[test]
+  fn it_parses_checksums() {
+    let contents = format!("{KUBELET_SHA256}  kubelet\n\n# upstream\n{RUNC_SHA256} */usr/local/sbin/runc\n");
+    let checksums = parse_checksums(&contents).unwrap();
+
+    assert_eq!(checksums.get("kubelet").unwrap(), KUBELET_SHA256);
+    assert_eq!(checksums.get("runc").unwrap(), RUNC_SHA256);
+    assert_eq!(checksums.get("/usr/local/sbin/runc").unwrap(), RUNC_SHA256);
+  }
+
+  #[test]
+  fn it_rejects_invalid_checksums() {
+    assert!(parse_checksums("abc123  kubelet").is_err());
+    assert!(parse_checksums(KUBELET_SHA256).is_err());
+  }
+
+  #[test]
+  fn it_verifies_artifacts() {
+    let expected = BTreeMap::from([
+      ("kubelet".to_owned(), KUBELET_SHA256.to_owned()),
+      ("/usr/local/sbin/runc".to_owned(), RUNC_SHA256.to_owned()),
+    ]);
+
+    let kubelet = verify_artifact("kubelet", "/usr/bin/kubelet", KUBELET_SHA256, &expected);
+    assert_eq!(kubelet.status, Status::Verified);
+
+    let runc = verify_artifact("runc", "/usr/local/sbin/runc", KUBELET_SHA256, &expected);
+    assert_eq!(runc.status, Status::Mismatch);
+
+    let ctr = verify_artifact("ctr", "/usr/local/bin/ctr", KUBELET_SHA256, &expected);
+    assert_eq!(ctr.status, Status::Unverified);
+  }
+}
@@ -1,73 +1,223 @@
-use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
 use clap::Args;
 use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tracing::{debug, info};
 
-use crate::{ec2, resource};
+use crate::{ec2, kubelet::ApiClient, resource};
 
 #[derive(Args, Debug, Serialize, Deserialize)]
 #[command(group = clap::ArgGroup::new("instance-type").multiple(false).required(true))]
 pub struct CalculateMaxPodsInput {
   /// The instance type used to calculate max pods
-  #[arg(short, long, group = "instance-type")]
+  #[arg(short, long, env = "EKSNODE_INSTANCE_TYPE", group = "instance-type")]
   pub instance_type: Option<String>,
 
   /// The instance type is be queried from the instance metadata service
-  #[arg(long, group = "instance-type")]
+  #[arg(long, env = "EKSNODE_INSTANCE_TYPE_FROM_IMDS", group = "instance-type")]
   pub instance_type_from_imds: bool,
 
   /// The version of the VPC-CNI (i.e. -  v1.12.6-eksbuild.2 or 1.12.6)
-  #[arg(long)]
-  pub cni_version: String,
+  #[arg(
+    long,
+    env = "EKSNODE_CNI_VERSION",
+    required_unless_present = "cni_settings_from_cluster"
+  )]
+  pub cni_version: Option<String>,
 
   /// VPC-CNI custom networking is enabled
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_CNI_CUSTOM_NETWORKING_ENABLED")]
   pub cni_custom_networking_enabled: bool,
 
   /// VPC-CNI prefix-delegation is enabled
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_CNI_PREFIX_DELEGATION_ENABLED")]
   pub cni_prefix_delegation_enabled: bool,
 
+  /// VPC-CNI security groups for pods (ENABLE_POD_ENI) is enabled
+  ///
+  /// The trunk ENI attached to Nitro instances does not provide pod IPs and is excluded
+  #[arg(long, env = "EKSNODE_SGPP_ENABLED")]
+  pub sgpp_enabled: bool,
+
   /// The max number of ENIs used for prefix delegation
   ///
   /// Defaults to using all ENIs available to the instance
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_CNI_MAX_ENIS")]
   pub cni_max_enis: Option<i32>,
+
+  /// Read the VPC-CNI version and settings from the aws-node DaemonSet on the cluster through the Kubernetes API
+  ///
+  /// Replaces the --cni-* and --sgpp-enabled flags; requires permission to get the DaemonSet in kube-system
+  #[arg(
+    long,
+    env = "EKSNODE_CNI_SETTINGS_FROM_CLUSTER",
+    conflicts_with_all = [
+      "cni_version",
+      "cni_custom_networking_enabled",
+      "cni_prefix_delegation_enabled",
+      "sgpp_enabled",
+      "cni_max_enis"
+    ]
+  )]
+  pub cni_settings_from_cluster: bool,
+
+  /// The kubeconfig used to read the VPC-CNI settings from the cluster
+  ///
+  /// Defaults to the first file of KUBECONFIG, or ~/.kube/config
+  #[arg(long, env = "EKSNODE_CALCULATE_KUBECONFIG", requires = "cni_settings_from_cluster")]
+  pub kubeconfig: Option<PathBuf>,
 }
 
-impl CalculateMaxPodsInput {
-  pub async fn calculate(&self) -> Result<i32> {
-    let instance_type = if self.instance_type_from_imds {
-      ec2::get_instance_type().await?
-    } else {
-      self.instance_type.to_owned().unwrap()
-    };
-    let instance = match ec2::get_instance(&instance_type)? {
-      Some(instance) => instance,
-      None => return Err(anyhow!("Instance type {instance_type} is not supported or invalid")),
+/// Name of the VPC-CNI DaemonSet and its container in kube-system
+const VPC_CNI_NAME: &str = "aws-node";
+
+/// VPC-CNI settings that determine the maximum number of pods
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct VpcCniSettings {
+  pub version: String,
+  pub custom_networking: bool,
+  pub prefix_delegation: bool,
+  pub sgpp: bool,
+  pub max_enis: Option<i32>,
+}
+
+impl VpcCniSettings {
+  /// Parse the settings from the image tag and environment variables of the aws-node DaemonSet
+  ///
+  /// The warm IP, ENI, and prefix targets only change how many IPs are held in reserve and are not used
+  fn from_daemonset(daemonset: &JsonValue) -> Result<Self> {
+    let mut containers = daemonset
+      .pointer("/spec/template/spec/containers")
+      .and_then(|c| c.as_array())
+      .into_iter()
+      .flatten();
+    let Some(container) = containers.find(|c| c["name"] == VPC_CNI_NAME) else {
+      bail!("Container {VPC_CNI_NAME} not found in the {VPC_CNI_NAME} DaemonSet");
     };
 
-    let prefix_supported = resource::prefix_delegation_supported(&self.cni_version)?;
+    let Some((_, version)) = container["image"].as_str().and_then(|image| image.rsplit_once(':')) else {
+      bail!("Unable to determine the VPC-CNI version from the {VPC_CNI_NAME} image");
+    };
 
-    // Take the min of either the number of ENIs passed by the CLI or the number of ENIs available to the instance
-    let mut num_enis = match self.cni_max_enis {
-      Some(enis) => std::cmp::min(instance.maximum_network_interfaces, enis),
-      None => instance.maximum_network_interfaces,
+    let env = container["env"].as_array().into_iter().flatten();
+    let get_env = |name: &str| {
+      env
+        .clone()
+        .find(|e| e["name"] == name)
+        .and_then(|e| e["value"].as_str())
     };
+    let is_enabled = |name: &str| get_env(name).is_some_and(|v| v.eq_ignore_ascii_case("true"));
 
-    if self.cni_custom_networking_enabled {
-      // If custom networking is enabled, we need to reserve an ENI for the CNI
-      num_enis -= 1;
+    for name in [
+      "WARM_ENI_TARGET",
+      "WARM_IP_TARGET",
+      "MINIMUM_IP_TARGET",
+      "WARM_PREFIX_TARGET",
+    ] {
+      if let Some(value) = get_env(name) {
+        debug!("VPC-CNI {name}: {value}");
+      }
     }
 
-    let use_prefix_del = instance.hypervisor == "nitro" && prefix_supported && self.cni_prefix_delegation_enabled;
-    let max_pods = resource::calculate_eni_max_pods(num_enis, instance.ipv4_addresses_per_interface, use_prefix_del);
+    Ok(Self {
+      version: version.to_owned(),
+      custom_networking: is_enabled("AWS_VPC_K8S_CNI_CUSTOM_NETWORK_CFG"),
+      prefix_delegation: is_enabled("ENABLE_PREFIX_DELEGATION"),
+      sgpp: is_enabled("ENABLE_POD_ENI"),
+      // -1 (the default) does not limit the number of ENIs
+      max_enis: get_env("MAX_ENI").and_then(|v| v.parse().ok()).filter(|v| *v >= 0),
+    })
+  }
+}
+
+/// Get the VPC-CNI settings from the aws-node DaemonSet on the cluster
+pub async fn get_vpc_cni_settings(client: &ApiClient) -> Result<VpcCniSettings> {
+  let daemonset = client
+    .get(&format!(
+      "/apis/apps/v1/namespaces/kube-system/daemonsets/{VPC_CNI_NAME}"
+    ))
+    .await
+    .with_context(|| format!("Unable to get the {VPC_CNI_NAME} DaemonSet"))?;
+
+  let settings = VpcCniSettings::from_daemonset(&daemonset)?;
+  info!("VPC-CNI settings from the cluster: {settings:?}");
+  Ok(settings)
+}
+
+/// Get the kubeconfig kubectl would use: the first file of KUBECONFIG, or ~/.kube/config
+fn get_default_kubeconfig() -> Result<PathBuf> {
+  if let Some(path) = std::env::var_os("KUBECONFIG").and_then(|paths| std::env::split_paths(&paths).next()) {
+    return Ok(path);
+  }
 
-    let result = match instance.default_vcpus > 30 {
-      true => std::cmp::min(250, max_pods),
-      _ => std::cmp::min(110, max_pods),
+  let home = std::env::var_os("HOME").context("Unable to locate the kubeconfig; set --kubeconfig")?;
+  Ok(PathBuf::from(home).join(".kube").join("config"))
+}
+
+/// Calculate the maximum number of pods for the instance type with the VPC-CNI settings
+pub fn calculate_max_pods(instance_type: &str, settings: &VpcCniSettings) -> Result<i32> {
+  let instance = match ec2::get_instance(instance_type)? {
+    Some(instance) => instance,
+    None => return Err(anyhow!("Instance type {instance_type} is not supported or invalid")),
+  };
+
+  let prefix_supported = resource::prefix_delegation_supported(&settings.version)?;
+
+  // Take the min of either the number of ENIs passed by the CLI or the number of ENIs available to the instance
+  let mut num_enis = match settings.max_enis {
+    Some(enis) => std::cmp::min(instance.maximum_network_interfaces, enis),
+    None => instance.maximum_network_interfaces,
+  };
+
+  if settings.custom_networking {
+    // If custom networking is enabled, we need to reserve an ENI for the CNI
+    num_enis -= 1;
+  }
+  if settings.sgpp {
+    num_enis = resource::sgpp_available_enis(num_enis, &instance.hypervisor);
+  }
+
+  let use_prefix_del = instance.hypervisor == "nitro" && prefix_supported && settings.prefix_delegation;
+  let max_pods = resource::calculate_eni_max_pods(num_enis, instance.ipv4_addresses_per_interface, use_prefix_del);
+
+  let result = match instance.default_vcpus > 30 {
+    true => std::cmp::min(250, max_pods),
+    _ => std::cmp::min(110, max_pods),
+  };
+
+  Ok(result)
+}
+
+impl CalculateMaxPodsInput {
+  pub async fn calculate(&self) -> Result<i32> {
+    let instance_type = if self.instance_type_from_imds {
+      ec2::get_instance_type().await?
+    } else {
+      self.instance_type.to_owned().unwrap()
+    };
+    let settings = match self.cni_settings_from_cluster {
+      true => {
+        let kubeconfig = match &self.kubeconfig {
+          Some(kubeconfig) => kubeconfig.to_owned(),
+          None => get_default_kubeconfig()?,
+        };
+        get_vpc_cni_settings(&ApiClient::from_kubeconfig(kubeconfig)?).await?
+      }
+      false => VpcCniSettings {
+        version: self
+          .cni_version
+          .to_owned()
+          .ok_or_else(|| anyhow!("--cni-version is required"))?,
+        custom_networking: self.cni_custom_networking_enabled,
+        prefix_delegation: self.cni_prefix_delegation_enabled,
+        sgpp: self.sgpp_enabled,
+        max_enis: self.cni_max_enis,
+      },
     };
 
-    Ok(result)
+    calculate_max_pods(&instance_type, &settings)
   }
 
   pub async fn result(&self) -> Result<()> {
@@ -78,3 +228,46 @@ impl CalculateMaxPodsInput {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn it_gets_vpc_cni_settings_from_daemonset() {
+    let daemonset = json!({
+      "spec": {"template": {"spec": {"containers": [
+        {
+          "name": "aws-eks-nodeagent",
+          "image": "602401143452.dkr.ecr.us-west-2.amazonaws.com/amazon/aws-network-policy-agent:v1.0.7-eksbuild.1"
+        },
+        {
+          "name": "aws-node",
+          "image": "602401143452.dkr.ecr.us-west-2.amazonaws.com/amazon-k8s-cni:v1.16.0-eksbuild.1",
+          "env": [
+            {"name": "ENABLE_PREFIX_DELEGATION", "value": "true"},
+            {"name": "AWS_VPC_K8S_CNI_CUSTOM_NETWORK_CFG", "value": "false"},
+            {"name": "ENABLE_POD_ENI", "value": "TRUE"},
+            {"name": "MAX_ENI", "value": "-1"},
+            {"name": "WARM_PREFIX_TARGET", "value": "1"},
+            {"name": "MY_NODE_NAME", "valueFrom": {"fieldRef": {"fieldPath": "spec.nodeName"}}}
+          ]
+        }
+      ]}}}
+    });
+
+    assert_eq!(
+      VpcCniSettings::from_daemonset(&daemonset).unwrap(),
+      VpcCniSettings {
+        version: "v1.16.0-eksbuild.1".to_owned(),
+        custom_networking: false,
+        prefix_delegation: true,
+        sgpp: true,
+        max_enis: None,
+      }
+    );
+    assert!(VpcCniSettings::from_daemonset(&json!({})).is_err());
+  }
+}
@@ -0,0 +1,191 @@
+use std::{
+  path::PathBuf,
+  time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Result};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use tracing::{error, info};
+
+use crate::{aws, ec2, ecr, systemd, utils};
+
+/// Docker config file containing the ECR auth entries
+///
+/// Kept separate from /root/.docker/config.json, which uses the ECR credential helper; point tools at it with
+/// `DOCKER_CONFIG=/etc/eksnode/ecr`
+pub const ECR_AUTH_CONFIG_PATH: &str = "/etc/eksnode/ecr/config.json";
+
+/// Interval between refreshes when the token expiration is not known; tokens are valid for 12 hours
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Minimum interval between refreshes
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Interval between attempts after a failed refresh
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Input arguments for `ecr-credential-refresh` command
+#[derive(Args, Debug, Default, Serialize, Deserialize)]
+pub struct EcrCredentialRefreshInput {
+  /// Docker config file where the ECR auth entries are written
+  #[arg(long, env = "EKSNODE_ECR_AUTH_PATH", default_value = ECR_AUTH_CONFIG_PATH)]
+  pub path: PathBuf,
+
+  /// ECR registry to write an auth entry for (i.e. - `111122223333.dkr.ecr.us-west-2.amazonaws.com`)
+  ///
+  /// May be repeated; defaults to the registry of the node's account and the EKS registry of the region
+  #[arg(long, env = "EKSNODE_ECR_AUTH_REGISTRY")]
+  pub registry: Vec<String>,
+
+  /// Enable FIPS mode for the default EKS registry
+  #[arg(long, env = "EKSNODE_ENABLE_FIPS")]
+  pub enable_fips: bool,
+
+  /// Write the auth entries once and exit rather than refreshing them before the token expires
+  #[arg(long, env = "EKSNODE_ECR_AUTH_ONCE")]
+  pub once: bool,
+}
+
+impl EcrCredentialRefreshInput {
+  /// Maintain valid ECR auth entries for host level pulls that bypass the kubelet credential provider
+  ///
+  /// (i.e. - nerdctl, ctr, or a registry mirror that requires basic auth)
+  pub async fn refresh(&self) -> Result<()> {
+    let client = aws::get_ecr_client().await;
+    let mut ready = false;
+
+    loop {
+      let interval = match self.write_auth_config(&client).await {
+        Ok(expires_at) => {
+          if !ready {
+            systemd::notify_ready();
+            ready = true;
+          }
+          get_refresh_interval(expires_at, SystemTime::now())
+        }
+        Err(e) if self.once => return Err(e),
+        Err(e) => {
+          error!("Unable to refresh the ECR auth entries: {e}");
+          RETRY_INTERVAL
+        }
+      };
+      if self.once {
+        return Ok(());
+      }
+
+      info!("Refreshing the ECR auth entries in {}s", interval.as_secs());
+      systemd::sleep_with_watchdog(interval).await;
+    }
+  }
+
+  /// Get a new ECR token and write it for each registry, returning when the token expires
+  async fn write_auth_config(&self, client: &aws_sdk_ecr::Client) -> Result<Option<SystemTime>> {
+    let authorization = ecr::get_authorization(client).await?;
+
+    let registries = match self.registry.is_empty() {
+      true => {
+        let mut registries = vec![ecr::get_ecr_uri(&ec2::get_region().await?, self.enable_fips)?];
+        registries.extend(authorization.proxy_endpoint.as_deref().map(String::from));
+        registries
+      }
+      false => self.registry.to_owned(),
+    };
+
+    let existing = match tokio::fs::read(&self.path).await {
+      Ok(contents) => Some(serde_json::from_slice(&contents)?),
+      Err(_) => None,
+    };
+    let config = get_auth_config(existing, &registries, authorization.token.expose())?;
+
+    if let Some(parent) = self.path.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+    let contents = serde_json::to_string_pretty(&config)?;
+    utils::write_file(contents.as_bytes(), &self.path, Some(0o600), true).await?;
+    info!(
+      "Wrote ECR auth entries for {} to {}",
+      registries.join(", "),
+      self.path.display()
+    );
+
+    Ok(authorization.expires_at)
+  }
+}
+
+/// Set the auth entry of each registry in the Docker config, preserving its other settings and entries
+fn get_auth_config(existing: Option<JsonValue>, registries: &[String], token: &str) -> Result<JsonValue> {
+  let mut config = existing.unwrap_or_else(|| json!({}));
+  let Some(auths) = config
+    .as_object_mut()
+    .map(|c| c.entry("auths").or_insert_with(|| json!({})))
+    .and_then(|auths| auths.as_object_mut())
+  else {
+    bail!("Invalid Docker config; expected an object with an `auths` object");
+  };
+
+  for registry in registries {
+    let host = registry.trim_start_matches("https://").trim_end_matches('/');
+    auths.insert(host.to_owned(), json!({ "auth": token }));
+  }
+
+  Ok(config)
+}
+
+/// Refresh halfway to the expiration so that a failed refresh can be retried before the token expires
+fn get_refresh_interval(expires_at: Option<SystemTime>, now: SystemTime) -> Duration {
+  match expires_at {
+    Some(expires_at) => {
+      let remaining = expires_at.duration_since(now).unwrap_or_default();
+      (remaining / 2).max(MIN_REFRESH_INTERVAL)
+    }
+    None => DEFAULT_REFRESH_INTERVAL,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_gets_auth_config() {
+    let existing = json!({
+      "auths": {
+        "registry.example.com": { "auth": "dXNlcjpwYXNz" },
+        "111122223333.dkr.ecr.us-west-2.amazonaws.com": { "auth": "ZXhwaXJlZA==" }
+      },
+      "credsStore": "ecr-login"
+    });
+    let registries = [
+      "https://111122223333.dkr.ecr.us-west-2.amazonaws.com".to_owned(),
+      "602401143452.dkr.ecr.us-west-2.amazonaws.com".to_owned(),
+    ];
+
+    let config = get_auth_config(Some(existing), &registries, "QVdTOnRva2Vu").unwrap();
+    assert_eq!(
+      config,
+      json!({
+        "auths": {
+          "registry.example.com": { "auth": "dXNlcjpwYXNz" },
+          "111122223333.dkr.ecr.us-west-2.amazonaws.com": { "auth": "QVdTOnRva2Vu" },
+          "602401143452.dkr.ecr.us-west-2.amazonaws.com": { "auth": "QVdTOnRva2Vu" }
+        },
+        "credsStore": "ecr-login"
+      })
+    );
+    assert!(get_auth_config(Some(json!({ "auths": [] })), &registries, "QVdTOnRva2Vu").is_err());
+  }
+
+  #[test]
+  fn it_gets_refresh_interval() {
+    let now = SystemTime::now();
+
+    assert_eq!(
+      get_refresh_interval(Some(now + Duration::from_secs(12 * 60 * 60)), now),
+      Duration::from_secs(6 * 60 * 60)
+    );
+    assert_eq!(get_refresh_interval(Some(now), now), MIN_REFRESH_INTERVAL);
+    assert_eq!(get_refresh_interval(None, now), DEFAULT_REFRESH_INTERVAL);
+  }
+}
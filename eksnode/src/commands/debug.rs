@@ -3,19 +3,45 @@ use std::{fs::File, io::prelude::*, path::Path};
 use anyhow::Result;
 use clap::Args;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
 use walkdir::{DirEntry, WalkDir};
 use zip::{result::ZipError, write::SimpleFileOptions};
 
+use crate::{events, instance_store};
+
+/// Name of the manifest written at the root of the archive
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// A file collected into the archive
+///
+/// Used to detect bundles that were partially transferred or modified after collection
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestEntry {
+  /// Path of the file within the archive
+  path: String,
+  /// Size of the file in bytes
+  size: u64,
+  /// Hex encoded SHA256 digest of the file contents
+  sha256: String,
+  /// RFC 3339 timestamp of when the file was collected
+  collected_at: String,
+}
+
 #[derive(Args, Debug, Default, Serialize, Deserialize)]
 pub struct DebugInput {
   /// Collect various log files and package into a zip archive
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_CREATE_LOG_ARCHIVE")]
   pub create_log_archive: bool,
 }
 
 impl DebugInput {
   pub async fn debug(&self) -> Result<()> {
     if self.create_log_archive {
+      // Written under /var/log so that the NVMe health data is collected into the archive
+      if let Err(e) = instance_store::collect_health_data(instance_store::HEALTH_LOG_DIR) {
+        warn!("Unable to collect the instance store health data: {e}");
+      }
       collect_logs(&["/var/log"], "/tmp/eksnode-logs.zip")?;
     }
 
@@ -50,6 +76,7 @@ where
     .compression_method(zip::CompressionMethod::BZIP2)
     .unix_permissions(0o755);
 
+  let mut manifest = Vec::new();
   let mut buffer = Vec::new();
   for entry in it {
     let path = entry.path();
@@ -58,22 +85,76 @@ where
     // Write file or directory explicitly
     // Some unzip tools unzip files with directory paths correctly, some do not!
     if path.is_file() {
-      println!("adding file {path:?} as {name:?} ...");
+      debug!("adding file {path:?} as {name:?} ...");
       #[allow(deprecated)]
       zip.start_file_from_path(name, options)?;
       let mut f = File::open(path)?;
 
       f.read_to_end(&mut buffer)?;
       zip.write_all(&buffer)?;
+      manifest.push(ManifestEntry {
+        path: name.to_string_lossy().into_owned(),
+        size: buffer.len() as u64,
+        sha256: format!("{:x}", Sha256::digest(&buffer)),
+        collected_at: events::now(),
+      });
       buffer.clear();
     } else if !name.as_os_str().is_empty() {
       // Only if not root! Avoids path spec / warning
       // and mapname conversion failed error on unzip
-      println!("adding dir {path:?} as {name:?} ...");
+      debug!("adding dir {path:?} as {name:?} ...");
       #[allow(deprecated)]
       zip.add_directory_from_path(name, options)?;
     }
   }
+
+  zip.start_file(MANIFEST_NAME, options.unix_permissions(0o644))?;
+  serde_json::to_writer_pretty(&mut zip, &manifest).map_err(std::io::Error::from)?;
+
   zip.finish()?;
   Result::Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_writes_archive_manifest() {
+    let src = tempfile::tempdir().unwrap();
+    std::fs::create_dir(src.path().join("containerd")).unwrap();
+    std::fs::write(src.path().join("containerd/containerd.log"), "eksnode").unwrap();
+    std::fs::write(src.path().join("messages"), "").unwrap();
+
+    let dst = tempfile::NamedTempFile::new().unwrap();
+    let prefix = src.path().to_str().unwrap();
+    let mut it = WalkDir::new(prefix)
+      .sort_by_file_name()
+      .into_iter()
+      .filter_map(|e| e.ok());
+    zip_dir(&mut it, prefix, dst.reopen().unwrap()).unwrap();
+
+    let mut archive = zip::ZipArchive::new(File::open(dst.path()).unwrap()).unwrap();
+    let manifest: Vec<ManifestEntry> = serde_json::from_reader(archive.by_name(MANIFEST_NAME).unwrap()).unwrap();
+    let entries = manifest
+      .iter()
+      .map(|e| (e.path.as_str(), e.size, e.sha256.as_str()))
+      .collect::<Vec<_>>();
+
+    assert_eq!(
+      entries,
+      vec![
+        (
+          "containerd/containerd.log",
+          7,
+          "0f0be138463a248b7f256afeccea397ab8c4b405c90eb1b84a68be9ce7bca2d7"
+        ),
+        (
+          "messages",
+          0,
+          "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        ),
+      ]
+    );
+  }
+}
@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Args;
+use tabled::Table;
+use time::OffsetDateTime;
+
+use crate::events::{self, parse_time, Event, EventKind};
+
+/// Input arguments for `events` command
+#[derive(Args, Debug)]
+pub struct EventsInput {
+  /// Event log to read
+  #[arg(long, env = "EKSNODE_EVENTS_PATH", default_value = events::EVENTS_PATH)]
+  pub path: PathBuf,
+
+  /// Only show events of the kind (repeatable)
+  #[arg(long, env = "EKSNODE_EVENTS_KIND", value_enum)]
+  pub kind: Vec<EventKind>,
+
+  /// Only show events recorded by the command (i.e. - `join-cluster`)
+  #[arg(long, env = "EKSNODE_EVENTS_COMMAND")]
+  pub command: Option<String>,
+
+  /// Only show events whose subject contains the text (i.e. - `kubelet`)
+  #[arg(long, env = "EKSNODE_EVENTS_SUBJECT")]
+  pub subject: Option<String>,
+
+  /// Only show events recorded at or after the RFC 3339 time (i.e. - `2024-01-02T03:04:05Z`)
+  #[arg(long, env = "EKSNODE_EVENTS_SINCE", value_parser = parse_time)]
+  pub since: Option<OffsetDateTime>,
+
+  /// Only show the most recent events
+  #[arg(long, env = "EKSNODE_EVENTS_TAIL")]
+  pub tail: Option<usize>,
+
+  /// Output the events as JSON lines
+  #[arg(long, env = "EKSNODE_EVENTS_OUTPUT_JSON")]
+  pub output_json: bool,
+}
+
+impl EventsInput {
+  /// Query the event log of the actions taken on the node
+  pub async fn query(&self) -> Result<()> {
+    let events = self.filter(events::read(&self.path)?);
+
+    match self.output_json {
+      true => {
+        for event in &events {
+          println!("{}", serde_json::to_string(event)?);
+        }
+      }
+      false => println!("{}", Table::new(&events)),
+    }
+
+    Ok(())
+  }
+
+  fn filter(&self, events: Vec<Event>) -> Vec<Event> {
+    let mut events = events
+      .into_iter()
+      .filter(|e| self.kind.is_empty() || self.kind.contains(&e.kind))
+      .filter(|e| self.command.as_ref().is_none_or(|c| &e.command == c))
+      .filter(|e| self.subject.as_ref().is_none_or(|s| e.subject.contains(s.as_str())))
+      .filter(|e| match &self.since {
+        Some(since) => parse_time(&e.timestamp).is_ok_and(|t| t >= *since),
+        None => true,
+      })
+      .collect::<Vec<_>>();
+
+    if let Some(tail) = self.tail {
+      events.drain(..events.len().saturating_sub(tail));
+    }
+
+    events
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn event(timestamp: &str, kind: EventKind, subject: &str) -> Event {
+    Event {
+      timestamp: timestamp.to_owned(),
+      command: "join-cluster".to_owned(),
+      kind,
+      subject: subject.to_owned(),
+      detail: None,
+    }
+  }
+
+  #[test]
+  fn it_filters_events() {
+    let events = vec![
+      event(
+        "2024-01-02T03:04:05Z",
+        EventKind::FileWritten,
+        "/etc/kubernetes/kubelet/kubelet-config.json",
+      ),
+      event("2024-01-02T03:04:06Z", EventKind::UnitChanged, "kubelet"),
+      event(
+        "2024-01-03T03:04:05Z",
+        EventKind::FileWritten,
+        "/etc/containerd/config.toml",
+      ),
+      event("2024-01-03T03:04:06Z", EventKind::UnitChanged, "containerd"),
+    ];
+    let input = EventsInput {
+      path: PathBuf::from(events::EVENTS_PATH),
+      kind: vec![EventKind::FileWritten],
+      command: None,
+      subject: None,
+      since: Some(parse_time("2024-01-03T00:00:00Z").unwrap()),
+      tail: None,
+      output_json: false,
+    };
+    assert_eq!(input.filter(events.to_owned()), vec![events[2].to_owned()]);
+
+    let input = EventsInput {
+      kind: vec![],
+      subject: Some("kubelet".to_owned()),
+      since: None,
+      tail: Some(1),
+      ..input
+    };
+    assert_eq!(input.filter(events.to_owned()), vec![events[1].to_owned()]);
+  }
+}
@@ -1,77 +1,470 @@
-use std::{net::IpAddr, path::PathBuf};
-
-use anyhow::Result;
-use base64::{engine::general_purpose, Engine as _};
+use std::{
+  collections::BTreeMap,
+  net::IpAddr,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use base64::Engine as _;
 use clap::{Args, ValueEnum};
-use ipnet::IpNet;
+use ipnet::{IpNet, Ipv4Net};
 use rand::{seq::SliceRandom, thread_rng};
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use tokio::{fs::OpenOptions, io::AsyncWriteExt};
-use tracing::{debug, error, info};
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use walkdir::WalkDir;
 
-use crate::{commands, containerd, ec2, ecr, eks, gpu, kubelet, resource, utils};
+use crate::{
+  adopt, aws, commands, containerd, disks, ec2, ecr, eks, fips, gpu, hybrid, instance_store, kubelet, log_export,
+  network, nodeconfig, pki, preflight, profile, resource, secret::Secret, ssm, state, systemd, timing, utils,
+  Architecture,
+};
 
 #[derive(Args, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct JoinClusterInput {
+  /// Path to a NodeConfig YAML file declaring the inputs (i.e. - `kind: NodeConfig`, `spec: {cluster_name: example}`)
+  ///
+  /// Inputs set on the command line or through the environment take precedence over those of the file
+  #[arg(long, env = "EKSNODE_CONFIG_FILE")]
+  #[serde(skip)]
+  pub config_file: Option<PathBuf>,
+
   /// The EKS cluster API Server endpoint
   ///
   /// Only valid when used with --b64-cluster-ca. Bypasses calling "aws eks describe-cluster"
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_APISERVER_ENDPOINT")]
   pub apiserver_endpoint: Option<String>,
 
   /// The base64 encoded cluster CA content
   ///
   /// Only valid when used with --apiserver-endpoint. Bypasses calling "aws eks describe-cluster"
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_B64_CLUSTER_CA")]
   pub b64_cluster_ca: Option<String>,
 
+  /// Path to the PEM encoded cluster CA, as an alternative to --b64-cluster-ca
+  ///
+  /// For CAs delivered to disk by other tooling (i.e. - from Secrets Manager or SSM Parameter Store)
+  #[arg(long, env = "EKSNODE_CLUSTER_CA_FILE", conflicts_with = "b64_cluster_ca")]
+  pub cluster_ca_file: Option<PathBuf>,
+
   /// The ID of your local Amazon EKS cluster on an Amazon Web Services Outpost
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_CLUSTER_ID")]
   pub cluster_id: Option<String>,
 
-  /// The name of the EKS cluster
-  #[arg(long)]
+  /// The name of the EKS cluster; optional with `--standalone`
+  ///
+  /// Discovered from the `eks:cluster-name` or `kubernetes.io/cluster/<name>=owned` instance tag when omitted
+  #[arg(long, env = "EKSNODE_CLUSTER_NAME", default_value = "", hide_default_value = true)]
   pub cluster_name: String,
 
-  /// File containing the containerd configuration to be used in place of AMI defaults
-  #[arg(long)]
+  /// Run kubelet in standalone mode, serving only the static pods in /etc/kubernetes/manifests
+  ///
+  /// The cluster is not discovered and no kubeconfig is written, so the node does not register with an API server
+  /// (i.e. - edge nodes or AMI smoke tests)
+  #[arg(long, env = "EKSNODE_STANDALONE")]
+  pub standalone: bool,
+
+  /// SSM Parameter Store path containing the node bootstrap parameters (i.e. - `/eks/<cluster>/bootstrap`)
+  ///
+  /// Provides the `apiserver-endpoint`, `b64-cluster-ca`, `service-cidr`, and `kubelet-extra-args` parameters
+  /// stored under the path. Values provided on the command line take precedence
+  #[arg(long, env = "EKSNODE_FROM_SSM")]
+  pub from_ssm: Option<String>,
+
+  /// File containing containerd configuration merged over the generated configuration
+  ///
+  /// Only the settings in the file are overridden (i.e. - a single CRI plugin setting); the file must be a version 2
+  /// config
+  #[arg(long, env = "EKSNODE_CONTAINERD_CONFIG_FILE")]
   pub containerd_config_file: Option<String>,
 
+  /// OOM score adjustment of the containerd daemon (-1000 to 1000)
+  ///
+  /// Set in the containerd config and on the containerd service; a low score protects the runtime from the OOM
+  /// killer on memory-pressured nodes
+  #[arg(
+    long,
+    env = "EKSNODE_CONTAINERD_OOM_SCORE",
+    allow_hyphen_values = true,
+    value_parser = clap::value_parser!(i32).range(-1000..=1000)
+  )]
+  pub containerd_oom_score: Option<i32>,
+
+  /// systemd slice the containerd daemon runs in (i.e. - `runtime-containerd.slice`)
+  ///
+  /// The slice unit is created and its cgroup is set in the containerd config. Nest the slice under
+  /// runtime.slice to keep containerd within the kube-reserved cgroup
+  #[arg(long, env = "EKSNODE_CONTAINERD_SLICE")]
+  pub containerd_slice: Option<String>,
+
+  /// Maximum number of image layers containerd downloads concurrently per image pull
+  ///
+  /// Takes precedence over the value of the profile; raise for large images on nodes with high network bandwidth
+  #[arg(
+    long,
+    env = "EKSNODE_CONTAINERD_MAX_CONCURRENT_DOWNLOADS",
+    value_parser = clap::value_parser!(u32).range(1..)
+  )]
+  pub containerd_max_concurrent_downloads: Option<u32>,
+
+  /// Time an image pull may go without progress before containerd cancels it (i.e. - `30m0s`)
+  ///
+  /// Takes precedence over the value of the profile
+  #[arg(long, env = "EKSNODE_CONTAINERD_IMAGE_PULL_PROGRESS_TIMEOUT")]
+  pub containerd_image_pull_progress_timeout: Option<String>,
+
+  /// Whether containerd discards the compressed image layers once they are unpacked
+  ///
+  /// Defaults to true; keeping the layers uses more disk space but speeds up re-pulls of the image
+  #[arg(long, env = "EKSNODE_CONTAINERD_DISCARD_UNPACKED_LAYERS")]
+  pub containerd_discard_unpacked_layers: Option<bool>,
+
+  /// Endpoint of a separate CRI image service (i.e. - `unix:///run/containerd-stargz-grpc/containerd-stargz-grpc.sock`)
+  ///
+  /// Sets imageServiceEndpoint in the kubelet config. When the stargz snapshotter socket is used, containerd is
+  /// configured to use the stargz proxy snapshotter
+  #[arg(long, env = "EKSNODE_IMAGE_SERVICE_ENDPOINT")]
+  pub image_service_endpoint: Option<String>,
+
+  /// Colon-separated directories containing containerd registry host configs (i.e. - `<registry>/hosts.toml`)
+  ///
+  /// Defaults to /etc/containerd/certs.d. Paths from an existing containerd config and a populated
+  /// /etc/docker/certs.d on the AMI are preserved
+  #[arg(long, env = "EKSNODE_REGISTRY_CONFIG_PATH")]
+  pub registry_config_path: Option<String>,
+
   /// Overrides the IP address used for DNS queries within the cluster
   ///
   /// Defaults to 10.100.0.10 or 172.20.0.10 for IPv4 based on the IP address of the primary interface
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_CLUSTER_DNS_IP")]
   pub cluster_dns_ip: Option<IpAddr>,
 
+  /// Host index of the cluster DNS service within the service CIDR, used when deriving the cluster DNS IP (default: 10)
+  ///
+  /// For clusters that run CoreDNS on a service IP other than x.x.x.10 (IPv4) or ::a (IPv6)
+  #[arg(long, env = "EKSNODE_CLUSTER_DNS_HOST_INDEX", conflicts_with = "cluster_dns_ip")]
+  pub cluster_dns_host_index: Option<u32>,
+
+  /// IAM role assumed by the ECR credential provider to pull images from registries in another account
+  ///
+  /// Useful for pulling application images from a central shared-services registry account
+  #[arg(long, env = "EKSNODE_ECR_ASSUME_ROLE_ARN")]
+  pub ecr_assume_role_arn: Option<String>,
+
   /// Specifies cluster is a local cluster on Outpost
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_IS_LOCAL_CLUSTER")]
   pub is_local_cluster: bool,
 
+  /// The source of the AWS credentials used by the node
+  ///
+  /// Hybrid modes (iam-roles-anywhere, ssm) are intended for non-EC2 hosts where IMDS is not available;
+  /// EC2 specific steps are skipped and --region/--node-name must be provided
+  #[arg(long, env = "EKSNODE_CREDENTIAL_PROVIDER", value_enum, default_value_t)]
+  pub credential_provider: hybrid::CredentialProvider,
+
+  #[command(flatten)]
+  #[serde(flatten)]
+  pub roles_anywhere: hybrid::RolesAnywhereInput,
+
+  /// The AWS region of the cluster
+  ///
+  /// Required for hybrid nodes, otherwise the region is sourced from IMDS
+  #[arg(long, env = "EKSNODE_REGION")]
+  pub region: Option<String>,
+
+  /// The name of the node object
+  ///
+  /// Required for hybrid nodes, otherwise the name is sourced according to --node-name-strategy
+  #[arg(long, env = "EKSNODE_NODE_NAME")]
+  pub node_name: Option<String>,
+
+  /// Source of the name of the node object on EC2 instances, which kubelet is given as --hostname-override
+  ///
+  /// Use `resource-name` on clusters whose nodes are named after the instance ID, or `hostname` when the node name
+  /// follows the custom domain name of the DHCP option set of the VPC
+  #[arg(long, env = "EKSNODE_NODE_NAME_STRATEGY", value_enum, default_value_t)]
+  pub node_name_strategy: ec2::NodeNameStrategy,
+
+  /// The IP address of the node
+  ///
+  /// Only used on hybrid nodes; when not provided, kubelet selects the node IP address
+  #[arg(long, env = "EKSNODE_NODE_IP")]
+  pub node_ip: Option<IpAddr>,
+
+  /// A `credential_process` command used to source the AWS credentials of the kubeconfig exec credential plugin
+  ///
+  /// The command is written to an AWS shared config file that the exec credential plugin is pointed at
+  #[arg(long, env = "EKSNODE_KUBECONFIG_CREDENTIAL_PROCESS")]
+  pub kubeconfig_credential_process: Option<String>,
+
+  /// Command that replaces aws-iam-authenticator as the kubeconfig exec credential plugin
+  ///
+  /// Must be an absolute path to a helper that implements the ExecCredential protocol (i.e. - a wrapper that adds
+  /// MFA or proxy settings). Unlike aws-iam-authenticator, the helper is not required to support `--version`
+  #[arg(long, env = "EKSNODE_KUBECONFIG_EXEC_COMMAND")]
+  pub kubeconfig_exec_command: Option<String>,
+
+  /// Argument passed to the kubeconfig exec credential plugin in place of the default arguments (repeatable)
+  #[arg(long, env = "EKSNODE_KUBECONFIG_EXEC_ARG", allow_hyphen_values = true)]
+  pub kubeconfig_exec_arg: Vec<String>,
+
+  /// Additional environment variable NAME=VALUE to set on the kubeconfig exec credential plugin (repeatable)
+  #[arg(long, env = "EKSNODE_KUBECONFIG_EXEC_ENV", value_parser = parse_env_var)]
+  pub kubeconfig_exec_env: Vec<(String, String)>,
+
+  /// Cluster added to the kubelet kubeconfig as another context, as `<name>=<endpoint>,ca=<base64 CA>` (repeatable)
+  ///
+  /// The node stays joined to --cluster-name; `eksnode switch-cluster <name>` moves kubelet to the added cluster
+  /// during a blue/green cluster migration
+  #[arg(long, env = "EKSNODE_ADDITIONAL_CLUSTER", value_parser = parse_additional_cluster)]
+  pub additional_cluster: Vec<AdditionalCluster>,
+
+  /// Text shown when the kubeconfig exec credential plugin executable is not present
+  #[arg(long, env = "EKSNODE_KUBECONFIG_INSTALL_HINT")]
+  pub kubeconfig_install_hint: Option<String>,
+
+  /// Provide cluster information to the kubeconfig exec credential plugin through KUBERNETES_EXEC_INFO
+  #[arg(long, env = "EKSNODE_KUBECONFIG_PROVIDE_CLUSTER_INFO")]
+  pub kubeconfig_provide_cluster_info: bool,
+
   /// Specify ip family of the cluster
-  #[arg(long, value_enum, default_value_t)]
+  #[arg(long, env = "EKSNODE_IP_FAMILY", value_enum, default_value_t)]
   pub ip_family: crate::IpvFamily,
 
   /// Extra arguments to add to the kubelet
   ///
-  /// Useful for adding labels or taints
-  #[arg(long)]
+  /// Prefer --node-labels and --node-taint for labels and taints, which are validated before kubelet is started
+  #[arg(long, env = "EKSNODE_KUBELET_EXTRA_ARGS")]
   pub kubelet_extra_args: Option<String>,
 
+  /// Label the node with its zone ID, placement group, capacity reservation, and network topology from EC2
+  ///
+  /// Requires `ec2:DescribeInstances` and, for the network topology, `ec2:DescribeInstanceTopology`
+  #[arg(long, env = "EKSNODE_TOPOLOGY_LABELS")]
+  pub topology_labels: bool,
+
+  /// Kubelet feature gate to set in the kubelet config (i.e. - `SidecarContainers=true`)
+  ///
+  /// May be repeated; gates are validated against those available in the installed kubelet version
+  #[arg(
+    long = "kubelet-feature-gate",
+    env = "EKSNODE_KUBELET_FEATURE_GATE",
+    value_parser = kubelet::parse_feature_gate
+  )]
+  pub kubelet_feature_gates: Vec<(String, bool)>,
+
+  /// Endpoint of the OTLP gRPC collector that kubelet exports traces to (i.e. - `localhost:4317`)
+  ///
+  /// Sets tracing in the kubelet config and enables the containerd OTLP tracing plugin so that runtime traces can be
+  /// correlated with kubelet traces; the connection does not use TLS. Supported from Kubernetes 1.25
+  #[arg(long, env = "EKSNODE_KUBELET_TRACING_ENDPOINT")]
+  pub kubelet_tracing_endpoint: Option<String>,
+
+  /// Number of kubelet and containerd spans sampled per million (0 to 1000000)
+  ///
+  /// When not provided, spans are only sampled when their parent span is sampled
+  #[arg(
+    long,
+    env = "EKSNODE_KUBELET_TRACING_SAMPLING_RATE",
+    requires = "kubelet_tracing_endpoint",
+    value_parser = clap::value_parser!(i32).range(0..=1_000_000)
+  )]
+  pub kubelet_tracing_sampling_rate: Option<i32>,
+
+  /// MTU of the primary interface in bytes, or `auto` to select 9001 in-region and 1500 cross-region
+  ///
+  /// Written as a systemd-networkd drop-in for the primary ENI; when not provided, the MTU is left unchanged
+  #[arg(long, env = "EKSNODE_INTERFACE_MTU")]
+  pub interface_mtu: Option<network::InterfaceMtu>,
+
+  /// Network sysctl applied in place of the value scaled to the instance size (i.e. - `net.ipv4.tcp_fin_timeout=30`)
+  ///
+  /// May be repeated; the connection tracking table and ephemeral port range are otherwise sized from the vCPUs
+  #[arg(long, env = "EKSNODE_SYSCTL", value_parser = network::parse_sysctl)]
+  pub sysctl: Vec<(String, String)>,
+
   /// Setup instance storage NVMe disks in raid0 or mount the individual disks for use by pods
-  #[arg(long, value_enum)]
+  #[arg(long, env = "EKSNODE_LOCAL_DISKS", value_enum)]
   pub local_disks: Option<LocalDisks>,
 
+  /// Tuning profile applied to the kubelet reservations, eviction thresholds, image pulls, and containerd
+  #[arg(long, env = "EKSNODE_PROFILE", value_enum, default_value_t)]
+  pub profile: profile::ProfileName,
+
+  /// YAML file of tuning profiles that replace the embedded profiles of the same name
+  #[arg(long, env = "EKSNODE_PROFILE_FILE")]
+  pub profile_file: Option<PathBuf>,
+
   /// The pause container image <registry>:<tag/version>
-  #[arg(long)]
-  pub pause_container_image: Option<String>,
+  ///
+  /// Use `{arch}` in the image to substitute the node's architecture (`amd64` or `arm64`), or provide an image per
+  /// architecture (i.e. - `amd64=<registry>:<tag>,arm64=<registry>:<tag>`)
+  #[arg(long, env = "EKSNODE_PAUSE_CONTAINER_IMAGE")]
+  pub pause_container_image: Option<containerd::PauseImage>,
 
   /// IPv4 or IPv6 CIDR range of the cluster
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_SERVICE_CIDR")]
   pub service_cidr: Option<IpNet>,
 
+  /// Total duration the node delays shutdown to terminate pods (default: 45s)
+  #[arg(
+    long,
+    env = "EKSNODE_SHUTDOWN_GRACE_PERIOD",
+    conflicts_with = "shutdown_grace_period_by_pod_priority"
+  )]
+  pub shutdown_grace_period: Option<String>,
+
+  /// Duration reserved for terminating critical pods during node shutdown (default: 15s, or a third of
+  /// --shutdown-grace-period when it is 15s or less)
+  ///
+  /// Must be less than --shutdown-grace-period
+  #[arg(
+    long,
+    env = "EKSNODE_SHUTDOWN_GRACE_PERIOD_CRITICAL_PODS",
+    conflicts_with = "shutdown_grace_period_by_pod_priority"
+  )]
+  pub shutdown_grace_period_critical_pods: Option<String>,
+
+  /// Shutdown grace period in seconds for pods at or above a priority class value (i.e. - `2000000000=10`)
+  ///
+  /// May be repeated; replaces --shutdown-grace-period and --shutdown-grace-period-critical-pods
+  #[arg(long, env = "EKSNODE_SHUTDOWN_GRACE_PERIOD_BY_POD_PRIORITY")]
+  pub shutdown_grace_period_by_pod_priority: Vec<kubelet::ShutdownGracePeriodByPodPriority>,
+
+  /// Taint added to the node when it registers with the cluster (i.e. - `dedicated=gpu:NoSchedule`)
+  ///
+  /// May be repeated; the effect must be one of NoSchedule, PreferNoSchedule, or NoExecute
+  #[arg(long, env = "EKSNODE_NODE_TAINT", visible_alias = "register-with-taints")]
+  pub node_taint: Vec<kubelet::Taint>,
+
+  /// Label added to the node when it registers with the cluster (i.e. - `team=data`)
+  ///
+  /// May be repeated; labels of the kubernetes.io and k8s.io namespaces are limited to those kubelet may set, such as
+  /// `node.kubernetes.io/<name>`. Takes precedence over the labels eksnode sets from EC2
+  #[arg(long, env = "EKSNODE_NODE_LABELS")]
+  pub node_labels: Vec<kubelet::NodeLabel>,
+
+  /// Image garbage collection low and high disk usage thresholds in percent (i.e. - `70,80`)
+  ///
+  /// Also sets the nodefs/imagefs hard eviction thresholds above the high threshold so that images are garbage
+  /// collected before pods are evicted. Takes precedence over the tuning profile
+  #[arg(long, env = "EKSNODE_IMAGE_GC_POLICY")]
+  pub image_gc_policy: Option<kubelet::ImageGcPolicy>,
+
+  /// Restrict the kubelet server to FIPS approved TLS settings and pull the pause image from the ECR FIPS endpoint
+  ///
+  /// The host must be booted in FIPS mode; the posture is recorded in /etc/eksnode/state.json for
+  /// `validate-node --check-fips`
+  #[arg(long, env = "EKSNODE_ENABLE_FIPS")]
+  pub enable_fips: bool,
+
+  /// Skip verifying the node IAM role permissions, and NVIDIA driver compatibility on GPU instances, before
+  /// joining the cluster
+  #[arg(long, env = "EKSNODE_SKIP_PREFLIGHT")]
+  pub skip_preflight: bool,
+
+  /// Take over a node already configured by bootstrap.sh or nodeadm
+  ///
+  /// Their configuration is moved to /var/lib/eksnode/adopted and regenerated by eksnode; without it, the join is
+  /// refused so that two tools do not manage the same files
+  #[arg(long, env = "EKSNODE_ADOPT")]
+  pub adopt: bool,
+
+  /// Perform the full bootstrap when an instance that was already joined resumes from a warm pool or hibernation
+  ///
+  /// By default, when the instance, cluster, and inputs are unchanged and the kubelet client certificate is present,
+  /// just the node IP and /etc/hosts entries of a local cluster are refreshed and kubelet is restarted
+  #[arg(long, env = "EKSNODE_FULL_BOOTSTRAP")]
+  pub full_bootstrap: bool,
+
+  /// Maximum offset in milliseconds of the clock from Amazon Time Sync allowed by the preflight checks (default: 1000)
+  ///
+  /// Only checked on EC2 instances; the check is skipped with a warning when the offset cannot be measured
+  #[arg(long, env = "EKSNODE_MAX_CLOCK_SKEW_MS")]
+  pub max_clock_skew_ms: Option<u64>,
+
+  /// Generate the node configuration files without changing the host or starting any services
+  ///
+  /// Cluster and instance details are still discovered; the files are written to --output-dir
+  #[arg(long, env = "EKSNODE_DRY_RUN", requires = "output_dir")]
+  pub dry_run: bool,
+
+  /// Directory where the files generated by --dry-run are written, in a tree mirroring `/`
+  #[arg(long, env = "EKSNODE_OUTPUT_DIR", requires = "dry_run")]
+  pub output_dir: Option<PathBuf>,
+
+  /// Print unified diffs of the files generated by --dry-run against the files currently on the host
+  #[arg(long, env = "EKSNODE_DIFF", requires = "dry_run")]
+  pub diff: bool,
+
+  /// Configure the host without reloading, enabling, or starting the systemd units
+  ///
+  /// Runs the full join in containers and CI sandboxes where systemd is unavailable; unlike --dry-run, the files
+  /// are written in place
+  #[arg(long, env = "EKSNODE_NO_SYSTEMD", conflicts_with = "dry_run")]
+  pub no_systemd: bool,
+
+  /// Print single line progress markers of the join phases to stdout, separate from the logged output
+  ///
+  /// Use `plain` to follow where the bootstrap is on the EC2 serial console or in the cloud-init logs
+  #[arg(long, env = "EKSNODE_PROGRESS", value_enum, default_value_t)]
+  pub progress: timing::Progress,
+
+  /// Export the log of eksnode once the join finishes or fails (i.e. - `cloudwatch:/eks/example/bootstrap`)
+  ///
+  /// The log is written to the `<instance-id>/eksnode` stream of the log group, which must already exist; the node
+  /// role requires logs:CreateLogStream and logs:PutLogEvents. Hybrid nodes use the node name in place of the
+  /// instance ID
+  #[arg(long, env = "EKSNODE_LOG_EXPORT")]
+  pub log_export: Option<log_export::LogExport>,
+
+  /// Also export the kubelet log of the current boot, to the `<instance-id>/kubelet` stream
+  #[arg(long, env = "EKSNODE_LOG_EXPORT_KUBELET", requires = "log_export")]
+  pub log_export_kubelet: bool,
+
+  /// The CNI plugin used by the cluster
+  ///
+  /// With `external` (i.e. - Cilium, Calico), max pods is not derived from the instance ENI limits and is
+  /// instead sourced from --max-pods (default: 110) and --pods-per-core
+  #[arg(long, env = "EKSNODE_CNI", value_enum, default_value_t)]
+  pub cni: Cni,
+
+  /// Directory of the CNI plugin binaries used by containerd (default: /opt/cni/bin)
+  #[arg(long, env = "EKSNODE_CNI_BIN_DIR")]
+  pub cni_bin_dir: Option<String>,
+
+  /// Directory containerd reads the CNI network configuration from (default: /etc/cni/net.d)
+  ///
+  /// Created with `--cni external`, otherwise it must already exist
+  #[arg(long, env = "EKSNODE_CNI_CONF_DIR")]
+  pub cni_conf_dir: Option<String>,
+
+  /// VPC CNI security groups for pods (ENABLE_POD_ENI) is enabled on the cluster
+  ///
+  /// Max pods excludes the trunk ENI that the VPC CNI attaches to Nitro instances, which does not provide pod IPs
+  #[arg(long, env = "EKSNODE_SGPP_ENABLED")]
+  pub sgpp_enabled: bool,
+
+  /// Derive max pods from the VPC CNI version and settings of the aws-node DaemonSet on the cluster
+  ///
+  /// Read through the Kubernetes API with the credentials of the kubelet kubeconfig, which requires the node role to
+  /// be authorized to get the DaemonSet in kube-system; nodes are not authorized to by default
+  #[arg(long, env = "EKSNODE_CNI_SETTINGS_FROM_CLUSTER")]
+  pub cni_settings_from_cluster: bool,
+
+  /// Overrides the maximum number of pods that can run on the node
+  #[arg(long, env = "EKSNODE_MAX_PODS")]
+  pub max_pods: Option<i32>,
+
+  /// Maximum number of pods per CPU core; the lower of this and --max-pods is used
+  #[arg(long, env = "EKSNODE_PODS_PER_CORE")]
+  pub pods_per_core: Option<i32>,
+
   /// Sets --max-pods for the kubelet when true (default: true)
-  #[arg(long, default_value = "true")]
+  #[arg(long, env = "EKSNODE_USE_MAX_PODS", default_value = "true")]
   pub use_max_pods: bool,
 }
 
@@ -89,68 +482,538 @@ impl Default for LocalDisks {
   }
 }
 
+/// Cluster added to the kubelet kubeconfig that the node can be switched to
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdditionalCluster {
+  /// Name of the cluster, which is also the name of its kubeconfig context
+  pub name: String,
+  /// Cluster API server endpoint
+  pub endpoint: String,
+  /// Base64 encoded certificate data
+  pub b64_ca: Secret<String>,
+}
+
+impl AdditionalCluster {
+  /// File the certificate authority of the cluster is written to
+  pub fn ca_path(&self) -> String {
+    format!("/etc/kubernetes/pki/{}-ca.crt", self.name)
+  }
+}
+
+/// Decode the base64 encoded CA, verifying that it is a valid CA certificate
+fn decode_ca(base64_ca: &str) -> Result<Vec<u8>> {
+  let decoded = pki::CA_BASE64
+    .decode(base64_ca)
+    .context("Cluster CA is not valid base64")?;
+  pki::parse_ca_bundle(&decoded).context("Cluster CA is not a valid CA certificate")?;
+
+  Ok(decoded)
+}
+
+/// Parse an additional cluster provided as `<name>=<endpoint>,ca=<base64 CA>`
+fn parse_additional_cluster(s: &str) -> Result<AdditionalCluster> {
+  let Some((name, endpoint, ca)) = s
+    .split_once('=')
+    .and_then(|(name, rest)| rest.rsplit_once(",ca=").map(|(endpoint, ca)| (name, endpoint, ca)))
+  else {
+    bail!("Invalid additional cluster {s}; expected <name>=<endpoint>,ca=<base64 CA>");
+  };
+  if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+    bail!("Invalid additional cluster name {name}; expected letters, digits, hyphens, and underscores");
+  }
+
+  Ok(AdditionalCluster {
+    name: name.to_owned(),
+    endpoint: endpoint.to_owned(),
+    b64_ca: Secret::new(ca.to_owned()),
+  })
+}
+
+/// Parse an environment variable provided as NAME=VALUE
+fn parse_env_var(s: &str) -> Result<(String, String)> {
+  match s.split_once('=') {
+    Some((name, value)) if !name.is_empty() => Ok((name.to_owned(), value.to_owned())),
+    _ => bail!("Invalid environment variable {s}; expected NAME=VALUE"),
+  }
+}
+
+/// IAM role ARNs across partitions (i.e. - `arn:aws:iam::111122223333:role/example`)
+fn is_iam_role_arn(arn: &str) -> bool {
+  let parts = arn.splitn(6, ':').collect::<Vec<_>>();
+  matches!(
+    parts.as_slice(),
+    ["arn", partition, "iam", "", account, resource]
+      if partition.starts_with("aws")
+        && account.len() == 12
+        && account.chars().all(|c| c.is_ascii_digit())
+        && resource.starts_with("role/")
+  )
+}
+
+/// Inputs that only change how join-cluster runs rather than the configuration it generates
+///
+/// Excluded from the input digest so that they do not force a full join of a resumed node
+const RUN_MODE_INPUTS: &[&str] = &[
+  "progress",
+  "dry_run",
+  "output_dir",
+  "diff",
+  "no_systemd",
+  "skip_preflight",
+  "adopt",
+  "full_bootstrap",
+  "max_clock_skew_ms",
+  "log_export",
+  "log_export_kubelet",
+];
+
+/// Whether a node joined with the state resumed from a warm pool or hibernation, rather than needing a full bootstrap
+///
+/// The node was joined on the same instance to the same cluster with the same inputs; only the node IP may have
+/// changed. A new instance ID (i.e. - a volume or AMI reused on another instance) or changed inputs require a full join
+fn is_resumed(state: &state::NodeState, cluster_name: &str, instance_id: &str, input_digest: &str) -> bool {
+  state.cluster_name.as_deref() == Some(cluster_name)
+    && state.instance_id.as_deref() == Some(instance_id)
+    && state.input_digest.as_deref() == Some(input_digest)
+}
+
+/// Get the unified diffs of the files generated under the output directory against the same files under the root
+///
+/// Files missing from the root are diffed against an empty file; the join timings describe the dry run and are skipped
+fn get_file_diffs(output_dir: &Path, root: &Path) -> Result<Vec<String>> {
+  let mut diffs = Vec::new();
+  for entry in WalkDir::new(output_dir)
+    .sort_by_file_name()
+    .into_iter()
+    .filter_map(|e| e.ok())
+  {
+    if !entry.file_type().is_file() {
+      continue;
+    }
+    let path = Path::new("/").join(entry.path().strip_prefix(output_dir)?);
+    if path == Path::new(timing::JOIN_TIMINGS_PATH) {
+      continue;
+    }
+
+    let generated = std::fs::read(entry.path())?;
+    let existing = std::fs::read(utils::rooted(root, &path.to_string_lossy())).ok();
+    if existing.as_ref() == Some(&generated) {
+      continue;
+    }
+
+    let name = path.display().to_string();
+    let old_name = if existing.is_some() { name.as_str() } else { "/dev/null" };
+    let diff = match (
+      std::str::from_utf8(existing.as_deref().unwrap_or_default()),
+      std::str::from_utf8(&generated),
+    ) {
+      (Ok(old), Ok(new)) => TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(old_name, &name)
+        .to_string(),
+      _ => format!("Binary files {old_name} and {name} differ\n"),
+    };
+    diffs.push(diff);
+  }
+
+  Ok(diffs)
+}
+
+/// Maximum number of pods when the limit is not bound by ENIs - matches the kubelet default
+const DEFAULT_MAX_PODS: i32 = 110;
+
+#[derive(Copy, Clone, Debug, Default, ValueEnum, Serialize, Deserialize)]
+pub enum Cni {
+  /// Amazon VPC CNI - max pods is derived from the ENI limits of the instance type
+  #[default]
+  VpcCni,
+  /// A CNI plugin that does not assign pod IPs from ENIs (i.e. - Cilium, Calico)
+  External,
+}
+
 struct KubeletKubeConfig {
   config: kubelet::KubeConfig,
   path: PathBuf,
 }
 
+/// Node details collected during discovery that determine the generated configuration files
+struct NodeContext {
+  region: String,
+  cluster: eks::Cluster,
+  kubelet_version: Version,
+  max_pods: i32,
+  cpus: i32,
+  node_name: String,
+  node_ip: Option<String>,
+  node_labels: BTreeMap<String, String>,
+  /// The availability zone and instance ID of EC2 instances, used for the kubelet provider ID
+  placement: Option<(String, String)>,
+  default_container_runtime: containerd::DefaultRuntime,
+  profile: profile::Profile,
+  credential_env: Vec<(String, String)>,
+}
+
 impl JoinClusterInput {
-  /// Get the cluster info required to join the node to the cluster
-  async fn get_cluster(&self) -> Result<eks::Cluster> {
-    let imds_data = ec2::get_imds_data().await?;
-    debug!("Instance metadata: {imds_data:#?}");
+  /// Merge the inputs of --config-file with those set on the command line, given by the IDs in `explicit`
+  ///
+  /// The merged inputs are validated, and printed as a NodeConfig with --dry-run
+  pub fn merge_config_file(self, explicit: &[String]) -> Result<Self> {
+    let Some(path) = self.config_file.clone() else {
+      return Ok(self);
+    };
+    let contents = std::fs::read_to_string(&path).with_context(|| format!("Unable to read {}", path.display()))?;
+    let spec = nodeconfig::get_spec(&contents).with_context(|| format!("{} is invalid", path.display()))?;
+
+    let mut input = nodeconfig::merge(&spec, &self, explicit)?;
+    input.config_file = Some(path.clone());
+
+    let mut issues = nodeconfig::get_unknown_fields(&spec)?
+      .into_iter()
+      .map(|field| format!("Unknown field: {field}"))
+      .collect::<Vec<_>>();
+    issues.extend(input.validate_config());
+    if !issues.is_empty() {
+      bail!("{} is invalid:\n  {}", path.display(), issues.join("\n  "));
+    }
+
+    if input.dry_run {
+      print!("{}", serde_yaml::to_string(&nodeconfig::NodeConfig::new(&input)?)?);
+    }
+
+    Ok(input)
+  }
+
+  /// Validate the inputs without calling AWS or reading from the filesystem
+  ///
+  /// Returns the list of issues found, which is empty when the inputs are valid
+  pub fn validate_config(&self) -> Vec<String> {
+    let mut issues = Vec::new();
+
+    if self.b64_cluster_ca.is_some() && self.cluster_ca_file.is_some() {
+      issues.push("b64_cluster_ca and cluster_ca_file are mutually exclusive".to_owned());
+    }
+    if self.cluster_dns_ip.is_some() && self.cluster_dns_host_index.is_some() {
+      issues.push("cluster_dns_ip and cluster_dns_host_index are mutually exclusive".to_owned());
+    }
+    if self.cluster_dns_host_index == Some(0) {
+      issues.push("cluster_dns_host_index must be greater than 0: 0".to_owned());
+    }
+    let has_ca = self.b64_cluster_ca.is_some() || self.cluster_ca_file.is_some();
+    match (&self.apiserver_endpoint, has_ca) {
+      (Some(endpoint), true) => {
+        if !endpoint.starts_with("https://") {
+          issues.push(format!("apiserver_endpoint must be an https:// URL: {endpoint}"));
+        }
+      }
+      (Some(_), false) | (None, true) => {
+        issues.push("apiserver_endpoint and b64_cluster_ca (or cluster_ca_file) must be provided together".to_owned())
+      }
+      (None, false) => {}
+    }
+    if let Some(b64_ca) = &self.b64_cluster_ca {
+      if pki::CA_BASE64.decode(b64_ca).is_err() {
+        issues.push("b64_cluster_ca is not valid base64".to_owned());
+      }
+    }
+
+    for cluster in &self.additional_cluster {
+      if cluster.name == self.cluster_name || cluster.name == kubelet::KUBECONFIG_CONTEXT {
+        issues.push(format!(
+          "additional_cluster name conflicts with the node's cluster: {}",
+          cluster.name
+        ));
+      }
+      if !cluster.endpoint.starts_with("https://") {
+        issues.push(format!(
+          "additional_cluster endpoint must be an https:// URL: {}",
+          cluster.endpoint
+        ));
+      }
+      if pki::CA_BASE64.decode(cluster.b64_ca.expose()).is_err() {
+        issues.push(format!("additional_cluster {} CA is not valid base64", cluster.name));
+      }
+    }
+    if !self.additional_cluster.is_empty() && (self.is_local_cluster || self.standalone) {
+      issues.push("additional_cluster cannot be used with is_local_cluster or standalone".to_owned());
+    }
+    if self.cni_settings_from_cluster {
+      if self.standalone || !matches!(self.cni, Cni::VpcCni) {
+        issues.push("cni_settings_from_cluster requires the vpc-cni and cannot be used with standalone".to_owned());
+      }
+      // The settings read from the cluster replace those of the flags, and the credential process config is not
+      // written until after max pods is determined
+      for (name, set) in [
+        ("sgpp_enabled", self.sgpp_enabled),
+        (
+          "kubeconfig_credential_process",
+          self.kubeconfig_credential_process.is_some(),
+        ),
+      ] {
+        if set {
+          issues.push(format!("cni_settings_from_cluster cannot be used with {name}"));
+        }
+      }
+    }
+
+    if let Some(path) = &self.from_ssm {
+      if !path.starts_with('/') {
+        issues.push(format!("from_ssm must be an absolute SSM parameter path: {path}"));
+      }
+    }
+
+    if self.is_local_cluster && self.cluster_id.is_none() {
+      issues.push("cluster_id is required when is_local_cluster is set".to_owned());
+    }
+
+    if let Some(region) = self
+      .region
+      .as_deref()
+      .filter(|r| self.enable_fips && !ecr::has_fips_endpoint(r))
+    {
+      issues.push(format!(
+        "enable_fips is not supported in {region}, which has no ECR FIPS endpoint"
+      ));
+    }
+
+    if self.credential_provider.is_hybrid() {
+      if self.cluster_name.trim().is_empty() && !self.standalone {
+        issues.push("cluster_name is required for hybrid nodes".to_owned());
+      }
+      if self.region.is_none() {
+        issues.push("region is required for hybrid nodes".to_owned());
+      }
+      if self.node_name.is_none() {
+        issues.push("node_name is required for hybrid nodes".to_owned());
+      }
+    }
+    if let hybrid::CredentialProvider::IamRolesAnywhere = self.credential_provider {
+      if let Err(e) = self.roles_anywhere.get_aws_config("") {
+        issues.push(e.to_string());
+      }
+      if self.kubeconfig_credential_process.is_some() {
+        issues.push("kubeconfig_credential_process cannot be used with iam-roles-anywhere".to_owned());
+      }
+    }
+
+    let arns = [
+      ("ecr_assume_role_arn", &self.ecr_assume_role_arn),
+      ("roles_anywhere_role_arn", &self.roles_anywhere.roles_anywhere_role_arn),
+    ];
+    for (name, arn) in arns {
+      if let Some(arn) = arn.as_deref().filter(|arn| !is_iam_role_arn(arn)) {
+        issues.push(format!("{name} is not a valid IAM role ARN: {arn}"));
+      }
+    }
+
+    if let Some(command) = self.kubeconfig_exec_command.as_deref() {
+      if !command.starts_with('/') {
+        issues.push(format!("kubeconfig_exec_command must be an absolute path: {command}"));
+      }
+    }
+    for (name, dir) in [("cni_bin_dir", &self.cni_bin_dir), ("cni_conf_dir", &self.cni_conf_dir)] {
+      if let Some(dir) = dir.as_deref().filter(|dir| !dir.starts_with('/')) {
+        issues.push(format!("{name} must be an absolute path: {dir}"));
+      }
+    }
+
+    if let Some(Err(e)) = self.containerd_slice.as_deref().map(containerd::get_slice_cgroup_path) {
+      issues.push(e.to_string());
+    }
+
+    for (name, _) in &self.kubeconfig_exec_env {
+      if name.is_empty() || name.contains('=') {
+        issues.push(format!("kubeconfig_exec_env contains an invalid variable name: {name}"));
+      }
+    }
+
+    if let Some(service_cidr) = self.service_cidr {
+      let family_matches = matches!(
+        (&self.ip_family, service_cidr),
+        (crate::IpvFamily::Ipv4, IpNet::V4(_)) | (crate::IpvFamily::Ipv6, IpNet::V6(_))
+      );
+      if !family_matches {
+        issues.push(format!(
+          "service_cidr {service_cidr} does not match ip_family {:?}",
+          self.ip_family
+        ));
+      }
+      if let Some(dns_ip) = self.cluster_dns_ip.filter(|ip| !service_cidr.contains(ip)) {
+        issues.push(format!(
+          "cluster_dns_ip {dns_ip} is not within service_cidr {service_cidr}"
+        ));
+      }
+      if let Some(host_index) = self.cluster_dns_host_index.filter(|i| *i > 0) {
+        match eks::derive_cluster_dns_ip(&Some(service_cidr), &self.ip_family, &[], host_index) {
+          Ok(ip) if !service_cidr.contains(&ip) => issues.push(format!(
+            "cluster_dns_host_index {host_index} is not within service_cidr {service_cidr}"
+          )),
+          Ok(_) => {}
+          Err(e) => issues.push(e.to_string()),
+        }
+      }
+    }
+
+    for (name, value) in [("max_pods", self.max_pods), ("pods_per_core", self.pods_per_core)] {
+      if let Some(value) = value.filter(|v| *v <= 0) {
+        issues.push(format!("{name} must be greater than 0: {value}"));
+      }
+    }
+    if self.containerd_max_concurrent_downloads == Some(0) {
+      issues.push("containerd_max_concurrent_downloads must be greater than 0: 0".to_owned());
+    }
+
+    if let Some(endpoint) = self
+      .image_service_endpoint
+      .as_deref()
+      .filter(|e| !e.starts_with("unix://"))
+    {
+      issues.push(format!("image_service_endpoint must be a unix:// socket: {endpoint}"));
+    }
+
+    if let Some(endpoint) = self.kubelet_tracing_endpoint.as_deref() {
+      if endpoint.contains("://") {
+        issues.push(format!(
+          "kubelet_tracing_endpoint must be a <host>:<port> address: {endpoint}"
+        ));
+      }
+    }
+    if let Some(rate) = self.kubelet_tracing_sampling_rate {
+      if self.kubelet_tracing_endpoint.is_none() {
+        issues.push("kubelet_tracing_sampling_rate requires kubelet_tracing_endpoint".to_owned());
+      }
+      if !(0..=1_000_000).contains(&rate) {
+        issues.push(format!(
+          "kubelet_tracing_sampling_rate must be between 0 and 1000000: {rate}"
+        ));
+      }
+    }
+
+    // Labels of config files are deserialized without the validation of the command line
+    for label in &self.node_labels {
+      if let Err(e) = format!("{}={}", label.key, label.value).parse::<kubelet::NodeLabel>() {
+        issues.push(e.to_string());
+      }
+    }
+
+    if let Some(pause_image) = &self.pause_container_image {
+      for image in pause_image.images() {
+        if !image.contains(':') && !image.contains('@') {
+          issues.push(format!("pause_container_image must include a tag or digest: {image}"));
+        }
+      }
+    }
+
+    // The run mode rules of the command line are not applied to config files or the library API
+    match (self.dry_run, self.output_dir.is_some()) {
+      (true, false) => issues.push("dry_run requires output_dir".to_owned()),
+      (false, true) => issues.push("output_dir requires dry_run".to_owned()),
+      _ => {}
+    }
+    if self.diff && !self.dry_run {
+      issues.push("diff requires dry_run".to_owned());
+    }
+    if self.no_systemd && self.dry_run {
+      issues.push("no_systemd cannot be used with dry_run".to_owned());
+    }
+
+    issues
+  }
 
+  /// Get the cluster info required to join the node to the cluster
+  async fn get_cluster(&self, vpc_ipv4_cidr_blocks: &[Ipv4Net]) -> Result<eks::Cluster> {
     // Info required to join node to cluster
-    let cluster = eks::collect_or_get_cluster(self, &imds_data.vpc_ipv4_cidr_blocks).await?;
+    let cluster = match self.standalone {
+      true => eks::get_standalone_cluster(self, vpc_ipv4_cidr_blocks)?,
+      false => eks::collect_or_get_cluster(self, vpc_ipv4_cidr_blocks).await?,
+    };
     debug!("Cluster: {cluster:#?}");
 
     Ok(cluster)
   }
 
   /// Get the configuration for kubelet
-  fn get_kubelet_config(
+  ///
+  /// Generated from the inputs and the provided node details only; nothing is read from the host or AWS
+  pub fn get_kubelet_config(
     &self,
     cluster_dns_ip: IpAddr,
     max_pods: i32,
+    cpus: i32,
     kubelet_version: &Version,
     availability_zone: &str,
     instance_id: &str,
   ) -> Result<kubelet::KubeletConfiguration> {
     let mebibytes_to_reserve = resource::memory_mebibytes_to_reserve(max_pods)?;
-    let cpu_millicores_to_reserve = resource::cpu_millicores_to_reserve(max_pods, num_cpus::get() as i32)?;
+    let cpu_millicores_to_reserve = resource::cpu_millicores_to_reserve(max_pods, cpus)?;
 
     let mut config: kubelet::KubeletConfiguration =
       kubelet::KubeletConfiguration::new(cluster_dns_ip, mebibytes_to_reserve, cpu_millicores_to_reserve);
+    let matrix = kubelet::VersionMatrix::new(kubelet_version)?;
 
     if self.use_max_pods {
       config.max_pods = Some(max_pods);
     }
+    config.pods_per_core = self.pods_per_core;
+    config.set_register_with_taints(&self.node_taint);
+    if self.image_service_endpoint.is_some() && matrix.supports_requested("imageServiceEndpoint")? {
+      config.image_service_endpoint = self.image_service_endpoint.to_owned();
+    }
+    let mut by_pod_priority = self.shutdown_grace_period_by_pod_priority.as_slice();
+    if !by_pod_priority.is_empty() && !matrix.supports_requested("shutdownGracePeriodByPodPriority")? {
+      by_pod_priority = &[];
+    }
+    config.set_shutdown_grace_periods(
+      self.shutdown_grace_period.as_deref(),
+      self.shutdown_grace_period_critical_pods.as_deref(),
+      by_pod_priority,
+    )?;
 
-    // Increase the API priority and fairness for the K8s versions that support it.
-    // Starting with 1.27, the default is already increased to 50/100, so leave the higher defaults
-    if kubelet_version.ge(&Version::parse("1.22.0")?) && kubelet_version.lt(&Version::parse("1.27.0")?) {
+    // Increase the API priority and fairness for the K8s versions that support it
+    if matrix.supports("kubeAPIQPS")? {
       config.kube_api_qps = Some(10);
+    }
+    if matrix.supports("kubeAPIBurst")? {
       config.kube_api_burst = Some(20);
     }
 
-    match kubelet_version.lt(&Version::parse("1.26.0")?) {
-      true => config.provider_id = None,
-      false => config.provider_id = Some(config.get_provider_id(availability_zone, instance_id)?),
+    config.provider_id = match matrix.supports("providerID")? {
+      true => Some(config.get_provider_id(availability_zone, instance_id)?),
+      false => None,
+    };
+
+    if matrix.supports("featureGates.KubeletCredentialProviders")? {
+      config
+        .feature_gates
+        .get_or_insert_with(Default::default)
+        .insert("KubeletCredentialProviders".to_owned(), true);
     }
 
-    if kubelet_version.lt(&Version::parse("1.28.0")?) {
-      match config.feature_gates {
-        Some(ref mut feature_gates) => {
-          feature_gates.insert("KubeletCredentialProviders".to_owned(), true);
-        }
-        None => {
-          let mut feature_gates = std::collections::BTreeMap::new();
-          feature_gates.insert("KubeletCredentialProviders".to_owned(), true);
-          config.feature_gates = Some(feature_gates);
+    if let Some(endpoint) = &self.kubelet_tracing_endpoint {
+      if matrix.supports_requested("tracing")? {
+        config.set_tracing(endpoint, self.kubelet_tracing_sampling_rate);
+        if matrix.supports("featureGates.KubeletTracing")? {
+          config
+            .feature_gates
+            .get_or_insert_with(Default::default)
+            .insert("KubeletTracing".to_owned(), true);
         }
       }
     }
 
+    if self.enable_fips {
+      config.set_tls_policy(fips::FIPS_TLS_MIN_VERSION, fips::FIPS_TLS_CIPHER_SUITES);
+    }
+    if self.standalone {
+      config.set_standalone(kubelet::STATIC_POD_PATH);
+    }
+
+    // User provided feature gates are last so that they take precedence
+    kubelet::validate_feature_gates(&self.kubelet_feature_gates, kubelet_version)?;
+    config
+      .feature_gates
+      .get_or_insert_with(Default::default)
+      .extend(self.kubelet_feature_gates.iter().cloned());
+
     Ok(config)
   }
 
@@ -165,13 +1028,13 @@ impl JoinClusterInput {
       true => self
         .cluster_id
         .as_ref()
-        .expect("Cluster ID is required when your local Amazon EKS cluster is on an Amazon Web Services Outpost"),
+        .context("Cluster ID is required when your local Amazon EKS cluster is on an Amazon Web Services Outpost")?,
       false => &cluster.name,
     };
 
     let path = match self.is_local_cluster {
       true => "/var/lib/kubelet/bootstrap-kubeconfig",
-      false => "/var/lib/kubelet/kubeconfig",
+      false => kubelet::KUBECONFIG_PATH,
     };
 
     let config = kubelet::KubeConfig::new(&cluster.endpoint, name, region)?;
@@ -182,16 +1045,57 @@ impl JoinClusterInput {
     })
   }
 
+  /// Get the VPC CNI settings of the cluster with the credentials of the kubelet kubeconfig
+  ///
+  /// The CA is embedded in the kubeconfig since the CA file has not yet been written
+  async fn get_vpc_cni_settings(
+    &self,
+    cluster: &eks::Cluster,
+    region: &str,
+    credential_env: &[(String, String)],
+  ) -> Result<commands::calculate::VpcCniSettings> {
+    let mut config = self.get_kubelet_kubeconfig(cluster, region)?.config;
+    config.set_exec_options(&self.get_kubeconfig_exec_options(credential_env)?);
+    config.set_certificate_authority_data(&decode_ca(cluster.b64_ca.expose())?);
+
+    commands::calculate::get_vpc_cni_settings(&kubelet::ApiClient::new(&config)?).await
+  }
+
+  /// Get the optional settings for the exec credential plugin of the kubelet kubeconfig
+  fn get_kubeconfig_exec_options(&self, credential_env: &[(String, String)]) -> Result<kubelet::ExecOptions> {
+    let mut options = kubelet::ExecOptions {
+      command: self.kubeconfig_exec_command.to_owned(),
+      args: (!self.kubeconfig_exec_arg.is_empty()).then(|| self.kubeconfig_exec_arg.to_owned()),
+      env: credential_env.to_vec(),
+      install_hint: self.kubeconfig_install_hint.to_owned(),
+      provide_cluster_info: self.kubeconfig_provide_cluster_info.then_some(true),
+    };
+
+    if self.kubeconfig_credential_process.is_some() {
+      if let hybrid::CredentialProvider::IamRolesAnywhere = self.credential_provider {
+        bail!("--kubeconfig-credential-process cannot be used with --credential-provider iam-roles-anywhere");
+      }
+      options = options.with_credential_process(kubelet::CREDENTIAL_PROCESS_CONFIG_PATH);
+    }
+    // User provided values are last so that they take precedence
+    options.env.extend(self.kubeconfig_exec_env.iter().cloned());
+
+    Ok(options)
+  }
+
   fn get_kubelet_args(
     &self,
-    imds: &ec2::InstanceMetadata,
+    node_ip: Option<String>,
+    region: &str,
     kubelet_version: &semver::Version,
-    private_dns_name: &str,
+    node_name: &str,
+    node_labels: BTreeMap<String, String>,
   ) -> Result<kubelet::Args> {
-    let node_ip = imds.get_node_ip(&self.ip_family)?;
-    let pod_infra_container_image = self.get_pause_container_image(imds)?;
+    let pod_infra_container_image = self.get_pause_container_image(region, kubelet_version)?;
 
-    let cloud_provider = match kubelet_version.lt(&Version::parse("1.26.0")?) {
+    let matrix = kubelet::VersionMatrix::new(kubelet_version)?;
+
+    let cloud_provider = match matrix.supports("--cloud-provider=aws")? {
       true => "aws".to_owned(),
       false => "external".to_owned(),
     };
@@ -200,14 +1104,13 @@ impl JoinClusterInput {
     // If the VPC has a custom `domain-name` in its DHCP options set, and the VPC has `enableDnsHostnames` set to
     // `true`, then /etc/hostname is not the same as EC2's PrivateDnsName.
     // The name of the Node object must be equal to EC2's PrivateDnsName for the aws-iam-authenticator to allow kubelet
-    // to manage it.
+    // to manage it, unless another --node-name-strategy is chosen for clusters that map node names differently.
     let hostname_override = match cloud_provider.as_str() {
-      "external" => Some(private_dns_name.to_owned()),
+      "external" => Some(node_name.to_owned()),
       _ => None,
     };
 
-    // TODO --container-runtime flag is removed in 1.27+
-    let container_runtime = match kubelet_version.lt(&Version::parse("1.27.0")?) {
+    let container_runtime = match matrix.supports("--container-runtime")? {
       true => Some("remote".to_owned()),
       false => None,
     };
@@ -218,69 +1121,207 @@ impl JoinClusterInput {
       hostname_override,
       cloud_provider,
       container_runtime,
+      node_labels,
     };
 
     Ok(args)
   }
 
-  fn get_kubelet_extra_args(&self) -> Result<kubelet::ExtraArgs> {
-    let args = self.kubelet_extra_args.to_owned();
-
-    Ok(kubelet::ExtraArgs::new(args))
+  /// Merge the bootstrap parameters from SSM Parameter Store, preferring values provided on the command line
+  ///
+  /// Kubelet extra args are combined with those on the command line appended last so that they take precedence
+  fn merge_bootstrap_parameters(&mut self, params: ssm::BootstrapParameters) {
+    if self.apiserver_endpoint.is_none() && self.b64_cluster_ca.is_none() {
+      self.apiserver_endpoint = params.apiserver_endpoint;
+      self.b64_cluster_ca = params.b64_cluster_ca.map(Secret::into_inner);
+    }
+    self.service_cidr = self.service_cidr.or(params.service_cidr);
+    let ssm_extra_args = params.kubelet_extra_args.map(Secret::into_inner);
+    self.kubelet_extra_args = match (ssm_extra_args, self.kubelet_extra_args.take()) {
+      (Some(ssm), Some(cli)) => Some(format!("{ssm} {cli}")),
+      (ssm, cli) => cli.or(ssm),
+    };
+  }
+
+  fn get_kubelet_extra_args(&self, kubelet_version: &Version) -> Result<kubelet::ExtraArgs> {
+    let args = self.kubelet_extra_args.to_owned();
+    if let Some(args) = &args {
+      for flag in kubelet::VersionMatrix::new(kubelet_version)?.removed_flags(args) {
+        warn!(
+          "Kubelet extra arg {flag} has been removed from kubelet {kubelet_version} and will prevent it from starting"
+        );
+      }
+    }
+
+    Ok(kubelet::ExtraArgs::new(args))
   }
 
   /// Get the pause container image
   ///
-  /// Use the container image specified if provided by the user, otherwise default to the ECR image
-  fn get_pause_container_image(&self, imds: &ec2::InstanceMetadata) -> Result<String> {
-    let uri = format!(
-      "{}/eks/pause:{}",
-      ecr::get_ecr_uri(&imds.region, false)?,
-      containerd::SANDBOX_IMAGE_TAG
-    );
-    let sandbox_img = match &self.pause_container_image {
-      Some(img) => img,
-      None => &uri,
-    };
-
-    Ok(sandbox_img.to_string())
+  /// Use the container image specified if provided by the user, resolved for the node's architecture, otherwise
+  /// default to the ECR image
+  fn get_pause_container_image(&self, region: &str, kubelet_version: &Version) -> Result<String> {
+    match &self.pause_container_image {
+      Some(img) => img.resolve(&Architecture::detect()?),
+      None => Ok(format!(
+        "{}/eks/pause:{}",
+        ecr::get_ecr_uri(region, self.enable_fips)?,
+        kubelet::VersionMatrix::new(kubelet_version)?.pause_tag()
+      )),
+    }
   }
 
   /// Get the rendered containerd configuration
   async fn get_containerd_config(
     &self,
-    imds: ec2::InstanceMetadata,
+    region: &str,
+    kubelet_version: &Version,
     container_runtime: containerd::DefaultRuntime,
+    root: &Path,
   ) -> Result<containerd::ContainerdConfiguration> {
-    let sandbox_img = self.get_pause_container_image(&imds)?;
-    let config = containerd::ContainerdConfiguration::new(&container_runtime, &sandbox_img)?;
+    let sandbox_img = self.get_pause_container_image(region, kubelet_version)?;
+    let existing =
+      containerd::ContainerdConfiguration::read(utils::rooted(root, containerd::CONTAINERD_CONFIG_PATH)).ok();
+    let registry_config_path = containerd::get_registry_config_path(
+      self.registry_config_path.as_deref(),
+      existing.as_ref().and_then(|c| c.registry_config_path()),
+    );
+    debug!("Containerd registry config path: {registry_config_path}");
+    let mut config = containerd::ContainerdConfiguration::new(&container_runtime, &sandbox_img, &registry_config_path)?;
+    let image_service_sock = self
+      .image_service_endpoint
+      .as_deref()
+      .and_then(|endpoint| endpoint.strip_prefix("unix://"));
+    if image_service_sock == Some(containerd::STARGZ_SOCK) {
+      config.set_proxy_snapshotter("stargz", containerd::STARGZ_SOCK);
+    }
+    if let Some(endpoint) = &self.kubelet_tracing_endpoint {
+      config.set_tracing(endpoint, self.kubelet_tracing_sampling_rate);
+    }
 
     Ok(config)
   }
 
-  /// Decode the base64 encoded CA certificate and write it to disk
-  async fn write_ca_cert(&self, base64_ca: &str) -> Result<()> {
-    let decoded = general_purpose::STANDARD_NO_PAD.decode(base64_ca)?;
+  /// Decode the base64 encoded CA certificate and write it to disk once it is verified to be a valid CA
+  async fn write_ca_cert<P: AsRef<Path>>(&self, base64_ca: &str, path: P, chown: bool) -> Result<()> {
+    let decoded = decode_ca(base64_ca)?;
 
-    utils::write_file(&decoded, "/etc/kubernetes/pki/ca.crt", Some(0o644), true).await
+    utils::write_file(&decoded, path, Some(0o644), chown).await
   }
 
   /// Update /etc/hosts for the cluster endpoint IPs for Outpost local cluster
+  ///
+  /// Existing entries of the endpoint are replaced so that the IPs are refreshed when the node re-joins
   async fn update_etc_hosts(&self, endpoint: &str, path: PathBuf) -> Result<()> {
-    let mut hostfile = OpenOptions::new().append(true).open(path).await?;
     let mut ips: Vec<IpAddr> = dns_lookup::lookup_host(endpoint)?;
 
     // Shuffle the IPs to avoid always using the first IP
     ips.shuffle(&mut thread_rng());
-    let entries: Vec<String> = ips.iter().map(|ip| format!("{ip} {endpoint}\n")).collect();
+    let contents = tokio::fs::read_to_string(&path).await?;
+    let mut entries: Vec<String> = contents
+      .lines()
+      .filter(|line| line.split_whitespace().nth(1) != Some(endpoint))
+      .map(|line| format!("{line}\n"))
+      .collect();
+    entries.extend(ips.iter().map(|ip| format!("{ip} {endpoint}\n")));
+
+    tokio::fs::write(path, entries.concat())
+      .await
+      .map_err(anyhow::Error::from)
+  }
+
+  /// Get the state of the previous join when the instance resumes from a warm pool or hibernation
+  ///
+  /// The node was joined on the same instance to the same cluster with the same inputs, and its client certificate
+  /// is still present
+  fn get_resumed_state(&self, imds: &ec2::InstanceMetadata) -> Result<Option<state::NodeState>> {
+    if self.full_bootstrap
+      || self.dry_run
+      || self.no_systemd
+      || self.standalone
+      || !Path::new(kubelet::CLIENT_CERT_PATH).is_file()
+    {
+      return Ok(None);
+    }
+    let Ok(state) = state::NodeState::read(state::NODE_STATE_PATH) else {
+      return Ok(None);
+    };
 
-    hostfile.write_all(entries.join("").as_bytes()).await?;
-    hostfile.flush().await.map_err(anyhow::Error::from)
+    let input_digest = self.get_input_digest()?;
+    match is_resumed(&state, &self.cluster_name, &imds.instance_id, &input_digest) {
+      true => Ok(Some(state)),
+      false => Ok(None),
+    }
+  }
+
+  /// Digest of the inputs the node is joined with, recorded so that a re-join with changed inputs is a full join
+  ///
+  /// Only the inputs that affect the generated configuration are included; see `RUN_MODE_INPUTS`
+  fn get_input_digest(&self) -> Result<String> {
+    let mut inputs = serde_json::to_value(self)?;
+    if let Some(inputs) = inputs.as_object_mut() {
+      for name in RUN_MODE_INPUTS {
+        inputs.remove(*name);
+      }
+    }
+
+    Ok(format!("{:x}", Sha256::digest(serde_json::to_vec(&inputs)?)))
+  }
+
+  /// Re-join a node that resumed from a warm pool or hibernation
+  ///
+  /// The kubelet credentials, provider ID, and the remaining configuration written by the full bootstrap are still
+  /// valid since the instance and inputs are unchanged; only the node IP is updated when it changed
+  async fn rejoin(&self, state: state::NodeState, imds: &ec2::InstanceMetadata) -> Result<()> {
+    let node_ip = imds.get_node_ip(&self.ip_family)?;
+    if state.node_ip.as_deref() != Some(node_ip.as_str()) {
+      info!(phase = "rejoin", "Updating node IP to {node_ip}");
+      let contents = tokio::fs::read_to_string(kubelet::ARGS_PATH).await?;
+      let contents = kubelet::set_node_ip(&contents, &node_ip);
+      utils::write_file(contents.as_bytes(), kubelet::ARGS_PATH, Some(0o644), true).await?;
+
+      let node_state = state::NodeState {
+        node_ip: Some(node_ip),
+        ..state
+      };
+      node_state.write(state::NODE_STATE_PATH, true).await?;
+    }
+
+    if let (true, Some(endpoint)) = (self.is_local_cluster, &self.apiserver_endpoint) {
+      info!(phase = "rejoin", "Refreshing /etc/hosts entries of {endpoint}");
+      self.update_etc_hosts(endpoint, PathBuf::from("/etc/hosts")).await?;
+    }
+
+    info!(phase = "systemd", "Restarting kubelet");
+    systemd::systemctl(vec!["daemon-reload"])?;
+    systemd::systemctl(vec!["restart", "kubelet"])?;
+
+    Ok(())
+  }
+
+  /// Get the maximum number of pods, taking into account the overrides and pods per core limit
+  ///
+  /// `eni_max_pods` is the ENI based limit, only applicable when the VPC CNI is used on EC2
+  pub fn get_effective_max_pods(&self, eni_max_pods: Option<i32>, cpus: i32) -> i32 {
+    let max_pods = match (self.max_pods, self.cni, eni_max_pods) {
+      (Some(max_pods), _, _) => max_pods,
+      (None, Cni::VpcCni, Some(eni_max_pods)) => eni_max_pods,
+      _ => DEFAULT_MAX_PODS,
+    };
+
+    match self.pods_per_core {
+      Some(pods_per_core) if pods_per_core > 0 => max_pods.min(pods_per_core * cpus),
+      _ => max_pods,
+    }
   }
 
-  /// Get the max pods for the instance
-  async fn get_max_pods(&self, instance_type: &str) -> Result<i32> {
+  pub async fn get_max_pods(&self, instance_type: &str) -> Result<i32> {
     match ec2::get_instance(instance_type)? {
+      Some(instance) if self.sgpp_enabled => Ok(resource::calculate_eni_max_pods(
+        resource::sgpp_available_enis(instance.maximum_network_interfaces, &instance.hypervisor),
+        instance.ipv4_addresses_per_interface,
+        false,
+      )),
       Some(instance) => Ok(instance.eni_maximum_pods),
       None => {
         info!("Instance type {instance_type} not found in static instance data. Attempting to derive max pods");
@@ -288,10 +1329,13 @@ impl JoinClusterInput {
         let max_pods = commands::calculate::CalculateMaxPodsInput {
           instance_type: Some(instance_type.to_owned()),
           instance_type_from_imds: false,
-          cni_version: "1.10.0".to_owned(),
+          cni_version: Some("1.10.0".to_owned()),
           cni_custom_networking_enabled: false,
           cni_prefix_delegation_enabled: false,
+          sgpp_enabled: self.sgpp_enabled,
           cni_max_enis: None,
+          cni_settings_from_cluster: false,
+          kubeconfig: None,
         };
         max_pods.calculate().await
       }
@@ -299,76 +1343,625 @@ impl JoinClusterInput {
   }
 
   /// Configure the node to join the cluster
-  pub async fn join_node_to_cluster(&self) -> Result<()> {
-    let instance_metadata = ec2::get_imds_data().await?;
-    let cluster = self.get_cluster().await?;
-    let kubelet_version = kubelet::get_kubelet_version()?;
-    let max_pods = self.get_max_pods(&instance_metadata.instance_type).await?;
-    let pause_image = self.get_pause_container_image(&instance_metadata)?;
+  ///
+  /// The duration of each phase is logged as a summary table and written to the join timings file
+  pub async fn join_node_to_cluster(&mut self) -> Result<()> {
+    let mut timer = timing::PhaseTimer::new(self.progress);
+    timer.start("imds");
+    let instance_metadata = match self.credential_provider.is_hybrid() {
+      true => None,
+      false => Some(ec2::get_imds_data().await?),
+    };
+    if let Some(imds) = &instance_metadata {
+      if self.cluster_name.trim().is_empty() && !self.standalone {
+        let ec2_client = aws::get_ec2_client().await;
+        self.cluster_name = ec2::discover_cluster_name(imds, &ec2_client)
+          .await?
+          .context("--cluster-name is required when the instance is not tagged with the name of the cluster")?;
+        info!("Discovered cluster name {} from the instance tags", self.cluster_name);
+      }
+    }
 
-    let ec2_client = ec2::get_client().await?;
-    let private_dns_name = ec2::get_private_dns_name(&instance_metadata.instance_id, &ec2_client).await?;
+    // Logs are exported under the instance ID, or the node name of hybrid nodes
+    let export_stream_name = match &instance_metadata {
+      Some(imds) => Some(imds.instance_id.to_owned()),
+      None => self.node_name.to_owned(),
+    };
+    let region = match &instance_metadata {
+      Some(imds) => Some(imds.region.to_owned()),
+      None => self.region.to_owned(),
+    };
 
-    self.write_ca_cert(&cluster.b64_ca).await?;
-    if self.is_local_cluster {
+    // Cluster and node identifiers are attached to all events emitted while joining
+    let span = info_span!(
+      "join",
+      cluster = %self.cluster_name,
+      instance_id = %instance_metadata.as_ref().map_or("", |imds| imds.instance_id.as_str()),
+      node_name = %self.node_name.as_deref().unwrap_or_default(),
+    );
+    let result = self.join(instance_metadata, &mut timer).instrument(span).await;
+    if result.is_ok() {
+      systemd::notify_ready();
+    }
+
+    timer.finish();
+    timer.print_result(result.as_ref().err());
+    info!("Join phase durations:\n{}", timer.summary());
+    let root = self.output_dir.to_owned().unwrap_or_else(|| PathBuf::from("/"));
+    let path = utils::rooted(&root, timing::JOIN_TIMINGS_PATH);
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent).ok();
+    }
+    // The durations are informational and do not fail the join
+    if let Err(e) = timer.write(&path, self.output_dir.is_none()).await {
+      warn!("Unable to write join phase durations to {}: {e}", path.display());
+    }
+
+    if let Some(destination) = self.log_export.as_ref().filter(|_| !self.dry_run) {
+      match export_stream_name {
+        // Export failures are logged rather than returned so that they do not mask the result of the join
+        Some(name) => {
+          if let Err(e) = log_export::export(destination, &name, region.as_deref(), self.log_export_kubelet).await {
+            warn!("Unable to export logs: {e:#}");
+          }
+        }
+        None => warn!("Unable to export logs without the instance ID or --node-name"),
+      }
+    }
+
+    result
+  }
+
+  async fn join(
+    &mut self,
+    instance_metadata: Option<ec2::InstanceMetadata>,
+    timer: &mut timing::PhaseTimer,
+  ) -> Result<()> {
+    if let Some(imds) = &instance_metadata {
+      debug!("Instance metadata: {imds:#?}");
+    }
+    // The directories are only checked on the host since they are not written by eksnode
+    if self.output_dir.is_none() {
+      self.verify_cni_dirs(Path::new("/"))?;
+    }
+
+    let region = match &instance_metadata {
+      Some(imds) => imds.region.to_owned(),
+      None => self
+        .region
+        .to_owned()
+        .context("--region is required for hybrid nodes")?,
+    };
+
+    // Hybrid nodes source credentials from outside of IMDS. The AWS SDK clients of this process are
+    // directed to the same credentials that kubelet's plugins receive through their environment
+    let credential_env = self.credential_provider.env();
+    let mut node_credentials = aws::NodeCredentials::default();
+    if let hybrid::CredentialProvider::Ssm = self.credential_provider {
+      node_credentials.credentials_file = Some(PathBuf::from(hybrid::SSM_CREDENTIALS_PATH));
+    }
+    if let hybrid::CredentialProvider::IamRolesAnywhere = self.credential_provider {
+      let root = self.output_dir.to_owned().unwrap_or_else(|| PathBuf::from("/"));
+      let path = utils::rooted(&root, hybrid::AWS_CONFIG_PATH);
+      if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+      }
       self
-        .update_etc_hosts(&cluster.endpoint, PathBuf::from("/etc/hosts"))
+        .roles_anywhere
+        .write_aws_config(&path, &region, !self.dry_run)
         .await?;
+      node_credentials.config_file = Some(path);
+    }
+    if self.credential_provider.is_hybrid() {
+      node_credentials.region = Some(region.to_owned());
+      aws::set_node_credentials(node_credentials);
     }
 
-    let cred_provider_config = kubelet::CredentialProviderConfig::new(&kubelet_version)?;
-    cred_provider_config.write(kubelet::CREDENTIAL_PROVIDER_CONFIG_PATH, true)?;
+    if let Some(path) = &self.cluster_ca_file {
+      let contents = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Unable to read cluster CA file {}", path.display()))?;
+      pki::parse_ca_bundle(&contents)
+        .with_context(|| format!("Cluster CA file {} is not a valid CA certificate", path.display()))?;
+      self.b64_cluster_ca = Some(pki::CA_BASE64.encode(contents));
+    }
 
-    let kubelet_kubeconfig = self.get_kubelet_kubeconfig(&cluster, &instance_metadata.region)?;
-    kubelet_kubeconfig.config.write(kubelet_kubeconfig.path, Some(0))?;
+    if let Some(path) = self.from_ssm.to_owned() {
+      timer.start("ssm");
+      info!(
+        phase = "discovery",
+        "Fetching bootstrap parameters from SSM path {path}"
+      );
+      self.merge_bootstrap_parameters(ssm::get_bootstrap_parameters(&path, &region).await?);
+    }
 
-    let kubelet_config = self.get_kubelet_config(
-      cluster.cluster_dns_ip,
-      max_pods,
-      &kubelet_version,
-      &instance_metadata.availability_zone,
-      &instance_metadata.instance_id,
-    )?;
-    let kubelet_config_path = "/etc/kubernetes/kubelet/kubelet-config.json";
-    match kubelet_config.write(kubelet_config_path, Some(0)) {
-      Ok(_) => (info!("created kubelet config at {kubelet_config_path}"),),
-      Err(e) => {
-        error!("failed to write kubelet config at {kubelet_config_path}");
-        return Err(e);
+    if let Some(imds) = &instance_metadata {
+      if let Some(state) = self.get_resumed_state(imds)? {
+        timer.start("rejoin");
+        info!(
+          phase = "rejoin",
+          "Node resumed from a warm pool or hibernation; refreshing the settings that changed"
+        );
+        return self.rejoin(state, imds).await;
+      }
+    }
+
+    let artifacts = adopt::detect("/");
+    if !artifacts.is_empty() {
+      let found = artifacts
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n  ");
+      match (self.adopt, self.dry_run) {
+        (_, true) => warn!("Node is already configured by other bootstrap tooling:\n  {found}"),
+        (true, false) => {
+          timer.start("adopt");
+          info!(phase = "adopt", "Adopting the node from other bootstrap tooling");
+          adopt::adopt("/", &artifacts, true)?;
+        }
+        (false, false) => bail!(
+          "Node is already configured by other bootstrap tooling:\n  {found}\nRe-run with --adopt to move their \
+           configuration to {} and manage the node with eksnode",
+          adopt::ADOPTED_DIR
+        ),
+      }
+    }
+
+    if !self.skip_preflight {
+      timer.start("preflight");
+      info!(phase = "preflight", "Verifying node IAM role permissions");
+      let mut permissions = vec![preflight::Permission::EcrGetAuthorizationToken];
+      if !self.standalone && (self.apiserver_endpoint.is_none() || self.b64_cluster_ca.is_none()) {
+        permissions.push(preflight::Permission::EksDescribeCluster);
+      }
+      // Only the topology labels and the private DNS name of VPCs with a custom domain are read from the EC2 API
+      let describes_instance = instance_metadata
+        .as_ref()
+        .is_some_and(|imds| self.topology_labels || ec2::node_name_requires_ec2_api(imds, self.node_name_strategy));
+      if describes_instance {
+        permissions.push(preflight::Permission::Ec2DescribeInstances);
+      }
+      let instance_id = instance_metadata.as_ref().map(|imds| imds.instance_id.as_str());
+      preflight::check_iam_permissions(&permissions, &self.cluster_name, instance_id).await?;
+
+      if instance_metadata.is_some() {
+        match preflight::get_clock_offset(preflight::TIME_SYNC_SERVER) {
+          Ok(offset) => {
+            info!(phase = "preflight", "Clock offset from Amazon Time Sync: {offset}ms");
+            let max_skew_ms = self.max_clock_skew_ms.unwrap_or(preflight::DEFAULT_MAX_CLOCK_SKEW_MS);
+            preflight::verify_clock_offset(offset, max_skew_ms)?;
+          }
+          Err(e) => warn!("Preflight: unable to measure the clock offset: {e}"),
+        }
+      }
+    }
+
+    if let (Some(local_disks), Some(_)) = (&self.local_disks, &instance_metadata) {
+      timer.start("local-disks");
+      let devices = instance_store::get_instance_store_disks("/sys/block")?;
+      // The smoke test reads from the disks, which a dry run does not touch
+      if !self.dry_run {
+        info!(phase = "local-disks", "Checking the health of the instance store disks");
+        instance_store::check_disks(&devices)?;
+      }
+
+      info!(
+        phase = "local-disks",
+        "Setting up {} instance store disk(s) as {local_disks:?}",
+        devices.len()
+      );
+      let root = self.output_dir.to_owned().unwrap_or_else(|| PathBuf::from("/"));
+      disks::setup(local_disks, &devices, &root, !self.no_systemd).await?;
+    }
+
+    if self.enable_fips {
+      let issues = fips::get_crypto_policy_issues(Path::new("/"));
+      match (issues.is_empty(), self.dry_run) {
+        (true, _) => info!("Host crypto policy is in FIPS mode"),
+        (false, true) => warn!("Host is not in FIPS mode: {}", issues.join("; ")),
+        (false, false) => bail!("--enable-fips requires a host in FIPS mode: {}", issues.join("; ")),
+      }
+    }
+
+    let profile = profile::get_profile(self.profile, self.profile_file.as_deref())?;
+    debug!("Tuning profile {}: {profile:?}", self.profile);
+
+    timer.start("discovery");
+    info!(phase = "discovery", "Collecting cluster details");
+    let vpc_ipv4_cidr_blocks = match &instance_metadata {
+      Some(imds) => imds.vpc_ipv4_cidr_blocks.to_owned(),
+      None => vec![],
+    };
+    let cluster = self.get_cluster(&vpc_ipv4_cidr_blocks).await?;
+    if let (Some(mtu), Some(imds), false) = (&self.interface_mtu, &instance_metadata, self.dry_run) {
+      let cluster_region = network::get_endpoint_region(&cluster.endpoint);
+      let mtu = mtu.resolve(&imds.region, cluster_region.as_deref());
+      network::configure_interface_mtu(&imds.mac_address, mtu).await?;
+    }
+    if let (Some(access), Some(imds)) = (&cluster.endpoint_access, &instance_metadata) {
+      access.check_node_access(imds.vpc_id.as_deref(), imds.public_ipv4)?;
+    }
+    if !self.standalone && !self.dry_run {
+      let certificates = pki::parse_ca_bundle(&decode_ca(cluster.b64_ca.expose())?)?;
+      pki::verify_endpoint(&cluster.endpoint, &certificates).await?;
+    }
+    let kubelet_version = kubelet::get_kubelet_version().await?;
+    if !kubelet::VersionMatrix::new(&kubelet_version)?.is_supported_version() {
+      warn!(
+        "Kubelet {kubelet_version} is not supported by eksnode; using the details of the closest supported version"
+      );
+    }
+    let cpus = num_cpus::get() as i32;
+    let eni_max_pods = match (&instance_metadata, self.cni, self.max_pods) {
+      (Some(imds), Cni::VpcCni, None) if self.cni_settings_from_cluster => {
+        let settings = self.get_vpc_cni_settings(&cluster, &region, &credential_env).await?;
+        Some(commands::calculate::calculate_max_pods(&imds.instance_type, &settings)?)
+      }
+      (Some(imds), Cni::VpcCni, None) => Some(self.get_max_pods(&imds.instance_type).await?),
+      _ => None,
+    };
+    let max_pods = self.get_effective_max_pods(eni_max_pods, cpus);
+    info!("Max pods: {max_pods}");
+
+    let mut node_labels = BTreeMap::new();
+    let (node_name, node_ip) = match &instance_metadata {
+      Some(imds) => {
+        let ec2_client = aws::get_ec2_client().await;
+        let identity = ec2::get_node_identity(imds, &ec2_client, self.node_name_strategy).await?;
+        match imds.capacity_type {
+          Some(capacity_type) => {
+            info!("Capacity type: {capacity_type:?}");
+            node_labels.extend(capacity_type.labels());
+          }
+          None => warn!("Capacity type is unknown; the capacity type labels are not set"),
+        }
+        if imds.zone_type != ec2::ZoneType::AvailabilityZone {
+          info!(
+            "Instance is in {:?} {}; using endpoints of parent region {}",
+            imds.zone_type, imds.availability_zone, imds.region
+          );
+        }
+        if self.topology_labels {
+          let placement = ec2::get_instance_placement(imds, &ec2_client).await?;
+          debug!("Instance placement: {placement:?}");
+          node_labels.extend(placement.labels());
+        }
+        (identity.node_name, Some(imds.get_node_ip(&self.ip_family)?))
       }
+      None => (
+        self
+          .node_name
+          .to_owned()
+          .context("--node-name is required for hybrid nodes")?,
+        self.node_ip.map(|ip| ip.to_string()),
+      ),
     };
-    let kubelet_args = self.get_kubelet_args(&instance_metadata, &kubelet_version, &private_dns_name)?;
-    kubelet_args.write(kubelet::ARGS_PATH, true).await?;
-    let kubelet_extra_args = self.get_kubelet_extra_args()?;
-    kubelet_extra_args.write(kubelet::EXTRA_ARGS_PATH, true).await?;
 
     // If the instance has NVIDIA GPUs, use the NVIDIA container runtime
-    let default_container_runtime = match ec2::get_instance(&instance_metadata.instance_type)? {
+    let instance = match &instance_metadata {
+      Some(imds) => ec2::get_instance(&imds.instance_type)?,
+      None => None,
+    };
+    let default_container_runtime = match instance {
       Some(instance) => match instance.gpu_manufacturer.as_str() {
         "NVIDIA" => containerd::DefaultRuntime::Nvidia,
         _ => containerd::DefaultRuntime::Containerd,
       },
       None => containerd::DefaultRuntime::Containerd,
     };
+    if let (containerd::DefaultRuntime::Nvidia, false) = (default_container_runtime, self.dry_run) {
+      gpu::validate_nvidia_runtime(&Architecture::detect()?)?;
+
+      if !self.skip_preflight {
+        timer.start("preflight");
+        info!(phase = "preflight", "Verifying NVIDIA driver and toolkit compatibility");
+        let versions = gpu::get_nvidia_versions()?;
+        debug!("NVIDIA versions: {versions:?}");
+        let issues = gpu::check_nvidia_compatibility(&versions)?;
+        if !issues.is_empty() {
+          bail!("NVIDIA GPU compatibility check failed:\n  {}", issues.join("\n  "));
+        }
+      }
+    }
+
+    node_labels.extend(
+      self
+        .node_labels
+        .iter()
+        .map(|label| (label.key.to_owned(), label.value.to_owned())),
+    );
+
+    let ctx = NodeContext {
+      region,
+      cluster,
+      kubelet_version,
+      max_pods,
+      cpus,
+      node_name,
+      node_ip,
+      node_labels,
+      placement: instance_metadata
+        .as_ref()
+        .map(|imds| (imds.availability_zone.to_owned(), imds.instance_id.to_owned())),
+      default_container_runtime,
+      profile,
+      credential_env,
+    };
 
-    let containerd_config = self
-      .get_containerd_config(instance_metadata, default_container_runtime)
-      .await?;
-    containerd_config.write("/etc/containerd/config.toml", true).await?;
+    if let Some(output_dir) = self.output_dir.to_owned() {
+      info!(
+        phase = "dry-run",
+        "Writing node configuration to {}",
+        output_dir.display()
+      );
+      self.write_files(&ctx, &output_dir, timer).await?;
+      if self.diff {
+        let diffs = get_file_diffs(&output_dir, Path::new("/"))?;
+        for diff in &diffs {
+          print!("{diff}");
+        }
+        info!(phase = "dry-run", "{} file(s) differ from the host", diffs.len());
+      }
+      return Ok(());
+    }
 
-    // Requries that containerd is running - should be running at boot from AMI build
-    containerd::create_sandbox_image_service(containerd::SANDBOX_IMAGE_SERVICE_PATH, &pause_image, true).await?;
+    self.write_files(&ctx, Path::new("/"), timer).await?;
+    network::apply_sysctls(network::NETWORK_SYSCTL_PATH);
+    if self.is_local_cluster {
+      self
+        .update_etc_hosts(&ctx.cluster.endpoint, PathBuf::from("/etc/hosts"))
+        .await?;
+    }
 
     if let containerd::DefaultRuntime::Nvidia = default_container_runtime {
       // Set the max clock for Nvidia GPUs
       gpu::set_nvidia_max_clock()?;
     }
 
+    if self.no_systemd {
+      info!(
+        phase = "systemd",
+        "Skipping the start of containerd, sandbox-image, and kubelet"
+      );
+      return Ok(());
+    }
+
     // Enable & start systemd units - this should be the last step
-    utils::cmd_exec("systemctl", vec!["daemon-reload"])?;
-    utils::cmd_exec("systemctl", vec!["enable", "containerd", "sandbox-image", "kubelet"])?;
-    utils::cmd_exec("systemctl", vec!["reload-or-restart", "containerd"])?;
-    utils::cmd_exec("systemctl", vec!["start", "sandbox-image", "kubelet"])?;
+    timer.start("systemd");
+    info!(phase = "systemd", "Starting containerd, sandbox-image, and kubelet");
+    systemd::systemctl(vec!["daemon-reload"])?;
+    systemd::systemctl(vec!["enable", "containerd", "sandbox-image", "kubelet"])?;
+    systemd::systemctl(vec!["reload-or-restart", "containerd"])?;
+    // kubelet requires sandbox-image, which waits for the pause image to be pulled before it is started
+    timer.start("image-pull");
+    let pause_image = self.get_pause_container_image(&ctx.region, &ctx.kubelet_version)?;
+    info!(phase = "image-pull", "Pre-warming the pause image {pause_image}");
+    commands::pull::prewarm_image(&pause_image, &ctx.credential_env).await?;
+    systemd::systemctl(vec!["start", "sandbox-image"])?;
+    timer.start("kubelet-start");
+    systemd::systemctl(vec!["start", "kubelet"])?;
+
+    Ok(())
+  }
+
+  /// Verify the CNI directories provided exist before any of the node configuration is written
+  ///
+  /// The configuration directory of `--cni external` is created when the files are written
+  fn verify_cni_dirs(&self, root: &Path) -> Result<()> {
+    let conf_dir = match self.cni {
+      Cni::External => None,
+      Cni::VpcCni => self.cni_conf_dir.as_ref(),
+    };
+    for (flag, dir) in [
+      ("--cni-bin-dir", self.cni_bin_dir.as_ref()),
+      ("--cni-conf-dir", conf_dir),
+    ] {
+      if let Some(dir) = dir.filter(|dir| !utils::rooted(root, dir).is_dir()) {
+        bail!("CNI directory {dir} provided by {flag} does not exist");
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Write the node configuration files under the root directory (`/` unless performing a dry run)
+  async fn write_files(&self, ctx: &NodeContext, root: &Path, timer: &mut timing::PhaseTimer) -> Result<()> {
+    // Ownership is only changed when configuring the host
+    let chown = root == Path::new("/");
+    let path = |path: &str| -> Result<PathBuf> {
+      let path = utils::rooted(root, path);
+      // Files under a mapped directory are written to its writable directory
+      if let Some(parent) = utils::map_path(&path).parent() {
+        std::fs::create_dir_all(parent)?;
+      }
+      Ok(path)
+    };
+
+    timer.start("credentials");
+    info!(
+      phase = "credentials",
+      "Writing cluster CA and credential provider configuration"
+    );
+    if !self.standalone {
+      self
+        .write_ca_cert(ctx.cluster.b64_ca.expose(), path("/etc/kubernetes/pki/ca.crt")?, chown)
+        .await?;
+    }
+
+    let mut cred_provider_config = kubelet::CredentialProviderConfig::new(&ctx.kubelet_version)?;
+    let mut cred_provider_env = ctx.credential_env.clone();
+    if let Some(role_arn) = &self.ecr_assume_role_arn {
+      if self.credential_provider.is_hybrid() {
+        bail!("--ecr-assume-role-arn is not supported with hybrid credential providers");
+      }
+      ecr::write_assume_role_config(role_arn, path(ecr::ASSUME_ROLE_CONFIG_PATH)?, chown).await?;
+      cred_provider_env.extend(ecr::get_assume_role_env(ecr::ASSUME_ROLE_CONFIG_PATH));
+    }
+    cred_provider_config.set_env(&cred_provider_env);
+    cred_provider_config
+      .write(path(kubelet::CREDENTIAL_PROVIDER_CONFIG_PATH)?, chown)
+      .await?;
+
+    timer.start("kubelet");
+    info!(phase = "kubelet", "Writing kubelet configuration");
+    match self.standalone {
+      true => {
+        info!(phase = "kubelet", "Running kubelet standalone without a kubeconfig");
+        kubelet::create_standalone_service_dropin(path(kubelet::STANDALONE_DROPIN_PATH)?, chown).await?;
+      }
+      false => {
+        let standalone_dropin = utils::rooted(root, kubelet::STANDALONE_DROPIN_PATH);
+        if standalone_dropin.exists() {
+          tokio::fs::remove_file(standalone_dropin).await?;
+        }
+        let mut kubelet_kubeconfig = self.get_kubelet_kubeconfig(&ctx.cluster, &ctx.region)?;
+        for cluster in &self.additional_cluster {
+          let ca_path = cluster.ca_path();
+          self
+            .write_ca_cert(cluster.b64_ca.expose(), path(&ca_path)?, chown)
+            .await?;
+          kubelet_kubeconfig
+            .config
+            .add_cluster(&cluster.name, &cluster.endpoint, Path::new(&ca_path), &ctx.region)?;
+        }
+        let exec_options = self.get_kubeconfig_exec_options(&ctx.credential_env)?;
+        if let Some(credential_process) = &self.kubeconfig_credential_process {
+          kubelet::write_credential_process_config(
+            credential_process,
+            path(kubelet::CREDENTIAL_PROCESS_CONFIG_PATH)?,
+            chown,
+          )
+          .await?;
+        }
+        kubelet_kubeconfig.config.set_exec_options(&exec_options);
+        match (&self.kubeconfig_exec_command, self.dry_run) {
+          (_, true) => {}
+          // Custom helpers are not required to report a version
+          (Some(command), false) => {
+            if !Path::new(command).is_file() {
+              bail!("Exec credential plugin {command} provided by --kubeconfig-exec-command not found");
+            }
+          }
+          (None, false) => {
+            for version in kubelet_kubeconfig.config.verify_exec_commands()? {
+              info!("Exec credential plugin version: {version}");
+            }
+          }
+        }
+        kubelet_kubeconfig
+          .config
+          .write(path(&kubelet_kubeconfig.path.to_string_lossy())?, chown)
+          .await?;
+      }
+    }
+
+    let mut kubelet_config = match &ctx.placement {
+      Some((availability_zone, instance_id)) => self.get_kubelet_config(
+        ctx.cluster.cluster_dns_ip,
+        ctx.max_pods,
+        ctx.cpus,
+        &ctx.kubelet_version,
+        availability_zone,
+        instance_id,
+      )?,
+      None => {
+        // The provider ID is specific to EC2 instances
+        let mut config = self.get_kubelet_config(
+          ctx.cluster.cluster_dns_ip,
+          ctx.max_pods,
+          ctx.cpus,
+          &ctx.kubelet_version,
+          "",
+          "",
+        )?;
+        config.provider_id = None;
+        config
+      }
+    };
+    kubelet_config.apply_profile(&ctx.profile);
+    if let Some(policy) = &self.image_gc_policy {
+      kubelet_config.set_image_gc_policy(policy);
+    }
+    kubelet_config.validate()?;
+    let kubelet_config_path = kubelet::KUBELET_CONFIG_PATH;
+    match kubelet_config.write(path(kubelet_config_path)?, chown).await {
+      Ok(_) => (info!("created kubelet config at {kubelet_config_path}"),),
+      Err(e) => {
+        error!("failed to write kubelet config at {kubelet_config_path}");
+        return Err(e);
+      }
+    };
+    let node_state = state::NodeState {
+      fips_enabled: self.enable_fips,
+      cluster_name: Some(ctx.cluster.name.to_owned()),
+      instance_id: ctx.placement.as_ref().map(|(_, instance_id)| instance_id.to_owned()),
+      node_ip: ctx.node_ip.to_owned(),
+      input_digest: Some(self.get_input_digest()?),
+    };
+    node_state.write(path(state::NODE_STATE_PATH)?, chown).await?;
+    let kubelet_args = self.get_kubelet_args(
+      ctx.node_ip.to_owned(),
+      &ctx.region,
+      &ctx.kubelet_version,
+      &ctx.node_name,
+      ctx.node_labels.clone(),
+    )?;
+    kubelet_args.write(path(kubelet::ARGS_PATH)?, chown).await?;
+    let kubelet_extra_args = self.get_kubelet_extra_args(&ctx.kubelet_version)?;
+    kubelet_extra_args.write(path(kubelet::EXTRA_ARGS_PATH)?, chown).await?;
+
+    let cni_conf_dir = self.cni_conf_dir.as_deref().unwrap_or(containerd::CNI_CONF_DIR);
+    if let Cni::External = self.cni {
+      // kubelet reports the node NotReady until the external CNI writes its configuration here
+      tokio::fs::create_dir_all(utils::rooted(root, cni_conf_dir)).await?;
+    }
+
+    let sysctls = network::get_network_sysctls(ctx.cpus, &self.sysctl);
+    network::write_sysctls(&sysctls, path(network::NETWORK_SYSCTL_PATH)?, chown).await?;
+    network::write_modules(path(network::NETWORK_MODULES_PATH)?, chown).await?;
+
+    timer.start("containerd");
+    info!(phase = "containerd", "Writing containerd configuration");
+    let mut containerd_config = self
+      .get_containerd_config(&ctx.region, &ctx.kubelet_version, ctx.default_container_runtime, root)
+      .await?;
+    if let Some(cri) = &ctx.profile.containerd {
+      containerd_config.merge_cri_config(cri);
+    }
+    containerd_config.set_cni_dirs(self.cni_bin_dir.as_deref(), self.cni_conf_dir.as_deref());
+    containerd_config.set_image_pull_options(
+      self.containerd_max_concurrent_downloads,
+      self.containerd_image_pull_progress_timeout.as_deref(),
+      self.containerd_discard_unpacked_layers,
+    );
+    let slice = self.containerd_slice.as_deref();
+    containerd_config.set_daemon_options(self.containerd_oom_score, slice)?;
+    if self.containerd_oom_score.is_some() || slice.is_some() {
+      containerd::create_daemon_service_dropin(
+        path(containerd::DAEMON_SERVICE_DROPIN_PATH)?,
+        self.containerd_oom_score,
+        slice,
+        chown,
+      )
+      .await?;
+    }
+    if let Some(slice) = slice {
+      containerd::create_slice_unit(path(&containerd::get_slice_unit_path(slice))?, slice, chown).await?;
+    }
+    if let Some(file) = &self.containerd_config_file {
+      containerd_config
+        .merge_file(file)
+        .with_context(|| format!("Unable to merge containerd config file {file}"))?;
+    }
+    containerd_config
+      .write(path(containerd::CONTAINERD_CONFIG_PATH)?, chown)
+      .await?;
+
+    // Requries that containerd is running - should be running at boot from AMI build
+    let pause_image = self.get_pause_container_image(&ctx.region, &ctx.kubelet_version)?;
+    containerd::create_sandbox_image_service(path(containerd::SANDBOX_IMAGE_SERVICE_PATH)?, &pause_image, chown)
+      .await?;
 
     Ok(())
   }
@@ -376,7 +1969,10 @@ impl JoinClusterInput {
 
 #[cfg(test)]
 mod tests {
-  use std::net::Ipv4Addr;
+  use std::{collections::BTreeMap, net::Ipv4Addr};
+
+  use base64::engine::general_purpose;
+  use rstest::*;
 
   use super::*;
 
@@ -391,6 +1987,7 @@ mod tests {
       .get_kubelet_config(
         IpAddr::V4(Ipv4Addr::new(10, 1, 0, 10)),
         110,
+        8,
         &Version::parse("1.22.0").unwrap(),
         "us-east-1a",
         "i-0e46d9575664f45bd",
@@ -411,6 +2008,7 @@ mod tests {
       .get_kubelet_config(
         IpAddr::V4(Ipv4Addr::new(10, 1, 0, 10)),
         110,
+        8,
         &Version::parse("1.26.0").unwrap(),
         "us-east-1a",
         "i-0e46d9575664f45bd",
@@ -434,6 +2032,7 @@ mod tests {
       .get_kubelet_config(
         IpAddr::V4(Ipv4Addr::new(10, 1, 0, 10)),
         110,
+        8,
         &Version::parse("1.27.0").unwrap(),
         "us-east-1a",
         "i-0e46d9575664f45bd",
@@ -449,6 +2048,169 @@ mod tests {
     );
   }
 
+  #[rstest]
+  // imageServiceEndpoint is not supported until 1.27 and is ignored
+  #[case("1.26.0", None)]
+  #[case("1.27.0", Some("unix:///run/containerd-stargz-grpc/containerd-stargz-grpc.sock"))]
+  fn it_gets_kubelet_config_image_service_and_feature_gates(
+    #[case] version: &str,
+    #[case] image_service_endpoint: Option<&str>,
+  ) {
+    let cluster = JoinClusterInput {
+      image_service_endpoint: Some("unix:///run/containerd-stargz-grpc/containerd-stargz-grpc.sock".to_string()),
+      kubelet_feature_gates: vec![("MemoryQoS".to_string(), true), ("NodeSwap".to_string(), false)],
+      ..JoinClusterInput::default()
+    };
+
+    let kubelet_config = cluster
+      .get_kubelet_config(
+        IpAddr::V4(Ipv4Addr::new(10, 1, 0, 10)),
+        110,
+        8,
+        &Version::parse(version).unwrap(),
+        "us-east-1a",
+        "i-0e46d9575664f45bd",
+      )
+      .unwrap();
+
+    assert_eq!(kubelet_config.image_service_endpoint.as_deref(), image_service_endpoint);
+    let feature_gates = kubelet_config.feature_gates.unwrap();
+    assert_eq!(feature_gates.get("MemoryQoS"), Some(&true));
+    assert_eq!(feature_gates.get("NodeSwap"), Some(&false));
+    assert_eq!(feature_gates.get("RotateKubeletServerCertificate"), Some(&true));
+  }
+
+  #[rstest]
+  #[case("1.24.17", None, None)]
+  #[case("1.26.15", Some("localhost:4317"), Some(true))]
+  #[case("1.29.3", Some("localhost:4317"), None)]
+  fn it_gets_kubelet_config_tracing(
+    #[case] version: &str,
+    #[case] endpoint: Option<&str>,
+    #[case] feature_gate: Option<bool>,
+  ) {
+    let cluster = JoinClusterInput {
+      kubelet_tracing_endpoint: Some("localhost:4317".to_string()),
+      kubelet_tracing_sampling_rate: Some(10_000),
+      ..JoinClusterInput::default()
+    };
+
+    let kubelet_config = cluster
+      .get_kubelet_config(
+        IpAddr::V4(Ipv4Addr::new(10, 1, 0, 10)),
+        110,
+        8,
+        &Version::parse(version).unwrap(),
+        "us-east-1a",
+        "i-0e46d9575664f45bd",
+      )
+      .unwrap();
+
+    let tracing_config = kubelet_config.tracing.as_ref();
+    assert_eq!(tracing_config.and_then(|t| t.endpoint.as_deref()), endpoint);
+    assert_eq!(
+      tracing_config.and_then(|t| t.sampling_rate_per_million),
+      endpoint.map(|_| 10_000)
+    );
+    assert_eq!(
+      kubelet_config
+        .feature_gates
+        .as_ref()
+        .and_then(|gates| gates.get("KubeletTracing").copied()),
+      feature_gate
+    );
+  }
+
+  #[rstest]
+  #[case(false)]
+  #[case(true)]
+  fn it_gets_kubelet_config_fips(#[case] enable_fips: bool) {
+    let cluster = JoinClusterInput {
+      enable_fips,
+      ..JoinClusterInput::default()
+    };
+
+    let kubelet_config = cluster
+      .get_kubelet_config(
+        IpAddr::V4(Ipv4Addr::new(10, 1, 0, 10)),
+        110,
+        8,
+        &Version::parse("1.29.3").unwrap(),
+        "us-east-1a",
+        "i-0e46d9575664f45bd",
+      )
+      .unwrap();
+
+    assert_eq!(
+      kubelet_config.uses_tls_policy(fips::FIPS_TLS_MIN_VERSION, fips::FIPS_TLS_CIPHER_SUITES),
+      enable_fips
+    );
+  }
+
+  #[test]
+  fn it_gets_kubelet_config_standalone() {
+    let node = JoinClusterInput {
+      standalone: true,
+      ..JoinClusterInput::default()
+    };
+    assert!(node.validate_config().is_empty());
+
+    let kubelet_config = node
+      .get_kubelet_config(
+        IpAddr::V4(Ipv4Addr::new(10, 1, 0, 10)),
+        110,
+        8,
+        &Version::parse("1.29.3").unwrap(),
+        "us-east-1a",
+        "i-0e46d9575664f45bd",
+      )
+      .unwrap();
+
+    let value = serde_json::to_value(&kubelet_config).unwrap();
+    assert_eq!(value["staticPodPath"], kubelet::STATIC_POD_PATH);
+    assert_eq!(value["enableServer"], false);
+  }
+
+  #[rstest]
+  #[case(Cni::VpcCni, None, None, Some(58), 8, 58)]
+  #[case(Cni::VpcCni, Some(250), None, Some(58), 8, 250)]
+  #[case(Cni::VpcCni, None, Some(4), Some(58), 8, 32)]
+  #[case(Cni::VpcCni, None, None, None, 8, DEFAULT_MAX_PODS)]
+  #[case(Cni::External, None, None, None, 8, DEFAULT_MAX_PODS)]
+  #[case(Cni::External, Some(250), None, None, 8, 250)]
+  #[case(Cni::External, Some(250), Some(10), None, 16, 160)]
+  #[case(Cni::External, None, Some(0), None, 16, DEFAULT_MAX_PODS)]
+  fn it_gets_effective_max_pods(
+    #[case] cni: Cni,
+    #[case] max_pods: Option<i32>,
+    #[case] pods_per_core: Option<i32>,
+    #[case] eni_max_pods: Option<i32>,
+    #[case] cpus: i32,
+    #[case] expected: i32,
+  ) {
+    let node = JoinClusterInput {
+      cni,
+      max_pods,
+      pods_per_core,
+      ..JoinClusterInput::default()
+    };
+
+    assert_eq!(node.get_effective_max_pods(eni_max_pods, cpus), expected);
+  }
+
+  #[rstest]
+  #[case(false, 29)]
+  #[case(true, 20)]
+  #[tokio::test]
+  async fn it_gets_max_pods(#[case] sgpp_enabled: bool, #[case] expected: i32) {
+    let node = JoinClusterInput {
+      sgpp_enabled,
+      ..JoinClusterInput::default()
+    };
+
+    assert_eq!(node.get_max_pods("m5.large").await.unwrap(), expected);
+  }
+
   #[test]
   fn it_gets_kubelet_kubeconfig_local() {
     let node = JoinClusterInput {
@@ -460,9 +2222,10 @@ mod tests {
     let cluster = eks::Cluster {
       name: "example".to_string(),
       endpoint: "http://localhost:8080".to_string(),
-      b64_ca: "c3VwZXIgc2VjcmV0IGNsdXN0ZXIgY2VydGlmaWNhdGU".to_string(),
+      b64_ca: Secret::new("c3VwZXIgc2VjcmV0IGNsdXN0ZXIgY2VydGlmaWNhdGU".to_string()),
       is_local_cluster: true,
       cluster_dns_ip: IpAddr::V4(Ipv4Addr::new(10, 1, 0, 10)),
+      endpoint_access: None,
     };
 
     let kubelet_kubeconfig = node.get_kubelet_kubeconfig(&cluster, "us-west-2").unwrap();
@@ -480,9 +2243,10 @@ mod tests {
     let cluster = eks::Cluster {
       name: "example".to_string(),
       endpoint: "http://localhost:8080".to_string(),
-      b64_ca: "c3VwZXIgc2VjcmV0IGNsdXN0ZXIgY2VydGlmaWNhdGU".to_string(),
+      b64_ca: Secret::new("c3VwZXIgc2VjcmV0IGNsdXN0ZXIgY2VydGlmaWNhdGU".to_string()),
       is_local_cluster: false,
       cluster_dns_ip: IpAddr::V4(Ipv4Addr::new(10, 1, 0, 10)),
+      endpoint_access: None,
     };
 
     let kubelet_kubeconfig = node.get_kubelet_kubeconfig(&cluster, "eu-west-1").unwrap();
@@ -490,4 +2254,333 @@ mod tests {
     assert_eq!(kubelet_kubeconfig.path, PathBuf::from("/var/lib/kubelet/kubeconfig"));
     insta::assert_debug_snapshot!(kubelet_kubeconfig.config);
   }
+
+  #[test]
+  fn it_merges_bootstrap_parameters() {
+    let mut node = JoinClusterInput {
+      service_cidr: Some("10.100.0.0/16".parse().unwrap()),
+      kubelet_extra_args: Some("--node-labels=team=b".to_string()),
+      ..JoinClusterInput::default()
+    };
+
+    node.merge_bootstrap_parameters(ssm::BootstrapParameters {
+      apiserver_endpoint: Some("https://example.com".to_string()),
+      b64_cluster_ca: Some(Secret::new("c3VwZXIgc2VjcmV0".to_string())),
+      service_cidr: Some("172.20.0.0/16".parse().unwrap()),
+      kubelet_extra_args: Some(Secret::new("--node-labels=team=a".to_string())),
+    });
+
+    assert_eq!(node.apiserver_endpoint.as_deref(), Some("https://example.com"));
+    assert_eq!(node.b64_cluster_ca.as_deref(), Some("c3VwZXIgc2VjcmV0"));
+    assert_eq!(node.service_cidr, Some("10.100.0.0/16".parse().unwrap()));
+    assert_eq!(
+      node.kubelet_extra_args.as_deref(),
+      Some("--node-labels=team=a --node-labels=team=b")
+    );
+  }
+
+  #[rstest]
+  // Resumed from a warm pool or hibernation, with or without a new node IP
+  #[case("example", "i-0e46d9575664f45bd", "digest", true)]
+  // The volume or AMI was reused on a new instance
+  #[case("example", "i-0a1b2c3d4e5f67890", "digest", false)]
+  #[case("other", "i-0e46d9575664f45bd", "digest", false)]
+  // The inputs changed since the node was joined
+  #[case("example", "i-0e46d9575664f45bd", "changed", false)]
+  fn it_detects_resumed_node(
+    #[case] cluster_name: &str,
+    #[case] instance_id: &str,
+    #[case] input_digest: &str,
+    #[case] expected: bool,
+  ) {
+    let state = state::NodeState {
+      cluster_name: Some("example".to_string()),
+      instance_id: Some("i-0e46d9575664f45bd".to_string()),
+      node_ip: Some("10.0.1.23".to_string()),
+      input_digest: Some("digest".to_string()),
+      ..state::NodeState::default()
+    };
+
+    assert_eq!(is_resumed(&state, cluster_name, instance_id, input_digest), expected);
+  }
+
+  #[test]
+  fn it_digests_only_configuration_inputs() {
+    let node = JoinClusterInput {
+      cluster_name: "example".to_string(),
+      ..JoinClusterInput::default()
+    };
+    let digest = node.get_input_digest().unwrap();
+
+    let run_mode = JoinClusterInput {
+      cluster_name: "example".to_string(),
+      progress: timing::Progress::Plain,
+      skip_preflight: true,
+      adopt: true,
+      full_bootstrap: true,
+      ..JoinClusterInput::default()
+    };
+    assert_eq!(run_mode.get_input_digest().unwrap(), digest);
+
+    let changed = JoinClusterInput {
+      cluster_name: "example".to_string(),
+      max_pods: Some(58),
+      ..JoinClusterInput::default()
+    };
+    assert_ne!(changed.get_input_digest().unwrap(), digest);
+  }
+
+  #[test]
+  fn it_parses_additional_cluster() {
+    let cluster =
+      parse_additional_cluster("green=https://GREEN.gr7.us-west-2.eks.amazonaws.com,ca=c3VwZXIgc2VjcmV0").unwrap();
+    assert_eq!(cluster.name, "green");
+    assert_eq!(cluster.endpoint, "https://GREEN.gr7.us-west-2.eks.amazonaws.com");
+    assert_eq!(cluster.b64_ca.expose(), "c3VwZXIgc2VjcmV0");
+    assert_eq!(cluster.ca_path(), "/etc/kubernetes/pki/green-ca.crt");
+
+    assert!(parse_additional_cluster("green=https://GREEN.gr7.us-west-2.eks.amazonaws.com").is_err());
+    assert!(parse_additional_cluster("../green=https://example.com,ca=c3VwZXIgc2VjcmV0").is_err());
+  }
+
+  #[test]
+  fn it_validates_config() {
+    let node = JoinClusterInput {
+      cluster_name: "example".to_string(),
+      apiserver_endpoint: Some("https://example.com".to_string()),
+      b64_cluster_ca: Some("c3VwZXIgc2VjcmV0".to_string()),
+      service_cidr: Some("172.20.0.0/16".parse().unwrap()),
+      cluster_dns_ip: Some(IpAddr::V4(Ipv4Addr::new(172, 20, 0, 10))),
+      ecr_assume_role_arn: Some("arn:aws-us-gov:iam::111122223333:role/ecr".to_string()),
+      ..JoinClusterInput::default()
+    };
+    assert!(node.validate_config().is_empty());
+
+    let node = JoinClusterInput {
+      apiserver_endpoint: Some("example.com".to_string()),
+      service_cidr: Some("fd00::/108".parse().unwrap()),
+      cluster_dns_ip: Some(IpAddr::V4(Ipv4Addr::new(10, 100, 0, 10))),
+      cluster_dns_host_index: Some(0),
+      ecr_assume_role_arn: Some("arn:aws:iam::111122223333:user/ecr".to_string()),
+      max_pods: Some(0),
+      kubeconfig_exec_command: Some("token-helper".to_string()),
+      credential_provider: hybrid::CredentialProvider::IamRolesAnywhere,
+      ..JoinClusterInput::default()
+    };
+    insta::assert_debug_snapshot!(node.validate_config());
+
+    let node = JoinClusterInput {
+      region: Some("eu-west-1".to_string()),
+      enable_fips: true,
+      ..JoinClusterInput::default()
+    };
+    assert_eq!(
+      node.validate_config(),
+      vec!["enable_fips is not supported in eu-west-1, which has no ECR FIPS endpoint"]
+    );
+
+    let node = JoinClusterInput {
+      cni_settings_from_cluster: true,
+      cni: Cni::External,
+      sgpp_enabled: true,
+      ..JoinClusterInput::default()
+    };
+    assert_eq!(
+      node.validate_config(),
+      vec![
+        "cni_settings_from_cluster requires the vpc-cni and cannot be used with standalone",
+        "cni_settings_from_cluster cannot be used with sgpp_enabled",
+      ]
+    );
+  }
+
+  #[rstest]
+  #[case(true, None, false, false, vec!["dry_run requires output_dir"])]
+  #[case(false, Some("/tmp/eksnode"), true, false, vec!["output_dir requires dry_run", "diff requires dry_run"])]
+  #[case(true, Some("/tmp/eksnode"), false, true, vec!["no_systemd cannot be used with dry_run"])]
+  #[case(true, Some("/tmp/eksnode"), true, false, vec![])]
+  fn it_validates_config_run_mode(
+    #[case] dry_run: bool,
+    #[case] output_dir: Option<&str>,
+    #[case] diff: bool,
+    #[case] no_systemd: bool,
+    #[case] expected: Vec<&str>,
+  ) {
+    let node = JoinClusterInput {
+      dry_run,
+      output_dir: output_dir.map(PathBuf::from),
+      diff,
+      no_systemd,
+      ..JoinClusterInput::default()
+    };
+
+    assert_eq!(node.validate_config(), expected);
+  }
+
+  #[test]
+  fn it_decodes_padded_cluster_ca() {
+    // DescribeCluster returns the CA with padding, which user data often strips
+    let bundle = format!("{}\n", pki::TEST_CA_PEM);
+    let padded = general_purpose::STANDARD.encode(&bundle);
+    assert!(padded.ends_with('='));
+    let unpadded = general_purpose::STANDARD_NO_PAD.encode(&bundle);
+
+    for b64_ca in [padded, unpadded] {
+      assert_eq!(decode_ca(&b64_ca).unwrap(), bundle.as_bytes());
+      let node = JoinClusterInput {
+        cluster_name: "example".to_string(),
+        apiserver_endpoint: Some("https://example.com".to_string()),
+        b64_cluster_ca: Some(b64_ca.clone()),
+        additional_cluster: vec![
+          parse_additional_cluster(&format!("green=https://green.example.com,ca={b64_ca}")).unwrap(),
+        ],
+        ..JoinClusterInput::default()
+      };
+      assert!(node.validate_config().is_empty());
+    }
+  }
+
+  /// Replace the digest of the inputs in the node state, which changes with any input added to the command
+  fn redact_digest(mut contents: String) -> String {
+    let key = "\"input_digest\": \"";
+    if let Some(start) = contents.find(key).map(|i| i + key.len()) {
+      let end = start + contents[start..].find('"').unwrap();
+      contents.replace_range(start..end, "[digest]");
+    }
+
+    contents
+  }
+
+  /// Write the generated files for the node to a temporary directory and render the tree
+  async fn render_files(node: JoinClusterInput, kubelet_version: &str) -> String {
+    let ctx = NodeContext {
+      region: "us-west-2".to_string(),
+      cluster: eks::Cluster {
+        name: "example".to_string(),
+        endpoint: "https://ABC.gr7.us-west-2.eks.amazonaws.com".to_string(),
+        b64_ca: Secret::new(general_purpose::STANDARD_NO_PAD.encode(pki::TEST_CA_PEM)),
+        is_local_cluster: false,
+        cluster_dns_ip: IpAddr::V4(Ipv4Addr::new(172, 20, 0, 10)),
+        endpoint_access: None,
+      },
+      kubelet_version: Version::parse(kubelet_version).unwrap(),
+      max_pods: 58,
+      cpus: 8,
+      node_name: "ip-10-0-1-23.us-west-2.compute.internal".to_string(),
+      node_ip: Some("10.0.1.23".to_string()),
+      node_labels: BTreeMap::from([("topology.kubernetes.io/zone".to_string(), "us-west-2a".to_string())]),
+      placement: Some(("us-west-2a".to_string(), "i-0e46d9575664f45bd".to_string())),
+      default_container_runtime: containerd::DefaultRuntime::Containerd,
+      profile: profile::get_profile(node.profile, None).unwrap(),
+      credential_env: node.credential_provider.env(),
+    };
+
+    let root = tempfile::tempdir().unwrap();
+    node
+      .write_files(&ctx, root.path(), &mut timing::PhaseTimer::default())
+      .await
+      .unwrap();
+
+    WalkDir::new(root.path())
+      .sort_by_file_name()
+      .into_iter()
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| entry.path() != root.path())
+      .map(|entry| {
+        let path = entry.path().strip_prefix(root.path()).unwrap().display().to_string();
+        match entry.file_type().is_file() {
+          true => format!(
+            "--- /{path}\n{}\n",
+            redact_digest(std::fs::read_to_string(entry.path()).unwrap())
+          ),
+          false => format!("--- /{path}/\n"),
+        }
+      })
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn it_writes_files_126() {
+    let node = JoinClusterInput {
+      dry_run: true,
+      ..JoinClusterInput::default()
+    };
+    insta::assert_snapshot!(render_files(node, "1.26.15").await);
+  }
+
+  #[test]
+  fn it_verifies_cni_dirs() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("opt/cni/bin")).unwrap();
+    let mut node = JoinClusterInput {
+      cni_bin_dir: Some("/opt/cni/bin".to_string()),
+      cni_conf_dir: Some("/etc/cni/net.d".to_string()),
+      ..JoinClusterInput::default()
+    };
+
+    let err = node.verify_cni_dirs(root.path()).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "CNI directory /etc/cni/net.d provided by --cni-conf-dir does not exist"
+    );
+
+    node.cni = Cni::External;
+    assert!(node.verify_cni_dirs(root.path()).is_ok());
+  }
+
+  #[tokio::test]
+  async fn it_writes_files_129_external_cni() {
+    let node = JoinClusterInput {
+      dry_run: true,
+      cni: Cni::External,
+      max_pods: Some(110),
+      ecr_assume_role_arn: Some("arn:aws:iam::111122223333:role/ecr".to_string()),
+      containerd_oom_score: Some(-999),
+      containerd_slice: Some("runtime-containerd.slice".to_string()),
+      ..JoinClusterInput::default()
+    };
+    insta::assert_snapshot!(render_files(node, "1.29.3").await);
+  }
+
+  #[tokio::test]
+  async fn it_writes_files_130_hybrid() {
+    let node = JoinClusterInput {
+      dry_run: true,
+      credential_provider: hybrid::CredentialProvider::Ssm,
+      kubelet_extra_args: Some("--node-labels=team=a".to_string()),
+      node_taint: vec!["dedicated=hybrid:NoSchedule".parse().unwrap()],
+      ..JoinClusterInput::default()
+    };
+    insta::assert_snapshot!(render_files(node, "1.30.6").await);
+  }
+
+  #[test]
+  fn it_gets_file_diffs() {
+    let output_dir = tempfile::tempdir().unwrap();
+    let root = tempfile::tempdir().unwrap();
+    for (dir, path, contents) in [
+      (output_dir.path(), "etc/eks/unchanged", "same\n"),
+      (root.path(), "etc/eks/unchanged", "same\n"),
+      (output_dir.path(), "etc/eks/changed", "a\nb\n"),
+      (root.path(), "etc/eks/changed", "a\nc\n"),
+      (output_dir.path(), "etc/eks/new", "new\n"),
+      (
+        output_dir.path(),
+        timing::JOIN_TIMINGS_PATH.trim_start_matches('/'),
+        "{}",
+      ),
+    ] {
+      std::fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+      std::fs::write(dir.join(path), contents).unwrap();
+    }
+
+    let diffs = get_file_diffs(output_dir.path(), root.path()).unwrap();
+    assert_eq!(
+      diffs,
+      vec![
+        "--- /etc/eks/changed\n+++ /etc/eks/changed\n@@ -1,2 +1,2 @@\n a\n-c\n+b\n",
+        "--- /dev/null\n+++ /etc/eks/new\n@@ -0,0 +1 @@\n+new\n",
+      ]
+    );
+  }
 }
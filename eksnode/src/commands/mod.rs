@@ -1,6 +1,16 @@
+pub mod accelerators;
+pub mod artifacts;
 pub mod calculate;
+pub mod credential;
 pub mod debug;
+pub mod events;
 pub mod join;
+pub mod provision;
 pub mod pull;
+pub mod reconcile;
+pub mod render;
+pub mod reservations;
+pub mod status;
+pub mod switch;
 pub mod validate;
 pub mod versions;
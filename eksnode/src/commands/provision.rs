@@ -0,0 +1,494 @@
+use std::{
+  fs::Permissions,
+  io::ErrorKind,
+  os::unix::fs::PermissionsExt,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{kubelet, utils, Assets};
+
+/// Contents of a file written during AMI provisioning
+enum Source {
+  /// File embedded from `files/provision/`
+  Asset(&'static str),
+  /// Static contents
+  Inline(&'static str),
+}
+
+/// Directories created during AMI provisioning and their mode
+const DIRECTORIES: &[(&str, u32)] = &[
+  ("/etc/containerd", 0o755),
+  ("/etc/eks", 0o755),
+  ("/etc/eks/containerd", 0o755),
+  ("/etc/eks/image-credential-provider", 0o755),
+  ("/etc/eksnode", 0o755),
+  ("/etc/eksnode/aws", 0o700),
+  ("/etc/kubernetes/kubelet", 0o755),
+  ("/etc/kubernetes/manifests", 0o755),
+  ("/etc/kubernetes/pki", 0o755),
+  ("/etc/systemd/system/containerd.service.d", 0o755),
+  ("/etc/systemd/system/kubelet.service.d", 0o755),
+  ("/root/.docker", 0o755),
+  ("/usr/lib/systemd/networkd.conf.d", 0o755),
+  ("/var/lib/kubelet", 0o755),
+  ("/var/lib/kubernetes", 0o755),
+];
+
+/// Files written during AMI provisioning, their contents, and mode
+const FILES: &[(&str, Source, u32)] = &[
+  // Mask udev triggers installed by amazon-ec2-net-utils package
+  (
+    "/etc/udev/rules.d/99-vpc-policy-routes.rules",
+    Source::Inline(""),
+    0o644,
+  ),
+  // Make networkd ignore foreign settings, else it may unexpectedly delete IP rules and routes added by CNI
+  (
+    "/usr/lib/systemd/networkd.conf.d/80-release.conf",
+    Source::Inline("[Network]\nManageForeignRoutes=no\nManageForeignRoutingPolicyRules=no\n"),
+    0o644,
+  ),
+  ("/etc/logrotate.conf", Source::Asset("provision/logrotate.conf"), 0o644),
+  (
+    "/etc/logrotate.d/kube-proxy",
+    Source::Inline("/var/log/kube-proxy.log {\n  copytruncate\n  missingok\n  rotate 5\n  daily\n  compress\n}\n"),
+    0o644,
+  ),
+  (
+    "/etc/systemd/system/runtime.slice",
+    Source::Asset("provision/runtime.slice"),
+    0o644,
+  ),
+  (
+    "/etc/systemd/system/containerd.service",
+    Source::Asset("provision/containerd.service"),
+    0o644,
+  ),
+  (
+    "/etc/systemd/system/kubelet.service",
+    Source::Asset("provision/kubelet.service"),
+    0o644,
+  ),
+  // https://kubernetes.io/docs/setup/production-environment/container-runtimes/#forwarding-ipv4-and-letting-iptables-see-bridged-traffic
+  (
+    "/etc/modules-load.d/containerd.conf",
+    Source::Inline("br_netfilter\noverlay\n"),
+    0o644,
+  ),
+  (
+    "/etc/sysctl.d/99-kubernetes-cri.conf",
+    Source::Inline(
+      "net.bridge.bridge-nf-call-ip6tables = 1\nnet.bridge.bridge-nf-call-iptables = 1\nnet.ipv4.ip_forward = 1\n",
+    ),
+    0o644,
+  ),
+  (
+    "/etc/sysctl.d/99-amazon.conf",
+    Source::Inline("vm.overcommit_memory=1\nkernel.panic=10\nkernel.panic_on_oops=1\n"),
+    0o644,
+  ),
+  ("/etc/sysctl.conf", Source::Asset("provision/sysctl.conf"), 0o644),
+  // For amazon-ecr-credential-helper used by nerdctl to pull images
+  (
+    "/root/.docker/config.json",
+    Source::Inline("{\n  \"credsStore\": \"ecr-login\"\n}\n"),
+    0o644,
+  ),
+];
+
+/// Proxy mode kube-proxy is run in on the node
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum KubeProxyMode {
+  /// Only requires the modules and settings written for all nodes
+  #[default]
+  Iptables,
+  /// Requires the IP Virtual Server modules
+  Ipvs,
+  /// Requires the nf_tables module
+  Nftables,
+}
+
+impl KubeProxyMode {
+  /// Kernel modules loaded at boot so that kube-proxy can program the rules as soon as it is scheduled
+  fn modules(&self) -> &'static [&'static str] {
+    match self {
+      Self::Iptables => &[],
+      Self::Ipvs => &["ip_vs", "ip_vs_rr", "ip_vs_wrr", "ip_vs_lc", "ip_vs_sh", "nf_conntrack"],
+      Self::Nftables => &["nf_tables", "nf_conntrack"],
+    }
+  }
+
+  /// Connection tracking sysctls, matching the values kube-proxy would otherwise set once it starts
+  ///
+  /// `nf_conntrack_max` is left to the network sysctls written at join, which scale it to the instance size
+  fn sysctls(&self) -> &'static [(&'static str, &'static str)] {
+    match self {
+      Self::Iptables => &[],
+      Self::Ipvs => &[
+        ("net.netfilter.nf_conntrack_tcp_timeout_established", "86400"),
+        ("net.netfilter.nf_conntrack_tcp_timeout_close_wait", "3600"),
+        ("net.ipv4.vs.conntrack", "1"),
+        ("net.ipv4.vs.conn_reuse_mode", "0"),
+        ("net.ipv4.vs.expire_nodest_conn", "1"),
+        ("net.ipv4.vs.expire_quiescent_template", "1"),
+      ],
+      Self::Nftables => &[
+        ("net.netfilter.nf_conntrack_tcp_timeout_established", "86400"),
+        ("net.netfilter.nf_conntrack_tcp_timeout_close_wait", "3600"),
+      ],
+    }
+  }
+}
+
+/// Kernel modules loaded for the kube-proxy mode
+const KUBE_PROXY_MODULES_PATH: &str = "/etc/modules-load.d/kube-proxy.conf";
+
+/// Sysctls set for the kube-proxy mode
+const KUBE_PROXY_SYSCTL_PATH: &str = "/etc/sysctl.d/99-kube-proxy.conf";
+
+/// How the directories mapped with --path-map are redirected to their writable directories
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PathMapMode {
+  /// The directory is replaced with a relative symlink to the writable directory, moving its existing files
+  #[default]
+  Symlink,
+  /// A systemd mount unit bind mounts the writable directory over the directory at boot
+  BindMount,
+}
+
+/// Input arguments for `provision-ami` command
+#[derive(Args, Debug, Default, Serialize, Deserialize)]
+pub struct ProvisionAmiInput {
+  /// The Kubernetes version of the kubelet installed on the AMI (i.e. - `1.29.3`)
+  ///
+  /// Recorded so the kubelet version can be determined when `kubelet --version` is unavailable
+  #[arg(long, env = "EKSNODE_KUBERNETES_VERSION")]
+  pub kubernetes_version: Option<String>,
+
+  /// The mode kube-proxy runs in on the node; `ipvs` and `nftables` load the kernel modules and set the conntrack
+  /// sysctls they require at boot
+  #[arg(long, env = "EKSNODE_KUBE_PROXY_MODE", value_enum, default_value_t)]
+  #[serde(default)]
+  pub kube_proxy_mode: KubeProxyMode,
+
+  /// How the directories mapped with --path-map are redirected to their writable directories
+  ///
+  /// For AMIs with an immutable root filesystem, where the files generated by eksnode are written elsewhere
+  #[arg(long, env = "EKSNODE_PATH_MAP_MODE", value_enum, default_value_t)]
+  #[serde(default)]
+  pub path_map_mode: PathMapMode,
+
+  /// Root directory of the filesystem to provision
+  #[arg(long, env = "EKSNODE_PROVISION_ROOT", default_value = "/")]
+  pub root: PathBuf,
+}
+
+impl ProvisionAmiInput {
+  /// Perform the AMI build time setup of directories, system configuration, and service units
+  pub async fn provision(&self) -> Result<()> {
+    provision(
+      &self.root,
+      self.kubernetes_version.as_deref(),
+      self.kube_proxy_mode,
+      utils::path_maps(),
+      self.path_map_mode,
+      true,
+    )
+    .await?;
+    info!("Provisioned AMI at {}", self.root.display());
+
+    Ok(())
+  }
+}
+
+async fn provision<P: AsRef<Path>>(
+  root: P,
+  kubernetes_version: Option<&str>,
+  kube_proxy_mode: KubeProxyMode,
+  path_maps: &[utils::PathMap],
+  path_map_mode: PathMapMode,
+  chown: bool,
+) -> Result<()> {
+  // Symlinked directories are redirected first so that the files provisioned below land in the writable directories
+  if path_map_mode == PathMapMode::Symlink {
+    for map in path_maps {
+      link_path_map(&root, map, path_map_mode).await?;
+    }
+  }
+
+  for (dir, mode) in DIRECTORIES {
+    let path = utils::rooted(&root, dir);
+    tokio::fs::create_dir_all(&path).await?;
+    tokio::fs::set_permissions(&path, Permissions::from_mode(*mode)).await?;
+  }
+
+  for (file, source, mode) in FILES {
+    let contents = match source {
+      Source::Asset(name) => Assets::load(name)?.into_owned(),
+      Source::Inline(contents) => contents.as_bytes().to_vec(),
+    };
+
+    let path = utils::rooted(&root, file);
+    if let Some(parent) = path.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+    utils::write_file(&contents, &path, Some(*mode), chown).await?;
+  }
+
+  if kube_proxy_mode != KubeProxyMode::Iptables {
+    let modules = kube_proxy_mode
+      .modules()
+      .iter()
+      .map(|module| format!("{module}\n"))
+      .collect::<String>();
+    utils::write_file(
+      modules.as_bytes(),
+      utils::rooted(&root, KUBE_PROXY_MODULES_PATH),
+      Some(0o644),
+      chown,
+    )
+    .await?;
+
+    let sysctls = kube_proxy_mode
+      .sysctls()
+      .iter()
+      .map(|(setting, value)| format!("{setting} = {value}\n"))
+      .collect::<String>();
+    utils::write_file(
+      sysctls.as_bytes(),
+      utils::rooted(&root, KUBE_PROXY_SYSCTL_PATH),
+      Some(0o644),
+      chown,
+    )
+    .await?;
+  }
+
+  if let Some(version) = kubernetes_version {
+    let version = utils::get_semver(version)?;
+    let contents = format!("Kubernetes v{version}\n");
+    utils::write_file(
+      contents.as_bytes(),
+      utils::rooted(&root, kubelet::KUBELET_VERSION_PATH),
+      Some(0o644),
+      chown,
+    )
+    .await?;
+  }
+
+  // Mount units are written last, into the systemd unit directory provisioned above
+  if path_map_mode == PathMapMode::BindMount {
+    for map in path_maps {
+      link_path_map(&root, map, path_map_mode).await?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Redirect the mapped directory under the root to its writable directory
+async fn link_path_map<P: AsRef<Path>>(root: P, map: &utils::PathMap, mode: PathMapMode) -> Result<()> {
+  let from = utils::rooted(&root, &map.from.to_string_lossy());
+  let to = utils::rooted(&root, &map.to.to_string_lossy());
+  tokio::fs::create_dir_all(&to).await?;
+
+  match mode {
+    PathMapMode::Symlink => {
+      match tokio::fs::symlink_metadata(&from).await {
+        Ok(metadata) if metadata.is_symlink() => tokio::fs::remove_file(&from).await?,
+        Ok(metadata) if metadata.is_dir() => {
+          let mut entries = tokio::fs::read_dir(&from).await?;
+          while let Some(entry) = entries.next_entry().await? {
+            tokio::fs::rename(entry.path(), to.join(entry.file_name()))
+              .await
+              .with_context(|| format!("Unable to move {} to {}", entry.path().display(), to.display()))?;
+          }
+          tokio::fs::remove_dir(&from).await?;
+        }
+        Ok(_) => bail!("Mapped path {} is not a directory", map.from.display()),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+      }
+      if let Some(parent) = from.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+      }
+      // Relative so that the link resolves both on the node and under the root being provisioned
+      tokio::fs::symlink(get_relative_target(&map.from, &map.to), &from).await?;
+    }
+    PathMapMode::BindMount => {
+      tokio::fs::create_dir_all(&from).await?;
+
+      let name = get_mount_unit_name(&map.from);
+      let unit = format!(
+        "[Unit]\nDescription=Bind mount {to} on {from}\nDefaultDependencies=no\nBefore=local-fs.target\n\n\
+         [Mount]\nWhat={to}\nWhere={from}\nType=none\nOptions=bind\n\n[Install]\nWantedBy=local-fs.target\n",
+        to = map.to.display(),
+        from = map.from.display(),
+      );
+      // Written in place rather than through the path mappings, since the unit is needed before they are mounted
+      let path = format!("/etc/systemd/system/{name}");
+      tokio::fs::write(utils::rooted(&root, &path), unit).await?;
+
+      // Enabled the same way as `systemctl enable`, which is not available when provisioning under a root directory
+      let wants = utils::rooted(&root, "/etc/systemd/system/local-fs.target.wants");
+      tokio::fs::create_dir_all(&wants).await?;
+      if tokio::fs::symlink_metadata(wants.join(&name)).await.is_err() {
+        tokio::fs::symlink(&path, wants.join(&name)).await?;
+      }
+    }
+  }
+  info!("Mapped {} to {}", map.from.display(), map.to.display());
+
+  Ok(())
+}
+
+/// Get the target of the symlink at `from` to `to` relative to the directory of the link
+fn get_relative_target(from: &Path, to: &Path) -> PathBuf {
+  let depth = from
+    .parent()
+    .map_or(0, |parent| parent.components().count().saturating_sub(1));
+  let mut target = PathBuf::new();
+  for _ in 0..depth {
+    target.push("..");
+  }
+
+  target.join(to.strip_prefix("/").unwrap_or(to))
+}
+
+/// Get the name of the systemd mount unit of the path, escaped the same as `systemd-escape --path --suffix=mount`
+fn get_mount_unit_name(path: &Path) -> String {
+  let path = path.to_string_lossy();
+  let mut name = String::new();
+  for (i, c) in path.trim_matches('/').char_indices() {
+    match c {
+      '/' => name.push('-'),
+      '.' if i == 0 => name.push_str("\\x2e"),
+      c if c.is_ascii_alphanumeric() || c == ':' || c == '_' || c == '.' => name.push(c),
+      c => {
+        let mut buffer = [0; 4];
+        for byte in c.encode_utf8(&mut buffer).bytes() {
+          name.push_str(&format!("\\x{byte:02x}"));
+        }
+      }
+    }
+  }
+
+  format!("{name}.mount")
+}
+
+#[cfg(test)]
+mod tests {
+  use walkdir::WalkDir;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn it_provisions_ami() {
+    let root = tempfile::tempdir().unwrap();
+    provision(
+      root.path(),
+      Some("1.30.6"),
+      KubeProxyMode::Iptables,
+      &[],
+      PathMapMode::Symlink,
+      false,
+    )
+    .await
+    .unwrap();
+
+    let paths = WalkDir::new(root.path())
+      .sort_by_file_name()
+      .into_iter()
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| entry.file_type().is_file())
+      .map(|entry| entry.path().strip_prefix(root.path()).unwrap().display().to_string())
+      .collect::<Vec<_>>();
+    insta::assert_debug_snapshot!(paths);
+
+    let version = std::fs::read_to_string(root.path().join("etc/eksnode/kubelet-version")).unwrap();
+    assert_eq!(version, "Kubernetes v1.30.6\n");
+  }
+
+  #[tokio::test]
+  async fn it_provisions_kube_proxy_ipvs() {
+    let root = tempfile::tempdir().unwrap();
+    provision(root.path(), None, KubeProxyMode::Ipvs, &[], PathMapMode::Symlink, false)
+      .await
+      .unwrap();
+
+    let modules = std::fs::read_to_string(utils::rooted(root.path(), KUBE_PROXY_MODULES_PATH)).unwrap();
+    assert_eq!(
+      modules,
+      "ip_vs\nip_vs_rr\nip_vs_wrr\nip_vs_lc\nip_vs_sh\nnf_conntrack\n"
+    );
+    let sysctls = std::fs::read_to_string(utils::rooted(root.path(), KUBE_PROXY_SYSCTL_PATH)).unwrap();
+    assert!(sysctls.contains("net.ipv4.vs.conntrack = 1\n"));
+    assert!(!sysctls.contains("nf_conntrack_max"));
+  }
+
+  #[tokio::test]
+  async fn it_provisions_path_maps() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("etc/kubernetes/pki")).unwrap();
+    std::fs::write(root.path().join("etc/kubernetes/pki/ca.crt"), "ca").unwrap();
+    let maps = [utils::parse_path_map("/etc/kubernetes=/var/lib/eksnode/etc/kubernetes").unwrap()];
+
+    provision(
+      root.path(),
+      None,
+      KubeProxyMode::Iptables,
+      &maps,
+      PathMapMode::Symlink,
+      false,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      std::fs::read_link(root.path().join("etc/kubernetes")).unwrap(),
+      PathBuf::from("../var/lib/eksnode/etc/kubernetes")
+    );
+    let writable = root.path().join("var/lib/eksnode/etc/kubernetes");
+    assert!(writable.join("pki/ca.crt").is_file());
+    assert!(writable.join("manifests").is_dir());
+
+    let root = tempfile::tempdir().unwrap();
+    provision(
+      root.path(),
+      None,
+      KubeProxyMode::Iptables,
+      &maps,
+      PathMapMode::BindMount,
+      false,
+    )
+    .await
+    .unwrap();
+    // The mount unit is written alongside the provisioned units, and the directories are provisioned in place
+    assert!(root.path().join("etc/systemd/system/kubelet.service").is_file());
+    assert!(root.path().join("etc/kubernetes/manifests").is_dir());
+    let unit = std::fs::read_to_string(root.path().join("etc/systemd/system/etc-kubernetes.mount")).unwrap();
+    assert!(unit.contains("What=/var/lib/eksnode/etc/kubernetes\nWhere=/etc/kubernetes\n"));
+    assert!(root
+      .path()
+      .join("etc/systemd/system/local-fs.target.wants/etc-kubernetes.mount")
+      .is_symlink());
+  }
+
+  #[test]
+  fn it_gets_mount_unit_name() {
+    assert_eq!(
+      get_mount_unit_name(Path::new("/etc/kubernetes")),
+      "etc-kubernetes.mount"
+    );
+    assert_eq!(
+      get_mount_unit_name(Path::new("/var/lib/eks-node/.cache")),
+      "var-lib-eks\\x2dnode-.cache.mount"
+    );
+  }
+}
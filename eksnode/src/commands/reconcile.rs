@@ -0,0 +1,265 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  os::unix::fs::PermissionsExt,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use anyhow::{bail, Result};
+use clap::Args;
+use tracing::{debug, error, info, warn};
+use walkdir::WalkDir;
+
+use crate::{aws, commands::join::JoinClusterInput, containerd, hybrid, nodeconfig, systemd, timing, utils};
+
+/// Generated file contents and mode, keyed by the absolute path on the host
+type RenderedFiles = BTreeMap<PathBuf, (Vec<u8>, u32)>;
+
+/// Input arguments for `reconcile` command
+#[derive(Args, Debug)]
+pub struct ReconcileInput {
+  /// Path to the NodeConfig, or the YAML or JSON config file containing the `join-cluster` inputs (i.e. -
+  /// `cluster_name: example`)
+  pub path: PathBuf,
+
+  /// Keep running, re-rendering when the config file changes and reapplying the generated files on drift
+  #[arg(long, env = "EKSNODE_RECONCILE_WATCH")]
+  pub watch: bool,
+
+  /// Seconds between checks of the config file and the generated files when watching
+  #[arg(long, env = "EKSNODE_RECONCILE_INTERVAL", default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+  pub interval: u64,
+}
+
+impl ReconcileInput {
+  /// Render the node configuration from the config file and reapply any generated files that have drifted
+  ///
+  /// Cluster and instance details are only discovered again when the config file changes
+  pub async fn reconcile(&self) -> Result<()> {
+    let mut config = tokio::fs::read(&self.path).await?;
+    let mut rendered = self.render(&config).await?;
+    apply(&rendered, Path::new("/")).await?;
+    systemd::notify_ready();
+    if !self.watch {
+      return Ok(());
+    }
+
+    loop {
+      systemd::notify_status("Watching for configuration drift");
+      systemd::sleep_with_watchdog(Duration::from_secs(self.interval)).await;
+
+      let current = match tokio::fs::read(&self.path).await {
+        Ok(current) => current,
+        Err(e) => {
+          warn!("Unable to read {}: {e}", self.path.display());
+          continue;
+        }
+      };
+      if current != config {
+        info!("{} changed; rendering node configuration", self.path.display());
+        match self.render(&current).await {
+          Ok(files) => rendered = files,
+          // The previously rendered files continue to be enforced until the config is fixed
+          Err(e) => {
+            error!("Unable to render node configuration: {e}");
+            continue;
+          }
+        }
+        config = current;
+      }
+
+      if let Err(e) = apply(&rendered, Path::new("/")).await {
+        error!("Unable to reapply node configuration: {e}");
+      }
+    }
+  }
+
+  /// Generate the node configuration files into a temporary directory using a dry run of `join-cluster`
+  async fn render(&self, config: &[u8]) -> Result<RenderedFiles> {
+    let spec = nodeconfig::get_spec(std::str::from_utf8(config)?)?;
+    let mut input: JoinClusterInput = serde_yaml::from_value(serde_yaml::Value::Mapping(spec))?;
+    let issues = input.validate_config();
+    if !issues.is_empty() {
+      bail!("{} is invalid:\n  {}", self.path.display(), issues.join("\n  "));
+    }
+
+    let dir = std::env::temp_dir().join(format!("eksnode-reconcile-{}", uuid::Uuid::new_v4()));
+    input.dry_run = true;
+    input.output_dir = Some(dir.to_owned());
+    // Permissions were verified when the node joined the cluster
+    input.skip_preflight = true;
+
+    // Settings preserved from the existing containerd config, such as the registry config path, are read from the
+    // render directory
+    let containerd_config = utils::rooted(&dir, containerd::CONTAINERD_CONFIG_PATH);
+    if let Some(parent) = containerd_config.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+    if let Err(e) = tokio::fs::copy(containerd::CONTAINERD_CONFIG_PATH, &containerd_config).await {
+      debug!("Unable to copy {}: {e}", containerd::CONTAINERD_CONFIG_PATH);
+    }
+
+    let result = match input.join_node_to_cluster().await {
+      Ok(_) => read_rendered_files(&dir),
+      Err(e) => Err(e),
+    };
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+      warn!("Unable to remove {}: {e}", dir.display());
+    }
+    if let hybrid::CredentialProvider::IamRolesAnywhere = input.credential_provider {
+      // Point subsequent AWS calls at the config on the host rather than the removed render directory
+      aws::set_node_credentials(aws::NodeCredentials {
+        region: input.region.to_owned(),
+        config_file: Some(PathBuf::from(hybrid::AWS_CONFIG_PATH)),
+        ..aws::NodeCredentials::default()
+      });
+    }
+
+    result
+  }
+}
+
+/// Read the files generated under the render directory
+fn read_rendered_files(dir: &Path) -> Result<RenderedFiles> {
+  let mut files = RenderedFiles::new();
+  for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+    if !entry.file_type().is_file() {
+      continue;
+    }
+
+    let path = Path::new("/").join(entry.path().strip_prefix(dir)?);
+    // The join timings describe the render rather than the node configuration
+    if path == Path::new(timing::JOIN_TIMINGS_PATH) {
+      continue;
+    }
+    let mode = entry.metadata()?.permissions().mode() & 0o7777;
+    files.insert(path, (std::fs::read(entry.path())?, mode));
+  }
+
+  Ok(files)
+}
+
+/// Get the generated files whose contents under the root directory are missing or differ
+fn get_drifted_files<'a>(files: &'a RenderedFiles, root: &Path) -> Vec<&'a Path> {
+  files
+    .iter()
+    .filter(|(path, (contents, _))| {
+      let path = utils::rooted(root, &path.to_string_lossy());
+      std::fs::read(path).ok().as_ref() != Some(contents)
+    })
+    .map(|(path, _)| path.as_path())
+    .collect()
+}
+
+/// Get the systemd units that are restarted to pick up the changed files, in the order they are restarted
+///
+/// containerd and sandbox-image own the files that reference them; all other generated files are read by kubelet
+fn get_units_to_restart(paths: &[&Path]) -> Vec<&'static str> {
+  let units = paths
+    .iter()
+    .map(|path| {
+      let path = path.to_string_lossy();
+      if path == containerd::SANDBOX_IMAGE_SERVICE_PATH {
+        "sandbox-image"
+      } else if path.contains("containerd") {
+        "containerd"
+      } else {
+        "kubelet"
+      }
+    })
+    .collect::<BTreeSet<_>>();
+
+  ["containerd", "sandbox-image", "kubelet"]
+    .into_iter()
+    .filter(|unit| units.contains(unit))
+    .collect()
+}
+
+/// Write the drifted files under the root directory and restart the units that use them
+async fn apply(files: &RenderedFiles, root: &Path) -> Result<()> {
+  let drifted = get_drifted_files(files, root);
+  if drifted.is_empty() {
+    debug!("Node configuration is up to date");
+    return Ok(());
+  }
+
+  // Ownership is only changed when configuring the host
+  let chown = root == Path::new("/");
+  for path in &drifted {
+    info!("Reapplying {}", path.display());
+    let (contents, mode) = &files[*path];
+    let dest = utils::rooted(root, &path.to_string_lossy());
+    if let Some(parent) = dest.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+    utils::write_file(contents, &dest, Some(*mode), chown).await?;
+    // The mode is only applied when the file is created
+    tokio::fs::set_permissions(&dest, std::fs::Permissions::from_mode(*mode)).await?;
+  }
+
+  if drifted.iter().any(|path| path.starts_with("/etc/systemd")) {
+    systemd::systemctl(vec!["daemon-reload"])?;
+  }
+  for unit in get_units_to_restart(&drifted) {
+    info!("Restarting {unit}");
+    systemd::systemctl(vec!["restart", unit])?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_gets_drifted_files() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("etc/containerd")).unwrap();
+    std::fs::write(root.path().join("etc/containerd/config.toml"), "version = 2\n").unwrap();
+    std::fs::create_dir_all(root.path().join("etc/kubernetes/kubelet")).unwrap();
+    std::fs::write(root.path().join("etc/kubernetes/kubelet/kubelet-config.json"), "{}").unwrap();
+
+    let files = RenderedFiles::from([
+      (
+        PathBuf::from(containerd::CONTAINERD_CONFIG_PATH),
+        (b"version = 2\n".to_vec(), 0o644),
+      ),
+      (
+        PathBuf::from("/etc/kubernetes/kubelet/kubelet-config.json"),
+        (b"{\"maxPods\":58}".to_vec(), 0o644),
+      ),
+      (
+        PathBuf::from(containerd::SANDBOX_IMAGE_SERVICE_PATH),
+        (b"[Unit]\n".to_vec(), 0o644),
+      ),
+    ]);
+
+    assert_eq!(
+      get_drifted_files(&files, root.path()),
+      vec![
+        Path::new("/etc/kubernetes/kubelet/kubelet-config.json"),
+        Path::new(containerd::SANDBOX_IMAGE_SERVICE_PATH),
+      ]
+    );
+  }
+
+  #[test]
+  fn it_gets_units_to_restart() {
+    let paths = [
+      Path::new("/etc/kubernetes/kubelet/kubelet-config.json"),
+      Path::new(containerd::DAEMON_SERVICE_DROPIN_PATH),
+      Path::new(containerd::SANDBOX_IMAGE_SERVICE_PATH),
+      Path::new(containerd::CONTAINERD_CONFIG_PATH),
+    ];
+
+    assert_eq!(
+      get_units_to_restart(&paths),
+      vec!["containerd", "sandbox-image", "kubelet"]
+    );
+    assert_eq!(
+      get_units_to_restart(&[Path::new(containerd::CONTAINERD_CONFIG_PATH)]),
+      vec!["containerd"]
+    );
+  }
+}
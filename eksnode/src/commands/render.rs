@@ -0,0 +1,291 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::{Args, ValueEnum};
+use tracing::info;
+
+use crate::{containerd, utils, Assets};
+
+/// Frequency directives of logrotate, of which only one applies
+const LOGROTATE_FREQUENCIES: &[&str] = &["hourly", "daily", "weekly", "monthly", "yearly"];
+
+/// Templates that can be rendered, and the values they accept through `--set`
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum Template {
+  /// sandbox-image.service that pulls the pause image; requires `pause_image=<image>` and accepts
+  /// `<Section>.<Key>=<value>`
+  SandboxImageService,
+  /// /etc/sysctl.conf; accepts `<setting>=<value>` (i.e. - `vm.max_map_count=262144`)
+  Sysctl,
+  /// /etc/logrotate.conf; accepts global directives (i.e. - `rotate=7`, `daily`, `compress`)
+  Logrotate,
+  /// containerd.service; accepts `<Section>.<Key>=<value>` (i.e. - `Service.LimitNOFILE=1048576`)
+  ContainerdService,
+  /// kubelet.service; accepts `<Section>.<Key>=<value>`
+  KubeletService,
+  /// runtime.slice; accepts `<Section>.<Key>=<value>`
+  RuntimeSlice,
+}
+
+/// Input arguments for `render` command
+#[derive(Args, Debug)]
+pub struct RenderInput {
+  /// Template to render
+  #[arg(value_enum)]
+  pub template: Template,
+
+  /// Value applied to the template (i.e. - `pause_image=<image>`, `Service.LimitNOFILE=1048576`)
+  ///
+  /// May be repeated; a setting that already exists in the template is replaced, otherwise it is added
+  #[arg(long = "set", env = "EKSNODE_RENDER_SET", value_parser = parse_value)]
+  pub values: Vec<(String, String)>,
+
+  /// File where the rendered template is written; written to stdout when not provided
+  #[arg(long, env = "EKSNODE_RENDER_OUTPUT", short)]
+  pub output: Option<PathBuf>,
+}
+
+impl RenderInput {
+  /// Render the template with the values provided for use in custom AMI variants
+  pub async fn render(&self) -> Result<()> {
+    let contents = render(self.template, &self.values)?;
+
+    match &self.output {
+      Some(path) => {
+        if let Some(parent) = path.parent() {
+          tokio::fs::create_dir_all(parent).await?;
+        }
+        utils::write_file(contents.as_bytes(), path, Some(0o644), false).await?;
+        info!("Rendered {:?} to {}", self.template, path.display());
+      }
+      None => print!("{contents}"),
+    }
+
+    Ok(())
+  }
+}
+
+/// Parse a template value (i.e. - `rotate=7`); the value is empty for directives without one (i.e. - `compress`)
+fn parse_value(s: &str) -> Result<(String, String)> {
+  let (key, value) = s.split_once('=').unwrap_or((s, ""));
+  if key.trim().is_empty() {
+    bail!("Invalid value {s}; expected <key>=<value>");
+  }
+
+  Ok((key.trim().to_owned(), value.trim().to_owned()))
+}
+
+fn render(template: Template, values: &[(String, String)]) -> Result<String> {
+  match template {
+    Template::SandboxImageService => {
+      let (pause_image, values): (Vec<_>, Vec<_>) = values.iter().cloned().partition(|(k, _)| k == "pause_image");
+      let Some((_, pause_image)) = pause_image.last() else {
+        bail!("The sandbox-image-service template requires --set pause_image=<image>");
+      };
+      set_unit_values(&containerd::get_sandbox_image_service(pause_image), &values)
+    }
+    Template::Sysctl => Ok(set_sysctl_values(&get_asset("provision/sysctl.conf")?, values)),
+    Template::Logrotate => Ok(set_logrotate_values(&get_asset("provision/logrotate.conf")?, values)),
+    Template::ContainerdService => set_unit_values(&get_asset("provision/containerd.service")?, values),
+    Template::KubeletService => set_unit_values(&get_asset("provision/kubelet.service")?, values),
+    Template::RuntimeSlice => set_unit_values(&get_asset("provision/runtime.slice")?, values),
+  }
+}
+
+fn get_asset(name: &str) -> Result<String> {
+  Ok(String::from_utf8(Assets::load(name)?.into_owned())?)
+}
+
+/// Join the lines, preserving the trailing newline of the template
+fn join_lines(lines: &[String]) -> String {
+  let mut contents = lines.join("\n");
+  contents.push('\n');
+  contents
+}
+
+/// Index after the last non-empty line in the range, where a new line is inserted
+fn get_insert_index(lines: &[String], start: usize, end: usize) -> usize {
+  (start..end)
+    .rev()
+    .find(|&i| !lines[i].trim().is_empty())
+    .map_or(start, |i| i + 1)
+}
+
+/// Set `<Section>.<Key>=<value>` settings in a systemd unit
+///
+/// The first assignment of the key in the section is replaced, along with its continuation lines
+fn set_unit_values(contents: &str, values: &[(String, String)]) -> Result<String> {
+  let mut lines = contents.lines().map(String::from).collect::<Vec<_>>();
+
+  for (setting, value) in values {
+    let Some((section, key)) = setting.split_once('.') else {
+      bail!("Invalid unit setting {setting}; expected <Section>.<Key> (i.e. - Service.LimitNOFILE)");
+    };
+    let line = format!("{key}={value}");
+    let header = format!("[{section}]");
+
+    let Some(start) = lines.iter().position(|l| l.trim() == header) else {
+      if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+        lines.push(String::new());
+      }
+      lines.extend([header, line]);
+      continue;
+    };
+    let end = (start + 1..lines.len())
+      .find(|&i| lines[i].trim_start().starts_with('['))
+      .unwrap_or(lines.len());
+
+    let existing = (start + 1..end).find(|&i| {
+      lines[i]
+        .split_once('=')
+        .is_some_and(|(k, _)| k.trim() == key && !k.trim_start().starts_with(['#', ';']))
+    });
+    match existing {
+      Some(i) => {
+        let continued = (i..end).take_while(|&j| lines[j].trim_end().ends_with('\\')).count();
+        lines.splice(i..=(i + continued).min(end - 1), [line]);
+      }
+      None => {
+        let index = get_insert_index(&lines, start + 1, end);
+        lines.insert(index, line);
+      }
+    }
+  }
+
+  Ok(join_lines(&lines))
+}
+
+/// Set `<setting>=<value>` sysctl settings, replacing the existing setting or adding it to the end
+fn set_sysctl_values(contents: &str, values: &[(String, String)]) -> String {
+  let mut lines = contents.lines().map(String::from).collect::<Vec<_>>();
+
+  for (key, value) in values {
+    let line = format!("{key}={value}");
+    let existing = lines
+      .iter()
+      .position(|l| !l.trim_start().starts_with(['#', ';']) && l.split_once('=').is_some_and(|(k, _)| k.trim() == key));
+    match existing {
+      Some(i) => lines[i] = line,
+      None => lines.push(line),
+    }
+  }
+
+  join_lines(&lines)
+}
+
+/// Set global logrotate directives, which precede the first `{ ... }` block
+///
+/// A directive replaces its existing or commented out (i.e. - `#compress`) line; a frequency directive replaces the
+/// existing frequency
+fn set_logrotate_values(contents: &str, values: &[(String, String)]) -> String {
+  let mut lines = contents.lines().map(String::from).collect::<Vec<_>>();
+
+  for (key, value) in values {
+    let line = match value.is_empty() {
+      true => key.to_owned(),
+      false => format!("{key} {value}"),
+    };
+    let end = lines.iter().position(|l| l.contains('{')).unwrap_or(lines.len());
+    let matches = |directive: Option<&str>| match directive {
+      Some(d) if LOGROTATE_FREQUENCIES.contains(&key.as_str()) => LOGROTATE_FREQUENCIES.contains(&d),
+      Some(d) => d == key,
+      None => false,
+    };
+
+    let existing = (0..end)
+      .find(|&i| matches(lines[i].split_whitespace().next()))
+      .or_else(|| {
+        (0..end).find(|&i| {
+          // Disabled directives are commented without a space (i.e. - `#compress`), unlike descriptions
+          let disabled = lines[i].trim_start().strip_prefix('#');
+          matches(
+            disabled
+              .filter(|d| !d.starts_with(char::is_whitespace))
+              .and_then(|d| d.split_whitespace().next()),
+          )
+        })
+      });
+    match existing {
+      Some(i) => lines[i] = line,
+      None => {
+        let index = get_insert_index(&lines, 0, end);
+        lines.insert(index, line);
+      }
+    }
+  }
+
+  join_lines(&lines)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn values(values: &[&str]) -> Vec<(String, String)> {
+    values.iter().map(|v| parse_value(v).unwrap()).collect()
+  }
+
+  #[test]
+  fn it_renders_sandbox_image_service() {
+    let rendered = render(
+      Template::SandboxImageService,
+      &values(&["pause_image=registry.example.com/pause:3.9", "Service.RestartSec=10"]),
+    )
+    .unwrap();
+
+    assert!(rendered.contains("--image registry.example.com/pause:3.9 --namespace k8s.io\n"));
+    assert!(rendered.contains("\nRestartSec=10\n"));
+    assert!(render(Template::SandboxImageService, &[]).is_err());
+  }
+
+  #[test]
+  fn it_sets_unit_values() {
+    let contents =
+      "[Unit]\nDescription=Example\n\n[Service]\nExecStart=/usr/bin/example \\\n    --flag\nRestart=always\n";
+
+    let rendered = set_unit_values(
+      contents,
+      &values(&[
+        "Service.ExecStart=/usr/bin/example --other",
+        "Service.LimitNOFILE=1048576",
+        "Install.WantedBy=multi-user.target",
+      ]),
+    )
+    .unwrap();
+    assert_eq!(
+      rendered,
+      "[Unit]\nDescription=Example\n\n[Service]\nExecStart=/usr/bin/example --other\nRestart=always\n\
+       LimitNOFILE=1048576\n\n[Install]\nWantedBy=multi-user.target\n"
+    );
+    assert!(set_unit_values(contents, &values(&["LimitNOFILE=1048576"])).is_err());
+  }
+
+  #[test]
+  fn it_sets_sysctl_values() {
+    let contents = "# comment\nvm.max_map_count=524288\nfs.inotify.max_user_watches = 524288\n";
+
+    assert_eq!(
+      set_sysctl_values(
+        contents,
+        &values(&["fs.inotify.max_user_watches=1048576", "kernel.pid_max=4194304"])
+      ),
+      "# comment\nvm.max_map_count=524288\nfs.inotify.max_user_watches=1048576\nkernel.pid_max=4194304\n"
+    );
+  }
+
+  #[test]
+  fn it_sets_logrotate_values() {
+    let rendered = set_logrotate_values(
+      &get_asset("provision/logrotate.conf").unwrap(),
+      &values(&["daily", "rotate=7", "compress", "maxsize=100M"]),
+    );
+
+    assert!(rendered.contains("# rotate log files weekly\ndaily\n"));
+    assert!(rendered.contains("\nrotate 7\n"));
+    assert!(rendered.contains("\ncompress\n"));
+    assert!(rendered.contains("configured here.\nmaxsize 100M\n"));
+    // Directives within blocks are left unchanged
+    assert!(rendered.contains("    monthly\n"));
+    assert!(rendered.contains("    rotate 1\n"));
+  }
+}
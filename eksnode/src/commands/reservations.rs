@@ -0,0 +1,338 @@
+use std::{
+  collections::BTreeMap,
+  net::{IpAddr, Ipv4Addr},
+  path::PathBuf,
+};
+
+use anyhow::{anyhow, Result};
+use clap::{Args, ValueEnum};
+use semver::Version;
+use serde::Serialize;
+use tabled::{Table, Tabled};
+
+use crate::{
+  commands::join::{Cni, JoinClusterInput},
+  ec2, kubelet, profile, utils,
+};
+
+/// Format the reservations are output in
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReservationsOutput {
+  #[default]
+  Text,
+  Json,
+}
+
+/// Input arguments for `reservations` command
+#[derive(Args, Debug, Default)]
+pub struct ReservationsInput {
+  /// The instance type to compute the reservations for
+  #[arg(short, long, env = "EKSNODE_INSTANCE_TYPE")]
+  pub instance_type: String,
+
+  /// The Kubernetes version of the kubelet (default: the latest version supported by eksnode)
+  #[arg(long, env = "EKSNODE_KUBERNETES_VERSION")]
+  pub kubernetes_version: Option<String>,
+
+  /// The CNI plugin used by the cluster; `external` does not derive max pods from the instance ENI limits
+  #[arg(long, env = "EKSNODE_CNI", value_enum, default_value_t)]
+  pub cni: Cni,
+
+  /// VPC CNI security groups for pods (ENABLE_POD_ENI) is enabled on the cluster
+  #[arg(long, env = "EKSNODE_SGPP_ENABLED")]
+  pub sgpp_enabled: bool,
+
+  /// Overrides the maximum number of pods, which defaults to the ENI based limit of the instance type
+  #[arg(long, env = "EKSNODE_MAX_PODS")]
+  pub max_pods: Option<i32>,
+
+  /// Maximum number of pods per CPU core; the lower of this and --max-pods is used
+  #[arg(long, env = "EKSNODE_PODS_PER_CORE")]
+  pub pods_per_core: Option<i32>,
+
+  /// Tuning profile applied to the kubelet reservations and eviction thresholds
+  #[arg(long, env = "EKSNODE_PROFILE", value_enum, default_value_t)]
+  pub profile: profile::ProfileName,
+
+  /// YAML file of tuning profiles that replace the embedded profiles of the same name
+  #[arg(long, env = "EKSNODE_PROFILE_FILE")]
+  pub profile_file: Option<PathBuf>,
+
+  /// Memory of the instance in MiB, used to estimate the allocatable memory
+  #[arg(long, env = "EKSNODE_MEMORY_MIB")]
+  pub memory_mib: Option<i64>,
+
+  /// Size of the root volume in GiB, used to estimate the allocatable ephemeral storage
+  #[arg(long, env = "EKSNODE_EPHEMERAL_STORAGE_GIB")]
+  pub ephemeral_storage_gib: Option<i64>,
+
+  /// Format of the output
+  #[arg(long, env = "EKSNODE_RESERVATIONS_OUTPUT", value_enum, default_value_t)]
+  pub output: ReservationsOutput,
+}
+
+/// Reservation of a single resource and the allocatable amount left for pods
+#[derive(Debug, PartialEq, Eq, Serialize, Tabled)]
+pub struct Reservation {
+  pub resource: String,
+  #[tabled(display_with = "display_option")]
+  pub capacity: Option<String>,
+  pub kube_reserved: String,
+  #[tabled(display_with = "display_option")]
+  pub system_reserved: Option<String>,
+  #[tabled(display_with = "display_option")]
+  pub eviction_hard: Option<String>,
+  /// Capacity less the kubeReserved, systemReserved, and hard eviction threshold
+  #[tabled(display_with = "display_option")]
+  pub allocatable: Option<String>,
+}
+
+fn display_option(value: &Option<String>) -> String {
+  value.clone().unwrap_or_else(|| "-".to_owned())
+}
+
+/// Reservations computed for an instance type, as they are written to the kubelet config by `join-cluster`
+#[derive(Debug, Serialize)]
+pub struct ReservationReport {
+  pub instance_type: String,
+  pub vcpus: i32,
+  pub max_pods: i32,
+  pub kube_reserved: BTreeMap<String, String>,
+  pub system_reserved: BTreeMap<String, String>,
+  pub eviction_hard: BTreeMap<String, String>,
+  pub resources: Vec<Reservation>,
+}
+
+/// Resources reported, with their unit and the eviction signal that applies to them
+const RESOURCES: [(&str, &str, Option<&str>); 3] = [
+  ("cpu", "m", None),
+  ("memory", "Mi", Some("memory.available")),
+  ("ephemeral-storage", "Mi", Some("nodefs.available")),
+];
+
+impl ReservationsInput {
+  /// Print the kubeReserved, eviction thresholds, and allocatable estimates of the instance type
+  pub async fn report(&self) -> Result<()> {
+    let report = self.get_report().await?;
+
+    match self.output {
+      ReservationsOutput::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+      ReservationsOutput::Text => {
+        println!("Instance type: {}", report.instance_type);
+        println!("vCPUs: {}", report.vcpus);
+        println!("Max pods: {}", report.max_pods);
+        let eviction_hard = report.eviction_hard.iter().map(|(k, v)| format!("{k}<{v}"));
+        println!("Eviction hard: {}", eviction_hard.collect::<Vec<_>>().join(", "));
+        println!("{}", Table::new(&report.resources));
+      }
+    }
+
+    Ok(())
+  }
+
+  async fn get_report(&self) -> Result<ReservationReport> {
+    let instance = ec2::get_instance(&self.instance_type)?
+      .ok_or_else(|| anyhow!("Instance type {} is not supported or invalid", self.instance_type))?;
+    let vcpus = instance.default_vcpus;
+
+    // Built the same way as `join-cluster` so the report follows any change to the formulas or profiles
+    let node = JoinClusterInput {
+      cni: self.cni,
+      sgpp_enabled: self.sgpp_enabled,
+      max_pods: self.max_pods,
+      pods_per_core: self.pods_per_core,
+      profile: self.profile,
+      profile_file: self.profile_file.to_owned(),
+      ..JoinClusterInput::default()
+    };
+    let eni_max_pods = match (self.cni, self.max_pods) {
+      (Cni::VpcCni, None) => Some(node.get_max_pods(&self.instance_type).await?),
+      _ => None,
+    };
+    let max_pods = node.get_effective_max_pods(eni_max_pods, vcpus);
+
+    let kubernetes_version = self.get_kubernetes_version()?;
+    let mut config = node.get_kubelet_config(
+      IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+      max_pods,
+      vcpus,
+      &kubernetes_version,
+      "",
+      "",
+    )?;
+    config.apply_profile(&profile::get_profile(self.profile, self.profile_file.as_deref())?);
+    let kube_reserved = config.kube_reserved().cloned().unwrap_or_default();
+    let system_reserved = config.system_reserved().cloned().unwrap_or_default();
+    let eviction_hard = config.eviction_hard().cloned().unwrap_or_default();
+
+    let capacities = [
+      Some(i64::from(vcpus) * 1000),
+      self.memory_mib,
+      self.ephemeral_storage_gib.map(|gib| gib * 1024),
+    ];
+    let resources = RESOURCES
+      .iter()
+      .zip(capacities)
+      .map(|((name, unit, signal), capacity)| {
+        let reserved = kube_reserved.get(*name).cloned().unwrap_or_else(|| "0".to_owned());
+        let system = system_reserved.get(*name).cloned();
+        let threshold = signal.and_then(|s| eviction_hard.get(s)).cloned();
+        let allocatable = capacity.and_then(|capacity| {
+          let optional = |quantity: &Option<String>| match quantity {
+            Some(quantity) => parse_quantity(name, quantity, capacity),
+            None => Some(0),
+          };
+          let reserved = parse_quantity(name, &reserved, capacity)? + optional(&system)? + optional(&threshold)?;
+          Some(format!("{}{unit}", (capacity - reserved).max(0)))
+        });
+
+        Reservation {
+          resource: name.to_string(),
+          capacity: capacity.map(|capacity| format!("{capacity}{unit}")),
+          kube_reserved: reserved,
+          system_reserved: system,
+          eviction_hard: threshold,
+          allocatable,
+        }
+      })
+      .collect();
+
+    Ok(ReservationReport {
+      instance_type: self.instance_type.to_owned(),
+      vcpus,
+      max_pods,
+      kube_reserved,
+      system_reserved,
+      eviction_hard,
+      resources,
+    })
+  }
+
+  /// Get the Kubernetes version, defaulting to the latest version supported by eksnode
+  fn get_kubernetes_version(&self) -> Result<Version> {
+    if let Some(version) = &self.kubernetes_version {
+      return utils::get_semver(version);
+    }
+
+    kubelet::get_kubernetes_versions()?
+      .values()
+      .map(|version| utils::get_semver(&version.kubernetes_version))
+      .collect::<Result<Vec<_>>>()?
+      .into_iter()
+      .max()
+      .ok_or_else(|| anyhow!("No Kubernetes versions found in kubernetes-versions.yaml"))
+  }
+}
+
+/// Convert a quantity to millicores for CPU or MiB otherwise; percentages are of the capacity
+fn parse_quantity(resource: &str, quantity: &str, capacity: i64) -> Option<i64> {
+  if let Some(percent) = quantity.strip_suffix('%') {
+    return Some((capacity as f64 * percent.parse::<f64>().ok()? / 100.0).round() as i64);
+  }
+  if resource == "cpu" {
+    return match quantity.strip_suffix('m') {
+      Some(millicores) => millicores.parse().ok(),
+      None => Some((quantity.parse::<f64>().ok()? * 1000.0).round() as i64),
+    };
+  }
+
+  let (value, mebibytes) = [
+    ("Ki", 1.0 / 1024.0),
+    ("Mi", 1.0),
+    ("Gi", 1024.0),
+    ("Ti", 1024.0 * 1024.0),
+  ]
+  .into_iter()
+  .find_map(|(suffix, factor)| quantity.strip_suffix(suffix).map(|value| (value, factor)))
+  .unwrap_or((quantity, 1.0 / (1024.0 * 1024.0)));
+
+  Some((value.parse::<f64>().ok()? * mebibytes).round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_parses_quantities() {
+    assert_eq!(parse_quantity("cpu", "70m", 2000), Some(70));
+    assert_eq!(parse_quantity("cpu", "1.5", 2000), Some(1500));
+    assert_eq!(parse_quantity("memory", "100Mi", 8192), Some(100));
+    assert_eq!(parse_quantity("memory", "3Gi", 8192), Some(3072));
+    assert_eq!(parse_quantity("memory", "1048576", 8192), Some(1));
+    assert_eq!(parse_quantity("ephemeral-storage", "10%", 20480), Some(2048));
+    assert_eq!(parse_quantity("memory", "lots", 8192), None);
+  }
+
+  #[tokio::test]
+  async fn it_computes_reservations() {
+    let input = ReservationsInput {
+      instance_type: "m5.large".to_owned(),
+      memory_mib: Some(8192),
+      ephemeral_storage_gib: Some(20),
+      ..ReservationsInput::default()
+    };
+    let report = input.get_report().await.unwrap();
+
+    assert_eq!((report.vcpus, report.max_pods), (2, 29));
+    assert_eq!(
+      report.resources,
+      vec![
+        Reservation {
+          resource: "cpu".to_owned(),
+          capacity: Some("2000m".to_owned()),
+          kube_reserved: "70m".to_owned(),
+          system_reserved: None,
+          eviction_hard: None,
+          allocatable: Some("1930m".to_owned()),
+        },
+        Reservation {
+          resource: "memory".to_owned(),
+          capacity: Some("8192Mi".to_owned()),
+          kube_reserved: "574Mi".to_owned(),
+          system_reserved: None,
+          eviction_hard: Some("100Mi".to_owned()),
+          allocatable: Some("7518Mi".to_owned()),
+        },
+        Reservation {
+          resource: "ephemeral-storage".to_owned(),
+          capacity: Some("20480Mi".to_owned()),
+          kube_reserved: "3Gi".to_owned(),
+          system_reserved: None,
+          eviction_hard: Some("10%".to_owned()),
+          allocatable: Some("15360Mi".to_owned()),
+        },
+      ]
+    );
+
+    let input = ReservationsInput {
+      max_pods: Some(110),
+      memory_mib: None,
+      ..input
+    };
+    let report = input.get_report().await.unwrap();
+    assert_eq!(report.kube_reserved["memory"], "1465Mi");
+    assert_eq!(report.resources[1].allocatable, None);
+  }
+
+  #[tokio::test]
+  async fn it_computes_reservations_with_join_options() {
+    let input = ReservationsInput {
+      instance_type: "m5.large".to_owned(),
+      cni: Cni::External,
+      pods_per_core: Some(20),
+      profile: profile::ProfileName::MemoryOptimized,
+      memory_mib: Some(8192),
+      ..ReservationsInput::default()
+    };
+    let report = input.get_report().await.unwrap();
+
+    // The external CNI default of 110 is capped by the pods per core
+    assert_eq!(report.max_pods, 40);
+    assert_eq!(report.kube_reserved["memory"], "695Mi");
+    assert_eq!(report.system_reserved["memory"], "1Gi");
+    assert_eq!(report.eviction_hard["memory.available"], "500Mi");
+    // 8192Mi less 695Mi kubeReserved, 1024Mi systemReserved, and the 500Mi threshold
+    assert_eq!(report.resources[1].allocatable, Some("5973Mi".to_owned()));
+  }
+}
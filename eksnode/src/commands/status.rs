@@ -0,0 +1,45 @@
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+
+use crate::{aws, ec2};
+
+/// Format the status is output in
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum StatusOutput {
+  #[default]
+  Text,
+  Json,
+}
+
+/// Input arguments for `status` command
+#[derive(Args, Debug)]
+pub struct StatusInput {
+  /// Format of the output
+  #[arg(long, env = "EKSNODE_STATUS_OUTPUT", value_enum, default_value_t)]
+  pub output: StatusOutput,
+
+  /// Source of the node name, matching the --node-name-strategy the node was joined with
+  #[arg(long, env = "EKSNODE_NODE_NAME_STRATEGY", value_enum, default_value_t)]
+  pub node_name_strategy: ec2::NodeNameStrategy,
+}
+
+impl StatusInput {
+  pub async fn status(&self) -> Result<()> {
+    let imds = ec2::get_imds_data().await?;
+    let client = aws::get_ec2_client().await;
+    let identity = ec2::get_node_identity(&imds, &client, self.node_name_strategy).await?;
+
+    match self.output {
+      StatusOutput::Json => println!("{}", serde_json::to_string_pretty(&identity)?),
+      StatusOutput::Text => {
+        println!("Node name: {}", identity.node_name);
+        println!("Provider ID: {}", identity.provider_id);
+        println!("Instance ID: {}", identity.instance_id);
+        println!("Availability zone: {}", identity.availability_zone);
+        println!("Region: {}", identity.region);
+      }
+    }
+
+    Ok(())
+  }
+}
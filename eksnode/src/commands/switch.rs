@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::Args;
+use tracing::info;
+
+use crate::{kubelet, systemd, utils};
+
+/// Input arguments for `switch-cluster` command
+#[derive(Args, Debug)]
+pub struct SwitchClusterInput {
+  /// Kubeconfig context to switch kubelet to; the name of a cluster added with `join-cluster --additional-cluster`,
+  /// or `kubelet` for the cluster the node was joined to
+  pub context: String,
+
+  /// Kubeconfig used by kubelet
+  #[arg(long, env = "EKSNODE_SWITCH_KUBECONFIG", default_value = kubelet::KUBECONFIG_PATH)]
+  pub kubeconfig: PathBuf,
+
+  /// Update the kubeconfig and kubelet config without restarting kubelet
+  #[arg(long, env = "EKSNODE_SWITCH_NO_RESTART")]
+  pub no_restart: bool,
+}
+
+impl SwitchClusterInput {
+  /// Move kubelet to another cluster in its kubeconfig for blue/green cluster migrations
+  ///
+  /// The kubelet config is pointed at the certificate authority of the cluster so that the API server of the new
+  /// cluster can reach the kubelet server
+  pub async fn switch(&self) -> Result<()> {
+    let mut kubeconfig = kubelet::KubeConfig::read(&self.kubeconfig)?;
+    let certificate_authority = kubeconfig.use_context(&self.context)?;
+
+    if let Some(certificate_authority) = certificate_authority {
+      let mut kubelet_config = kubelet::KubeletConfiguration::read(kubelet::KUBELET_CONFIG_PATH)?;
+      kubelet_config.set_client_ca_file(&certificate_authority.to_string_lossy());
+      let tmp = get_tmp_path(Path::new(kubelet::KUBELET_CONFIG_PATH));
+      kubelet_config.write(&tmp, true).await?;
+      std::fs::rename(utils::map_path(&tmp), utils::map_path(kubelet::KUBELET_CONFIG_PATH))?;
+    }
+
+    // Renamed into place so that kubelet never reads a partially written kubeconfig
+    let tmp = get_tmp_path(&self.kubeconfig);
+    kubeconfig.write(&tmp, true).await?;
+    std::fs::rename(utils::map_path(&tmp), utils::map_path(&self.kubeconfig))?;
+    info!("Switched kubelet to context {}", self.context);
+
+    if !self.no_restart {
+      info!("Restarting kubelet");
+      systemd::systemctl(vec!["restart", "kubelet"])?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Temporary file in the same directory, so that it can be atomically renamed over the path
+fn get_tmp_path(path: &Path) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_os_string();
+  name.push(".tmp");
+  path.with_file_name(name)
+}
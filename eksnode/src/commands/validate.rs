@@ -3,14 +3,26 @@ use std::os::linux::fs::MetadataExt;
 // For development on macOS system
 #[cfg(target_os = "macos")]
 use std::os::macos::fs::MetadataExt;
-use std::{fs, os::unix::fs::PermissionsExt};
+use std::{
+  fs,
+  os::unix::fs::PermissionsExt,
+  path::{Path, PathBuf},
+  time::SystemTime,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Result};
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
 use clap::Args;
 use serde::{Deserialize, Serialize};
 use tracing::{error, info};
 
-use crate::Assets;
+use crate::{
+  cis,
+  commands::join::JoinClusterInput,
+  containerd, ec2, fips, kubelet, nodeconfig, preflight,
+  securityhub::{self, Finding, Severity},
+  state, utils, Assets,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Metadata<'a> {
@@ -31,27 +43,338 @@ struct Validate<'a> {
 }
 
 #[derive(Args, Debug)]
-pub struct ValidateNodeInput {}
+pub struct ValidateNodeInput {
+  /// Check that containerd and its CRI plugin are running and healthy
+  ///
+  /// Verifies the snapshotter and runtime handlers configured in the containerd config are available
+  #[arg(long, env = "EKSNODE_CHECK_CRI")]
+  pub check_cri: bool,
+
+  /// Check that kubelet reports itself healthy and its metrics show no anomalies
+  ///
+  /// Queries the local healthz endpoint and the PLEG relist latency and container runtime operation errors from
+  /// /metrics, authenticated with the client certificate of the node
+  #[arg(long, env = "EKSNODE_CHECK_KUBELET")]
+  pub check_kubelet: bool,
+
+  /// Check that the cached images match the digests recorded in /etc/eks/cached-images.lock.json
+  #[arg(long, env = "EKSNODE_CHECK_IMAGE_LOCK")]
+  pub check_image_lock: bool,
+
+  /// Check the offset of the clock from Amazon Time Sync
+  #[arg(long, env = "EKSNODE_CHECK_CLOCK_SKEW")]
+  pub check_clock_skew: bool,
+
+  /// Maximum offset in milliseconds of the clock from Amazon Time Sync
+  #[arg(
+    long,
+    env = "EKSNODE_MAX_CLOCK_SKEW_MS",
+    default_value_t = preflight::DEFAULT_MAX_CLOCK_SKEW_MS,
+    requires = "check_clock_skew"
+  )]
+  pub max_clock_skew_ms: u64,
+
+  /// Check that the node was joined with --enable-fips and remains in FIPS mode
+  ///
+  /// Verifies the host crypto policy and that the kubelet server is restricted to FIPS approved TLS settings
+  #[arg(long, env = "EKSNODE_CHECK_FIPS")]
+  pub check_fips: bool,
+
+  /// Check the kubelet configuration against the CIS Amazon EKS Benchmark recommendations
+  ///
+  /// The flags of the running kubelet take precedence over its config file
+  #[arg(long, env = "EKSNODE_CHECK_CIS")]
+  pub check_cis: bool,
+
+  /// Import the results into AWS Security Hub as findings against the instance
+  ///
+  /// Findings are converted to the AWS Security Finding Format (ASFF); requires `securityhub:BatchImportFindings`
+  #[arg(long, env = "EKSNODE_SECURITY_HUB")]
+  pub security_hub: bool,
+}
 
 impl ValidateNodeInput {
   pub async fn validate(&self) -> Result<()> {
-    let file = Assets::get("validate.yaml").unwrap();
-    let contents = std::str::from_utf8(file.data.as_ref())?;
+    let file = Assets::load("validate.yaml")?;
+    let contents = std::str::from_utf8(file.as_ref())?;
     let validation: Validate = serde_yaml::from_str(contents)?;
 
-    validate(validation.files.iter()).await
+    let mut findings = check_files(validation.files.iter())?;
+    if self.check_cri {
+      findings.push(check_cri().await?);
+    }
+    if self.check_kubelet {
+      findings.push(check_kubelet().await);
+    }
+    if self.check_clock_skew {
+      findings.push(check_clock_skew(self.max_clock_skew_ms));
+    }
+    if self.check_image_lock {
+      findings.push(check_image_lock().await?);
+    }
+    if self.check_fips {
+      findings.push(check_fips());
+    }
+    if self.check_cis {
+      findings.extend(check_cis()?);
+    }
+
+    if self.security_hub {
+      let identity = ec2::get_instance_identity().await?;
+      let timestamp = DateTime::from(SystemTime::now()).fmt(DateTimeFormat::DateTime)?;
+      let asff = securityhub::to_asff(&findings, &identity, "validate-node", &timestamp)?;
+      securityhub::import_findings(&asff, &identity.region).await?;
+    }
+
+    let failed = findings.iter().filter(|f| !f.passed).count();
+    if failed > 0 {
+      bail!("Validation failed: {failed} check(s) did not pass");
+    }
+
+    info!("Validation succeeded");
+    Ok(())
+  }
+}
+
+/// Check containerd and its CRI plugin through the containerd socket
+async fn check_cri() -> Result<Finding> {
+  let contents = tokio::fs::read_to_string(containerd::CONTAINERD_CONFIG_PATH).await?;
+  let config = containerd::parse_cri_config(&contents)?;
+  let issues = containerd::check_cri_health(containerd::CONTAINERD_SOCK, &config).await?;
+
+  for issue in &issues {
+    error!("{issue}");
+  }
+  let description = match issues.is_empty() {
+    true => {
+      info!("CRI validation succeeded");
+      "containerd and its CRI plugin are running and healthy".to_owned()
+    }
+    false => issues.join("; "),
+  };
+
+  Ok(Finding {
+    check: "containerd-cri".to_owned(),
+    title: "containerd CRI plugin health".to_owned(),
+    description,
+    severity: Severity::High,
+    passed: issues.is_empty(),
+  })
+}
+
+/// Check the kubelet healthz endpoint and the anomalies in its metrics
+async fn check_kubelet() -> Finding {
+  let issues = match kubelet::check_healthz().await {
+    Ok(()) => kubelet::get_metric_issues()
+      .await
+      .unwrap_or_else(|e| vec![e.to_string()]),
+    Err(e) => vec![e.to_string()],
+  };
+
+  for issue in &issues {
+    error!("{issue}");
+  }
+  let description = match issues.is_empty() {
+    true => {
+      info!("Kubelet validation succeeded");
+      "kubelet is healthy and its metrics show no anomalies".to_owned()
+    }
+    false => issues.join("; "),
+  };
+
+  Finding {
+    check: "kubelet-health".to_owned(),
+    title: "kubelet health".to_owned(),
+    description,
+    severity: Severity::High,
+    passed: issues.is_empty(),
+  }
+}
+
+/// Check the clock offset from Amazon Time Sync, reporting the measured offset
+fn check_clock_skew(max_skew_ms: u64) -> Finding {
+  let result = preflight::get_clock_offset(preflight::TIME_SYNC_SERVER).and_then(|offset| {
+    preflight::verify_clock_offset(offset, max_skew_ms)?;
+    Ok(offset)
+  });
+
+  let (description, passed) = match result {
+    Ok(offset) => {
+      info!("Clock offset from Amazon Time Sync: {offset}ms");
+      (format!("The clock is {offset}ms off from Amazon Time Sync"), true)
+    }
+    Err(e) => {
+      error!("{e}");
+      (e.to_string(), false)
+    }
+  };
+
+  Finding {
+    check: "clock-skew".to_owned(),
+    title: "Clock synchronized with Amazon Time Sync".to_owned(),
+    description,
+    severity: Severity::High,
+    passed,
+  }
+}
+
+/// Check the FIPS posture recorded when the node joined against the host and the kubelet config
+fn check_fips() -> Finding {
+  let issues = match state::NodeState::read(state::NODE_STATE_PATH) {
+    Ok(state) if state.fips_enabled => get_fips_issues(Path::new("/")),
+    Ok(_) => vec!["The node was not joined with --enable-fips".to_owned()],
+    Err(e) => vec![format!("Unable to read {}: {e}", state::NODE_STATE_PATH)],
+  };
+
+  for issue in &issues {
+    error!("{issue}");
+  }
+  let description = match issues.is_empty() {
+    true => {
+      info!("FIPS validation succeeded");
+      "The host is in FIPS mode and kubelet is restricted to FIPS approved TLS settings".to_owned()
+    }
+    false => issues.join("; "),
+  };
+
+  Finding {
+    check: "fips".to_owned(),
+    title: "FIPS mode".to_owned(),
+    description,
+    severity: Severity::High,
+    passed: issues.is_empty(),
   }
 }
 
-/// Iterate over the array of files and validate their properties
-/// against the expected values
-async fn validate<'a, I>(files: I) -> Result<()>
+/// Check the kubelet configuration against the CIS benchmark, with a finding for each recommendation
+fn check_cis() -> Result<Vec<Finding>> {
+  let snapshot = cis::NodeSnapshot::collect(Path::new("/"))?;
+
+  let findings = cis::get_checks()
+    .into_iter()
+    .map(|check| {
+      let issue = check.evaluate(&snapshot);
+      match &issue {
+        Some(issue) => error!("CIS {}: {issue}", check.id()),
+        None => info!("CIS {} passed", check.id()),
+      }
+
+      Finding {
+        check: format!("cis-{}", check.id()),
+        title: format!("CIS {} {}", check.id(), check.title()),
+        description: issue.clone().unwrap_or_else(|| check.title().to_owned()),
+        severity: Severity::Medium,
+        passed: issue.is_none(),
+      }
+    })
+    .collect();
+
+  Ok(findings)
+}
+
+/// Get the reasons the host under the root directory and its kubelet config are not in FIPS mode
+fn get_fips_issues(root: &Path) -> Vec<String> {
+  let mut issues = fips::get_crypto_policy_issues(root);
+
+  let path = utils::rooted(root, kubelet::KUBELET_CONFIG_PATH);
+  match kubelet::KubeletConfiguration::read(&path) {
+    Ok(config) if config.uses_tls_policy(fips::FIPS_TLS_MIN_VERSION, fips::FIPS_TLS_CIPHER_SUITES) => {}
+    Ok(_) => issues.push(format!(
+      "{} allows TLS settings that are not FIPS approved",
+      kubelet::KUBELET_CONFIG_PATH
+    )),
+    Err(e) => issues.push(format!("Unable to read {}: {e}", kubelet::KUBELET_CONFIG_PATH)),
+  }
+
+  issues
+}
+
+/// Check the images in containerd against the cached images lockfile
+async fn check_image_lock() -> Result<Finding> {
+  let lock = containerd::ImageLock::read(containerd::CACHED_IMAGES_LOCK_PATH)?;
+  let mut client = containerd::ImageClient::connect(containerd::K8S_NAMESPACE).await?;
+
+  let mut issues = Vec::new();
+  for locked in &lock.images {
+    let issue = match client.get(&locked.name).await? {
+      Some(image) => lock.verify(&containerd::LockedImage::from_image(&image, &[])?),
+      None => Some(format!("{} is not cached", locked.name)),
+    };
+    issues.extend(issue);
+  }
+
+  for issue in &issues {
+    error!("{issue}");
+  }
+  let description = match issues.is_empty() {
+    true => {
+      info!("Image lock validation succeeded");
+      format!("{} cached image(s) match the image lock", lock.images.len())
+    }
+    false => issues.join("; "),
+  };
+
+  Ok(Finding {
+    check: "cached-images-lock".to_owned(),
+    title: "Cached images match the image lock".to_owned(),
+    description,
+    severity: Severity::Medium,
+    passed: issues.is_empty(),
+  })
+}
+
+/// Input arguments for `validate-config` command
+#[derive(Args, Debug)]
+pub struct ValidateConfigInput {
+  /// Path to the NodeConfig, or the YAML or JSON config file containing the `join-cluster` inputs (i.e. -
+  /// `cluster_name: example`)
+  pub path: PathBuf,
+}
+
+impl ValidateConfigInput {
+  pub async fn validate(&self) -> Result<()> {
+    let contents = tokio::fs::read_to_string(&self.path).await?;
+    let issues = validate_config(&contents)?;
+
+    for issue in &issues {
+      error!("{issue}");
+    }
+    if !issues.is_empty() {
+      bail!("{} is invalid: {} issue(s) found", self.path.display(), issues.len());
+    }
+
+    info!("{} is valid", self.path.display());
+    Ok(())
+  }
+}
+
+/// Validate the `join-cluster` config, returning the issues found
+///
+/// Unknown fields are reported since they are otherwise silently ignored
+fn validate_config(contents: &str) -> Result<Vec<String>> {
+  let spec = nodeconfig::get_spec(contents)?;
+  let mut issues = nodeconfig::get_unknown_fields(&spec)?
+    .into_iter()
+    .map(|field| format!("Unknown field: {field}"))
+    .collect::<Vec<_>>();
+
+  match serde_yaml::from_value::<JoinClusterInput>(serde_yaml::Value::Mapping(spec)) {
+    Ok(input) => issues.extend(input.validate_config()),
+    Err(e) => issues.push(e.to_string()),
+  }
+
+  Ok(issues)
+}
+
+/// Iterate over the array of files and check their properties
+/// against the expected values, returning a finding per file
+fn check_files<'a, I>(files: I) -> Result<Vec<Finding>>
 where
   I: Iterator<Item = &'a Metadata<'a>>,
 {
-  let mut pass = true;
   files
     .map(|f| {
+      let mut issues = Vec::new();
       match fs::metadata(f.path) {
         Ok(meta) => {
           let mode = meta.permissions().mode();
@@ -59,37 +382,37 @@ where
           let gid = meta.st_gid();
 
           if mode != u32::from_str_radix(f.mode, 8)? {
-            error!("{} has incorrect mode: {mode:0}", f.path);
-            pass = false;
+            issues.push(format!("{} has incorrect mode: {mode:o}", f.path));
           }
 
           if uid != f.uid {
-            error!("{} has incorrect uid: {uid}", f.path);
-            pass = false;
+            issues.push(format!("{} has incorrect uid: {uid}", f.path));
           }
 
           if gid != f.gid {
-            error!("{} has incorrect gid: {gid}", f.path);
-            pass = false;
+            issues.push(format!("{} has incorrect gid: {gid}", f.path));
           }
         }
-        Err(e) => {
-          error!("{}: {}", f.path, e);
-          pass = false;
-        }
+        Err(e) => issues.push(format!("{}: {}", f.path, e)),
       };
 
-      Ok(())
-    })
-    .collect::<Result<Vec<()>>>()?;
+      for issue in &issues {
+        error!("{issue}");
+      }
+      let description = match issues.is_empty() {
+        true => format!("{} has mode {}, uid {}, and gid {}", f.path, f.mode, f.uid, f.gid),
+        false => issues.join("; "),
+      };
 
-  match pass {
-    true => {
-      info!("Validation succeeded");
-      Ok(())
-    }
-    false => Err(anyhow!("Validation failed")),
-  }
+      Ok(Finding {
+        check: f.path.to_owned(),
+        title: format!("{} permissions and ownership", f.path),
+        description,
+        severity: Severity::Medium,
+        passed: issues.is_empty(),
+      })
+    })
+    .collect()
 }
 
 #[cfg(target_os = "linux")]
@@ -136,7 +459,39 @@ mod tests {
       // },
     ];
 
-    let result = validate(files.iter());
-    assert!(result.await.is_ok());
+    let findings = check_files(files.iter()).unwrap();
+    assert!(findings.iter().all(|f| f.passed));
+  }
+
+  #[test]
+  fn it_validates_config() {
+    let contents = r#"
+cluster_name: example
+apiserver_endpoint: https://ABC.gr7.us-west-2.eks.amazonaws.com
+b64_cluster_ca: c3VwZXIgc2VjcmV0
+service_cidr: 172.20.0.0/16
+max_pods: 58
+"#;
+    assert!(validate_config(contents).unwrap().is_empty());
+  }
+
+  #[test]
+  fn it_reports_invalid_config() {
+    let contents = r#"
+cluster_name: example
+apiserver_endpoint: https://ABC.gr7.us-west-2.eks.amazonaws.com
+cluster_nmae: typo
+"#;
+    let issues = validate_config(contents).unwrap();
+    assert_eq!(
+      issues,
+      vec![
+        "Unknown field: cluster_nmae".to_owned(),
+        "apiserver_endpoint and b64_cluster_ca (or cluster_ca_file) must be provided together".to_owned(),
+      ]
+    );
+
+    let issues = validate_config("cluster_name: example\nservice_cidr: 172.20.0.0").unwrap();
+    assert_eq!(issues.len(), 1);
   }
 }
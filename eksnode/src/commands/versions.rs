@@ -1,12 +1,19 @@
-use anyhow::Result;
+use std::time::SystemTime;
+
+use anyhow::{bail, Result};
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
 use clap::Args;
 use serde::{Deserialize, Serialize};
 use tabled::{Table, Tabled};
+use tracing::debug;
 
-use crate::utils;
+use crate::{gpu, sbom, utils};
 
 const RPM_SEPARATOR: char = '|';
 
+/// Binaries installed outside of the package manager that are included in the SBOM
+const BINARIES: &[&str] = &["kubelet", "containerd", "runc", "nerdctl"];
+
 /// Package details containing the name and version of the package
 ///
 /// Release is optional as it is not always available; typically
@@ -32,21 +39,38 @@ fn get_versions<T: PackageRepository>(pkg: T) -> Result<Vec<Package>> {
 #[derive(Args, Debug, Default, Serialize, Deserialize)]
 pub struct GetVersionsInput {
   /// Output versions in JSON format
-  #[arg(long, default_value = "true")]
+  #[arg(long, env = "EKSNODE_VERSIONS_OUTPUT_JSON", default_value = "true")]
   pub output_json: bool,
 
   /// Output versions in Markdown table format
-  #[arg(long)]
+  #[arg(long, env = "EKSNODE_VERSIONS_OUTPUT_MARKDOWN")]
   pub output_markdown: bool,
+
+  /// Output the installed packages and binaries as an SBOM document instead
+  #[arg(long, env = "EKSNODE_VERSIONS_OUTPUT", value_enum)]
+  pub output: Option<sbom::SbomFormat>,
+
+  /// Fail if the NVIDIA driver, CUDA toolkit, and container toolkit versions are not compatible with the GPU
+  #[arg(long, env = "EKSNODE_CHECK_GPU_COMPATIBILITY")]
+  pub check_gpu_compatibility: bool,
 }
 
 struct Rpm {}
 
+/// Binaries that report their version with `--version`
+struct Binaries {}
+
 impl GetVersionsInput {
   pub async fn get_versions(&self) -> Result<()> {
     let rpm = Rpm {};
     let rpm_versions = get_versions(rpm)?;
 
+    if let Some(format) = self.output {
+      let binary_versions = get_versions(Binaries {})?;
+      println!("{}", get_sbom(format, &rpm_versions, &binary_versions)?);
+      return Ok(());
+    }
+
     match self.output_markdown {
       true => {
         let table = Table::new(&rpm_versions).to_string();
@@ -55,22 +79,91 @@ impl GetVersionsInput {
       false => {}
     }
 
+    // Only NVIDIA GPU instances have the driver installed
+    let nvidia = match gpu::get_nvidia_versions() {
+      Ok(nvidia) => Some(nvidia),
+      Err(e) => {
+        debug!("Unable to get NVIDIA versions: {e}");
+        None
+      }
+    };
+
     match self.output_json {
       true => {
-        let versions = Versions { linux: rpm_versions };
+        let versions = Versions {
+          linux: rpm_versions,
+          nvidia: nvidia.as_ref(),
+        };
         println!("{}", serde_json::to_string_pretty(&versions)?);
       }
       false => {}
     }
 
+    if self.check_gpu_compatibility {
+      let Some(nvidia) = &nvidia else {
+        bail!("Unable to check GPU compatibility: NVIDIA driver not found");
+      };
+      let issues = gpu::check_nvidia_compatibility(nvidia)?;
+      if !issues.is_empty() {
+        bail!("NVIDIA GPU compatibility check failed:\n  {}", issues.join("\n  "));
+      }
+    }
+
     Ok(())
   }
 }
 
+/// Render the packages and binaries as an SBOM document in the given format
+fn get_sbom(format: sbom::SbomFormat, rpms: &[Package], binaries: &[Package]) -> Result<String> {
+  let components = rpms
+    .iter()
+    .map(|p| (p, sbom::ComponentKind::Rpm))
+    // Binaries installed by an RPM are already described by the package
+    .chain(
+      binaries
+        .iter()
+        .filter(|b| !rpms.iter().any(|p| p.name == b.name))
+        .map(|p| (p, sbom::ComponentKind::Binary)),
+    )
+    .map(|(p, kind)| sbom::Component {
+      name: p.name.to_owned(),
+      version: p.version.to_owned(),
+      kind,
+    })
+    .collect::<Vec<_>>();
+
+  let id = uuid::Uuid::new_v4().to_string();
+  let timestamp = DateTime::from(SystemTime::now()).fmt(DateTimeFormat::DateTime)?;
+  let distro = get_distro_id(&std::fs::read_to_string("/etc/os-release").unwrap_or_default());
+  let doc = sbom::Document {
+    id: &id,
+    timestamp: &timestamp,
+    distro: &distro,
+  };
+
+  let sbom = match format {
+    sbom::SbomFormat::Cyclonedx => sbom::cyclonedx(&doc, &components),
+    sbom::SbomFormat::Spdx => sbom::spdx(&doc, &components),
+  };
+
+  Ok(serde_json::to_string_pretty(&sbom)?)
+}
+
+/// Get the distribution ID from the contents of `/etc/os-release`, defaulting to Amazon Linux
+fn get_distro_id(os_release: &str) -> String {
+  os_release
+    .lines()
+    .find_map(|line| line.strip_prefix("ID="))
+    .map(|id| id.trim_matches('"').to_owned())
+    .unwrap_or_else(|| "amzn".to_owned())
+}
+
 /// Resulting output from version collection
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct Versions {
+#[derive(Debug, Default, Serialize)]
+struct Versions<'a> {
   linux: Vec<Package>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  nvidia: Option<&'a gpu::NvidiaVersions>,
 }
 
 impl PackageRepository for Rpm {
@@ -106,6 +199,30 @@ impl PackageRepository for Rpm {
   }
 }
 
+impl PackageRepository for Binaries {
+  fn versions(&self) -> Result<Vec<Package>> {
+    let pkgs = BINARIES
+      .iter()
+      .filter_map(|name| {
+        // Binaries that are not installed, or do not report a version, are excluded
+        let cmd = utils::cmd_exec(name, vec!["--version"]).ok()?;
+        match utils::get_semver(&cmd.stdout) {
+          Ok(version) => Some(Package {
+            name: name.to_string(),
+            version: version.to_string(),
+          }),
+          Err(e) => {
+            debug!("Unable to get version of {name}: {e}");
+            None
+          }
+        }
+      })
+      .collect::<Vec<Package>>();
+
+    Ok(pkgs)
+  }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -136,4 +253,14 @@ mod tests {
     assert_eq!(rpm_versions.first().unwrap().name, "package1");
     assert_eq!(rpm_versions.first().unwrap().version, "1.0.0");
   }
+
+  #[test]
+  fn it_gets_distro_id() {
+    assert_eq!(
+      get_distro_id("NAME=\"Amazon Linux\"\nID=\"amzn\"\nVERSION_ID=\"2023\"\n"),
+      "amzn"
+    );
+    assert_eq!(get_distro_id("NAME=Bottlerocket\nID=bottlerocket\n"), "bottlerocket");
+    assert_eq!(get_distro_id(""), "amzn");
+  }
 }
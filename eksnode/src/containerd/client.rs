@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use containerd_client::{
+  services::v1::{images_client::ImagesClient, CreateImageRequest, GetImageRequest, Image},
+  tonic::{transport::Channel, Code, Request},
+  with_namespace, Client,
+};
+
+use super::CONTAINERD_SOCK;
+
+/// Default containerd namespace used by the kubelet (CRI)
+pub const K8S_NAMESPACE: &str = "k8s.io";
+
+/// containerd images service client scoped to a single namespace
+///
+/// Every request made through the client carries the namespace so that callers
+/// do not need to thread it through each request individually
+pub struct ImageClient {
+  client: ImagesClient<Channel>,
+  namespace: String,
+}
+
+impl ImageClient {
+  /// Connect to the containerd socket and scope all requests to the namespace
+  pub async fn connect(namespace: &str) -> Result<Self> {
+    let client = Client::from_path(CONTAINERD_SOCK)
+      .await
+      .map_err(|e| anyhow!("Failed to connect to {CONTAINERD_SOCK}: {e}"))?;
+
+    Ok(Self {
+      client: client.images(),
+      namespace: namespace.to_owned(),
+    })
+  }
+
+  /// The namespace the client is scoped to
+  pub fn namespace(&self) -> &str {
+    &self.namespace
+  }
+
+  /// Get the image by name, returning `None` if it does not exist in the namespace
+  pub async fn get(&mut self, name: &str) -> Result<Option<Image>> {
+    let req = GetImageRequest { name: name.to_owned() };
+
+    match self.client.get(with_namespace!(req, self.namespace)).await {
+      Ok(rsp) => Ok(rsp.into_inner().image),
+      Err(status) if status.code() == Code::NotFound => Ok(None),
+      Err(status) => Err(anyhow!(
+        "Failed to get image {name} in namespace {}: {}",
+        self.namespace,
+        status.message()
+      )),
+    }
+  }
+
+  /// Create the image in the namespace
+  pub async fn create(&mut self, image: Image) -> Result<()> {
+    let name = image.name.to_owned();
+    let req = CreateImageRequest {
+      image: Some(image),
+      source_date_epoch: None,
+    };
+
+    self
+      .client
+      .create(with_namespace!(req, self.namespace))
+      .await
+      .map_err(|status| {
+        anyhow!(
+          "Failed to create image {name} in namespace {}: {}",
+          self.namespace,
+          status.message()
+        )
+      })?;
+
+    Ok(())
+  }
+}
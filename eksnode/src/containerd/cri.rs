@@ -0,0 +1,280 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use containerd_client::{
+  services::v1::PluginsRequest,
+  tonic::{client::Grpc, codec::ProstCodec, transport::Channel, Request},
+  Client,
+};
+use http::uri::PathAndQuery;
+use tracing::{debug, info, warn};
+
+/// CRI plugin type and ID as reported by the containerd introspection service
+const CRI_PLUGIN_TYPE: &str = "io.containerd.grpc.v1";
+const CRI_PLUGIN_ID: &str = "cri";
+
+/// Plugin type of containerd snapshotters, including proxy snapshotters
+const SNAPSHOTTER_PLUGIN_TYPE: &str = "io.containerd.snapshotter.v1";
+
+/// Snapshotter used by the CRI plugin when one is not configured
+const DEFAULT_SNAPSHOTTER: &str = "overlayfs";
+
+// Subset of the CRI `runtime.v1.RuntimeService/Status` messages; containerd-client does not include the CRI API
+// https://github.com/kubernetes/cri-api/blob/master/pkg/apis/runtime/v1/api.proto
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct StatusRequest {
+  #[prost(bool, tag = "1")]
+  verbose: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct StatusResponse {
+  #[prost(message, optional, tag = "1")]
+  status: Option<RuntimeStatus>,
+  #[prost(message, repeated, tag = "3")]
+  runtime_handlers: Vec<RuntimeHandler>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct RuntimeStatus {
+  #[prost(message, repeated, tag = "1")]
+  conditions: Vec<RuntimeCondition>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct RuntimeCondition {
+  #[prost(string, tag = "1")]
+  r#type: String,
+  #[prost(bool, tag = "2")]
+  status: bool,
+  #[prost(string, tag = "3")]
+  reason: String,
+  #[prost(string, tag = "4")]
+  message: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct RuntimeHandler {
+  #[prost(string, tag = "1")]
+  name: String,
+}
+
+/// The snapshotter and runtime handlers configured for the CRI plugin
+#[derive(Debug, Default, PartialEq)]
+pub struct CriConfig {
+  pub snapshotter: String,
+  /// Runtime handler names and the runtime binary, if one is set (i.e. - `nvidia-container-runtime`)
+  pub runtime_handlers: Vec<(String, Option<String>)>,
+}
+
+/// Parse the CRI plugin snapshotter and runtime handlers from the containerd config
+pub fn parse_cri_config(contents: &str) -> Result<CriConfig> {
+  let config: toml::Value = toml::from_str(contents)?;
+  let containerd = config
+    .get("plugins")
+    .and_then(|p| p.get(format!("{CRI_PLUGIN_TYPE}.{CRI_PLUGIN_ID}")))
+    .and_then(|cri| cri.get("containerd"));
+
+  let snapshotter = containerd
+    .and_then(|c| c.get("snapshotter"))
+    .and_then(|s| s.as_str())
+    .unwrap_or(DEFAULT_SNAPSHOTTER)
+    .to_owned();
+
+  let runtime_handlers = containerd
+    .and_then(|c| c.get("runtimes"))
+    .and_then(|r| r.as_table())
+    .map(|runtimes| {
+      runtimes
+        .iter()
+        .map(|(name, runtime)| {
+          let binary = runtime
+            .get("options")
+            .and_then(|o| o.get("BinaryName"))
+            .and_then(|b| b.as_str())
+            .map(String::from);
+          (name.to_owned(), binary)
+        })
+        .collect()
+    })
+    .unwrap_or_default();
+
+  Ok(CriConfig {
+    snapshotter,
+    runtime_handlers,
+  })
+}
+
+/// Call the CRI `Status` RPC served by containerd
+async fn get_cri_status(channel: Channel) -> Result<StatusResponse> {
+  let mut grpc = Grpc::new(channel);
+  grpc.ready().await.map_err(|e| anyhow!("{e}"))?;
+
+  let rsp = grpc
+    .unary(
+      Request::new(StatusRequest { verbose: false }),
+      PathAndQuery::from_static("/runtime.v1.RuntimeService/Status"),
+      ProstCodec::default(),
+    )
+    .await
+    .map_err(|status| anyhow!("{}", status.message()))?;
+
+  Ok(rsp.into_inner())
+}
+
+/// Check the health of containerd and its CRI plugin over the socket, returning the issues found
+///
+/// A missing socket is reported on its own since none of the remaining checks can be performed. Otherwise the
+/// containerd version is retrieved, the CRI plugin is checked for initialization errors and runtime readiness, and
+/// the configured snapshotter and runtime handlers are verified to be available
+pub async fn check_cri_health<P: AsRef<Path>>(sock: P, config: &CriConfig) -> Result<Vec<String>> {
+  let sock = sock.as_ref();
+  if !sock.exists() {
+    return Ok(vec![format!("containerd socket {} is missing", sock.display())]);
+  }
+
+  let client = match Client::from_path(sock).await {
+    Ok(client) => client,
+    Err(e) => {
+      return Ok(vec![format!(
+        "Unable to connect to containerd socket {}: {e}",
+        sock.display()
+      )])
+    }
+  };
+
+  let mut issues = Vec::new();
+  match client.version().version(()).await {
+    Ok(rsp) => {
+      let rsp = rsp.into_inner();
+      info!("containerd version {} ({})", rsp.version, rsp.revision);
+    }
+    Err(status) => issues.push(format!("containerd is unhealthy: Version failed: {}", status.message())),
+  }
+
+  let plugins = client
+    .introspection()
+    .plugins(PluginsRequest { filters: vec![] })
+    .await
+    .map_err(|status| anyhow!("Unable to list containerd plugins: {}", status.message()))?
+    .into_inner()
+    .plugins;
+  let plugin_error = |r#type: &str, id: &str| {
+    plugins
+      .iter()
+      .find(|p| p.r#type == r#type && p.id == id)
+      .map(|p| p.init_err.as_ref().map(|e| e.message.to_owned()))
+  };
+
+  match plugin_error(CRI_PLUGIN_TYPE, CRI_PLUGIN_ID) {
+    None => issues.push("CRI plugin is not loaded".to_owned()),
+    Some(Some(e)) => issues.push(format!("CRI plugin is unhealthy: failed to initialize: {e}")),
+    Some(None) => {}
+  }
+  match plugin_error(SNAPSHOTTER_PLUGIN_TYPE, &config.snapshotter) {
+    None => issues.push(format!("Snapshotter {} is not available", config.snapshotter)),
+    Some(Some(e)) => issues.push(format!("Snapshotter {} failed to initialize: {e}", config.snapshotter)),
+    Some(None) => {}
+  }
+
+  let status = match get_cri_status(client.channel()).await {
+    Ok(status) => status,
+    Err(e) => {
+      issues.push(format!("CRI plugin is unhealthy: Status failed: {e}"));
+      return Ok(issues);
+    }
+  };
+  for condition in status.status.map(|s| s.conditions).unwrap_or_default() {
+    debug!("CRI condition {}={}", condition.r#type, condition.status);
+    match (condition.r#type.as_str(), condition.status) {
+      (_, true) => {}
+      // The network is not ready until the CNI is installed on the node
+      ("NetworkReady", false) => warn!("CRI network is not ready: {} {}", condition.reason, condition.message),
+      (name, false) => issues.push(format!(
+        "CRI plugin is unhealthy: {name} is false: {} {}",
+        condition.reason, condition.message
+      )),
+    }
+  }
+
+  // Runtime handlers are only reported by newer versions of the CRI plugin
+  let reported = status
+    .runtime_handlers
+    .iter()
+    .map(|h| h.name.as_str())
+    .collect::<Vec<_>>();
+  for (name, binary) in &config.runtime_handlers {
+    if !reported.is_empty() && !reported.contains(&name.as_str()) {
+      issues.push(format!("Runtime handler {name} is not registered with the CRI plugin"));
+    }
+    if let Some(binary) = binary {
+      if !Path::new(binary).is_file() {
+        issues.push(format!("Runtime handler {name} binary {binary} is missing"));
+      }
+    }
+  }
+
+  Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_parses_cri_config() {
+    let contents = r#"
+      version = 2
+
+      [plugins."io.containerd.grpc.v1.cri".containerd]
+      default_runtime_name = "nvidia"
+      snapshotter = "stargz"
+
+      [plugins."io.containerd.grpc.v1.cri".containerd.runtimes.nvidia]
+      runtime_type = "io.containerd.runc.v2"
+
+      [plugins."io.containerd.grpc.v1.cri".containerd.runtimes.nvidia.options]
+      BinaryName = "/usr/bin/nvidia-container-runtime"
+
+      [plugins."io.containerd.grpc.v1.cri".containerd.runtimes.runc]
+      runtime_type = "io.containerd.runc.v2"
+    "#;
+
+    assert_eq!(
+      parse_cri_config(contents).unwrap(),
+      CriConfig {
+        snapshotter: "stargz".to_owned(),
+        runtime_handlers: vec![
+          (
+            "nvidia".to_owned(),
+            Some("/usr/bin/nvidia-container-runtime".to_owned())
+          ),
+          ("runc".to_owned(), None),
+        ],
+      }
+    );
+  }
+
+  #[test]
+  fn it_defaults_cri_snapshotter() {
+    assert_eq!(
+      parse_cri_config("version = 2").unwrap(),
+      CriConfig {
+        snapshotter: "overlayfs".to_owned(),
+        runtime_handlers: vec![],
+      }
+    );
+  }
+
+  #[tokio::test]
+  async fn it_reports_missing_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let issues = check_cri_health(dir.path().join("containerd.sock"), &CriConfig::default())
+      .await
+      .unwrap();
+
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].ends_with("is missing"));
+  }
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use containerd_client::services::v1::Image;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// Lockfile of the images cached on the host by `pull-image --cached-images`
+pub const CACHED_IMAGES_LOCK_PATH: &str = "/etc/eks/cached-images.lock.json";
+
+/// Cached image resolved from its tag to the digest of its manifest (or index)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedImage {
+  /// Image reference as pulled (i.e. - `<registry>/eks/pause:3.8`)
+  pub name: String,
+  /// Digest of the manifest or index the tag resolved to (i.e. - `sha256:<hex>`)
+  pub digest: String,
+  /// Size in bytes of the manifest or index
+  pub size: i64,
+  /// Platforms pulled for the image (i.e. - `linux/amd64`)
+  pub platforms: Vec<String>,
+}
+
+impl LockedImage {
+  /// Lock the containerd image to its current target
+  pub fn from_image(image: &Image, platforms: &[&str]) -> Result<Self> {
+    let Some(target) = &image.target else {
+      bail!("Image {} does not have a target descriptor", image.name);
+    };
+
+    Ok(Self {
+      name: image.name.to_owned(),
+      digest: target.digest.to_owned(),
+      size: target.size,
+      platforms: platforms.iter().map(|p| p.to_string()).collect(),
+    })
+  }
+}
+
+/// Images cached on the host and the digests their tags resolved to
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageLock {
+  pub images: Vec<LockedImage>,
+}
+
+impl ImageLock {
+  pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let contents = std::fs::read(path)?;
+
+    Ok(serde_json::from_slice(&contents)?)
+  }
+
+  pub async fn write<P: AsRef<Path>>(&self, path: P, chown: bool) -> Result<()> {
+    let contents = serde_json::to_string_pretty(self)?;
+    utils::write_file(contents.as_bytes(), path, Some(0o644), chown).await
+  }
+
+  /// Get the locked image by name
+  pub fn get(&self, name: &str) -> Option<&LockedImage> {
+    self.images.iter().find(|i| i.name == name)
+  }
+
+  /// Check that the image resolved to the locked digest, returning an issue when it does not
+  pub fn verify(&self, image: &LockedImage) -> Option<String> {
+    let Some(locked) = self.get(&image.name) else {
+      return Some(format!("{} is not in the image lock", image.name));
+    };
+    if locked.digest == image.digest {
+      return None;
+    }
+
+    Some(format!(
+      "{} resolved to {} but is locked to {}",
+      image.name, image.digest, locked.digest
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use containerd_client::types::Descriptor;
+
+  use super::*;
+
+  #[test]
+  fn it_locks_image() {
+    let image = Image {
+      name: "602401143452.dkr.ecr.us-west-2.amazonaws.com/eks/pause:3.8".to_owned(),
+      target: Some(Descriptor {
+        media_type: "application/vnd.docker.distribution.manifest.list.v2+json".to_owned(),
+        digest: "sha256:1111".to_owned(),
+        size: 1234,
+        ..Default::default()
+      }),
+      ..Default::default()
+    };
+
+    let locked = LockedImage::from_image(&image, &["linux/amd64"]).unwrap();
+    let lock = ImageLock {
+      images: vec![locked.to_owned()],
+    };
+    assert_eq!(lock.verify(&locked), None);
+
+    let changed = LockedImage {
+      digest: "sha256:2222".to_owned(),
+      ..locked
+    };
+    assert_eq!(
+      lock.verify(&changed).as_deref(),
+      Some(
+        "602401143452.dkr.ecr.us-west-2.amazonaws.com/eks/pause:3.8 resolved to sha256:2222 but is locked to \
+         sha256:1111"
+      )
+    );
+    assert!(ImageLock::default().verify(&changed).is_some());
+  }
+}
@@ -1,22 +1,50 @@
-use std::{collections::BTreeMap, path::Path};
+use std::{collections::BTreeMap, fmt, path::Path, str::FromStr};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::ValueEnum;
-use rust_embed::RustEmbed;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JsonValue};
 use taplo::formatter;
 
-use crate::utils;
+use crate::{gpu, utils, Architecture};
 
+mod client;
+mod cri;
+mod lock;
+
+pub use client::{ImageClient, K8S_NAMESPACE};
+pub use cri::{check_cri_health, parse_cri_config, CriConfig};
+pub use lock::{ImageLock, LockedImage, CACHED_IMAGES_LOCK_PATH};
+
+pub const CONTAINERD_CONFIG_PATH: &str = "/etc/containerd/config.toml";
+pub const CONTAINERD_SOCK: &str = "/run/containerd/containerd.sock";
+pub const REGISTRY_CONFIG_PATH: &str = "/etc/containerd/certs.d";
+pub const STARGZ_SOCK: &str = "/run/containerd-stargz-grpc/containerd-stargz-grpc.sock";
 pub const SANDBOX_IMAGE_SERVICE: &str = "sandbox-image.service";
 pub const SANDBOX_IMAGE_SERVICE_PATH: &str = "/etc/systemd/system/sandbox-image.service";
-pub const SANDBOX_IMAGE_TAG: &str = "3.8";
+pub const DAEMON_SERVICE_DROPIN_PATH: &str = "/etc/systemd/system/containerd.service.d/20-daemon.conf";
+/// Directory of the CNI plugin binaries, unless set with `--cni-bin-dir`
+pub const CNI_BIN_DIR: &str = "/opt/cni/bin";
+/// Directory where the CNI plugin writes its network configuration, unless set with `--cni-conf-dir`
+pub const CNI_CONF_DIR: &str = "/etc/cni/net.d";
+
+/// Registry host config directories that may be populated on the AMI and are preserved when present
+const LEGACY_REGISTRY_CONFIG_PATHS: &[&str] = &["/etc/docker/certs.d"];
+
+/// Maximum time (seconds) the sandbox image service waits for the containerd socket before failing
+const SANDBOX_IMAGE_CONTAINERD_WAIT: u32 = 60;
+
+/// Delay (seconds) between attempts to pull the sandbox image; the fixed interval on systemd older than 254
+const SANDBOX_IMAGE_RESTART_SEC: u32 = 15;
+
+/// Number of attempts over which the delay between attempts grows to the maximum delay (systemd 254+)
+const SANDBOX_IMAGE_RESTART_STEPS: u32 = 5;
 
-/// Embeds the contents of the `templates/` directory into the binary
-#[derive(RustEmbed)]
-#[folder = "src/containerd/templates/"]
-pub struct Templates;
+/// Maximum delay (seconds) between attempts to pull the sandbox image once backoff has been applied (systemd 254+)
+const SANDBOX_IMAGE_RESTART_MAX_DELAY_SEC: u32 = 60;
+
+/// Maximum number of attempts to pull the sandbox image within the start limit interval
+const SANDBOX_IMAGE_START_LIMIT_BURST: u32 = 20;
 
 #[derive(Copy, Clone, Debug, ValueEnum, Serialize, Deserialize)]
 pub enum DefaultRuntime {
@@ -30,17 +58,240 @@ impl Default for DefaultRuntime {
   }
 }
 
+/// Placeholder in a pause image template that is replaced with the node's architecture (i.e. - `amd64`)
+const PAUSE_IMAGE_ARCH_PLACEHOLDER: &str = "{arch}";
+
+/// Pause (sandbox) container image that may differ by architecture
+///
+/// Node groups of mixed architectures that share a launch template can provide either:
+///
+/// - a single image, optionally templated with `{arch}` (i.e. - `<registry>/pause-{arch}:3.9`)
+/// - an image per architecture (i.e. - `amd64=<registry>/pause:3.9-amd64,arm64=<registry>/pause:3.9-arm64`)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum PauseImage {
+  Template(String),
+  PerArch(Vec<(Architecture, String)>),
+}
+
+impl PauseImage {
+  /// The image references as provided, before the architecture is resolved
+  pub fn images(&self) -> Vec<&str> {
+    match self {
+      Self::Template(image) => vec![image.as_str()],
+      Self::PerArch(images) => images.iter().map(|(_, image)| image.as_str()).collect(),
+    }
+  }
+
+  /// Resolve the image reference for the architecture
+  pub fn resolve(&self, arch: &Architecture) -> Result<String> {
+    match self {
+      Self::Template(image) => Ok(image.replace(PAUSE_IMAGE_ARCH_PLACEHOLDER, arch.oci_arch())),
+      Self::PerArch(images) => match images.iter().find(|(a, _)| a == arch) {
+        Some((_, image)) => Ok(image.to_owned()),
+        None => bail!("No pause container image provided for {}", arch.oci_arch()),
+      },
+    }
+  }
+}
+
+impl FromStr for PauseImage {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    // `=` is not valid in an image reference so it only appears in the per-architecture form
+    if !s.contains('=') {
+      return Ok(Self::Template(s.to_owned()));
+    }
+
+    let mut images: Vec<(Architecture, String)> = Vec::new();
+    for entry in s.split(',') {
+      let Some((arch, image)) = entry.split_once('=') else {
+        bail!("Invalid pause container image {entry}; expected <arch>=<image>");
+      };
+      let arch = Architecture::from_machine(arch.trim())?;
+      if images.iter().any(|(a, _)| *a == arch) {
+        bail!("Duplicate pause container image for {}", arch.oci_arch());
+      }
+      images.push((arch, image.trim().to_owned()));
+    }
+
+    Ok(Self::PerArch(images))
+  }
+}
+
+impl TryFrom<String> for PauseImage {
+  type Error = anyhow::Error;
+
+  fn try_from(s: String) -> Result<Self> {
+    s.parse()
+  }
+}
+
+impl fmt::Display for PauseImage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Template(image) => write!(f, "{image}"),
+      Self::PerArch(images) => {
+        let images = images
+          .iter()
+          .map(|(arch, image)| format!("{}={image}", arch.oci_arch()))
+          .collect::<Vec<_>>();
+        write!(f, "{}", images.join(","))
+      }
+    }
+  }
+}
+
+impl From<PauseImage> for String {
+  fn from(image: PauseImage) -> Self {
+    image.to_string()
+  }
+}
+
+/// Render the systemd unit that pulls the sandbox (pause) image used by containerd
+///
+/// The pull is retried on failure with an increasing delay (`RestartSteps`/`RestartMaxDelaySec` require systemd
+/// 254+; older versions such as that of AL2023 ignore these and retry at a fixed `RestartSec` interval), and the start
+/// is bounded by a wait on the containerd socket so that a containerd that never becomes ready does not hang the unit.
+/// The start limit is sized for the fixed interval, so that the pull is retried for about
+/// `StartLimitBurst * RestartSec` seconds before the unit fails
+pub fn get_sandbox_image_service(pause_image: &str) -> String {
+  let start_limit_interval = SANDBOX_IMAGE_START_LIMIT_BURST * SANDBOX_IMAGE_RESTART_SEC;
+
+  format!(
+    r#"[Unit]
+Description=Fetch sandbox image used by containerd
+After=containerd.service network-online.target
+Wants=network-online.target
+Requires=containerd.service
+StartLimitIntervalSec={start_limit_interval}
+StartLimitBurst={SANDBOX_IMAGE_START_LIMIT_BURST}
+
+[Service]
+Type=oneshot
+ExecStartPre=/usr/bin/timeout {SANDBOX_IMAGE_CONTAINERD_WAIT} /bin/sh -c 'until [ -S {CONTAINERD_SOCK} ]; do sleep 1; done'
+ExecStart=eksnode pull-image --image {pause_image} --namespace k8s.io
+Restart=on-failure
+RestartSec={SANDBOX_IMAGE_RESTART_SEC}
+RestartSteps={SANDBOX_IMAGE_RESTART_STEPS}
+RestartMaxDelaySec={SANDBOX_IMAGE_RESTART_MAX_DELAY_SEC}
+
+[Install]
+WantedBy=multi-user.target
+"#
+  )
+}
+
 pub async fn create_sandbox_image_service<P: AsRef<Path>>(path: P, pause_image: &str, chown: bool) -> Result<()> {
-  let tmpl = Templates::get(SANDBOX_IMAGE_SERVICE).unwrap();
-  let tmpl = std::str::from_utf8(tmpl.data.as_ref())?;
+  let contents = get_sandbox_image_service(pause_image);
+  utils::write_file(contents.as_bytes(), path, Some(0o644), chown).await
+}
+
+/// Get the cgroup path of a systemd slice (i.e. - `runtime-containerd.slice` -> `/runtime.slice/runtime-containerd.slice`)
+///
+/// Each dash in the slice name denotes a parent slice in the systemd hierarchy
+pub fn get_slice_cgroup_path(slice: &str) -> Result<String> {
+  let name = slice.strip_suffix(".slice").unwrap_or_default();
+  if name.is_empty()
+    || name.starts_with('-')
+    || name.ends_with('-')
+    || name.contains("--")
+    || !name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+  {
+    bail!("Invalid systemd slice {slice}; expected a name such as runtime-containerd.slice");
+  }
+
+  let parts = name.split('-').collect::<Vec<_>>();
+  let path = (1..=parts.len())
+    .map(|i| format!("/{}.slice", parts[..i].join("-")))
+    .collect::<String>();
+
+  Ok(path)
+}
+
+/// Path of the systemd unit file for the slice
+pub fn get_slice_unit_path(slice: &str) -> String {
+  format!("/etc/systemd/system/{slice}")
+}
+
+/// Render the systemd slice unit that the containerd daemon runs in
+fn get_slice_unit(slice: &str) -> String {
+  format!(
+    r#"[Unit]
+Description=containerd daemon slice ({slice})
+Documentation=man:systemd.special(7)
+Before=slices.target
+"#
+  )
+}
+
+pub async fn create_slice_unit<P: AsRef<Path>>(path: P, slice: &str, chown: bool) -> Result<()> {
+  let contents = get_slice_unit(slice);
+  utils::write_file(contents.as_bytes(), path, Some(0o644), chown).await
+}
+
+/// Render the containerd service drop-in that matches the daemon settings of the containerd config
+///
+/// containerd only adjusts its own OOM score and cgroup once it has started; the drop-in applies the same settings
+/// to the service so that they hold from the moment systemd starts the daemon
+fn get_daemon_service_dropin(oom_score: Option<i32>, slice: Option<&str>) -> String {
+  let mut contents = "[Service]\n".to_owned();
+  if let Some(slice) = slice {
+    contents.push_str(&format!("Slice={slice}\n"));
+  }
+  if let Some(oom_score) = oom_score {
+    contents.push_str(&format!("OOMScoreAdjust={oom_score}\n"));
+  }
+
+  contents
+}
 
-  let contents = tmpl.replace(
-    "{{EXEC_START}}",
-    &format!("eksnode pull-image --image {pause_image} --namespace k8s.io"),
-  );
+pub async fn create_daemon_service_dropin<P: AsRef<Path>>(
+  path: P,
+  oom_score: Option<i32>,
+  slice: Option<&str>,
+  chown: bool,
+) -> Result<()> {
+  let contents = get_daemon_service_dropin(oom_score, slice);
   utils::write_file(contents.as_bytes(), path, Some(0o644), chown).await
 }
 
+/// Merge colon-separated registry config paths, preserving order and dropping duplicates
+fn merge_registry_config_paths(paths: &[&str]) -> String {
+  let mut merged: Vec<&str> = Vec::new();
+  for path in paths.iter().flat_map(|p| p.split(':')).map(str::trim) {
+    if !path.is_empty() && !merged.contains(&path) {
+      merged.push(path);
+    }
+  }
+
+  merged.join(":")
+}
+
+/// Whether the directory contains registry host configs (i.e. - `<registry>/hosts.toml`)
+fn has_registry_config<P: AsRef<Path>>(dir: P) -> bool {
+  std::fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_some())
+}
+
+/// Get the registry `config_path` for containerd
+///
+/// The requested paths (or the default certs.d) are merged with the paths in the existing containerd config and
+/// any populated legacy certs.d directories so that registry configs already present on the AMI are not ignored
+pub fn get_registry_config_path(requested: Option<&str>, existing: Option<&str>) -> String {
+  let legacy = LEGACY_REGISTRY_CONFIG_PATHS
+    .iter()
+    .copied()
+    .filter(|path| has_registry_config(path))
+    .collect::<Vec<_>>();
+
+  let mut paths = vec![requested.unwrap_or(REGISTRY_CONFIG_PATH)];
+  paths.extend(existing);
+  paths.extend(legacy);
+
+  merge_registry_config_paths(&paths)
+}
+
 // https://github.com/serde-rs/json/issues/377#issuecomment-341490464
 fn merge(a: &mut JsonValue, b: &JsonValue) {
   match (a, b) {
@@ -55,19 +306,42 @@ fn merge(a: &mut JsonValue, b: &JsonValue) {
   }
 }
 
-fn get_plugins_config(default_runtime: &DefaultRuntime, sandbox_image: &str) -> Result<JsonValue> {
+/// Deep merge the TOML tables of `b` into `a`, where the values of `b` replace those of `a` that are not tables
+fn merge_toml(a: &mut toml::Value, b: &toml::Value) {
+  match (a, b) {
+    (toml::Value::Table(a), toml::Value::Table(b)) => {
+      for (k, v) in b {
+        match a.get_mut(k) {
+          Some(existing) => merge_toml(existing, v),
+          None => {
+            a.insert(k.clone(), v.clone());
+          }
+        }
+      }
+    }
+    (a, b) => {
+      *a = b.clone();
+    }
+  }
+}
+
+fn get_plugins_config(
+  default_runtime: &DefaultRuntime,
+  sandbox_image: &str,
+  registry_config_path: &str,
+) -> Result<JsonValue> {
   let mut base = json!({
     "io.containerd.grpc.v1.cri": {
       "sandbox_image": sandbox_image,
       "cni": {
-        "bin_dir": "/opt/cni/bin",
-        "conf_dir": "/etc/cni/net.d"
+        "bin_dir": CNI_BIN_DIR,
+        "conf_dir": CNI_CONF_DIR
       },
       "containerd": {
         "discard_unpacked_layers": true,
       },
       "registry": {
-        "config_path": "/etc/containerd/certs.d"
+        "config_path": registry_config_path
       }
     }
   });
@@ -83,7 +357,7 @@ fn get_plugins_config(default_runtime: &DefaultRuntime, sandbox_image: &str) ->
               "runtime_type": "io.containerd.runc.v2",
               "options": {
                 "SystemdCgroup": true,
-                "BinaryName": "/usr/bin/nvidia-container-runtime"
+                "BinaryName": gpu::NVIDIA_CONTAINER_RUNTIME
               }
             }
           }
@@ -191,8 +465,8 @@ pub struct ContainerdConfiguration {
 }
 
 impl ContainerdConfiguration {
-  pub fn new(default_runtime: &DefaultRuntime, sandbox_image: &str) -> Result<Self> {
-    let plugins_config = get_plugins_config(default_runtime, sandbox_image)?;
+  pub fn new(default_runtime: &DefaultRuntime, sandbox_image: &str, registry_config_path: &str) -> Result<Self> {
+    let plugins_config = get_plugins_config(default_runtime, sandbox_image, registry_config_path)?;
 
     Ok(ContainerdConfiguration {
       version: 2,
@@ -222,6 +496,145 @@ impl ContainerdConfiguration {
     Ok(config)
   }
 
+  /// Merge the user provided containerd config file over the generated configuration
+  ///
+  /// Tables are merged key by key so that only the settings in the file are overridden (i.e. -
+  /// `[plugins."io.containerd.grpc.v1.cri".containerd] snapshotter = "soci"`); arrays are replaced as a whole
+  pub fn merge_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)?;
+    let overrides: toml::Value = toml::from_str(&contents)?;
+
+    let version = overrides.get("version").and_then(toml::Value::as_integer);
+    if version.is_some_and(|version| version != i64::from(self.version)) {
+      bail!(
+        "{} is a version {} containerd config; only version {} can be merged with the generated config",
+        path.display(),
+        version.unwrap_or_default(),
+        self.version
+      );
+    }
+
+    let mut config = toml::Value::try_from(&*self)?;
+    merge_toml(&mut config, &overrides);
+    *self = config.try_into()?;
+
+    Ok(())
+  }
+
+  /// Merge settings into the CRI plugin configuration (i.e. - `max_concurrent_downloads`)
+  pub fn merge_cri_config(&mut self, cri: &JsonValue) {
+    let plugins = self.plugins.get_or_insert_with(BTreeMap::new);
+    let config = plugins.entry("plugins".to_owned()).or_insert_with(|| json!({}));
+    merge(config, &json!({ "io.containerd.grpc.v1.cri": cri }));
+  }
+
+  /// Use a proxy snapshotter plugin (i.e. - stargz) served over a socket as the CRI snapshotter
+  ///
+  /// Snapshot annotations are enabled so that the snapshotter can lazily pull the image layers
+  pub fn set_proxy_snapshotter(&mut self, name: &str, address: &str) {
+    self.proxy_plugins.get_or_insert_with(BTreeMap::new).insert(
+      name.to_owned(),
+      ProxyPlugin {
+        type_: "snapshot".to_owned(),
+        address: address.to_owned(),
+        platform: None,
+      },
+    );
+
+    self.merge_cri_config(&json!({
+      "containerd": {
+        "snapshotter": name,
+        "disable_snapshot_annotations": false
+      }
+    }));
+  }
+
+  /// Export traces to the OTLP gRPC collector endpoint (i.e. - `localhost:4317`)
+  ///
+  /// The sampling rate matches that of kubelet so that the spans of CRI calls are sampled alongside the kubelet spans
+  /// they are part of; when not provided, spans are only sampled when their parent span is sampled
+  pub fn set_tracing(&mut self, endpoint: &str, sampling_rate_per_million: Option<i32>) {
+    let mut tracing_config = json!({
+      "io.containerd.tracing.processor.v1.otlp": {
+        "endpoint": endpoint,
+        "protocol": "grpc",
+        "insecure": true
+      },
+      "io.containerd.internal.v1.tracing": {
+        "service_name": "containerd"
+      }
+    });
+    if let Some(rate) = sampling_rate_per_million {
+      tracing_config["io.containerd.internal.v1.tracing"]["sampling_ratio"] = json!(f64::from(rate) / 1_000_000.0);
+    }
+
+    let plugins = self.plugins.get_or_insert_with(BTreeMap::new);
+    let config = plugins.entry("plugins".to_owned()).or_insert_with(|| json!({}));
+    merge(config, &tracing_config);
+  }
+
+  /// Set the CRI image pull settings, taking precedence over those of the profile
+  ///
+  /// Keeping the compressed layers after unpacking (`discard_unpacked_layers = false`) trades disk space for
+  /// faster re-pulls and image exports
+  pub fn set_image_pull_options(
+    &mut self,
+    max_concurrent_downloads: Option<u32>,
+    image_pull_progress_timeout: Option<&str>,
+    discard_unpacked_layers: Option<bool>,
+  ) {
+    let mut cri = json!({});
+    if let Some(downloads) = max_concurrent_downloads {
+      cri["max_concurrent_downloads"] = json!(downloads);
+    }
+    if let Some(timeout) = image_pull_progress_timeout {
+      cri["image_pull_progress_timeout"] = json!(timeout);
+    }
+    if let Some(discard) = discard_unpacked_layers {
+      cri["containerd"] = json!({ "discard_unpacked_layers": discard });
+    }
+
+    self.merge_cri_config(&cri);
+  }
+
+  /// Set the directories of the CNI plugin binaries and network configuration used by the CRI plugin
+  pub fn set_cni_dirs(&mut self, bin_dir: Option<&str>, conf_dir: Option<&str>) {
+    let mut cni = json!({});
+    if let Some(bin_dir) = bin_dir {
+      cni["bin_dir"] = json!(bin_dir);
+    }
+    if let Some(conf_dir) = conf_dir {
+      cni["conf_dir"] = json!(conf_dir);
+    }
+
+    self.merge_cri_config(&json!({ "cni": cni }));
+  }
+
+  /// Set the OOM score and the systemd slice (cgroup) of the containerd daemon process
+  pub fn set_daemon_options(&mut self, oom_score: Option<i32>, slice: Option<&str>) -> Result<()> {
+    if let Some(oom_score) = oom_score {
+      self.oom_score = Some(oom_score);
+    }
+    if let Some(slice) = slice {
+      self.cgroup = Some(CgroupConfig {
+        path: get_slice_cgroup_path(slice)?,
+      });
+    }
+
+    Ok(())
+  }
+
+  /// Get the CRI registry `config_path`
+  pub fn registry_config_path(&self) -> Option<&str> {
+    self
+      .plugins
+      .as_ref()?
+      .get("plugins")?
+      .pointer("/io.containerd.grpc.v1.cri/registry/config_path")?
+      .as_str()
+  }
+
   pub async fn write<P: AsRef<Path>>(&self, path: P, chown: bool) -> Result<()> {
     let conf = toml::to_string(self)?;
     let options = formatter::Options {
@@ -314,7 +727,8 @@ struct ProxyPlugin {
   #[serde(rename = "type")]
   type_: String,
   address: String,
-  platform: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  platform: Option<String>,
 }
 /// StreamProcessor provides configuration for diff content processors
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -337,10 +751,56 @@ struct StreamProcessor {
 mod tests {
   use std::io::{Read, Seek, SeekFrom};
 
+  use rstest::*;
   use tempfile::NamedTempFile;
 
   use super::*;
 
+  #[rstest]
+  #[case(
+    "602401143452.dkr.ecr.us-west-2.amazonaws.com/eks/pause:3.9",
+    Architecture::Aarch64,
+    "602401143452.dkr.ecr.us-west-2.amazonaws.com/eks/pause:3.9"
+  )]
+  #[case(
+    "registry.example.com/pause-{arch}:3.9",
+    Architecture::X86_64,
+    "registry.example.com/pause-amd64:3.9"
+  )]
+  #[case(
+    "registry.example.com/pause-{arch}:3.9",
+    Architecture::Aarch64,
+    "registry.example.com/pause-arm64:3.9"
+  )]
+  #[case(
+    "amd64=registry.example.com/pause:3.9-amd64,arm64=registry.example.com/pause:3.9-arm64",
+    Architecture::Aarch64,
+    "registry.example.com/pause:3.9-arm64"
+  )]
+  #[case(
+    "x86_64=registry.example.com/pause:3.9-amd64",
+    Architecture::X86_64,
+    "registry.example.com/pause:3.9-amd64"
+  )]
+  fn it_resolves_pause_image(#[case] input: &str, #[case] arch: Architecture, #[case] expected: &str) {
+    let image: PauseImage = input.parse().unwrap();
+    assert_eq!(image.resolve(&arch).unwrap(), expected);
+  }
+
+  #[rstest]
+  #[case("amd64=registry.example.com/pause:3.9,registry.example.com/pause:3.9")]
+  #[case("riscv64=registry.example.com/pause:3.9")]
+  #[case("amd64=registry.example.com/pause:3.9,x86_64=registry.example.com/pause:3.10")]
+  fn it_rejects_invalid_pause_image(#[case] input: &str) {
+    assert!(input.parse::<PauseImage>().is_err());
+  }
+
+  #[test]
+  fn it_requires_pause_image_for_arch() {
+    let image: PauseImage = "amd64=registry.example.com/pause:3.9".parse().unwrap();
+    assert!(image.resolve(&Architecture::Aarch64).is_err());
+  }
+
   #[test]
   fn it_serializes_containerd_config() {
     let config = r#"
@@ -380,6 +840,10 @@ mod tests {
 
     let deserialized: ContainerdConfiguration = toml::from_str(config).unwrap();
     insta::assert_debug_snapshot!(deserialized);
+    assert_eq!(
+      deserialized.registry_config_path(),
+      Some("/etc/containerd/certs.d:/etc/docker/certs.d")
+    );
 
     let serialized = toml::to_string_pretty(&deserialized).unwrap();
     insta::assert_debug_snapshot!(serialized);
@@ -388,7 +852,7 @@ mod tests {
   #[tokio::test]
   async fn it_creates_containerd_config() {
     let sandbox_img = "602401143452.dkr.ecr.us-east-1.amazonaws.com/eks/pause:3.8";
-    let config = ContainerdConfiguration::new(&DefaultRuntime::Containerd, sandbox_img).unwrap();
+    let config = ContainerdConfiguration::new(&DefaultRuntime::Containerd, sandbox_img, REGISTRY_CONFIG_PATH).unwrap();
     insta::assert_debug_snapshot!(config);
 
     let mut file = NamedTempFile::new().unwrap();
@@ -406,10 +870,79 @@ mod tests {
   #[test]
   fn it_creates_nvidia_containerd_config() {
     let sandbox_img = "602401143452.dkr.ecr.us-east-1.amazonaws.com/eks/pause:3.8";
-    let config = ContainerdConfiguration::new(&DefaultRuntime::Nvidia, sandbox_img).unwrap();
+    let config = ContainerdConfiguration::new(&DefaultRuntime::Nvidia, sandbox_img, REGISTRY_CONFIG_PATH).unwrap();
     insta::assert_debug_snapshot!(config);
   }
 
+  #[tokio::test]
+  async fn it_creates_stargz_containerd_config() {
+    let sandbox_img = "602401143452.dkr.ecr.us-east-1.amazonaws.com/eks/pause:3.8";
+    let mut config =
+      ContainerdConfiguration::new(&DefaultRuntime::Containerd, sandbox_img, REGISTRY_CONFIG_PATH).unwrap();
+    config.set_proxy_snapshotter("stargz", STARGZ_SOCK);
+
+    let file = NamedTempFile::new().unwrap();
+    config.write(&file, false).await.unwrap();
+    insta::assert_snapshot!(std::fs::read_to_string(file.path()).unwrap());
+  }
+
+  #[test]
+  fn it_merges_cri_config() {
+    let sandbox_img = "602401143452.dkr.ecr.us-east-1.amazonaws.com/eks/pause:3.8";
+    let mut config =
+      ContainerdConfiguration::new(&DefaultRuntime::Containerd, sandbox_img, REGISTRY_CONFIG_PATH).unwrap();
+    config.merge_cri_config(&json!({ "max_concurrent_downloads": 10 }));
+
+    let cri = config.plugins.unwrap()["plugins"]["io.containerd.grpc.v1.cri"].clone();
+    assert_eq!(cri["max_concurrent_downloads"], 10);
+    assert_eq!(cri["sandbox_image"], sandbox_img);
+  }
+
+  #[test]
+  fn it_sets_image_pull_options() {
+    let mut config = ContainerdConfiguration::new(&DefaultRuntime::Containerd, "pause", REGISTRY_CONFIG_PATH).unwrap();
+    config.merge_cri_config(&json!({ "max_concurrent_downloads": 6, "image_pull_progress_timeout": "30m0s" }));
+    config.set_image_pull_options(Some(12), None, Some(false));
+
+    let cri = config.plugins.unwrap()["plugins"]["io.containerd.grpc.v1.cri"].clone();
+    assert_eq!(cri["max_concurrent_downloads"], 12);
+    assert_eq!(cri["image_pull_progress_timeout"], "30m0s");
+    assert_eq!(cri["containerd"]["discard_unpacked_layers"], false);
+    assert_eq!(cri["containerd"]["default_runtime_name"], "runc");
+  }
+
+  #[test]
+  fn it_sets_cni_dirs() {
+    let mut config = ContainerdConfiguration::new(&DefaultRuntime::Containerd, "pause", REGISTRY_CONFIG_PATH).unwrap();
+    config.set_cni_dirs(Some("/usr/libexec/cni"), None);
+
+    let cri = config.plugins.unwrap()["plugins"]["io.containerd.grpc.v1.cri"].clone();
+    assert_eq!(cri["cni"]["bin_dir"], "/usr/libexec/cni");
+    assert_eq!(cri["cni"]["conf_dir"], CNI_CONF_DIR);
+  }
+
+  #[test]
+  fn it_merges_registry_config_paths() {
+    assert_eq!(
+      merge_registry_config_paths(&[
+        "/etc/containerd/certs.d",
+        "/etc/containerd/certs.d:/etc/docker/certs.d",
+        "/opt/registry::/etc/docker/certs.d",
+      ]),
+      "/etc/containerd/certs.d:/etc/docker/certs.d:/opt/registry"
+    );
+  }
+
+  #[test]
+  fn it_detects_registry_config() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(!has_registry_config(dir.path()));
+    assert!(!has_registry_config(dir.path().join("missing")));
+
+    std::fs::create_dir(dir.path().join("docker.io")).unwrap();
+    assert!(has_registry_config(dir.path()));
+  }
+
   #[tokio::test]
   async fn it_creates_sandbox_image_service() {
     let sandbox_img = "602401143452.dkr.ecr.us-east-1.amazonaws.com/eks/pause:3.9";
@@ -424,4 +957,83 @@ mod tests {
     file.read_to_string(&mut buf).unwrap();
     insta::assert_debug_snapshot!(buf);
   }
+
+  #[rstest]
+  #[case("runtime.slice", Some("/runtime.slice"))]
+  #[case("runtime-containerd.slice", Some("/runtime.slice/runtime-containerd.slice"))]
+  #[case("a-b_c-d.slice", Some("/a.slice/a-b_c.slice/a-b_c-d.slice"))]
+  #[case("runtime", None)]
+  #[case(".slice", None)]
+  #[case("runtime--containerd.slice", None)]
+  #[case("-runtime.slice", None)]
+  #[case("runtime/containerd.slice", None)]
+  fn it_gets_slice_cgroup_path(#[case] slice: &str, #[case] expected: Option<&str>) {
+    assert_eq!(get_slice_cgroup_path(slice).ok().as_deref(), expected);
+  }
+
+  #[test]
+  fn it_sets_tracing() {
+    let mut config = ContainerdConfiguration::new(&DefaultRuntime::Containerd, "pause", REGISTRY_CONFIG_PATH).unwrap();
+    config.set_tracing("localhost:4317", Some(10_000));
+
+    let plugins = config.plugins.unwrap()["plugins"].clone();
+    assert_eq!(
+      plugins["io.containerd.tracing.processor.v1.otlp"],
+      json!({ "endpoint": "localhost:4317", "protocol": "grpc", "insecure": true })
+    );
+    assert_eq!(plugins["io.containerd.internal.v1.tracing"]["sampling_ratio"], 0.01);
+    assert_eq!(plugins["io.containerd.grpc.v1.cri"]["sandbox_image"], "pause");
+  }
+
+  #[test]
+  fn it_sets_daemon_options() {
+    let mut config = ContainerdConfiguration::new(&DefaultRuntime::Containerd, "pause", REGISTRY_CONFIG_PATH).unwrap();
+    config
+      .set_daemon_options(Some(-999), Some("runtime-containerd.slice"))
+      .unwrap();
+
+    let conf = toml::to_string(&config).unwrap();
+    assert!(conf.contains("oom_score = -999\n"));
+    assert!(conf.contains("[cgroup]\npath = \"/runtime.slice/runtime-containerd.slice\"\n"));
+
+    let dropin = get_daemon_service_dropin(Some(-999), Some("runtime-containerd.slice"));
+    assert_eq!(
+      dropin,
+      "[Service]\nSlice=runtime-containerd.slice\nOOMScoreAdjust=-999\n"
+    );
+  }
+
+  #[test]
+  fn it_merges_config_file() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+      file.path(),
+      r#"
+version = 2
+oom_score = -999
+disabled_plugins = ["io.containerd.internal.v1.opt"]
+
+[plugins."io.containerd.grpc.v1.cri".containerd]
+discard_unpacked_layers = false
+snapshotter = "soci"
+"#,
+    )
+    .unwrap();
+
+    let mut config =
+      ContainerdConfiguration::new(&DefaultRuntime::Containerd, "pause", "/etc/containerd/certs.d").unwrap();
+    config.merge_file(file.path()).unwrap();
+
+    let cri = config.plugins.as_ref().unwrap()["plugins"]["io.containerd.grpc.v1.cri"].to_owned();
+    assert_eq!(cri["containerd"]["snapshotter"], "soci");
+    assert_eq!(cri["containerd"]["discard_unpacked_layers"], false);
+    // Settings not in the file keep their generated values
+    assert_eq!(cri["containerd"]["default_runtime_name"], "runc");
+    assert_eq!(cri["sandbox_image"], "pause");
+    assert_eq!(config.oom_score, Some(-999));
+    assert_eq!(config.disabled_plugins.as_ref().unwrap().len(), 1);
+
+    std::fs::write(file.path(), "version = 3\n").unwrap();
+    assert!(config.merge_file(file.path()).is_err());
+  }
 }
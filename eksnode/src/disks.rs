@@ -0,0 +1,313 @@
+//! Local storage on the NVMe instance store disks
+//!
+//! With `--local-disks raid0` the disks are striped into a single array, and the containerd, kubelet, and pod log
+//! state is relocated onto it; with `--local-disks mount` each disk is mounted on its own (i.e. - for the local volume
+//! static provisioner). Both match the layout of `setup-local-disks` on the EKS optimized AMI
+//!
+//! Instance store disks are wiped when the instance is stopped, so their mounts are `nofail`; the node otherwise drops
+//! into emergency mode at the next boot waiting for a filesystem UUID that no longer exists
+
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use tracing::{debug, info, warn};
+
+use crate::{commands::join::LocalDisks, systemd, utils};
+
+/// Directory the disks, or the array, are mounted under (i.e. - `/mnt/k8s-disks/0`)
+pub const MOUNT_DIR: &str = "/mnt/k8s-disks";
+
+/// Name of the raid0 array, which is assembled at `/dev/md/<name>`
+const ARRAY_NAME: &str = "kubernetes";
+
+const MDADM_CONFIG_PATH: &str = "/etc/mdadm.conf";
+
+const FSTAB_PATH: &str = "/etc/fstab";
+
+/// Options of every mount written to `/etc/fstab`, so that boot continues when the disks are gone
+const FSTAB_OPTIONS: &str = "nofail,x-systemd.device-timeout=10s";
+
+/// Directories relocated onto the raid0 array, by bind mounting a directory of the array over them
+const RELOCATED_DIRS: [&str; 3] = ["/var/lib/containerd", "/var/lib/kubelet", "/var/log/pods"];
+
+/// Line of `/etc/fstab`
+///
+/// The filesystems are never checked at boot (pass 0), since they are recreated rather than repaired
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FstabEntry {
+  /// Device (i.e. - `UUID=<uuid>`) or, for bind mounts, the directory mounted
+  pub source: String,
+  pub mount_point: String,
+  pub fs_type: String,
+  pub options: String,
+}
+
+impl FstabEntry {
+  fn xfs(source: &str, mount_point: &Path) -> Self {
+    Self {
+      source: source.to_owned(),
+      mount_point: mount_point.to_string_lossy().into_owned(),
+      fs_type: "xfs".to_owned(),
+      options: format!("defaults,noatime,{FSTAB_OPTIONS}"),
+    }
+  }
+
+  fn bind(source: &Path, mount_point: &str) -> Self {
+    Self {
+      source: source.to_string_lossy().into_owned(),
+      mount_point: mount_point.to_owned(),
+      fs_type: "none".to_owned(),
+      options: format!("bind,{FSTAB_OPTIONS}"),
+    }
+  }
+}
+
+impl fmt::Display for FstabEntry {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} {} {} {} 0 0",
+      self.source, self.mount_point, self.fs_type, self.options
+    )
+  }
+}
+
+/// Set up the instance store disks for local storage, returning the fstab entries of the mounts
+///
+/// Disks that are already formatted or mounted are reused, so that the node can be joined again. With `stop_services`,
+/// containerd and kubelet are stopped before their state is relocated; they are started again later in the join.
+///
+/// `/etc/fstab` and `/etc/mdadm.conf` are written under the root directory; when it is not `/` (a dry run), the disks
+/// are left untouched and the entries name the devices rather than the UUIDs of the filesystems not yet created
+pub async fn setup(mode: &LocalDisks, disks: &[PathBuf], root: &Path, stop_services: bool) -> Result<Vec<FstabEntry>> {
+  if disks.is_empty() {
+    warn!("No instance store disks found; local disks are not set up");
+    return Ok(Vec::new());
+  }
+  let dry_run = root != Path::new("/");
+
+  let mut entries = Vec::new();
+  match mode {
+    LocalDisks::Mount => {
+      for (index, disk) in disks.iter().enumerate() {
+        let mount_point = Path::new(MOUNT_DIR).join(index.to_string());
+        entries.push(mount_disk(disk, &mount_point, &[], dry_run)?);
+      }
+    }
+    LocalDisks::Raid0 => {
+      let array = create_array(disks, root, dry_run).await?;
+      let mount_point = Path::new(MOUNT_DIR).join("0");
+      // A stripe unit of 8 sectors (4KiB) matches the chunk size mdadm reports for the NVMe array
+      entries.push(mount_disk(&array, &mount_point, &["-l", "su=8b"], dry_run)?);
+
+      if stop_services && !dry_run {
+        systemd::systemctl(vec!["stop", "kubelet", "containerd"])?;
+      }
+      for dir in RELOCATED_DIRS {
+        let source = mount_point.join(dir.rsplit('/').next().unwrap_or(dir));
+        entries.push(relocate(dir, &source, dry_run)?);
+      }
+    }
+  }
+
+  let fstab_path = utils::rooted(root, FSTAB_PATH);
+  if let Some(parent) = fstab_path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  let fstab = std::fs::read_to_string(utils::map_path(&fstab_path)).unwrap_or_default();
+  utils::write_file(
+    add_fstab_entries(&fstab, &entries).as_bytes(),
+    &fstab_path,
+    Some(0o644),
+    !dry_run,
+  )
+  .await?;
+
+  Ok(entries)
+}
+
+/// Create the raid0 array of the disks, or reuse the array created when the node was previously joined
+async fn create_array(disks: &[PathBuf], root: &Path, dry_run: bool) -> Result<PathBuf> {
+  let array = Path::new("/dev/md").join(ARRAY_NAME);
+  if array.exists() {
+    debug!("Reusing raid0 array {}", array.display());
+    return Ok(array);
+  }
+  if dry_run {
+    info!(
+      "Would create raid0 array {} of {} disk(s)",
+      array.display(),
+      disks.len()
+    );
+    return Ok(array);
+  }
+
+  let raid_devices = format!("--raid-devices={}", disks.len());
+  let name = format!("--name={ARRAY_NAME}");
+  let mut args: Vec<&str> = vec![
+    "--create",
+    "--force",
+    "--run",
+    array.to_str().unwrap_or_default(),
+    "--level=0",
+    &name,
+    &raid_devices,
+  ];
+  let disks = disks
+    .iter()
+    .map(|disk| disk.to_string_lossy().into_owned())
+    .collect::<Vec<_>>();
+  args.extend(disks.iter().map(String::as_str));
+  exec("mdadm", args)?;
+
+  // Recorded so that the array is assembled under the same name at boot
+  let scan = exec("mdadm", vec!["--detail", "--scan"])?;
+  let config_path = utils::rooted(root, MDADM_CONFIG_PATH);
+  let config = std::fs::read_to_string(utils::map_path(&config_path)).unwrap_or_default();
+  utils::write_file(format!("{config}{scan}").as_bytes(), &config_path, Some(0o644), true).await?;
+  info!("Created raid0 array {} of {} disk(s)", array.display(), disks.len());
+
+  Ok(array)
+}
+
+/// Format the device with xfs unless it already is, and mount it
+fn mount_disk(device: &Path, mount_point: &Path, mkfs_args: &[&str], dry_run: bool) -> Result<FstabEntry> {
+  let name = device.to_string_lossy().into_owned();
+  if dry_run {
+    info!("Would format {name} with xfs and mount it at {}", mount_point.display());
+    return Ok(FstabEntry::xfs(&name, mount_point));
+  }
+
+  match blkid(&name, "TYPE")?.as_str() {
+    "" => {
+      let mut args = mkfs_args.to_vec();
+      args.push(name.as_str());
+      exec("mkfs.xfs", args)?;
+      info!("Formatted {name} with xfs");
+    }
+    "xfs" => debug!("{name} is already formatted with xfs"),
+    fs_type => bail!("{name} is formatted with {fs_type}; refusing to reformat a disk that may hold data"),
+  }
+
+  std::fs::create_dir_all(mount_point)?;
+  let mount_point_name = mount_point.to_string_lossy().into_owned();
+  if !is_mounted(&std::fs::read_to_string("/proc/mounts")?, &mount_point_name) {
+    exec("mount", vec!["-o", "defaults,noatime", &name, &mount_point_name])?;
+    info!("Mounted {name} at {mount_point_name}");
+  }
+
+  Ok(FstabEntry::xfs(&format!("UUID={}", blkid(&name, "UUID")?), mount_point))
+}
+
+/// Copy the contents of the directory to the source and bind mount the source over the directory
+fn relocate(dir: &str, source: &Path, dry_run: bool) -> Result<FstabEntry> {
+  if dry_run {
+    info!("Would relocate {dir} to {}", source.display());
+    return Ok(FstabEntry::bind(source, dir));
+  }
+  if is_mounted(&std::fs::read_to_string("/proc/mounts")?, dir) {
+    debug!("{dir} is already relocated");
+    return Ok(FstabEntry::bind(source, dir));
+  }
+
+  std::fs::create_dir_all(dir)?;
+  std::fs::create_dir_all(source)?;
+  let source_name = source.to_string_lossy().into_owned();
+  exec("cp", vec!["-a", &format!("{dir}/."), &format!("{source_name}/")])?;
+  exec("mount", vec!["--bind", &source_name, dir])?;
+  info!("Relocated {dir} to {source_name}");
+
+  Ok(FstabEntry::bind(source, dir))
+}
+
+fn blkid(device: &str, tag: &str) -> Result<String> {
+  let output = utils::cmd_exec("blkid", vec!["-o", "value", "-s", tag, device])?;
+  // blkid exits with 2 when the device has no filesystem
+  match output.status {
+    0 | 2 => Ok(output.stdout.trim().to_owned()),
+    _ => bail!("Unable to read the {tag} of {device}: {}", output.stderr.trim()),
+  }
+}
+
+fn exec(cmd: &str, args: Vec<&str>) -> Result<String> {
+  let output = utils::cmd_exec(cmd, args)?;
+  if output.status != 0 {
+    bail!("{cmd} failed: {}", output.stderr.trim());
+  }
+
+  Ok(output.stdout)
+}
+
+/// Whether a filesystem is mounted at the mount point, according to the contents of `/proc/mounts`
+fn is_mounted(mounts: &str, mount_point: &str) -> bool {
+  mounts
+    .lines()
+    .any(|line| line.split_whitespace().nth(1) == Some(mount_point))
+}
+
+/// Add the entries to the contents of `/etc/fstab`, replacing any entries of the same mount points
+fn add_fstab_entries(fstab: &str, entries: &[FstabEntry]) -> String {
+  let mut lines = fstab
+    .lines()
+    .filter(|line| {
+      let mount_point = line.split_whitespace().nth(1);
+      line.trim_start().starts_with('#') || !entries.iter().any(|e| mount_point == Some(e.mount_point.as_str()))
+    })
+    .map(str::to_owned)
+    .collect::<Vec<_>>();
+  lines.extend(entries.iter().map(|entry| entry.to_string()));
+
+  lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_adds_fstab_entries() {
+    let fstab = "#\nUUID=root / xfs defaults,noatime 1 1\nUUID=old /mnt/k8s-disks/0 xfs defaults 0 2\n";
+    let entries = [
+      FstabEntry::xfs("UUID=new", Path::new("/mnt/k8s-disks/0")),
+      FstabEntry::bind(Path::new("/mnt/k8s-disks/0/kubelet"), "/var/lib/kubelet"),
+    ];
+
+    assert_eq!(
+      add_fstab_entries(fstab, &entries),
+      "#\nUUID=root / xfs defaults,noatime 1 1\n\
+       UUID=new /mnt/k8s-disks/0 xfs defaults,noatime,nofail,x-systemd.device-timeout=10s 0 0\n\
+       /mnt/k8s-disks/0/kubelet /var/lib/kubelet none bind,nofail,x-systemd.device-timeout=10s 0 0\n"
+    );
+    // Joining the node again leaves the entries unchanged
+    let fstab = add_fstab_entries(fstab, &entries);
+    assert_eq!(add_fstab_entries(&fstab, &entries), fstab);
+  }
+
+  #[tokio::test]
+  async fn it_writes_fstab_under_root() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("etc")).unwrap();
+    std::fs::write(root.path().join("etc/fstab"), "UUID=root / xfs defaults,noatime 1 1\n").unwrap();
+
+    let disks = [PathBuf::from("/dev/nvme1n1"), PathBuf::from("/dev/nvme2n1")];
+    let entries = setup(&LocalDisks::Mount, &disks, root.path(), true).await.unwrap();
+
+    assert_eq!(entries[1].source, "/dev/nvme2n1");
+    assert_eq!(
+      std::fs::read_to_string(root.path().join("etc/fstab")).unwrap(),
+      "UUID=root / xfs defaults,noatime 1 1\n\
+       /dev/nvme1n1 /mnt/k8s-disks/0 xfs defaults,noatime,nofail,x-systemd.device-timeout=10s 0 0\n\
+       /dev/nvme2n1 /mnt/k8s-disks/1 xfs defaults,noatime,nofail,x-systemd.device-timeout=10s 0 0\n"
+    );
+  }
+
+  #[test]
+  fn it_checks_mounts() {
+    let mounts = "/dev/nvme0n1p1 / xfs rw 0 0\n/dev/md127 /mnt/k8s-disks/0 xfs rw,noatime 0 0\n";
+    assert!(is_mounted(mounts, "/mnt/k8s-disks/0"));
+    assert!(!is_mounted(mounts, "/mnt/k8s-disks"));
+  }
+}
@@ -1,33 +1,27 @@
 use std::{
-  collections::HashMap,
+  collections::{BTreeMap, HashMap},
   net::{IpAddr, Ipv4Addr, Ipv6Addr},
+  str::FromStr,
+  sync::OnceLock,
 };
 
 use anyhow::{Context, Result};
-use aws_config::{imds::client::Client as ImdsClient, provider_config::ProviderConfig, BehaviorVersion};
-use aws_sdk_ec2::{
-  config::{self, retry::RetryConfig},
-  Client,
-};
+use aws_config::{imds::client::Client as ImdsClient, provider_config::ProviderConfig};
+use aws_sdk_ec2::Client;
+use clap::ValueEnum;
 use http::Uri;
 use ipnet::Ipv4Net;
 use serde::{Deserialize, Serialize};
 use tokio::time::Duration;
+use tracing::{debug, warn};
 
-use crate::Assets;
+use crate::{
+  aws::{self, ResponseError},
+  events, Assets,
+};
 
-/// Get the EC2 client
-pub async fn get_client() -> Result<Client> {
-  let sdk_config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-  let client = Client::from_conf(
-    // Start with the shared environment configuration
-    config::Builder::from(&sdk_config)
-      // Set max attempts
-      .retry_config(RetryConfig::standard().with_max_attempts(3))
-      .build(),
-  );
-  Ok(client)
-}
+/// Static instance data keyed by instance type, parsed once by [`get_instances`]
+static INSTANCES: OnceLock<HashMap<String, Instance>> = OnceLock::new();
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Instance {
@@ -42,6 +36,10 @@ pub struct Instance {
   /// The GPU manufacturer (NVIDIA | AMD | Habana | None)
   pub gpu_manufacturer: String,
 
+  /// The total number of GPUs attached to the instance
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub gpu_count: Option<i32>,
+
   /// The hypervisor (nitro | xen | unknown)
   pub hypervisor: String,
 
@@ -53,14 +51,163 @@ pub struct Instance {
 
   /// The maximum number of ENIs
   pub maximum_network_interfaces: i32,
+
+  /// The baseline network bandwidth of the default network card in Gbps
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub network_baseline_bandwidth_gbps: Option<f64>,
+
+  /// The burst (peak) network bandwidth of the default network card in Gbps
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub network_burst_bandwidth_gbps: Option<f64>,
+
+  /// The baseline EBS throughput in MB/s, for EBS-optimized instances
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ebs_baseline_throughput_mbps: Option<f64>,
+
+  /// The maximum (burst) EBS throughput in MB/s, for EBS-optimized instances
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ebs_maximum_throughput_mbps: Option<f64>,
 }
 
+/// Get the static data of the instance type
+///
+/// Instance types newer than the static data (i.e. - a new size of a known family) fall back to the nearest known
+/// size of the same family, with the vCPUs scaled to the requested size and the GPU count left to be detected
 pub fn get_instance(instance: &str) -> Result<Option<Instance>> {
-  let file = Assets::get("ec2-instances.yaml").unwrap();
-  let contents = std::str::from_utf8(file.data.as_ref())?;
-  let instances: HashMap<String, Instance> = serde_yaml::from_str(contents)?;
+  let instances = get_instances()?;
+
+  if let Some(found) = instances.get(instance) {
+    return Ok(Some(found.clone()));
+  }
+
+  Ok(get_nearest_instance(instance, instances).map(|(nearest, mut found)| {
+    warn!("Instance type {instance} not found in static instance data; using the data of {nearest}");
+    let (requested, known) = (get_size_weight(instance), get_size_weight(&nearest));
+    if let (Some(requested), Some(known)) = (requested, known) {
+      found.default_vcpus = (i64::from(found.default_vcpus) * i64::from(requested) / i64::from(known)).max(1) as i32;
+    }
+    found.gpu_count = None;
+    found
+  }))
+}
+
+/// Get the static instance data, parsed once and reused for the life of the process
+fn get_instances() -> Result<&'static HashMap<String, Instance>> {
+  if let Some(instances) = INSTANCES.get() {
+    return Ok(instances);
+  }
+
+  let file = Assets::load("ec2-instances.yaml")?;
+  let instances: HashMap<String, Instance> = serde_yaml::from_slice(file.as_ref())?;
+
+  Ok(INSTANCES.get_or_init(|| instances))
+}
+
+/// Get the known size of the same family closest to the instance type, preferring the largest size below it so that
+/// ENI and pod limits are not overestimated
+fn get_nearest_instance(instance: &str, instances: &HashMap<String, Instance>) -> Option<(String, Instance)> {
+  let (family, _) = instance.split_once('.')?;
+  let requested = get_size_weight(instance)?;
+
+  let sizes = instances
+    .iter()
+    .filter(|(name, _)| name.split_once('.').is_some_and(|(f, _)| f == family))
+    .filter_map(|(name, instance)| get_size_weight(name).map(|weight| (weight, name, instance)))
+    .collect::<Vec<_>>();
+
+  // Sizes of equal weight (i.e. - `48xlarge` and `metal-48xl`) are ordered by name to keep the choice stable
+  let below = sizes
+    .iter()
+    .filter(|(weight, _, _)| *weight <= requested)
+    .max_by_key(|(weight, name, _)| (*weight, *name));
+  let above = sizes
+    .iter()
+    .filter(|(weight, _, _)| *weight > requested)
+    .min_by_key(|(weight, name, _)| (*weight, *name));
+  below
+    .or(above)
+    .map(|(_, name, instance)| (name.to_string(), (*instance).clone()))
+}
+
+/// Relative size of the instance type within its family, proportional to the vCPUs (i.e. - `large` = 16 = 2 vCPUs)
+///
+/// A bare `metal` size varies by family and has no weight
+fn get_size_weight(instance: &str) -> Option<u32> {
+  let (_, size) = instance.split_once('.')?;
+  match size {
+    "nano" => Some(1),
+    "micro" => Some(2),
+    "small" => Some(4),
+    "medium" => Some(8),
+    "large" => Some(16),
+    "xlarge" => Some(32),
+    size => {
+      let multiple = size
+        .strip_suffix("xlarge")
+        .or_else(|| size.strip_prefix("metal-").and_then(|s| s.strip_suffix("xl")))?;
+      multiple.parse::<u32>().ok().map(|m| m * 32)
+    }
+  }
+}
+
+/// Instance tag set to the name of the cluster on nodes launched by EKS managed node groups
+const CLUSTER_NAME_TAG: &str = "eks:cluster-name";
+
+/// Prefix of the instance tag marking the cluster the instance belongs to (i.e. - `kubernetes.io/cluster/<name>`)
+const CLUSTER_TAG_PREFIX: &str = "kubernetes.io/cluster/";
+
+/// Get the name of the cluster the instance belongs to from its tags
+///
+/// The `eks:cluster-name` tag is read from IMDS when instance tags are allowed in the instance metadata. Tag keys
+/// containing `/` are not available from IMDS, so the `kubernetes.io/cluster/<name>=owned` tag is retrieved from
+/// the EC2 API
+pub async fn discover_cluster_name(imds: &InstanceMetadata, client: &Client) -> Result<Option<String>> {
+  let imds_client = get_imds_client().await?;
+  if let Ok(name) = imds_client
+    .get(format!("/latest/meta-data/tags/instance/{CLUSTER_NAME_TAG}"))
+    .await
+  {
+    return Ok(Some(name.into()));
+  }
+  debug!("Tag {CLUSTER_NAME_TAG} not found in instance metadata, retrieving the instance tags from EC2");
 
-  Ok(instances.get(instance).cloned())
+  let instance_id = &imds.instance_id;
+  let tags = client
+    .describe_instances()
+    .instance_ids(instance_id.to_owned())
+    .send()
+    .await
+    .inspect_err(|e| events::record_api_failure("ec2:DescribeInstances", e))
+    .context(format!("Unable to describe instance {instance_id}"))?
+    .reservations()
+    .first()
+    .and_then(|r| r.instances().first())
+    .map(|i| {
+      i.tags()
+        .iter()
+        .filter_map(|t| Some((t.key()?.to_owned(), t.value().unwrap_or_default().to_owned())))
+        .collect::<Vec<_>>()
+    })
+    .unwrap_or_default();
+
+  Ok(get_cluster_name_from_tags(&tags))
+}
+
+/// Get the cluster name from the `eks:cluster-name` tag, or the `kubernetes.io/cluster/<name>` tag set to `owned`
+fn get_cluster_name_from_tags(tags: &[(String, String)]) -> Option<String> {
+  let tagged = tags
+    .iter()
+    .find(|(key, value)| key == CLUSTER_NAME_TAG && !value.is_empty())
+    .map(|(_, value)| value.to_owned());
+
+  tagged.or_else(|| {
+    tags.iter().find_map(|(key, value)| {
+      key
+        .strip_prefix(CLUSTER_TAG_PREFIX)
+        .filter(|name| !name.is_empty() && value == "owned")
+        .map(str::to_owned)
+    })
+  })
 }
 
 /// Get the IMDS client
@@ -81,12 +228,25 @@ async fn get_imds_client() -> Result<ImdsClient> {
   Ok(client.build())
 }
 
-pub async fn get_private_dns_name(instance_id: &str, client: &Client) -> Result<String> {
+/// Get the private DNS name of the instance
+///
+/// The IMDS `local-hostname` and `hostname` are used when they match the VPC DNS name of the instance, avoiding
+/// `DescribeInstances` calls that require additional IAM permissions and can be throttled when many nodes launch
+/// at once. DHCP option sets with a custom domain name change the IMDS hostnames, in which case the private DNS name
+/// is retrieved from the EC2 API
+pub async fn get_private_dns_name(imds: &InstanceMetadata, client: &Client) -> Result<String> {
+  if let Some(private_dns_name) = get_vpc_dns_name(imds) {
+    debug!("Using private DNS name {private_dns_name} from IMDS");
+    return Ok(private_dns_name);
+  }
+
+  let instance_id = &imds.instance_id;
   client
     .describe_instances()
     .instance_ids(instance_id.to_owned())
     .send()
     .await
+    .inspect_err(|e| events::record_api_failure("ec2:DescribeInstances", e))
     .context(format!("Unable to describe instance {instance_id}"))?
     .reservations
     .and_then(|reservations| {
@@ -102,6 +262,287 @@ pub async fn get_private_dns_name(instance_id: &str, client: &Client) -> Result<
     .context("Reservation.Instance.PrivateDNSName is empty")
 }
 
+/// The unique ID of the instance that an external provider (i.e. cloudprovider) can use to identify the node
+pub fn get_provider_id(availability_zone: &str, instance_id: &str) -> String {
+  format!("aws:///{availability_zone}/{instance_id}")
+}
+
+/// Source of the name of the node object on EC2 instances
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum NodeNameStrategy {
+  /// EC2 PrivateDnsName of the instance, which aws-iam-authenticator requires of the node name by default
+  #[default]
+  PrivateDns,
+  /// Hostname of the system, which follows the domain name of the DHCP option set of the VPC
+  Hostname,
+  /// EC2 resource based name of the instance (i.e. - `i-0e46d9575664f45bd.us-west-2.compute.internal`)
+  ResourceName,
+}
+
+/// Node name and provider ID of the instance, as written into the kubelet config by `join-cluster`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct NodeIdentity {
+  pub node_name: String,
+  pub provider_id: String,
+  pub instance_id: String,
+  pub availability_zone: String,
+  pub region: String,
+}
+
+/// Whether the node name of the strategy is retrieved from `DescribeInstances`, which is only the case for the
+/// private DNS name of VPCs whose DHCP option set changes the IMDS hostnames
+pub fn node_name_requires_ec2_api(imds: &InstanceMetadata, strategy: NodeNameStrategy) -> bool {
+  strategy == NodeNameStrategy::PrivateDns && get_vpc_dns_name(imds).is_none()
+}
+
+/// Get the node name and provider ID from the instance metadata
+///
+/// The EC2 API is only called when the private DNS name is used and cannot be determined from IMDS
+pub async fn get_node_identity(
+  imds: &InstanceMetadata,
+  client: &Client,
+  strategy: NodeNameStrategy,
+) -> Result<NodeIdentity> {
+  let node_name = match strategy {
+    NodeNameStrategy::PrivateDns => get_private_dns_name(imds, client).await?,
+    // Node names are lowercase, while hostnames may not be
+    NodeNameStrategy::Hostname => dns_lookup::get_hostname()
+      .context("Unable to get the hostname of the system")?
+      .to_lowercase(),
+    NodeNameStrategy::ResourceName => get_resource_name(imds),
+  };
+
+  Ok(NodeIdentity {
+    node_name,
+    provider_id: get_provider_id(&imds.availability_zone, &imds.instance_id),
+    instance_id: imds.instance_id.to_owned(),
+    availability_zone: imds.availability_zone.to_owned(),
+    region: imds.region.to_owned(),
+  })
+}
+
+/// Get the domain of the VPC DNS names in the region
+fn get_vpc_dns_domain(region: &str) -> String {
+  match region {
+    "us-east-1" => "ec2.internal".to_owned(),
+    region => format!("{region}.compute.internal"),
+  }
+}
+
+/// Get the resource based name of the instance, regardless of the hostname type the instance was launched with
+fn get_resource_name(imds: &InstanceMetadata) -> String {
+  format!("{}.{}", imds.instance_id, get_vpc_dns_domain(&imds.region))
+}
+
+/// Get the IMDS hostname that matches the VPC DNS name pattern of the instance
+///
+/// The host is either IP based (i.e. - `ip-10-0-1-23`) or resource based (i.e. - `i-0e46d9575664f45bd`), and the
+/// domain is `ec2.internal` in `us-east-1` or `<region>.compute.internal` in all other regions
+fn get_vpc_dns_name(imds: &InstanceMetadata) -> Option<String> {
+  let domain = get_vpc_dns_domain(&imds.region);
+  let mut hosts = vec![imds.instance_id.to_owned()];
+  if let Some(ip) = imds.local_ipv4 {
+    hosts.push(format!("ip-{}", ip.to_string().replace('.', "-")));
+  }
+
+  // Multiple names are separated by spaces when the DHCP option set has multiple domain names
+  imds
+    .hostnames
+    .iter()
+    .flat_map(|hostname| hostname.split_whitespace())
+    .find(|hostname| {
+      hostname
+        .split_once('.')
+        .is_some_and(|(host, rest)| rest == domain && hosts.iter().any(|h| h == host))
+    })
+    .map(String::from)
+}
+
+/// The type of zone the instance is launched in
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ZoneType {
+  #[default]
+  AvailabilityZone,
+  /// An extension of the region in a metro area (i.e. - `us-west-2-lax-1a`)
+  LocalZone,
+  /// A zone embedded in a telecommunication carrier network (i.e. - `us-east-1-wl1-bos-wlz-1`)
+  WavelengthZone,
+}
+
+impl ZoneType {
+  /// Determine the zone type from the zone name
+  pub fn from_zone_name(zone: &str) -> Self {
+    if zone.contains("-wlz-") {
+      return Self::WavelengthZone;
+    }
+
+    // Availability zones are the region name with a single letter suffix (i.e. - `us-west-2a`)
+    let region = get_parent_region(zone);
+    match zone.strip_prefix(&region) {
+      Some(suffix) if suffix.starts_with('-') => Self::LocalZone,
+      _ => Self::AvailabilityZone,
+    }
+  }
+}
+
+/// Get the parent region of a zone (availability, Local, or Wavelength zone)
+///
+/// Region names are returned unchanged (i.e. - `us-west-2a`, `us-west-2-lax-1a`, and `us-west-2` all map to `us-west-2`)
+pub fn get_parent_region(zone: &str) -> String {
+  let mut parts = Vec::new();
+  for part in zone.split('-') {
+    if part.starts_with(|c: char| c.is_ascii_digit()) {
+      parts.push(part.chars().take_while(|c| c.is_ascii_digit()).collect::<String>());
+      break;
+    }
+    parts.push(part.to_owned());
+  }
+
+  parts.join("-")
+}
+
+/// Purchasing option of the instance
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CapacityType {
+  OnDemand,
+  Spot,
+}
+
+impl CapacityType {
+  /// Parse the IMDS `instance-life-cycle` value; scheduled instances are billed as on-demand
+  pub fn from_instance_life_cycle(life_cycle: &str) -> Self {
+    match life_cycle.trim() {
+      "spot" => Self::Spot,
+      _ => Self::OnDemand,
+    }
+  }
+
+  /// Node labels used by EKS managed node groups and Karpenter to identify the capacity type
+  pub fn labels(&self) -> BTreeMap<String, String> {
+    let (eks, karpenter) = match self {
+      Self::OnDemand => ("ON_DEMAND", "on-demand"),
+      Self::Spot => ("SPOT", "spot"),
+    };
+
+    BTreeMap::from([
+      ("eks.amazonaws.com/capacityType".to_owned(), eks.to_owned()),
+      ("karpenter.sh/capacity-type".to_owned(), karpenter.to_owned()),
+    ])
+  }
+}
+
+/// Placement and network topology of the instance used to label the node
+#[derive(Debug, Default, PartialEq)]
+pub struct InstancePlacement {
+  pub region: String,
+  pub availability_zone: String,
+  /// The ID of the availability zone, which is consistent across accounts (i.e. - `usw2-az1`)
+  pub availability_zone_id: Option<String>,
+  pub placement_group: Option<String>,
+  /// The partition of the placement group when using the partition strategy
+  pub partition_number: Option<i32>,
+  pub capacity_reservation_id: Option<String>,
+  /// The network nodes of the instance, ordered from the top of the network hierarchy down to the instance
+  pub network_nodes: Vec<String>,
+}
+
+impl InstancePlacement {
+  /// Well-known node labels describing the placement of the instance
+  pub fn labels(&self) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::from([
+      ("topology.kubernetes.io/region".to_owned(), self.region.to_owned()),
+      (
+        "topology.kubernetes.io/zone".to_owned(),
+        self.availability_zone.to_owned(),
+      ),
+    ]);
+
+    if let Some(zone_id) = &self.availability_zone_id {
+      labels.insert("topology.k8s.aws/zone-id".to_owned(), zone_id.to_owned());
+    }
+    if let Some(group) = &self.placement_group {
+      labels.insert("topology.k8s.aws/placement-group".to_owned(), group.to_owned());
+    }
+    if let Some(partition) = self.partition_number {
+      labels.insert(
+        "topology.k8s.aws/placement-group-partition".to_owned(),
+        partition.to_string(),
+      );
+    }
+    if let Some(reservation) = &self.capacity_reservation_id {
+      labels.insert(
+        "topology.k8s.aws/capacity-reservation-id".to_owned(),
+        reservation.to_owned(),
+      );
+    }
+    for (layer, node) in self.network_nodes.iter().enumerate() {
+      labels.insert(
+        format!("topology.k8s.aws/network-node-layer-{}", layer + 1),
+        node.to_owned(),
+      );
+    }
+
+    labels
+  }
+}
+
+/// Get the placement and network topology of the instance
+///
+/// The zone ID is read from IMDS, the placement group and capacity reservation from `DescribeInstances`,
+/// and the network nodes from `DescribeInstanceTopology`. Instance topology is only available for a subset of
+/// instance types and requires `ec2:DescribeInstanceTopology`, so failures to retrieve it are logged and ignored
+pub async fn get_instance_placement(imds: &InstanceMetadata, client: &Client) -> Result<InstancePlacement> {
+  let imds_client = get_imds_client().await?;
+  let availability_zone_id = imds_client
+    .get("/latest/meta-data/placement/availability-zone-id")
+    .await
+    .ok()
+    .map(String::from);
+
+  let instance = client
+    .describe_instances()
+    .instance_ids(&imds.instance_id)
+    .send()
+    .await
+    .context(format!("Unable to describe instance {}", imds.instance_id))?
+    .reservations
+    .and_then(|reservations| reservations.into_iter().next())
+    .and_then(|reservation| reservation.instances)
+    .and_then(|instances| instances.into_iter().next())
+    .context(format!("Instance {} not found", imds.instance_id))?;
+  let placement = instance.placement();
+
+  let network_nodes = match client
+    .describe_instance_topology()
+    .instance_ids(&imds.instance_id)
+    .send()
+    .await
+  {
+    Ok(rsp) => rsp
+      .instances()
+      .first()
+      .map(|topology| topology.network_nodes().to_vec())
+      .unwrap_or_default(),
+    Err(e) => {
+      warn!("Unable to describe instance topology of {}: {e}", imds.instance_id);
+      vec![]
+    }
+  };
+
+  Ok(InstancePlacement {
+    region: imds.region.to_owned(),
+    availability_zone: imds.availability_zone.to_owned(),
+    availability_zone_id,
+    placement_group: placement
+      .and_then(|p| p.group_name())
+      .filter(|g| !g.is_empty())
+      .map(String::from),
+    partition_number: placement.and_then(|p| p.partition_number()),
+    capacity_reservation_id: instance.capacity_reservation_id().map(String::from),
+    network_nodes,
+  })
+}
+
 /// EC2 Instance metadata
 ///
 /// https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/instancedata-data-categories.html
@@ -109,6 +550,8 @@ pub async fn get_private_dns_name(instance_id: &str, client: &Client) -> Result<
 pub struct InstanceMetadata {
   /// The availablity zone in which the instance is launched
   pub availability_zone: String,
+  /// Whether the zone is an availability zone or a Local/Wavelength Zone
+  pub zone_type: ZoneType,
   /// The AWS Region in which the instance is launched
   pub region: String,
   /// The domain for AWS resources for the Region
@@ -127,87 +570,241 @@ pub struct InstanceMetadata {
   pub local_ipv4: Option<Ipv4Addr>,
   /// The IPv6 addresses associated with the interface
   pub ipv6_addresses: Option<Vec<Ipv6Addr>>,
+  /// The ID of the VPC in which the primary interface resides
+  pub vpc_id: Option<String>,
+  /// The public IPv4 address of the instance, if one is associated
+  pub public_ipv4: Option<Ipv4Addr>,
+  /// Whether the instance was launched as spot or on-demand capacity, if it could be read from IMDS
+  pub capacity_type: Option<CapacityType>,
   /// The instance type of the instance.
   pub instance_type: String,
   /// The ID of the instance.
   pub instance_id: String,
+  /// The private hostnames of the instance from `local-hostname` and `hostname`
+  pub hostnames: Vec<String>,
 }
 
 impl InstanceMetadata {
   pub fn get_node_ip(&self, ip_family: &crate::IpvFamily) -> Result<String> {
     let node_ip = match ip_family {
-      crate::IpvFamily::Ipv4 => IpAddr::V4(self.local_ipv4.expect("Failed to get node local IPv4 address")),
-      crate::IpvFamily::Ipv6 => {
-        let ips = self
+      crate::IpvFamily::Ipv4 => IpAddr::V4(
+        self
+          .local_ipv4
+          .ok_or(ResponseError::missing("IMDS network/interfaces/macs", "local-ipv4s"))?,
+      ),
+      crate::IpvFamily::Ipv6 => IpAddr::V6(
+        self
           .ipv6_addresses
-          .clone()
-          .expect("No IPv6 addresses found for the instance");
-        IpAddr::V6(ips.first().cloned().expect("Failed to get node IPv6 address"))
-      }
+          .as_ref()
+          .and_then(|ips| ips.first().copied())
+          .ok_or(ResponseError::missing("IMDS network/interfaces/macs", "ipv6s"))?,
+      ),
     };
 
     Ok(node_ip.to_string())
   }
 }
 
+/// Network interface reported by IMDS as its MAC address, network card index, and device number
+type ImdsInterface = (String, u32, u32);
+
+/// Select the MAC address of the primary network interface (network card 0, device number 0)
+///
+/// When the device number 0 interface is not present, the interface of network card 0 with the lowest device number is
+/// used. Instances with multiple network cards have a device number 0 interface on each card
+fn select_primary_mac(interfaces: &[ImdsInterface]) -> Option<String> {
+  interfaces
+    .iter()
+    .filter(|(_, network_card, _)| *network_card == 0)
+    .min_by_key(|(_, _, device_number)| *device_number)
+    .map(|(mac, _, _)| mac.to_owned())
+}
+
+/// Parse a numeric attribute of a network interface reported by IMDS (i.e. - `device-number`)
+fn parse_interface_number(value: &str, mac: &str, field: &str) -> Result<u32> {
+  value
+    .trim()
+    .parse::<u32>()
+    .with_context(|| format!("Failed to parse {field} of interface {mac}"))
+}
+
+/// Get the MAC address of the primary network interface from IMDS
+///
+/// `/latest/meta-data/mac` is not guaranteed to be the device number 0 interface when multiple
+/// network interfaces (i.e. - EFA) are attached at launch, so the network card and device number of each interface
+/// are checked. `network-card` is only reported on instances that support multiple network cards
+async fn get_primary_mac_address(client: &ImdsClient) -> Result<String> {
+  let macs = client.get("/latest/meta-data/network/interfaces/macs/").await?;
+
+  let mut interfaces = Vec::new();
+  for mac in macs.as_ref().lines().map(|m| m.trim().trim_end_matches('/')) {
+    if mac.is_empty() {
+      continue;
+    }
+    let uri = format!("/latest/meta-data/network/interfaces/macs/{mac}");
+    let network_card = match client.get(&format!("{uri}/network-card")).await {
+      Ok(s) => parse_interface_number(s.as_ref(), mac, "network card")?,
+      Err(_) => 0,
+    };
+    let device_number = client.get(&format!("{uri}/device-number")).await?;
+    let device_number = parse_interface_number(device_number.as_ref(), mac, "device number")?;
+    interfaces.push((mac.to_owned(), network_card, device_number));
+  }
+
+  match select_primary_mac(&interfaces) {
+    Some(mac) => Ok(mac),
+    None => Ok(client.get("/latest/meta-data/mac").await?.into()),
+  }
+}
+
+/// Parse each line of an IMDS network interface value (i.e. - `local-ipv4s`), naming the field that is invalid
+fn parse_imds_lines<T: FromStr>(value: &str, field: &'static str) -> Result<Vec<T>, ResponseError> {
+  value
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty())
+    .map(|line| {
+      line
+        .parse()
+        .map_err(|_| ResponseError::invalid("IMDS network/interfaces/macs", field, line))
+    })
+    .collect()
+}
+
 /// Get data from the IMDS endpoint
 ///
 /// Collects the relevant metadata from IMDS used in joining node to cluster
 pub async fn get_imds_data() -> Result<InstanceMetadata> {
   let client = get_imds_client().await?;
-  let availability_zone = client
+  let availability_zone: String = client
     .get("/latest/meta-data/placement/availability-zone")
     .await?
     .into();
-  let region = client.get("/latest/meta-data/placement/region").await?.into();
-  let domain = client.get("/latest/meta-data/services/domain").await?.into();
-  let mac_address = client.get("/latest/meta-data/mac").await?.into();
-  let vpc_ipv4_cidr_blocks = client
+  let zone_type = ZoneType::from_zone_name(&availability_zone);
+  // Local and Wavelength Zones report the parent region; derive it from the zone if the path is unavailable
+  let region: String = match client.get("/latest/meta-data/placement/region").await {
+    Ok(s) => s.into(),
+    Err(_) => get_parent_region(&availability_zone),
+  };
+  let domain = match client.get("/latest/meta-data/services/domain").await {
+    Ok(s) => s.into(),
+    Err(_) if region.starts_with("cn-") => "amazonaws.com.cn".to_owned(),
+    Err(_) => "amazonaws.com".to_owned(),
+  };
+  let mac_address = get_primary_mac_address(&client).await?;
+  let vpc_ipv4_cidr_blocks = match client
     .get(&format!(
       "/latest/meta-data/network/interfaces/macs/{mac_address}/vpc-ipv4-cidr-blocks"
     ))
     .await
-    .expect("Failed to get VPC IPv4 CIDR blocks")
-    .as_ref()
-    .split('\n')
-    .map(|s| s.parse::<Ipv4Net>().expect("Failed to parse VPC IPv4 CIDR block"))
-    .collect();
-  let local_ipv4 = match client.get("/latest/meta-data/local-ipv4").await {
-    Ok(s) => Some(
-      s.as_ref()
-        .parse::<Ipv4Addr>()
-        .expect("Failed to parse local IPv4 address"),
-    ),
+  {
+    Ok(s) => parse_imds_lines(s.as_ref(), "vpc-ipv4-cidr-blocks")?,
+    Err(e) => {
+      warn!("Unable to get VPC IPv4 CIDR blocks from IMDS, the cluster DNS IP cannot be derived from them: {e}");
+      vec![]
+    }
+  };
+  let local_ipv4s_uri = format!("/latest/meta-data/network/interfaces/macs/{mac_address}/local-ipv4s");
+  let local_ipv4 = match client.get(&local_ipv4s_uri).await {
+    Ok(s) => parse_imds_lines::<Ipv4Addr>(s.as_ref(), "local-ipv4s")?
+      .first()
+      .copied(),
     Err(_) => None,
   };
   let ipv6s_uri = format!("/latest/meta-data/network/interfaces/macs/{mac_address}/ipv6s");
   let ipv6_addresses = match client.get(&ipv6s_uri).await {
-    Ok(s) => Some(
-      s.as_ref()
-        .split('\n')
-        .map(|s| s.parse::<Ipv6Addr>().expect("Failed to parse IPv6 address"))
-        .collect(),
-    ),
+    Ok(s) => Some(parse_imds_lines(s.as_ref(), "ipv6s")?),
     Err(_) => None,
   };
+  let vpc_id = client
+    .get(&format!(
+      "/latest/meta-data/network/interfaces/macs/{mac_address}/vpc-id"
+    ))
+    .await
+    .ok()
+    .map(String::from);
+  let public_ipv4 = match client.get("/latest/meta-data/public-ipv4").await {
+    Ok(s) => s.as_ref().parse::<Ipv4Addr>().ok(),
+    Err(_) => None,
+  };
+  // Defaulting to on-demand would mislabel spot nodes, so the capacity type is left unknown
+  let capacity_type = match client.get("/latest/meta-data/instance-life-cycle").await {
+    Ok(s) => Some(CapacityType::from_instance_life_cycle(s.as_ref())),
+    Err(e) => {
+      warn!("Unable to get the instance life cycle from IMDS: {e}");
+      None
+    }
+  };
   let instance_type = client.get("/latest/meta-data/instance-type").await?.into();
   let instance_id = client.get("/latest/meta-data/instance-id").await?.into();
+  let mut hostnames = Vec::new();
+  for path in ["/latest/meta-data/local-hostname", "/latest/meta-data/hostname"] {
+    if let Ok(hostname) = client.get(path).await {
+      hostnames.push(hostname.into());
+    }
+  }
 
   let metadata = InstanceMetadata {
     availability_zone,
+    zone_type,
     region,
     domain,
     mac_address,
     vpc_ipv4_cidr_blocks,
     local_ipv4,
     ipv6_addresses,
+    vpc_id,
+    public_ipv4,
+    capacity_type,
     instance_type,
     instance_id,
+    hostnames,
   };
 
   Ok(metadata)
 }
 
+/// Identity of the instance from the IMDS instance identity document
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceIdentity {
+  pub account_id: String,
+  pub instance_id: String,
+  pub region: String,
+}
+
+impl InstanceIdentity {
+  /// The partition of the instance's region (i.e. - `aws-cn` for `cn-north-1`)
+  pub fn partition(&self) -> &'static str {
+    match self.region.as_str() {
+      r if r.starts_with("cn-") => "aws-cn",
+      r if r.starts_with("us-gov-") => "aws-us-gov",
+      r if r.starts_with("us-iso-") => "aws-iso",
+      r if r.starts_with("us-isob-") => "aws-iso-b",
+      _ => "aws",
+    }
+  }
+
+  /// The ARN of the instance
+  pub fn arn(&self) -> String {
+    format!(
+      "arn:{}:ec2:{}:{}:instance/{}",
+      self.partition(),
+      self.region,
+      self.account_id,
+      self.instance_id
+    )
+  }
+}
+
+/// Get the instance identity document from IMDS endpoint
+pub async fn get_instance_identity() -> Result<InstanceIdentity> {
+  let client = get_imds_client().await?;
+  let document = client.get("/latest/dynamic/instance-identity/document").await?;
+
+  Ok(serde_json::from_str(document.as_ref())?)
+}
+
 /// Get the instance type from IMDS endpoint
 pub async fn get_instance_type() -> Result<String> {
   let client = get_imds_client().await?;
@@ -226,7 +823,7 @@ pub async fn get_region() -> Result<String> {
 
 /// Returns all regions for the current partition
 pub async fn get_all_regions() -> Result<Vec<String>> {
-  let client = get_client().await?;
+  let client = aws::get_ec2_client().await;
 
   let regions = client.describe_regions().all_regions(true).send().await.map(|r| {
     r.regions
@@ -238,3 +835,249 @@ pub async fn get_all_regions() -> Result<Vec<String>> {
 
   Ok(regions)
 }
+
+#[cfg(test)]
+mod tests {
+  use rstest::*;
+
+  use super::*;
+
+  #[test]
+  fn it_selects_device_number_zero_as_primary() {
+    let interfaces = vec![
+      ("0e:00:00:00:00:02".to_owned(), 0, 1),
+      ("0e:00:00:00:00:01".to_owned(), 0, 0),
+      ("0e:00:00:00:00:03".to_owned(), 0, 2),
+    ];
+
+    assert_eq!(select_primary_mac(&interfaces), Some("0e:00:00:00:00:01".to_owned()));
+    assert_eq!(select_primary_mac(&[]), None);
+
+    // Each network card of an EFA instance has its own device number 0 interface
+    let interfaces = vec![
+      ("0e:00:00:00:01:00".to_owned(), 1, 0),
+      ("0e:00:00:00:00:01".to_owned(), 0, 0),
+      ("0e:00:00:00:02:00".to_owned(), 2, 0),
+    ];
+    assert_eq!(select_primary_mac(&interfaces), Some("0e:00:00:00:00:01".to_owned()));
+    assert_eq!(select_primary_mac(&interfaces[..1]), None);
+  }
+
+  #[rstest]
+  #[case("c5.large", Some(("c5.large", 2)))]
+  #[case("c5.8xlarge", Some(("c5.4xlarge", 32)))]
+  #[case("c5.48xlarge", Some(("c5.24xlarge", 192)))]
+  #[case("c7i.metal-96xl", Some(("c7i.metal-48xl", 384)))]
+  #[case("t3.4xlarge", Some(("t3.2xlarge", 16)))]
+  #[case("c5.medium", Some(("c5.large", 1)))]
+  #[case("c99.large", None)]
+  #[case("c5.metal-large", None)]
+  fn it_gets_nearest_instance(#[case] instance: &str, #[case] expected: Option<(&str, i32)>) {
+    let nearest = get_instance(instance).unwrap().map(|i| i.default_vcpus);
+    assert_eq!(
+      get_nearest_instance(instance, get_instances().unwrap()).map(|(name, _)| name),
+      expected.map(|(name, _)| name.to_owned())
+    );
+    assert_eq!(nearest, expected.map(|(_, vcpus)| vcpus));
+  }
+
+  #[rstest]
+  #[case("us-west-2", "us-west-2", ZoneType::AvailabilityZone)]
+  #[case("us-west-2a", "us-west-2", ZoneType::AvailabilityZone)]
+  #[case("us-gov-west-1b", "us-gov-west-1", ZoneType::AvailabilityZone)]
+  #[case("us-west-2-lax-1a", "us-west-2", ZoneType::LocalZone)]
+  #[case("us-east-1-wl1-bos-wlz-1", "us-east-1", ZoneType::WavelengthZone)]
+  fn it_gets_parent_region(#[case] zone: &str, #[case] region: &str, #[case] zone_type: ZoneType) {
+    assert_eq!(get_parent_region(zone), region);
+    assert_eq!(ZoneType::from_zone_name(zone), zone_type);
+  }
+
+  #[rstest]
+  #[case("us-west-2", "arn:aws:ec2:us-west-2:111122223333:instance/i-0e46d9575664f45bd")]
+  #[case("cn-north-1", "arn:aws-cn:ec2:cn-north-1:111122223333:instance/i-0e46d9575664f45bd")]
+  #[case(
+    "us-gov-west-1",
+    "arn:aws-us-gov:ec2:us-gov-west-1:111122223333:instance/i-0e46d9575664f45bd"
+  )]
+  fn it_gets_instance_arn(#[case] region: &str, #[case] expected: &str) {
+    let document = format!(
+      r#"{{"accountId": "111122223333", "instanceId": "i-0e46d9575664f45bd", "region": "{region}", "architecture": "x86_64"}}"#
+    );
+    let identity: InstanceIdentity = serde_json::from_str(&document).unwrap();
+
+    assert_eq!(identity.arn(), expected);
+  }
+
+  #[rstest]
+  #[case(
+    "us-west-2",
+    "ip-10-0-1-23.us-west-2.compute.internal",
+    Some("ip-10-0-1-23.us-west-2.compute.internal")
+  )]
+  #[case("us-east-1", "ip-10-0-1-23.ec2.internal", Some("ip-10-0-1-23.ec2.internal"))]
+  #[case(
+    "us-west-2",
+    "i-0e46d9575664f45bd.us-west-2.compute.internal",
+    Some("i-0e46d9575664f45bd.us-west-2.compute.internal")
+  )]
+  #[case(
+    "us-west-2",
+    "ip-10-0-1-23.corp.example.com ip-10-0-1-23.us-west-2.compute.internal",
+    Some("ip-10-0-1-23.us-west-2.compute.internal")
+  )]
+  #[case("us-west-2", "ip-10-0-1-23.corp.example.com", None)]
+  #[case("us-west-2", "ip-10-0-1-24.us-west-2.compute.internal", None)]
+  #[case("us-east-1", "ip-10-0-1-23.us-east-1.compute.internal", None)]
+  fn it_gets_vpc_dns_name(#[case] region: &str, #[case] hostname: &str, #[case] expected: Option<&str>) {
+    let imds = InstanceMetadata {
+      availability_zone: format!("{region}a"),
+      zone_type: ZoneType::AvailabilityZone,
+      region: region.to_owned(),
+      domain: "amazonaws.com".to_owned(),
+      mac_address: "0e:00:00:00:00:01".to_owned(),
+      vpc_ipv4_cidr_blocks: vec![],
+      local_ipv4: Some(Ipv4Addr::new(10, 0, 1, 23)),
+      ipv6_addresses: None,
+      vpc_id: None,
+      public_ipv4: None,
+      capacity_type: Some(CapacityType::OnDemand),
+      instance_type: "m5.large".to_owned(),
+      instance_id: "i-0e46d9575664f45bd".to_owned(),
+      hostnames: vec![hostname.to_owned()],
+    };
+
+    assert_eq!(get_vpc_dns_name(&imds).as_deref(), expected);
+    assert_eq!(
+      node_name_requires_ec2_api(&imds, NodeNameStrategy::PrivateDns),
+      expected.is_none()
+    );
+    assert!(!node_name_requires_ec2_api(&imds, NodeNameStrategy::ResourceName));
+    let domain = if region == "us-east-1" {
+      "ec2.internal"
+    } else {
+      "us-west-2.compute.internal"
+    };
+    assert_eq!(get_resource_name(&imds), format!("i-0e46d9575664f45bd.{domain}"));
+  }
+
+  #[rstest]
+  #[case(&[("eks:cluster-name", "example"), ("kubernetes.io/cluster/other", "owned")], Some("example"))]
+  #[case(&[("Name", "node"), ("kubernetes.io/cluster/example", "owned")], Some("example"))]
+  #[case(&[("kubernetes.io/cluster/example", "shared")], None)]
+  #[case(&[("eks:cluster-name", ""), ("kubernetes.io/cluster/", "owned")], None)]
+  fn it_gets_cluster_name_from_tags(#[case] tags: &[(&str, &str)], #[case] expected: Option<&str>) {
+    let tags = tags
+      .iter()
+      .map(|(key, value)| (key.to_string(), value.to_string()))
+      .collect::<Vec<_>>();
+
+    assert_eq!(get_cluster_name_from_tags(&tags).as_deref(), expected);
+  }
+
+  #[test]
+  fn it_gets_provider_id() {
+    assert_eq!(
+      get_provider_id("us-west-2a", "i-0e46d9575664f45bd"),
+      "aws:///us-west-2a/i-0e46d9575664f45bd"
+    );
+  }
+
+  #[rstest]
+  #[case("g5.xlarge", Some(1))]
+  #[case("g5.48xlarge", Some(8))]
+  #[case("p5.48xlarge", Some(8))]
+  #[case("m5.large", None)]
+  fn it_gets_gpu_count(#[case] instance: &str, #[case] expected: Option<i32>) {
+    assert_eq!(get_instance(instance).unwrap().unwrap().gpu_count, expected);
+
+    // Every GPU instance in the static data has a count, so that accelerator verification does not need the driver
+    for (name, instance) in get_instances().unwrap() {
+      assert_eq!(
+        instance.gpu_count.is_some(),
+        !instance.gpu_manufacturer.eq_ignore_ascii_case("none"),
+        "{name}"
+      );
+    }
+  }
+
+  #[test]
+  fn it_parses_instance_network_performance() {
+    let instance: Instance = serde_yaml::from_str(
+      r#"
+default_vcpus: 8
+eni_maximum_pods: 58
+gpu_manufacturer: none
+hypervisor: nitro
+instance_storage_supported: false
+ipv4_addresses_per_interface: 15
+maximum_network_interfaces: 4
+network_baseline_bandwidth_gbps: 2.5
+network_burst_bandwidth_gbps: 10.0
+ebs_baseline_throughput_mbps: 156.25
+ebs_maximum_throughput_mbps: 593.75
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(instance.network_baseline_bandwidth_gbps, Some(2.5));
+    assert_eq!(instance.network_burst_bandwidth_gbps, Some(10.0));
+    assert_eq!(instance.ebs_baseline_throughput_mbps, Some(156.25));
+    assert_eq!(instance.ebs_maximum_throughput_mbps, Some(593.75));
+  }
+
+  // The static instance data is populated by running `eksnode-gen update-ec2` with access to the EC2 API
+  #[test]
+  #[ignore]
+  fn it_gets_instance_network_performance() {
+    let instance = get_instance("m5.large").unwrap().unwrap();
+
+    assert_eq!(instance.network_baseline_bandwidth_gbps, Some(0.75));
+    assert_eq!(instance.network_burst_bandwidth_gbps, Some(10.0));
+    assert_eq!(instance.ebs_baseline_throughput_mbps, Some(81.25));
+    assert_eq!(instance.ebs_maximum_throughput_mbps, Some(593.75));
+  }
+
+  #[test]
+  fn it_parses_capacity_type() {
+    assert_eq!(CapacityType::from_instance_life_cycle("spot"), CapacityType::Spot);
+    assert_eq!(
+      CapacityType::from_instance_life_cycle("on-demand"),
+      CapacityType::OnDemand
+    );
+    assert_eq!(
+      CapacityType::from_instance_life_cycle("scheduled"),
+      CapacityType::OnDemand
+    );
+    assert_eq!(
+      CapacityType::Spot.labels().get("eks.amazonaws.com/capacityType"),
+      Some(&"SPOT".to_owned())
+    );
+  }
+
+  #[test]
+  fn it_gets_placement_labels() {
+    let placement = InstancePlacement {
+      region: "us-west-2".to_owned(),
+      availability_zone: "us-west-2a".to_owned(),
+      availability_zone_id: Some("usw2-az2".to_owned()),
+      placement_group: Some("training".to_owned()),
+      partition_number: Some(2),
+      capacity_reservation_id: Some("cr-0123456789abcdef0".to_owned()),
+      network_nodes: vec!["nn-1".to_owned(), "nn-2".to_owned(), "nn-3".to_owned()],
+    };
+
+    insta::assert_debug_snapshot!(placement.labels());
+  }
+
+  #[test]
+  fn it_parses_imds_lines() {
+    let cidrs = parse_imds_lines::<Ipv4Net>("10.0.0.0/16\n100.64.0.0/16\n", "vpc-ipv4-cidr-blocks").unwrap();
+    assert_eq!(cidrs.len(), 2);
+
+    let err = parse_imds_lines::<Ipv6Addr>("2600:1f14::1\nnot-an-ip", "ipv6s").unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      r#"IMDS network/interfaces/macs response has an invalid ipv6s: "not-an-ip""#
+    );
+  }
+}
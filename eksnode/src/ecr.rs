@@ -1,38 +1,49 @@
-use anyhow::Result;
-use aws_config::BehaviorVersion;
-use aws_sdk_ecr::{
-  config::{self, retry::RetryConfig, timeout::TimeoutConfig},
-  Client,
-};
-use tokio::time::Duration;
-use tracing::error;
-
-/// Get the ECR client
-pub async fn get_client() -> Result<Client> {
-  let sdk_config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-  let timeout_config = TimeoutConfig::builder()
-    .operation_attempt_timeout(Duration::from_secs(5))
-    .build();
-
-  let config = config::Builder::from(&sdk_config)
-    .retry_config(RetryConfig::adaptive().with_max_attempts(3))
-    .timeout_config(timeout_config)
-    .build();
-
-  Ok(Client::from_conf(config))
+use std::{path::Path, time::SystemTime};
+
+use crate::{ec2, events, secret::Secret, utils};
+use anyhow::{bail, Context, Result};
+use aws_sdk_ecr::Client;
+
+/// AWS shared config file used to assume a role for pulling images from ECR in another account
+pub const ASSUME_ROLE_CONFIG_PATH: &str = "/etc/eksnode/aws/ecr-assume-role";
+
+/// Profile within the assume role config file that is used for ECR authentication
+const ASSUME_ROLE_PROFILE: &str = "ecr-assume-role";
+
+/// ECR authorization token and the details of its validity
+#[derive(Debug)]
+pub struct Authorization {
+  /// Base64 encoded `AWS:<password>` credentials accepted by any ECR registry the caller has access to
+  pub token: Secret<String>,
+  /// Registry of the caller's account (i.e. - `https://111122223333.dkr.ecr.us-east-1.amazonaws.com`)
+  pub proxy_endpoint: Option<String>,
+  pub expires_at: Option<SystemTime>,
 }
 
-pub async fn get_authorization_token(client: &Client) -> Result<String> {
-  let resp = client.get_authorization_token().send().await?;
-  let token = resp
+pub async fn get_authorization(client: &Client) -> Result<Authorization> {
+  let resp = client
+    .get_authorization_token()
+    .send()
+    .await
+    .inspect_err(|e| events::record_api_failure("ecr:GetAuthorizationToken", e))?;
+  let data = resp
     .authorization_data
-    .expect("Failed to get ECR authorization data")
-    .pop()
-    .unwrap()
-    .authorization_token
-    .expect("Failed to get ECR authorization token");
+    .and_then(|mut data| data.pop())
+    .context("Failed to get ECR authorization data")?;
 
-  Ok(token)
+  Ok(Authorization {
+    token: Secret::new(
+      data
+        .authorization_token
+        .context("Failed to get ECR authorization token")?,
+    ),
+    proxy_endpoint: data.proxy_endpoint,
+    expires_at: data.expires_at.and_then(|t| SystemTime::try_from(t).ok()),
+  })
+}
+
+pub async fn get_authorization_token(client: &Client) -> Result<String> {
+  Ok(get_authorization(client).await?.token.into_inner())
 }
 
 /// Get the ECR URI for the given region and domain
@@ -40,7 +51,11 @@ pub async fn get_authorization_token(client: &Client) -> Result<String> {
 /// More details about the mappings in this file can be found here
 /// https://docs.aws.amazon.com/eks/latest/userguide/add-ons-images.html
 /// ECR endpoints https://docs.aws.amazon.com/general/latest/gr/ecr.html
+///
+/// Local and Wavelength Zones do not host ECR; the registry of the parent region is used
 pub fn get_ecr_uri(region: &str, enable_fips: bool) -> Result<String> {
+  let region = ec2::get_parent_region(region);
+  let region = region.as_str();
   let acct_id = match region {
     "af-south-1" => "877085696533",
     "ap-east-1" => "800184023465",
@@ -68,8 +83,8 @@ pub fn get_ecr_uri(region: &str, enable_fips: bool) -> Result<String> {
     _ => "amazonaws.com",
   };
 
-  if enable_fips && !region.starts_with("us-") {
-    error!("FIPS endpoints are only supported in US regions");
+  if enable_fips && !has_fips_endpoint(region) {
+    bail!("ECR does not have a FIPS endpoint in {region}");
   }
 
   let uri = match enable_fips {
@@ -80,6 +95,49 @@ pub fn get_ecr_uri(region: &str, enable_fips: bool) -> Result<String> {
   Ok(uri)
 }
 
+/// Whether ECR has a FIPS endpoint (`ecr-fips`) in the region, which is only the case for the US commercial and
+/// GovCloud regions
+///
+/// Local and Wavelength Zones are resolved to their parent region
+pub fn has_fips_endpoint(region: &str) -> bool {
+  matches!(
+    ec2::get_parent_region(region).as_str(),
+    "us-east-1" | "us-east-2" | "us-west-1" | "us-west-2" | "us-gov-east-1" | "us-gov-west-1"
+  )
+}
+
+/// Render the AWS shared config file that assumes the given role using the instance profile credentials
+pub fn get_assume_role_config(role_arn: &str) -> Result<String> {
+  if !role_arn.starts_with("arn:") || !role_arn.contains(":role/") {
+    bail!("Invalid ECR assume role ARN {role_arn}; expected arn:<partition>:iam::<account>:role/<name>");
+  }
+
+  Ok(format!(
+    "[profile {ASSUME_ROLE_PROFILE}]\nrole_arn = {role_arn}\ncredential_source = Ec2InstanceMetadata\n\
+     role_session_name = eksnode-ecr\n"
+  ))
+}
+
+/// Write the AWS shared config file that assumes the given role for ECR authentication
+pub async fn write_assume_role_config<P: AsRef<Path>>(role_arn: &str, path: P, chown: bool) -> Result<()> {
+  let contents = get_assume_role_config(role_arn)?;
+
+  if let Some(parent) = path.as_ref().parent() {
+    tokio::fs::create_dir_all(parent).await?;
+  }
+  utils::write_file(contents.as_bytes(), &path, Some(0o644), chown).await
+}
+
+/// Environment variables that direct the AWS SDKs (i.e. - ecr-credential-provider,
+/// amazon-ecr-credential-helper) to the assume role config file at the path
+pub fn get_assume_role_env(path: &str) -> Vec<(String, String)> {
+  vec![
+    ("AWS_CONFIG_FILE".to_owned(), path.to_owned()),
+    ("AWS_PROFILE".to_owned(), ASSUME_ROLE_PROFILE.to_owned()),
+    ("AWS_SDK_LOAD_CONFIG".to_owned(), "true".to_owned()),
+  ]
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -101,4 +159,28 @@ mod tests {
     let result = get_ecr_uri("us-east-1", true).unwrap();
     assert_eq!(result, "602401143452.dkr.ecr-fips.us-east-1.amazonaws.com");
   }
+
+  #[test]
+  fn it_rejects_ecr_uri_fips_without_endpoint() {
+    assert!(get_ecr_uri("eu-west-1", true).is_err());
+    assert!(get_ecr_uri("us-iso-east-1", true).is_err());
+    assert!(get_ecr_uri("us-west-2-lax-1a", true).is_ok());
+  }
+
+  #[test]
+  fn it_gets_ecr_uri_local_zone() {
+    let result = get_ecr_uri("us-west-2-lax-1a", false).unwrap();
+    assert_eq!(result, "602401143452.dkr.ecr.us-west-2.amazonaws.com");
+  }
+
+  #[test]
+  fn it_gets_assume_role_config() {
+    let result = get_assume_role_config("arn:aws:iam::111122223333:role/shared-ecr-pull").unwrap();
+    insta::assert_snapshot!(result);
+  }
+
+  #[test]
+  fn it_rejects_invalid_assume_role_arn() {
+    assert!(get_assume_role_config("shared-ecr-pull").is_err());
+  }
 }
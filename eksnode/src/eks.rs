@@ -1,53 +1,115 @@
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-
-use anyhow::{bail, Result};
-use aws_config::BehaviorVersion;
-use aws_sdk_eks::{
-  config::{self, retry::RetryConfig},
-  Client,
+use std::{
+  collections::BTreeMap,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr},
+  path::Path,
+  time::{Duration, SystemTime, UNIX_EPOCH},
 };
+
+use anyhow::{bail, Context, Result};
+use aws_sdk_eks::Client;
 use ipnet::{IpNet, Ipv4Net};
-use tracing::{debug, info};
-
-use crate::{commands::join::JoinClusterInput, IpvFamily};
-
-/// Get the EKS client
-async fn get_client() -> Result<Client> {
-  let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-  let client = Client::from_conf(
-    // Start with the shared environment configuration
-    config::Builder::from(&config)
-      // Set max attempts
-      .retry_config(RetryConfig::standard().with_max_attempts(3))
-      .build(),
-  );
-  Ok(client)
-}
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+  aws::{self, ResponseError},
+  commands::join::JoinClusterInput,
+  events,
+  secret::Secret,
+  utils, Assets, IpvFamily,
+};
+
+/// Disk cache of addon versions looked up from the EKS API
+pub const ADDON_VERSIONS_CACHE_PATH: &str = "/var/cache/eksnode/addon-versions.json";
+
+/// Duration for which cached addon versions are used before being refreshed from the EKS API
+const ADDON_VERSIONS_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Attempts to describe the cluster while the details required to join it are absent, before failing
+const DESCRIBE_CLUSTER_ATTEMPTS: u32 = 6;
+
+/// Delay before the first retry of describing the cluster, doubled for each retry after
+const DESCRIBE_CLUSTER_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+const DESCRIBE_CLUSTER: &str = "eks:DescribeCluster";
 
 /// Describe the cluster to extract the relevant details to join the cluster
 async fn describe_cluster(client: &Client, name: &str) -> Result<aws_sdk_eks::types::Cluster> {
   let request = client.describe_cluster().name(name);
-  let response = request.send().await?;
+  let response = request
+    .send()
+    .await
+    .inspect_err(|e| events::record_api_failure(DESCRIBE_CLUSTER, e))?;
+
+  Ok(
+    response
+      .cluster
+      .ok_or(ResponseError::missing(DESCRIBE_CLUSTER, "cluster"))?,
+  )
+}
+
+/// Get the details required to join the cluster that are absent from the described cluster
+///
+/// Newly created clusters are described before these have propagated (i.e. - without the certificate authority)
+fn get_missing_fields(cluster: &aws_sdk_eks::types::Cluster) -> Vec<&'static str> {
+  let ca = cluster.certificate_authority.as_ref().and_then(|ca| ca.data.as_deref());
+
+  [
+    ("name", cluster.name.as_deref()),
+    ("endpoint", cluster.endpoint.as_deref()),
+    ("certificateAuthority", ca),
+  ]
+  .into_iter()
+  .filter(|(_, value)| value.is_none_or(str::is_empty))
+  .map(|(field, _)| field)
+  .collect()
+}
+
+/// Describe the cluster, retrying with backoff until the details required to join it have propagated
+async fn describe_complete_cluster(client: &Client, name: &str) -> Result<aws_sdk_eks::types::Cluster> {
+  let mut delay = DESCRIBE_CLUSTER_RETRY_DELAY;
+  let mut attempt = 1;
+  loop {
+    let cluster = describe_cluster(client, name).await?;
+    let missing = get_missing_fields(&cluster);
+    if missing.is_empty() {
+      return Ok(cluster);
+    }
+    if attempt == DESCRIBE_CLUSTER_ATTEMPTS {
+      bail!(
+        "Cluster {name} is missing {} after {attempt} attempts; the cluster may still be creating",
+        missing.join(", ")
+      );
+    }
 
-  Ok(response.cluster.expect("Cluster not found"))
+    warn!(
+      "Cluster {name} is missing {}; retrying in {}s ({attempt}/{DESCRIBE_CLUSTER_ATTEMPTS})",
+      missing.join(", "),
+      delay.as_secs()
+    );
+    tokio::time::sleep(delay).await;
+    delay *= 2;
+    attempt += 1;
+  }
 }
 
-/// Given an IPv4 address, return its x.x.x.10 address
-fn ipv4_dns_ip_address(addr: Ipv4Addr) -> Result<Ipv4Addr> {
-  let mut octets = addr.octets();
-  if let Some(last) = octets.last_mut() {
-    *last = 10;
+/// Host index of the cluster DNS service within the service CIDR (i.e. - x.x.x.10)
+pub const DEFAULT_DNS_HOST_INDEX: u32 = 10;
+
+/// Given an IPv4 address, return the address at the host index from the start of its last octet (i.e. - x.x.x.10)
+fn ipv4_dns_ip_address(addr: Ipv4Addr, host_index: u32) -> Result<Ipv4Addr> {
+  match (u32::from(addr) & !0xff).checked_add(host_index) {
+    Some(result) => Ok(Ipv4Addr::from(result)),
+    None => bail!("Host index {host_index} is out of range for {addr}"),
   }
-  Ok(Ipv4Addr::from(octets))
 }
 
-/// Given an IPv6 address, return its :::a address
-fn ipv6_dns_ip_address(addr: Ipv6Addr) -> Result<Ipv6Addr> {
-  let mut segments = addr.segments();
-  if let Some(last) = segments.last_mut() {
-    *last = u16::from_str_radix("a", 16)?;
+/// Given an IPv6 address, return the address at the host index from the start of its last segment (i.e. - :::a)
+fn ipv6_dns_ip_address(addr: Ipv6Addr, host_index: u32) -> Result<Ipv6Addr> {
+  match (u128::from(addr) & !0xffff).checked_add(u128::from(host_index)) {
+    Some(result) => Ok(Ipv6Addr::from(result)),
+    None => bail!("Host index {host_index} is out of range for {addr}"),
   }
-  Ok(Ipv6Addr::from(segments))
 }
 
 /// Derive the IP address of the cluster DNS server
@@ -59,38 +121,37 @@ fn ipv6_dns_ip_address(addr: Ipv6Addr) -> Result<Ipv6Addr> {
 ///
 /// When --ip-family ipv6:
 /// --service-cidr is required, return :::a address from the CIDR
-fn derive_cluster_dns_ip(
+///
+/// The `.10`/`::a` suffix is replaced by --cluster-dns-host-index when CoreDNS runs on another service IP
+pub fn derive_cluster_dns_ip(
   service_cidr: &Option<IpNet>,
   ip_family: &IpvFamily,
   vpc_ipv4_cidr_blocks: &[Ipv4Net],
+  host_index: u32,
 ) -> Result<IpAddr> {
   match service_cidr {
-    Some(cidr) => match cidr.network() {
-      IpAddr::V4(addr) => {
-        let result = ipv4_dns_ip_address(addr)?;
-        println!("{result}");
-        Ok(IpAddr::V4(result))
-      }
-      IpAddr::V6(addr) => {
-        let result = ipv6_dns_ip_address(addr)?;
-        println!("{result}");
-        Ok(IpAddr::V6(result))
-      }
-    },
+    Some(cidr) => {
+      let result = match cidr.network() {
+        IpAddr::V4(addr) => IpAddr::V4(ipv4_dns_ip_address(addr, host_index)?),
+        IpAddr::V6(addr) => IpAddr::V6(ipv6_dns_ip_address(addr, host_index)?),
+      };
+      debug!("Cluster DNS IP: {result}");
+      Ok(result)
+    }
 
     None => match ip_family {
       IpvFamily::Ipv4 => {
-        let mut result = None;
-        for cidr in vpc_ipv4_cidr_blocks {
-          if cidr.addr().octets().first().unwrap_or(&192).eq(&10) {
-            result = Some(Ipv4Addr::new(172, 20, 0, 10));
-            break;
-          }
+        if vpc_ipv4_cidr_blocks.is_empty() {
+          warn!(
+            "VPC IPv4 CIDR blocks are unknown; assuming the service CIDR 10.100.0.0/16 for the cluster DNS IP. Set \
+             --service-cidr or --cluster-dns-ip if the cluster uses another service CIDR"
+          );
         }
-        if result.is_none() {
-          result = Some(Ipv4Addr::new(10, 100, 0, 10));
-        }
-        Ok(IpAddr::V4(result.unwrap()))
+        let addr = match vpc_ipv4_cidr_blocks.iter().any(|cidr| cidr.addr().octets()[0] == 10) {
+          true => Ipv4Addr::new(172, 20, 0, 0),
+          false => Ipv4Addr::new(10, 100, 0, 0),
+        };
+        Ok(IpAddr::V4(ipv4_dns_ip_address(addr, host_index)?))
       }
       IpvFamily::Ipv6 => bail!("--ip-family ipv6 requires --service-cidr to be supplied"),
     },
@@ -105,11 +166,85 @@ pub struct Cluster {
   /// Cluster API server endpoint
   pub endpoint: String,
   /// Base64 encoded certificate data
-  pub b64_ca: String,
+  pub b64_ca: Secret<String>,
   /// Identifies if the control plane is deployed on Outpost
   pub is_local_cluster: bool,
   /// Cluster DNS IP address
   pub cluster_dns_ip: IpAddr,
+  /// Cluster endpoint access configuration - only available when the cluster is described
+  pub endpoint_access: Option<EndpointAccess>,
+}
+
+/// Cluster API server endpoint access configuration from `resourcesVpcConfig`
+#[derive(Debug, Default)]
+pub struct EndpointAccess {
+  /// The VPC associated with the cluster
+  pub vpc_id: Option<String>,
+  /// Whether the private endpoint is enabled (reachable from within the cluster VPC)
+  pub private_access: bool,
+  /// Whether the public endpoint is enabled
+  pub public_access: bool,
+  /// CIDR blocks allowed to reach the public endpoint
+  pub public_access_cidrs: Vec<IpNet>,
+}
+
+impl EndpointAccess {
+  /// Verify the node is able to reach the cluster endpoint given where it resides
+  ///
+  /// Returns an error when the node definitively cannot reach the endpoint, and logs a warning when reachability
+  /// depends on network configuration that cannot be inspected from the node (NAT gateways, peering, transit gateways)
+  pub fn check_node_access(&self, node_vpc_id: Option<&str>, node_public_ipv4: Option<Ipv4Addr>) -> Result<()> {
+    let in_cluster_vpc = match (&self.vpc_id, node_vpc_id) {
+      (Some(cluster_vpc), Some(node_vpc)) => cluster_vpc == node_vpc,
+      // Unable to determine, assume the typical configuration
+      _ => true,
+    };
+
+    if self.private_access && in_cluster_vpc {
+      return Ok(());
+    }
+
+    if !self.public_access {
+      warn!(
+        "The cluster endpoint only allows private access from VPC {} but the node is in VPC {}. Ensure the node \
+         VPC is connected to the cluster VPC (peering, transit gateway) and can resolve the private endpoint",
+        self.vpc_id.as_deref().unwrap_or("unknown"),
+        node_vpc_id.unwrap_or("unknown"),
+      );
+      return Ok(());
+    }
+
+    if self.public_access_cidrs.iter().any(|cidr| cidr.prefix_len() == 0) {
+      return Ok(());
+    }
+
+    let cidrs = self
+      .public_access_cidrs
+      .iter()
+      .map(|c| c.to_string())
+      .collect::<Vec<_>>()
+      .join(", ");
+    match node_public_ipv4 {
+      Some(ip)
+        if !self
+          .public_access_cidrs
+          .iter()
+          .any(|cidr| cidr.contains(&IpAddr::V4(ip))) =>
+      {
+        bail!(
+          "The node public IP {ip} is not within the cluster public endpoint access CIDRs [{cidrs}]. Add the node \
+           IP (or its subnet) to publicAccessCidrs, or enable private endpoint access for nodes in the cluster VPC"
+        );
+      }
+      Some(_) => {}
+      None => warn!(
+        "The cluster endpoint is reached through the public endpoint, which is restricted to [{cidrs}]. Ensure the \
+         NAT gateway IP used by the node subnet is within the allowed CIDRs"
+      ),
+    }
+
+    Ok(())
+  }
 }
 
 /// Return the cluster details from the input collected
@@ -119,9 +254,10 @@ fn collect_cluster(node: &JoinClusterInput, cluster_dns_ip: IpAddr) -> Result<Op
       return Ok(Some(Cluster {
         name: node.cluster_name.to_owned(),
         endpoint,
-        b64_ca,
+        b64_ca: Secret::new(b64_ca),
         is_local_cluster: node.is_local_cluster,
         cluster_dns_ip,
+        endpoint_access: None,
       }));
     }
   }
@@ -129,18 +265,48 @@ fn collect_cluster(node: &JoinClusterInput, cluster_dns_ip: IpAddr) -> Result<Op
   Ok(None)
 }
 
-/// Collect cluster details from CLI input, or get from cluster describe call
-///
-/// If all the necessary details required to join a node to the cluster are provided, then
-/// we can save an API call. Otherwise, we need to describe the cluster to get the required info.
-pub async fn collect_or_get_cluster(node: &JoinClusterInput, vpc_ipv4_cidr_blocks: &[Ipv4Net]) -> Result<Cluster> {
+/// Get the cluster DNS IP from the CLI input, or derive it from the service CIDR
+fn get_cluster_dns_ip(node: &JoinClusterInput, vpc_ipv4_cidr_blocks: &[Ipv4Net]) -> Result<IpAddr> {
   // DNS cluster IP is not related to cluster - if it cannot be derived, it should fail
   let cluster_dns_ip = match node.cluster_dns_ip {
     Some(ip) => ip,
-    None => derive_cluster_dns_ip(&node.service_cidr, &node.ip_family, vpc_ipv4_cidr_blocks)?,
+    None => {
+      let host_index = node.cluster_dns_host_index.unwrap_or(DEFAULT_DNS_HOST_INDEX);
+      let ip = derive_cluster_dns_ip(&node.service_cidr, &node.ip_family, vpc_ipv4_cidr_blocks, host_index)?;
+      if let (Some(cidr), Some(_)) = (node.service_cidr, node.cluster_dns_host_index) {
+        if !cidr.contains(&ip) {
+          bail!("Cluster DNS IP {ip} at host index {host_index} is not within the service CIDR {cidr}");
+        }
+      }
+      ip
+    }
   };
   info!("DNS cluster IP address: {}", cluster_dns_ip);
 
+  Ok(cluster_dns_ip)
+}
+
+/// Get the cluster details of a standalone node, which has no API server to describe or connect to
+///
+/// The cluster DNS IP is still set in the kubelet config for static pods that use cluster DNS
+pub fn get_standalone_cluster(node: &JoinClusterInput, vpc_ipv4_cidr_blocks: &[Ipv4Net]) -> Result<Cluster> {
+  Ok(Cluster {
+    name: node.cluster_name.to_owned(),
+    endpoint: String::new(),
+    b64_ca: Secret::default(),
+    is_local_cluster: false,
+    cluster_dns_ip: get_cluster_dns_ip(node, vpc_ipv4_cidr_blocks)?,
+    endpoint_access: None,
+  })
+}
+
+/// Collect cluster details from CLI input, or get from cluster describe call
+///
+/// If all the necessary details required to join a node to the cluster are provided, then
+/// we can save an API call. Otherwise, we need to describe the cluster to get the required info.
+pub async fn collect_or_get_cluster(node: &JoinClusterInput, vpc_ipv4_cidr_blocks: &[Ipv4Net]) -> Result<Cluster> {
+  let cluster_dns_ip = get_cluster_dns_ip(node, vpc_ipv4_cidr_blocks)?;
+
   let cluster_name = &node.cluster_name.clone();
 
   match collect_cluster(node, cluster_dns_ip)? {
@@ -151,22 +317,41 @@ pub async fn collect_or_get_cluster(node: &JoinClusterInput, vpc_ipv4_cidr_block
     None => {
       debug!("Insufficient cluster details - describing cluster to get details");
 
-      let client = get_client().await?;
-      let describe = describe_cluster(&client, cluster_name).await?;
+      let client = aws::get_eks_client().await;
+      let describe = describe_complete_cluster(&client, cluster_name).await?;
+      let endpoint_access = describe.resources_vpc_config.map(|vpc_config| EndpointAccess {
+        vpc_id: vpc_config.vpc_id,
+        private_access: vpc_config.endpoint_private_access,
+        public_access: vpc_config.endpoint_public_access,
+        public_access_cidrs: vpc_config
+          .public_access_cidrs
+          .unwrap_or_default()
+          .iter()
+          .filter_map(|cidr| cidr.parse::<IpNet>().ok())
+          .collect(),
+      });
 
       Ok(Cluster {
-        name: describe.name.unwrap(),
-        endpoint: describe.endpoint.unwrap(),
-        b64_ca: describe.certificate_authority.unwrap().data.unwrap(),
+        name: describe.name.ok_or(ResponseError::missing(DESCRIBE_CLUSTER, "name"))?,
+        endpoint: describe
+          .endpoint
+          .ok_or(ResponseError::missing(DESCRIBE_CLUSTER, "endpoint"))?,
+        b64_ca: Secret::new(
+          describe
+            .certificate_authority
+            .and_then(|ca| ca.data)
+            .ok_or(ResponseError::missing(DESCRIBE_CLUSTER, "certificateAuthority.data"))?,
+        ),
         is_local_cluster: describe.outpost_config.is_some(),
         cluster_dns_ip,
+        endpoint_access,
       })
     }
   }
 }
 
 /// Addon version is relative to a given Kubernetes version
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AddonVersion {
   /// Latest supported version of the addon
   pub latest: String,
@@ -174,11 +359,13 @@ pub struct AddonVersion {
   pub default: String,
 }
 
+const DESCRIBE_ADDON_VERSIONS: &str = "eks:DescribeAddonVersions";
+
 /// Get the addon version details for the given addon and Kubernetes version
 ///
 /// Returns the default version and latest version of the addon for the given Kubernetes version
 pub async fn get_addon_versions(name: &str, kubernetes_version: &str) -> Result<AddonVersion> {
-  let client = get_client().await?;
+  let client = aws::get_eks_client().await;
 
   // Get all of the addon versions supported for the given addon and Kubernetes version
   let describe = client
@@ -186,19 +373,22 @@ pub async fn get_addon_versions(name: &str, kubernetes_version: &str) -> Result<
     .addon_name(name)
     .kubernetes_version(kubernetes_version)
     .send()
-    .await?;
+    .await
+    .inspect_err(|e| events::record_api_failure(DESCRIBE_ADDON_VERSIONS, e))?;
 
   // Since we are providing an addon name, we are only concerned with the first and only item
   let addon = describe
     .addons()
     .first()
-    .expect("describe addons failed to return results");
-  let latest_version = match addon.addon_versions().first() {
-    Some(version) => version.addon_version().unwrap_or_default(),
-    None => {
-      bail!("Version not found for addon {name}");
-    }
-  };
+    .ok_or(ResponseError::missing(DESCRIBE_ADDON_VERSIONS, "addons"))
+    .with_context(|| format!("Addon {name} is not available for Kubernetes {kubernetes_version}"))?;
+  let latest_version = addon
+    .addon_versions()
+    .first()
+    .ok_or(ResponseError::missing(DESCRIBE_ADDON_VERSIONS, "addonVersions"))
+    .with_context(|| format!("Version not found for addon {name}"))?
+    .addon_version()
+    .ok_or(ResponseError::missing(DESCRIBE_ADDON_VERSIONS, "addonVersion"))?;
 
   // The default version as specified by the EKS API for a given addon and Kubernetes version
   let default_version = addon
@@ -215,6 +405,150 @@ pub async fn get_addon_versions(name: &str, kubernetes_version: &str) -> Result<
   })
 }
 
+/// Get the version of the addon installed on the cluster
+///
+/// Returns `None` when the addon is not installed on the cluster as an EKS addon (i.e. - self-managed)
+pub async fn get_installed_addon_version(cluster_name: &str, name: &str) -> Result<Option<String>> {
+  let client = aws::get_eks_client().await;
+
+  match client
+    .describe_addon()
+    .cluster_name(cluster_name)
+    .addon_name(name)
+    .send()
+    .await
+  {
+    Ok(describe) => Ok(describe.addon.and_then(|addon| addon.addon_version)),
+    Err(e) => {
+      let e = e.into_service_error();
+      if e.is_resource_not_found_exception() {
+        Ok(None)
+      } else {
+        Err(e.into())
+      }
+    }
+  }
+}
+
+/// Addon version cached along with the time (seconds since the Unix epoch) it was retrieved
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedAddonVersion {
+  #[serde(flatten)]
+  version: AddonVersion,
+  fetched_at: u64,
+}
+
+/// Disk cache of addon versions keyed by `<addon>/<kubernetes version>`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AddonVersionsCache {
+  addons: BTreeMap<String, CachedAddonVersion>,
+}
+
+impl AddonVersionsCache {
+  /// Read the cache from disk, starting with an empty cache if it does not exist or cannot be parsed
+  fn read<P: AsRef<Path>>(path: P) -> Self {
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+      return Self::default();
+    };
+
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+      warn!("Ignoring invalid addon versions cache {}: {e}", path.as_ref().display());
+      Self::default()
+    })
+  }
+
+  async fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    if let Some(parent) = path.as_ref().parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+    let contents = serde_json::to_string_pretty(self)?;
+    utils::write_file(contents.as_bytes(), path, Some(0o644), false).await
+  }
+
+  /// Get the cached version, and whether it is still within the TTL, for the given key
+  fn get(&self, key: &str, now: u64) -> Option<(AddonVersion, bool)> {
+    self.addons.get(key).map(|cached| {
+      let fresh = now.saturating_sub(cached.fetched_at) < ADDON_VERSIONS_CACHE_TTL.as_secs();
+      (cached.version.clone(), fresh)
+    })
+  }
+
+  fn insert(&mut self, key: String, version: AddonVersion, now: u64) {
+    self.addons.insert(
+      key,
+      CachedAddonVersion {
+        version,
+        fetched_at: now,
+      },
+    );
+  }
+}
+
+/// Get the addon version details embedded in `eksnode` for the given addon and Kubernetes version
+fn get_embedded_addon_versions(name: &str, kubernetes_version: &str) -> Result<AddonVersion> {
+  let file = Assets::load("addon-versions.yaml")?;
+  let mut addons: BTreeMap<String, BTreeMap<String, AddonVersion>> = serde_yaml::from_slice(file.as_ref())?;
+
+  addons
+    .get_mut(name)
+    .and_then(|versions| versions.remove(kubernetes_version))
+    .with_context(|| format!("Addon version for {name}/{kubernetes_version} not found in addon-versions.yaml"))
+}
+
+/// Get the addon version details for the given addon and Kubernetes version, using the disk cache when possible
+///
+/// Cached versions are used while within the TTL. When `offline` is set, only the cache is consulted (irrespective
+/// of the TTL), falling back to the versions embedded in `eksnode`, and the EKS API is never called. If the EKS API
+/// call fails (i.e. - throttling), a stale cached version is used when available
+pub async fn get_cached_addon_versions<P: AsRef<Path>>(
+  name: &str,
+  kubernetes_version: &str,
+  cache_path: P,
+  offline: bool,
+) -> Result<AddonVersion> {
+  let key = format!("{name}/{kubernetes_version}");
+  let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+  let mut cache = AddonVersionsCache::read(&cache_path);
+  let cached = cache.get(&key, now);
+
+  match (cached, offline) {
+    (Some((version, _)), true) => return Ok(version),
+    (None, true) => {
+      warn!(
+        "Addon version for {key} not found in cache {}, using the version embedded in eksnode",
+        cache_path.as_ref().display()
+      );
+      return get_embedded_addon_versions(name, kubernetes_version);
+    }
+    (Some((version, true)), false) => {
+      debug!("Using cached addon version for {key}");
+      return Ok(version);
+    }
+    _ => {}
+  }
+
+  let version = match get_addon_versions(name, kubernetes_version).await {
+    Ok(version) => version,
+    Err(e) => match cache.get(&key, now) {
+      Some((version, _)) => {
+        warn!("Failed to get addon versions for {key}, using stale cached version: {e}");
+        return Ok(version);
+      }
+      None => return Err(e),
+    },
+  };
+
+  cache.insert(key, version.clone(), now);
+  if let Err(e) = cache.write(&cache_path).await {
+    warn!(
+      "Failed to write addon versions cache {}: {e}",
+      cache_path.as_ref().display()
+    );
+  }
+
+  Ok(version)
+}
+
 #[cfg(test)]
 mod tests {
   use ipnet::Ipv6Net;
@@ -222,13 +556,33 @@ mod tests {
 
   use super::*;
 
+  #[test]
+  fn it_gets_missing_cluster_fields() {
+    use aws_sdk_eks::types::{Certificate, Cluster as EksCluster};
+
+    let cluster = EksCluster::builder()
+      .name("example")
+      .endpoint("https://ABC.gr7.us-west-2.eks.amazonaws.com")
+      .certificate_authority(Certificate::builder().data("c3VwZXIgc2VjcmV0").build())
+      .build();
+    assert!(get_missing_fields(&cluster).is_empty());
+
+    // Newly created clusters are described before the endpoint and certificate authority are populated
+    let cluster = EksCluster::builder()
+      .name("example")
+      .endpoint("")
+      .certificate_authority(Certificate::builder().build())
+      .build();
+    assert_eq!(get_missing_fields(&cluster), vec!["endpoint", "certificateAuthority"]);
+  }
+
   #[rstest]
   #[case(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 10))]
   #[case(Ipv4Addr::new(10, 100, 12, 192), Ipv4Addr::new(10, 100, 12, 10))]
   #[case(Ipv4Addr::new(192, 168, 12, 34), Ipv4Addr::new(192, 168, 12, 10))]
   #[case(Ipv4Addr::new(172, 16, 123, 133), Ipv4Addr::new(172, 16, 123, 10))]
   fn ipv4_dns_ip_address_test(#[case] addr: Ipv4Addr, #[case] expected: Ipv4Addr) {
-    let result = ipv4_dns_ip_address(addr).unwrap();
+    let result = ipv4_dns_ip_address(addr, DEFAULT_DNS_HOST_INDEX).unwrap();
     assert_eq!(expected, result);
   }
 
@@ -238,7 +592,7 @@ mod tests {
   #[case("2001:db8:8:4::2".parse::<Ipv6Addr>().unwrap(), "2001:db8:8:4::a".parse::<Ipv6Addr>().unwrap())]
   #[case("2001:db8:85a3:8d3:1319:8a2e:370:7348".parse::<Ipv6Addr>().unwrap(), "2001:db8:85a3:8d3:1319:8a2e:370:a".parse::<Ipv6Addr>().unwrap())]
   fn ipv6_dns_ip_address_test(#[case] addr: Ipv6Addr, #[case] expected: Ipv6Addr) {
-    let result = ipv6_dns_ip_address(addr).unwrap();
+    let result = ipv6_dns_ip_address(addr, DEFAULT_DNS_HOST_INDEX).unwrap();
     assert_eq!(expected, result);
   }
 
@@ -267,7 +621,112 @@ mod tests {
     #[case] vpc_ipv4_cidr_blocks: &[Ipv4Net],
     #[case] expected: IpAddr,
   ) {
-    let result = derive_cluster_dns_ip(&service_cidr, ip_family, vpc_ipv4_cidr_blocks).unwrap();
+    let result = derive_cluster_dns_ip(&service_cidr, ip_family, vpc_ipv4_cidr_blocks, DEFAULT_DNS_HOST_INDEX).unwrap();
     assert_eq!(expected, result);
   }
+
+  #[rstest]
+  #[case("10.100.0.0/16", &IpvFamily::Ipv4, &[], 53, "10.100.0.53")]
+  #[case("10.100.0.0/16", &IpvFamily::Ipv4, &[], 300, "10.100.1.44")]
+  #[case("172.20.0.0/16", &IpvFamily::Ipv4, &[], 2, "172.20.0.2")]
+  #[case("fd00::/108", &IpvFamily::Ipv6, &[], 53, "fd00::35")]
+  #[case("", &IpvFamily::Ipv4, &["10.1.0.0/24".parse::<Ipv4Net>().unwrap()], 20, "172.20.0.20")]
+  fn it_derives_cluster_dns_ip_at_host_index(
+    #[case] service_cidr: &str,
+    #[case] ip_family: &IpvFamily,
+    #[case] vpc_ipv4_cidr_blocks: &[Ipv4Net],
+    #[case] host_index: u32,
+    #[case] expected: &str,
+  ) {
+    let service_cidr = service_cidr.parse::<IpNet>().ok();
+    let result = derive_cluster_dns_ip(&service_cidr, ip_family, vpc_ipv4_cidr_blocks, host_index).unwrap();
+    assert_eq!(result, expected.parse::<IpAddr>().unwrap());
+  }
+
+  fn endpoint_access(private_access: bool, public_access: bool, cidrs: &[&str]) -> EndpointAccess {
+    EndpointAccess {
+      vpc_id: Some("vpc-cluster".to_owned()),
+      private_access,
+      public_access,
+      public_access_cidrs: cidrs.iter().map(|c| c.parse::<IpNet>().unwrap()).collect(),
+    }
+  }
+
+  #[rstest]
+  // Private endpoint reachable from within the cluster VPC
+  #[case(endpoint_access(true, false, &[]), Some("vpc-cluster"), None, true)]
+  // Private only from another VPC is only a warning since connectivity may exist through peering/transit gateway
+  #[case(endpoint_access(true, false, &[]), Some("vpc-other"), None, true)]
+  // Public endpoint open to all
+  #[case(endpoint_access(false, true, &["0.0.0.0/0"]), Some("vpc-cluster"), Some(Ipv4Addr::new(54, 1, 2, 3)), true)]
+  // Public endpoint allows the node public IP
+  #[case(endpoint_access(false, true, &["54.1.2.0/24"]), Some("vpc-cluster"), Some(Ipv4Addr::new(54, 1, 2, 3)), true)]
+  // Public endpoint does not allow the node public IP
+  #[case(endpoint_access(false, true, &["203.0.113.0/24"]), Some("vpc-cluster"), Some(Ipv4Addr::new(54, 1, 2, 3)), false)]
+  // Node outside of cluster VPC falls back to the public endpoint
+  #[case(endpoint_access(true, true, &["203.0.113.0/24"]), Some("vpc-other"), Some(Ipv4Addr::new(54, 1, 2, 3)), false)]
+  // No public IP - egress through NAT cannot be verified
+  #[case(endpoint_access(false, true, &["203.0.113.0/24"]), Some("vpc-cluster"), None, true)]
+  fn it_checks_endpoint_access(
+    #[case] access: EndpointAccess,
+    #[case] node_vpc_id: Option<&str>,
+    #[case] node_public_ipv4: Option<Ipv4Addr>,
+    #[case] expected: bool,
+  ) {
+    let result = access.check_node_access(node_vpc_id, node_public_ipv4);
+    assert_eq!(result.is_ok(), expected);
+  }
+
+  fn write_cache(fetched_at: u64) -> tempfile::NamedTempFile {
+    let mut cache = AddonVersionsCache::default();
+    let version = AddonVersion {
+      latest: "v1.29.1-eksbuild.2".to_owned(),
+      default: "v1.29.0-eksbuild.1".to_owned(),
+    };
+    cache.insert("kube-proxy/1.29".to_owned(), version, fetched_at);
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), serde_json::to_string(&cache).unwrap()).unwrap();
+    file
+  }
+
+  #[tokio::test]
+  async fn it_gets_offline_addon_versions_from_cache() {
+    // Expired entries are still used when offline
+    let file = write_cache(0);
+
+    let result = get_cached_addon_versions("kube-proxy", "1.29", file.path(), true)
+      .await
+      .unwrap();
+    assert_eq!(result.default, "v1.29.0-eksbuild.1");
+    assert_eq!(result.latest, "v1.29.1-eksbuild.2");
+  }
+
+  #[tokio::test]
+  async fn it_gets_offline_addon_versions_not_cached() {
+    let file = write_cache(0);
+
+    let result = get_cached_addon_versions("vpc-cni", "1.29", file.path(), true)
+      .await
+      .unwrap();
+    assert_eq!(result.default, "v1.16.0-eksbuild.1");
+    assert_eq!(result.latest, "v1.18.3-eksbuild.1");
+
+    // Fails when neither the cache nor the embedded versions have the addon version
+    let result = get_cached_addon_versions("vpc-cni", "1.10", file.path(), true).await;
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn it_expires_cached_addon_versions() {
+    let file = write_cache(1_000);
+    let cache = AddonVersionsCache::read(file.path());
+
+    let (_, fresh) = cache.get("kube-proxy/1.29", 1_000 + 60).unwrap();
+    assert!(fresh);
+    let (_, fresh) = cache
+      .get("kube-proxy/1.29", 1_000 + ADDON_VERSIONS_CACHE_TTL.as_secs())
+      .unwrap();
+    assert!(!fresh);
+  }
 }
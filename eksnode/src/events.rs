@@ -0,0 +1,233 @@
+//! Durable log of the significant actions taken on the node
+//!
+//! Events are appended as JSON lines and outlive the journald retention, giving auditors and debuggers a record of
+//! what eksnode changed on the host. Recording is enabled for the command run by the binary with [`init`]; library
+//! callers and tests that do not call it record nothing
+//!
+//! The log is rotated once it reaches 1 MiB, so the events of at most two logs are retained
+
+use std::{
+  fmt,
+  fs::OpenOptions,
+  io::Write,
+  path::{Path, PathBuf},
+  sync::OnceLock,
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tabled::Tabled;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::debug;
+
+/// File the events are appended to
+pub const EVENTS_PATH: &str = "/var/lib/eksnode/events.jsonl";
+
+/// Size at which the event log is rotated to `<path>.1`, replacing the events previously rotated
+const MAX_EVENTS_BYTES: u64 = 1024 * 1024;
+
+/// Command that events are recorded for, set once by [`init`]
+static COMMAND: OnceLock<String> = OnceLock::new();
+
+/// Kind of action recorded
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+  /// File written to the host
+  FileWritten,
+  /// Action taken on a systemd unit (i.e. - started, restarted, enabled)
+  UnitChanged,
+  /// AWS API call that failed
+  ApiCallFailed,
+  /// eksnode command that failed
+  CommandFailed,
+}
+
+impl fmt::Display for EventKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::FileWritten => write!(f, "file-written"),
+      Self::UnitChanged => write!(f, "unit-changed"),
+      Self::ApiCallFailed => write!(f, "api-call-failed"),
+      Self::CommandFailed => write!(f, "command-failed"),
+    }
+  }
+}
+
+/// Action taken on the node
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Tabled)]
+pub struct Event {
+  /// RFC 3339 time the action was taken
+  pub timestamp: String,
+  /// eksnode command that took the action (i.e. - `join-cluster`)
+  pub command: String,
+  pub kind: EventKind,
+  /// File path, unit, or API operation acted on
+  pub subject: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  #[tabled(display_with = "display_detail")]
+  pub detail: Option<String>,
+}
+
+fn display_detail(detail: &Option<String>) -> String {
+  detail.clone().unwrap_or_else(|| "-".to_owned())
+}
+
+/// Enable recording of the events of the command
+pub fn init(command: &str) {
+  let _ = COMMAND.set(command.to_owned());
+}
+
+/// Append an event to the event log when recording is enabled
+///
+/// Failures are logged rather than returned so that the event log never fails the action it records
+pub fn record(kind: EventKind, subject: &str, detail: Option<String>) {
+  let Some(command) = COMMAND.get() else {
+    return;
+  };
+
+  let event = Event {
+    timestamp: now(),
+    command: command.to_owned(),
+    kind,
+    subject: subject.to_owned(),
+    detail,
+  };
+  if let Err(e) = append(&event, Path::new(EVENTS_PATH), MAX_EVENTS_BYTES) {
+    debug!("Unable to record event to {EVENTS_PATH}: {e}");
+  }
+}
+
+/// Current RFC 3339 time (i.e. - `2024-01-02T03:04:05Z`)
+pub fn now() -> String {
+  let now = OffsetDateTime::now_utc();
+  now
+    .replace_nanosecond(0)
+    .unwrap_or(now)
+    .format(&Rfc3339)
+    .unwrap_or_default()
+}
+
+/// Parse an RFC 3339 time
+pub fn parse_time(s: &str) -> Result<OffsetDateTime> {
+  Ok(OffsetDateTime::parse(s, &Rfc3339)?)
+}
+
+/// Record a failed AWS API call with the full error context of the SDK error
+pub fn record_api_failure<E: std::error::Error>(operation: &str, error: &E) {
+  record(EventKind::ApiCallFailed, operation, Some(error_chain(error)));
+}
+
+/// Format the error followed by each of its sources, which hold the details of SDK errors (i.e. - the service error
+/// code and message)
+fn error_chain(error: &dyn std::error::Error) -> String {
+  let mut chain = error.to_string();
+  let mut source = error.source();
+  while let Some(error) = source {
+    chain.push_str(&format!(": {error}"));
+    source = error.source();
+  }
+
+  chain
+}
+
+/// Path the log is rotated to once it reaches the maximum size
+fn rotated_path(path: &Path) -> PathBuf {
+  let mut rotated = path.as_os_str().to_owned();
+  rotated.push(".1");
+  PathBuf::from(rotated)
+}
+
+/// Append the value as a JSON line, rotating the file to `<path>.1` once it reaches the maximum size
+pub(crate) fn append<T: Serialize>(value: &T, path: &Path, max_bytes: u64) -> Result<()> {
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  if std::fs::metadata(path).is_ok_and(|m| m.len() >= max_bytes) {
+    std::fs::rename(path, rotated_path(path))?;
+  }
+  let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+  writeln!(file, "{}", serde_json::to_string(value)?)?;
+
+  Ok(())
+}
+
+/// Read the events in the order they were recorded, skipping lines that cannot be parsed
+///
+/// Events rotated out of the log are read first
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Event>> {
+  let rotated = std::fs::read_to_string(rotated_path(path.as_ref())).unwrap_or_default();
+  let contents = std::fs::read_to_string(path)?;
+
+  Ok(
+    rotated
+      .lines()
+      .chain(contents.lines())
+      .filter_map(|line| match serde_json::from_str(line) {
+        Ok(event) => Some(event),
+        Err(e) => {
+          debug!("Skipping invalid event {line:?}: {e}");
+          None
+        }
+      })
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_appends_and_reads_events() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.jsonl");
+    let event = Event {
+      timestamp: "2024-01-02T03:04:05Z".to_owned(),
+      command: "join-cluster".to_owned(),
+      kind: EventKind::FileWritten,
+      subject: "/etc/kubernetes/kubelet/kubelet-config.json".to_owned(),
+      detail: None,
+    };
+
+    append(&event, &path, MAX_EVENTS_BYTES).unwrap();
+    std::fs::write(&path, format!("{}not json\n", std::fs::read_to_string(&path).unwrap())).unwrap();
+    append(&event, &path, MAX_EVENTS_BYTES).unwrap();
+
+    assert_eq!(read(&path).unwrap(), vec![event.to_owned(), event]);
+  }
+
+  #[test]
+  fn it_rotates_events() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.jsonl");
+    let event = |subject: &str| Event {
+      timestamp: "2024-01-02T03:04:05Z".to_owned(),
+      command: "join-cluster".to_owned(),
+      kind: EventKind::UnitChanged,
+      subject: subject.to_owned(),
+      detail: None,
+    };
+
+    // Each event exceeds the maximum size, so the log is rotated before every append
+    for subject in ["containerd", "sandbox-image", "kubelet"] {
+      append(&event(subject), &path, 1).unwrap();
+    }
+
+    assert_eq!(read(&path).unwrap(), vec![event("sandbox-image"), event("kubelet")]);
+    assert!(!dir.path().join("events.jsonl.1.1").exists());
+  }
+
+  #[test]
+  fn it_formats_error_chain() {
+    let error = anyhow::anyhow!("AccessDeniedException: not authorized").context("service error");
+    let error: &(dyn std::error::Error + 'static) = error.as_ref();
+
+    assert_eq!(
+      error_chain(error),
+      "service error: AccessDeniedException: not authorized"
+    );
+    assert!(parse_time(&now()).is_ok());
+  }
+}
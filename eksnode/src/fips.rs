@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use crate::utils;
+
+/// Minimum TLS version of the kubelet server in FIPS mode
+pub const FIPS_TLS_MIN_VERSION: &str = "VersionTLS12";
+
+/// Kubelet server TLS 1.2 cipher suites approved for FIPS 140 (ECDHE key exchange with AES-GCM)
+pub const FIPS_TLS_CIPHER_SUITES: &[&str] = &[
+  "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+  "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+  "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+  "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+];
+
+/// Set to `1` when the kernel is booted in FIPS mode (`fips=1`)
+const FIPS_ENABLED_PATH: &str = "/proc/sys/crypto/fips_enabled";
+
+/// The system-wide crypto policy on hosts that use crypto-policies (i.e. - AL2023)
+const CRYPTO_POLICY_PATH: &str = "/etc/crypto-policies/state/current";
+
+/// Get the reasons the host under the root directory is not operating in FIPS mode
+///
+/// The crypto policy is only checked on hosts that use crypto-policies
+pub fn get_crypto_policy_issues(root: &Path) -> Vec<String> {
+  let mut issues = Vec::new();
+
+  match std::fs::read_to_string(utils::rooted(root, FIPS_ENABLED_PATH)) {
+    Ok(enabled) if enabled.trim() == "1" => {}
+    Ok(_) => issues.push("The kernel is not in FIPS mode; boot with fips=1".to_owned()),
+    Err(e) => issues.push(format!("Unable to read {FIPS_ENABLED_PATH}: {e}")),
+  }
+
+  if let Ok(policy) = std::fs::read_to_string(utils::rooted(root, CRYPTO_POLICY_PATH)) {
+    let policy = policy.trim();
+    if policy != "FIPS" && !policy.starts_with("FIPS:") {
+      issues.push(format!(
+        "The system crypto policy is {policy}; set it with `fips-mode-setup --enable`"
+      ));
+    }
+  }
+
+  issues
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_gets_crypto_policy_issues() {
+    let root = tempfile::tempdir().unwrap();
+    assert_eq!(get_crypto_policy_issues(root.path()).len(), 1);
+
+    let write = |path: &str, contents: &str| {
+      let path = utils::rooted(root.path(), path);
+      std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+      std::fs::write(path, contents).unwrap();
+    };
+    write(FIPS_ENABLED_PATH, "1\n");
+    assert!(get_crypto_policy_issues(root.path()).is_empty());
+
+    write(CRYPTO_POLICY_PATH, "DEFAULT\n");
+    assert_eq!(
+      get_crypto_policy_issues(root.path()),
+      vec!["The system crypto policy is DEFAULT; set it with `fips-mode-setup --enable`"]
+    );
+
+    write(CRYPTO_POLICY_PATH, "FIPS:OSPP\n");
+    assert!(get_crypto_policy_issues(root.path()).is_empty());
+  }
+}
@@ -1,9 +1,27 @@
-use std::fmt;
+use std::{collections::BTreeMap, fmt, path::Path};
 
-use anyhow::{anyhow, Result};
-use tracing::info;
+use anyhow::{anyhow, bail, Result};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tracing::{debug, info};
 
-use crate::utils::cmd_exec;
+use crate::{
+  utils::{self, cmd_exec},
+  Architecture, Assets,
+};
+
+/// Extended resource advertised by the NVIDIA device plugin
+pub const NVIDIA_GPU_RESOURCE: &str = "nvidia.com/gpu";
+
+/// Extended resource advertised by the Neuron device plugin
+pub const NEURON_RESOURCE: &str = "aws.amazon.com/neuron";
+
+/// Path to the NVIDIA container runtime used by containerd on NVIDIA GPU instances
+pub const NVIDIA_CONTAINER_RUNTIME: &str = "/usr/bin/nvidia-container-runtime";
+
+/// Version file written by the CUDA toolkit installer
+pub const CUDA_VERSION_PATH: &str = "/usr/local/cuda/version.json";
 
 enum NvidiaGpuClock {
   Graphics,
@@ -53,6 +71,211 @@ fn get_nvidia_max_clock(clock_type: &NvidiaGpuClock) -> Result<i32> {
   }
 }
 
+/// Validate the NVIDIA container runtime is available before configuring containerd to use it
+///
+/// NVIDIA GPU instances are available for both x86_64 and aarch64 (i.e. - g5g), but the AMI must ship the
+/// NVIDIA container toolkit built for the node's architecture
+pub fn validate_nvidia_runtime(arch: &Architecture) -> Result<()> {
+  if !Path::new(NVIDIA_CONTAINER_RUNTIME).is_file() {
+    bail!(
+      "NVIDIA GPU detected but {NVIDIA_CONTAINER_RUNTIME} was not found. Ensure the {arch} AMI includes \
+       the NVIDIA container toolkit"
+    );
+  }
+
+  let output = cmd_exec(NVIDIA_CONTAINER_RUNTIME, vec!["--version"])?;
+  if output.status != 0 {
+    bail!(
+      "NVIDIA container runtime is not functional on {arch}: {}",
+      output.stderr.trim()
+    );
+  }
+
+  Ok(())
+}
+
+/// NVIDIA GPU model and the versions of the driver and toolkits installed on the node
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NvidiaVersions {
+  /// GPU model name as reported by the driver (i.e. - `NVIDIA A10G`)
+  pub gpu: String,
+  pub driver: String,
+  /// Highest CUDA version supported by the driver
+  pub driver_cuda: Option<String>,
+  /// CUDA toolkit installed on the node, if any
+  pub cuda: Option<String>,
+  pub container_toolkit: Option<String>,
+}
+
+/// Parse the GPU model and driver version from `nvidia-smi --query-gpu=name,driver_version --format=csv,noheader`
+///
+/// All GPUs on an instance are the same model so only the first is used
+fn parse_gpu_query(output: &str) -> Result<(String, String)> {
+  output
+    .lines()
+    .find_map(|line| {
+      let (name, driver) = line.split_once(',')?;
+      Some((name.trim().to_owned(), driver.trim().to_owned()))
+    })
+    .ok_or_else(|| anyhow!("Unable to get NVIDIA GPU model and driver version"))
+}
+
+/// Parse the highest CUDA version supported by the driver from the `nvidia-smi` summary
+fn parse_driver_cuda_version(output: &str) -> Option<String> {
+  let (_, version) = output.split_once("CUDA Version:")?;
+  version.split_whitespace().next().map(String::from)
+}
+
+#[derive(Debug, Deserialize)]
+struct CudaVersionFile {
+  cuda: CudaVersionEntry,
+}
+
+#[derive(Debug, Deserialize)]
+struct CudaVersionEntry {
+  version: String,
+}
+
+/// Parse the CUDA toolkit version from its `version.json` file
+fn parse_cuda_version(contents: &str) -> Result<String> {
+  let file: CudaVersionFile = serde_json::from_str(contents)?;
+  Ok(file.cuda.version)
+}
+
+/// Get the NVIDIA GPU model and the versions of the driver, CUDA toolkit, and container toolkit
+pub fn get_nvidia_versions() -> Result<NvidiaVersions> {
+  let output = cmd_exec(
+    "nvidia-smi",
+    vec!["--query-gpu=name,driver_version", "--format=csv,noheader"],
+  )?;
+  if output.status != 0 {
+    bail!("nvidia-smi failed to query GPUs: {}", output.stderr.trim());
+  }
+  let (gpu, driver) = parse_gpu_query(&output.stdout)?;
+
+  let driver_cuda = cmd_exec("nvidia-smi", vec![])
+    .ok()
+    .and_then(|output| parse_driver_cuda_version(&output.stdout));
+
+  let cuda = match std::fs::read_to_string(CUDA_VERSION_PATH) {
+    Ok(contents) => Some(parse_cuda_version(&contents)?),
+    Err(_) => {
+      debug!("CUDA toolkit not found at {CUDA_VERSION_PATH}");
+      None
+    }
+  };
+
+  let container_toolkit = cmd_exec("nvidia-ctk", vec!["--version"])
+    .ok()
+    .and_then(|output| utils::get_semver(&output.stdout).ok())
+    .map(|version| version.to_string());
+
+  Ok(NvidiaVersions {
+    gpu,
+    driver,
+    driver_cuda,
+    cuda,
+    container_toolkit,
+  })
+}
+
+/// Driver versions supported by a GPU model
+#[derive(Debug, Deserialize)]
+struct GpuCompatibility {
+  model: String,
+  min_driver: Option<String>,
+  /// Last driver version to support the GPU, for models that newer drivers no longer support
+  max_driver: Option<String>,
+}
+
+/// Compatibility matrix of NVIDIA driver, CUDA toolkit, and container toolkit versions
+#[derive(Debug, Deserialize)]
+struct NvidiaCompatibility {
+  gpus: Vec<GpuCompatibility>,
+  /// Minimum driver version per CUDA toolkit major version
+  cuda: BTreeMap<String, String>,
+  container_toolkit: String,
+}
+
+fn get_nvidia_compatibility() -> Result<NvidiaCompatibility> {
+  let file = Assets::load("nvidia-compatibility.yaml")?;
+
+  Ok(serde_yaml::from_slice(file.as_ref())?)
+}
+
+/// Parse a driver version for comparison
+///
+/// Driver versions are not semantic versions (i.e. - `535.183.01`, `410.48`) so each component is compared numerically
+fn parse_driver_version(version: &str) -> Result<Vec<u32>> {
+  version
+    .split('.')
+    .map(|part| {
+      part
+        .parse::<u32>()
+        .map_err(|_| anyhow!("Invalid NVIDIA driver version: {version}"))
+    })
+    .collect()
+}
+
+/// Check the driver, CUDA toolkit, and container toolkit versions are compatible with each other and the GPU model,
+/// returning the issues found
+///
+/// Each issue describes a mismatch that prevents pods from using the GPU, such as a driver that is too old for the
+/// GPU model or the CUDA toolkit installed
+pub fn check_nvidia_compatibility(versions: &NvidiaVersions) -> Result<Vec<String>> {
+  let matrix = get_nvidia_compatibility()?;
+  let driver = parse_driver_version(&versions.driver)?;
+  let mut issues = Vec::new();
+
+  match matrix.gpus.iter().find(|g| versions.gpu.contains(&g.model)) {
+    Some(gpu) => {
+      if let Some(min) = &gpu.min_driver {
+        if driver < parse_driver_version(min)? {
+          issues.push(format!(
+            "NVIDIA driver {} does not support {}; driver {min} or later is required",
+            versions.driver, versions.gpu
+          ));
+        }
+      }
+      if let Some(max) = &gpu.max_driver {
+        if driver > parse_driver_version(max)? {
+          issues.push(format!(
+            "NVIDIA driver {} no longer supports {}; driver {max} is the last to support it",
+            versions.driver, versions.gpu
+          ));
+        }
+      }
+    }
+    None => debug!("No NVIDIA compatibility entry found for {}", versions.gpu),
+  }
+
+  if let Some(cuda) = &versions.cuda {
+    let major = cuda.split('.').next().unwrap_or_default();
+    match matrix.cuda.get(major) {
+      Some(min) if driver < parse_driver_version(min)? => issues.push(format!(
+        "CUDA toolkit {cuda} requires NVIDIA driver {min} or later but {} is installed",
+        versions.driver
+      )),
+      Some(_) => {}
+      None => debug!("No NVIDIA compatibility entry found for CUDA {cuda}"),
+    }
+  }
+
+  match &versions.container_toolkit {
+    Some(toolkit) => {
+      if Version::parse(toolkit)? < Version::parse(&matrix.container_toolkit)? {
+        issues.push(format!(
+          "NVIDIA container toolkit {toolkit} is not supported; {} or later is required",
+          matrix.container_toolkit
+        ));
+      }
+    }
+    None => issues.push("NVIDIA container toolkit (nvidia-ctk) was not found".to_owned()),
+  }
+
+  Ok(issues)
+}
+
 // Ref: https://developer.nvidia.com/blog/advanced-api-performance-setstablepowerstate/
 pub fn set_nvidia_max_clock() -> Result<()> {
   info!("Setting NVIDIA GPU to max clock");
@@ -74,3 +297,144 @@ pub fn set_nvidia_max_clock() -> Result<()> {
 
   Ok(())
 }
+
+/// Get the extended resource capacity advertised by the Node object, per resource name
+///
+/// Device plugins register their devices with kubelet, which reports them as the capacity of the node
+pub fn get_advertised_devices(node: &JsonValue) -> BTreeMap<String, usize> {
+  node
+    .pointer("/status/capacity")
+    .and_then(|capacity| capacity.as_object())
+    .into_iter()
+    .flatten()
+    .filter(|(resource, _)| resource.contains('/'))
+    .filter_map(|(resource, count)| Some((resource.to_owned(), count.as_str()?.parse().ok()?)))
+    .collect()
+}
+
+/// Get the number of NVIDIA GPUs visible to the driver
+pub fn get_nvidia_gpu_count() -> Result<usize> {
+  let output = cmd_exec("nvidia-smi", vec!["--list-gpus"])?;
+  if output.status != 0 {
+    bail!("nvidia-smi failed to list GPUs: {}", output.stderr.trim());
+  }
+
+  Ok(output.stdout.lines().filter(|l| l.starts_with("GPU ")).count())
+}
+
+/// Get the number of Neuron devices exposed by the driver
+pub fn get_neuron_device_count<P: AsRef<Path>>(dev: P) -> Result<usize> {
+  let count = std::fs::read_dir(dev)?
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| {
+      let name = entry.file_name();
+      let name = name.to_string_lossy();
+      name.strip_prefix("neuron").is_some_and(|n| n.parse::<u32>().is_ok())
+    })
+    .count();
+
+  Ok(count)
+}
+
+/// Verify the extended resource advertised by kubelet matches the expected number of devices
+pub fn verify_advertised_devices(advertised: &BTreeMap<String, usize>, resource: &str, expected: usize) -> Result<()> {
+  match advertised.get(resource) {
+    None => bail!(
+      "Node does not advertise {resource}. Verify the device plugin daemonset is running on this node \
+       and the driver is loaded"
+    ),
+    Some(count) if *count != expected => bail!(
+      "Node advertises {count} {resource} but {expected} are expected. Verify the driver detects all devices \
+       and check the device plugin logs"
+    ),
+    Some(count) => {
+      info!("Node advertises {count} {resource} as expected");
+      Ok(())
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rstest::*;
+
+  use super::*;
+
+  #[test]
+  fn it_gets_advertised_devices() {
+    let node = serde_json::json!({
+      "status": {
+        "capacity": {"cpu": "96", "memory": "1176548668Ki", "nvidia.com/gpu": "8", "pods": "250"},
+        "allocatable": {"nvidia.com/gpu": "7"}
+      }
+    });
+
+    let advertised = get_advertised_devices(&node);
+    assert_eq!(advertised, BTreeMap::from([(NVIDIA_GPU_RESOURCE.to_owned(), 8)]));
+    assert!(get_advertised_devices(&serde_json::json!({})).is_empty());
+  }
+
+  #[test]
+  fn it_verifies_advertised_devices() {
+    let advertised = BTreeMap::from([(NVIDIA_GPU_RESOURCE.to_owned(), 4)]);
+
+    assert!(verify_advertised_devices(&advertised, NVIDIA_GPU_RESOURCE, 4).is_ok());
+    assert!(verify_advertised_devices(&advertised, NVIDIA_GPU_RESOURCE, 8).is_err());
+    assert!(verify_advertised_devices(&advertised, NEURON_RESOURCE, 1).is_err());
+  }
+
+  #[test]
+  fn it_parses_nvidia_versions() {
+    assert_eq!(
+      parse_gpu_query("NVIDIA A10G, 535.183.01\nNVIDIA A10G, 535.183.01\n").unwrap(),
+      ("NVIDIA A10G".to_owned(), "535.183.01".to_owned())
+    );
+    assert!(parse_gpu_query("").is_err());
+
+    let summary = "| NVIDIA-SMI 535.183.01   Driver Version: 535.183.01   CUDA Version: 12.2     |";
+    assert_eq!(parse_driver_cuda_version(summary), Some("12.2".to_owned()));
+    assert_eq!(parse_driver_cuda_version("No devices were found"), None);
+
+    let contents = r#"{"cuda": {"name": "CUDA SDK", "version": "12.2.2"}, "cuda_cudart": {"version": "12.2.140"}}"#;
+    assert_eq!(parse_cuda_version(contents).unwrap(), "12.2.2");
+  }
+
+  #[rstest]
+  #[case("NVIDIA A10G", "535.183.01", Some("12.2.2"), Some("1.16.1"), 0)]
+  #[case("Tesla T4", "418.40.04", None, Some("1.16.1"), 0)]
+  // L40S is matched before L4
+  #[case("NVIDIA L40S", "525.60.13", None, Some("1.16.1"), 1)]
+  #[case("NVIDIA H100 80GB HBM3", "470.57.02", Some("12.2.2"), Some("1.16.1"), 2)]
+  #[case("Tesla K80", "535.183.01", None, Some("1.16.1"), 1)]
+  #[case("NVIDIA A10G", "535.183.01", None, Some("1.9.0"), 1)]
+  #[case("NVIDIA A10G", "535.183.01", None, None, 1)]
+  #[case("Unknown GPU", "410.48", Some("10.2.89"), Some("1.16.1"), 0)]
+  fn it_checks_nvidia_compatibility(
+    #[case] gpu: &str,
+    #[case] driver: &str,
+    #[case] cuda: Option<&str>,
+    #[case] container_toolkit: Option<&str>,
+    #[case] expected: usize,
+  ) {
+    let versions = NvidiaVersions {
+      gpu: gpu.to_owned(),
+      driver: driver.to_owned(),
+      driver_cuda: None,
+      cuda: cuda.map(String::from),
+      container_toolkit: container_toolkit.map(String::from),
+    };
+
+    let issues = check_nvidia_compatibility(&versions).unwrap();
+    assert_eq!(issues.len(), expected, "{issues:?}");
+  }
+
+  #[test]
+  fn it_counts_neuron_devices() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["neuron0", "neuron1", "null", "neuron_monitor"] {
+      std::fs::write(dir.path().join(name), "").unwrap();
+    }
+
+    assert_eq!(get_neuron_device_count(dir.path()).unwrap(), 2);
+  }
+}
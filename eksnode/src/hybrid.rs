@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// AWS shared config file written for IAM Roles Anywhere credentials
+pub const AWS_CONFIG_PATH: &str = "/etc/eksnode/aws/config";
+
+/// AWS shared credentials file maintained by the SSM agent for hybrid activations
+pub const SSM_CREDENTIALS_PATH: &str = "/root/.aws/credentials";
+
+/// The source of the AWS credentials used by the node
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum CredentialProvider {
+  /// EC2 instance profile credentials provided through IMDS
+  #[default]
+  Ec2,
+  /// IAM Roles Anywhere credentials using an X.509 certificate (hybrid node)
+  IamRolesAnywhere,
+  /// SSM hybrid activation credentials maintained by the SSM agent (hybrid node)
+  Ssm,
+}
+
+impl CredentialProvider {
+  /// Hybrid nodes do not run on EC2 and do not have access to IMDS
+  pub fn is_hybrid(&self) -> bool {
+    !matches!(self, Self::Ec2)
+  }
+
+  /// Environment variables that direct the AWS SDKs and CLI based tools to the hybrid node credentials
+  pub fn env(&self) -> Vec<(String, String)> {
+    match self {
+      Self::Ec2 => vec![],
+      Self::IamRolesAnywhere => vec![("AWS_CONFIG_FILE".to_owned(), AWS_CONFIG_PATH.to_owned())],
+      Self::Ssm => vec![(
+        "AWS_SHARED_CREDENTIALS_FILE".to_owned(),
+        SSM_CREDENTIALS_PATH.to_owned(),
+      )],
+    }
+  }
+}
+
+/// IAM Roles Anywhere configuration used with `--credential-provider iam-roles-anywhere`
+#[derive(Args, Debug, Default, Serialize, Deserialize)]
+pub struct RolesAnywhereInput {
+  /// The ARN of the IAM Roles Anywhere trust anchor
+  #[arg(long, env = "EKSNODE_ROLES_ANYWHERE_TRUST_ANCHOR_ARN")]
+  pub roles_anywhere_trust_anchor_arn: Option<String>,
+
+  /// The ARN of the IAM Roles Anywhere profile
+  #[arg(long, env = "EKSNODE_ROLES_ANYWHERE_PROFILE_ARN")]
+  pub roles_anywhere_profile_arn: Option<String>,
+
+  /// The ARN of the IAM role to assume
+  #[arg(long, env = "EKSNODE_ROLES_ANYWHERE_ROLE_ARN")]
+  pub roles_anywhere_role_arn: Option<String>,
+
+  /// Path to the X.509 certificate used to authenticate with IAM Roles Anywhere
+  #[arg(long, env = "EKSNODE_ROLES_ANYWHERE_CERTIFICATE")]
+  pub roles_anywhere_certificate: Option<PathBuf>,
+
+  /// Path to the private key of the X.509 certificate
+  #[arg(long, env = "EKSNODE_ROLES_ANYWHERE_PRIVATE_KEY")]
+  pub roles_anywhere_private_key: Option<PathBuf>,
+}
+
+impl RolesAnywhereInput {
+  /// Get the `credential_process` command for the AWS signing helper
+  fn credential_process(&self) -> Result<String> {
+    let (Some(trust_anchor), Some(profile), Some(role), Some(cert), Some(key)) = (
+      &self.roles_anywhere_trust_anchor_arn,
+      &self.roles_anywhere_profile_arn,
+      &self.roles_anywhere_role_arn,
+      &self.roles_anywhere_certificate,
+      &self.roles_anywhere_private_key,
+    ) else {
+      bail!(
+        "--credential-provider iam-roles-anywhere requires --roles-anywhere-trust-anchor-arn, \
+         --roles-anywhere-profile-arn, --roles-anywhere-role-arn, --roles-anywhere-certificate, \
+         and --roles-anywhere-private-key"
+      );
+    };
+
+    Ok(format!(
+      "/usr/local/bin/aws_signing_helper credential-process --certificate {} --private-key {} \
+       --trust-anchor-arn {trust_anchor} --profile-arn {profile} --role-arn {role}",
+      cert.display(),
+      key.display(),
+    ))
+  }
+
+  /// Render the AWS shared config file that sources credentials from IAM Roles Anywhere
+  pub fn get_aws_config(&self, region: &str) -> Result<String> {
+    let credential_process = self.credential_process()?;
+
+    Ok(format!(
+      "[default]\nregion = {region}\ncredential_process = {credential_process}\n"
+    ))
+  }
+
+  /// Write the AWS shared config file that sources credentials from IAM Roles Anywhere
+  pub async fn write_aws_config<P: AsRef<Path>>(&self, path: P, region: &str, chown: bool) -> Result<()> {
+    let contents = self.get_aws_config(region)?;
+
+    if let Some(parent) = path.as_ref().parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+    utils::write_file(contents.as_bytes(), path, Some(0o600), chown).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_renders_roles_anywhere_aws_config() {
+    let input = RolesAnywhereInput {
+      roles_anywhere_trust_anchor_arn: Some("arn:aws:rolesanywhere:us-west-2:111122223333:trust-anchor/ta".to_owned()),
+      roles_anywhere_profile_arn: Some("arn:aws:rolesanywhere:us-west-2:111122223333:profile/p".to_owned()),
+      roles_anywhere_role_arn: Some("arn:aws:iam::111122223333:role/hybrid-node".to_owned()),
+      roles_anywhere_certificate: Some(PathBuf::from("/etc/iam/pki/server.pem")),
+      roles_anywhere_private_key: Some(PathBuf::from("/etc/iam/pki/server.key")),
+    };
+
+    insta::assert_snapshot!(input.get_aws_config("us-west-2").unwrap());
+  }
+
+  #[test]
+  fn it_requires_all_roles_anywhere_inputs() {
+    let input = RolesAnywhereInput {
+      roles_anywhere_role_arn: Some("arn:aws:iam::111122223333:role/hybrid-node".to_owned()),
+      ..RolesAnywhereInput::default()
+    };
+
+    assert!(input.get_aws_config("us-west-2").is_err());
+  }
+}
@@ -0,0 +1,180 @@
+//! Health of the NVMe instance store disks
+//!
+//! Instance store disks that are dead on arrival otherwise surface later as pod I/O errors; the disks are checked
+//! before they are used for local storage, and their NVMe health data is collected into the debug bundle
+
+use std::{
+  fs::File,
+  io::{Read, Seek, SeekFrom},
+  path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::utils;
+
+/// Model reported by the NVMe controller of instance store disks
+const INSTANCE_STORE_MODEL: &str = "Amazon EC2 NVMe Instance Storage";
+
+/// Directory the NVMe health data is written to, collected by `eksnode debug --create-log-archive`
+pub const HEALTH_LOG_DIR: &str = "/var/log/eksnode/instance-store";
+
+/// Bytes read from the start and end of each disk by the smoke test
+const SMOKE_TEST_BYTES: usize = 1024 * 1024;
+
+/// SMART / health log page of an NVMe disk as reported by `nvme smart-log --output-format=json`
+#[derive(Debug, Deserialize)]
+struct SmartLog {
+  /// Bit field of critical warnings; any set bit indicates a failing disk
+  critical_warning: u64,
+  #[serde(default)]
+  media_errors: u64,
+}
+
+/// Get the block devices of the instance store disks (i.e. - `/dev/nvme1n1`)
+pub fn get_instance_store_disks<P: AsRef<Path>>(sys_block: P) -> Result<Vec<PathBuf>> {
+  let mut disks = Vec::new();
+  for entry in std::fs::read_dir(sys_block)? {
+    let entry = entry?;
+    let name = entry.file_name().to_string_lossy().into_owned();
+    if !name.starts_with("nvme") {
+      continue;
+    }
+    let model = std::fs::read_to_string(entry.path().join("device/model")).unwrap_or_default();
+    if model.trim() == INSTANCE_STORE_MODEL {
+      disks.push(Path::new("/dev").join(name));
+    }
+  }
+  disks.sort();
+
+  Ok(disks)
+}
+
+fn parse_smart_log(json: &str) -> Result<SmartLog> {
+  Ok(serde_json::from_str(json)?)
+}
+
+/// Get the SMART / health log page of the disk from nvme-cli
+fn get_smart_log(disk: &Path) -> Result<String> {
+  let disk = disk.to_string_lossy();
+  let output = utils::cmd_exec("nvme", vec!["smart-log", &disk, "--output-format=json"])?;
+  if output.status != 0 {
+    bail!("Unable to read the SMART log of {disk}: {}", output.stderr.trim());
+  }
+
+  Ok(output.stdout)
+}
+
+/// Read the start and end of the disk, failing when the disk does not return the data
+///
+/// The disks are only read, since the data on them may already be in use when a node is re-joined
+fn smoke_test(disk: &Path) -> Result<()> {
+  let mut file = File::open(disk)?;
+  let mut buffer = vec![0; SMOKE_TEST_BYTES];
+  file.read_exact(&mut buffer)?;
+
+  let size = file.seek(SeekFrom::End(0))?;
+  if size > SMOKE_TEST_BYTES as u64 {
+    file.seek(SeekFrom::Start(size - SMOKE_TEST_BYTES as u64))?;
+    file.read_exact(&mut buffer)?;
+  }
+
+  Ok(())
+}
+
+/// Check that the instance store disks report no critical warnings and can be read
+pub fn check_disks(disks: &[PathBuf]) -> Result<()> {
+  for disk in disks {
+    let name = disk.display();
+    match get_smart_log(disk).and_then(|json| parse_smart_log(&json)) {
+      Ok(log) if log.critical_warning != 0 => {
+        bail!(
+          "Instance store disk {name} reports critical warning {:#x}",
+          log.critical_warning
+        )
+      }
+      Ok(log) if log.media_errors != 0 => warn!("Instance store disk {name} reports {} media errors", log.media_errors),
+      Ok(_) => {}
+      // nvme-cli is not installed on every AMI; the read smoke test still applies
+      Err(e) => debug!("Unable to check the health of instance store disk {name}: {e}"),
+    }
+
+    smoke_test(disk).with_context(|| format!("Instance store disk {name} failed the read smoke test"))?;
+    info!("Instance store disk {name} passed the health check");
+  }
+
+  Ok(())
+}
+
+/// Write the NVMe health data of the instance store disks to the directory
+///
+/// Failures are logged so that the remaining data is still collected
+pub fn collect_health_data<P: AsRef<Path>>(dir: P) -> Result<()> {
+  let disks = get_instance_store_disks("/sys/block")?;
+  if disks.is_empty() {
+    return Ok(());
+  }
+  std::fs::create_dir_all(&dir)?;
+
+  for disk in disks {
+    let name = disk.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    for page in ["smart-log", "error-log", "id-ctrl"] {
+      let output = utils::cmd_exec("nvme", vec![page, &disk.to_string_lossy(), "--output-format=json"]);
+      match output {
+        Ok(output) if output.status == 0 => {
+          std::fs::write(dir.as_ref().join(format!("{name}-{page}.json")), output.stdout)?;
+        }
+        Ok(output) => warn!(
+          "Unable to collect the {page} of {}: {}",
+          disk.display(),
+          output.stderr.trim()
+        ),
+        Err(e) => warn!("Unable to collect the {page} of {}: {e}", disk.display()),
+      }
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_gets_instance_store_disks() {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, model) in [
+      ("nvme0n1", "Amazon Elastic Block Store"),
+      ("nvme2n1", "Amazon EC2 NVMe Instance Storage"),
+      ("nvme1n1", "Amazon EC2 NVMe Instance Storage          "),
+      ("loop0", ""),
+    ] {
+      std::fs::create_dir_all(dir.path().join(name).join("device")).unwrap();
+      std::fs::write(dir.path().join(name).join("device/model"), format!("{model}\n")).unwrap();
+    }
+
+    assert_eq!(
+      get_instance_store_disks(dir.path()).unwrap(),
+      vec![PathBuf::from("/dev/nvme1n1"), PathBuf::from("/dev/nvme2n1")]
+    );
+  }
+
+  #[test]
+  fn it_parses_smart_log() {
+    let log = parse_smart_log(r#"{"critical_warning": 4, "temperature": 310, "media_errors": 2}"#).unwrap();
+    assert_eq!((log.critical_warning, log.media_errors), (4, 2));
+  }
+
+  #[test]
+  fn it_smoke_tests_disk() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    file.as_file().set_len(3 * SMOKE_TEST_BYTES as u64).unwrap();
+    smoke_test(file.path()).unwrap();
+
+    file.as_file().set_len(1024).unwrap();
+    assert!(smoke_test(file.path()).is_err());
+  }
+}
@@ -0,0 +1,130 @@
+//! Client for the Kubernetes API server, authenticated with the credentials of a kubeconfig
+//!
+//! Only the requests eksnode makes are supported: reading a single object as JSON
+
+use std::{path::Path, sync::Arc, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use hyper::{
+  client::HttpConnector,
+  header::{ACCEPT, AUTHORIZATION},
+  Body, Client, Request,
+};
+use hyper_rustls::HttpsConnector;
+use rustls::{client::WebPkiVerifier, Certificate, ClientConfig, RootCertStore};
+use serde_json::Value as JsonValue;
+
+use super::{
+  health::{parse_client_identity, NoServerVerification},
+  KubeConfig,
+};
+use crate::{pki, secret::Secret};
+
+/// Time allowed for each request to the API server
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client for the API server of the cluster of a kubeconfig
+pub struct ApiClient {
+  client: Client<HttpsConnector<HttpConnector>>,
+  server: String,
+  token: Option<Secret<String>>,
+}
+
+impl ApiClient {
+  /// Create a client for the cluster of the current context of the kubeconfig
+  pub fn new(config: &KubeConfig) -> Result<Self> {
+    let credentials = config.get_credentials()?;
+
+    let builder = ClientConfig::builder().with_safe_defaults();
+    let builder = match (credentials.insecure_skip_tls_verify, &credentials.certificate_authority) {
+      (true, _) => builder.with_custom_certificate_verifier(Arc::new(NoServerVerification)),
+      (false, Some(pem)) => {
+        let mut roots = RootCertStore::empty();
+        for certificate in pki::parse_ca_bundle(pem).context("Invalid certificate authority in the kubeconfig")? {
+          roots.add(&Certificate(certificate))?;
+        }
+        builder.with_custom_certificate_verifier(Arc::new(WebPkiVerifier::new(roots, None)))
+      }
+      (false, None) => bail!(
+        "Cluster {} does not have a certificate authority in the kubeconfig",
+        credentials.server
+      ),
+    };
+    let tls = match &credentials.client_identity {
+      Some(pem) => {
+        let (certificates, key) = parse_client_identity(pem.expose())?;
+        builder.with_client_auth_cert(certificates, key)?
+      }
+      None => builder.with_no_client_auth(),
+    };
+
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+      .with_tls_config(tls)
+      .https_or_http()
+      .enable_http1()
+      .build();
+
+    Ok(Self {
+      client: Client::builder().build(connector),
+      server: credentials.server.trim_end_matches('/').to_owned(),
+      token: credentials.token,
+    })
+  }
+
+  /// Create a client from the kubeconfig file
+  pub fn from_kubeconfig<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let path = path.as_ref();
+    let config = KubeConfig::read(path).with_context(|| format!("Unable to read kubeconfig {}", path.display()))?;
+
+    Self::new(&config)
+  }
+
+  /// Get the object at the API path (i.e. - `/api/v1/nodes/<name>`)
+  pub async fn get(&self, path: &str) -> Result<JsonValue> {
+    let url = format!("{}{path}", self.server);
+    let mut request = Request::get(&url).header(ACCEPT, "application/json");
+    if let Some(token) = &self.token {
+      request = request.header(AUTHORIZATION, format!("Bearer {}", token.expose()));
+    }
+    let request = request.body(Body::empty())?;
+
+    let response = async {
+      let response = self.client.request(request).await?;
+      let status = response.status();
+      let body = hyper::body::to_bytes(response.into_body()).await?;
+      anyhow::Ok((status, body))
+    };
+    let (status, body) = match tokio::time::timeout(REQUEST_TIMEOUT, response).await {
+      Ok(result) => result.with_context(|| format!("Unable to reach the API server at {url}"))?,
+      Err(_) => bail!(
+        "Timed out after {}s reaching the API server at {url}",
+        REQUEST_TIMEOUT.as_secs()
+      ),
+    };
+    if !status.is_success() {
+      bail!("API server returned {status} for {path}: {}", get_status_message(&body));
+    }
+
+    serde_json::from_slice(&body).with_context(|| format!("API server returned invalid JSON for {path}"))
+  }
+}
+
+/// Get the message of the Status object returned for a failed request, or the body when it is not a Status
+fn get_status_message(body: &[u8]) -> String {
+  serde_json::from_slice::<JsonValue>(body)
+    .ok()
+    .and_then(|status| status["message"].as_str().map(str::to_owned))
+    .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_gets_status_message() {
+    let body = br#"{"kind":"Status","status":"Failure","message":"nodes \"ip-10-0-0-1\" is forbidden","code":403}"#;
+    assert_eq!(get_status_message(body), r#"nodes "ip-10-0-0-1" is forbidden"#);
+    assert_eq!(get_status_message(b"Unauthorized\n"), "Unauthorized");
+  }
+}
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::{collections::BTreeMap, path::Path};
 
 use anyhow::Result;
 
@@ -6,14 +6,47 @@ use crate::utils;
 
 pub const ARGS_PATH: &str = "/etc/systemd/system/kubelet.service.d/10-kubelet-args.conf";
 pub const EXTRA_ARGS_PATH: &str = "/etc/systemd/system/kubelet.service.d/30-kubelet-extra-args.conf";
+pub const STANDALONE_DROPIN_PATH: &str = "/etc/systemd/system/kubelet.service.d/20-kubelet-standalone.conf";
+
+/// Start kubelet without `--kubeconfig` so that it runs the static pods without registering with an API server
+const STANDALONE_DROPIN: &str = r#"[Service]
+ExecStart=
+ExecStart=/usr/bin/kubelet \
+    --config /etc/kubernetes/kubelet/kubelet-config.json \
+    --image-credential-provider-config /etc/eks/image-credential-provider/config.json \
+    --image-credential-provider-bin-dir /etc/eks/image-credential-provider \
+    $KUBELET_ARGS \
+    $KUBELET_EXTRA_ARGS
+"#;
+
+pub async fn create_standalone_service_dropin<P: AsRef<Path>>(path: P, chown: bool) -> Result<()> {
+  utils::write_file(STANDALONE_DROPIN.as_bytes(), path, Some(0o644), chown).await
+}
+
+/// Replace the `--node-ip` of the kubelet args drop-in, leaving the other args unchanged
+pub fn set_node_ip(contents: &str, node_ip: &str) -> String {
+  contents
+    .split_inclusive('\n')
+    .map(|line| {
+      let Some(start) = line.find("--node-ip=").map(|i| i + "--node-ip=".len()) else {
+        return line.to_owned();
+      };
+      let end = line[start..]
+        .find(char::is_whitespace)
+        .map_or(line.len(), |i| start + i);
+      format!("{}{node_ip}{}", &line[..start], &line[end..])
+    })
+    .collect()
+}
 
 #[derive(Debug, Default)]
 pub struct Args {
-  pub node_ip: String,
+  pub node_ip: Option<String>,
   pub pod_infra_container_image: String,
   pub hostname_override: Option<String>,
   pub cloud_provider: String,
   pub container_runtime: Option<String>,
+  pub node_labels: BTreeMap<String, String>,
 }
 
 impl Args {
@@ -21,7 +54,9 @@ impl Args {
     let end = " \\\n";
     let mut args = format!("--v=2{end}");
 
-    args.push_str(&format!("\t--node-ip={}{end}", self.node_ip));
+    if let Some(node_ip) = &self.node_ip {
+      args.push_str(&format!("\t--node-ip={}{end}", node_ip));
+    }
     args.push_str(&format!(
       "\t--pod-infra-container-image={}{end}",
       self.pod_infra_container_image
@@ -33,6 +68,15 @@ impl Args {
     if let Some(container_runtime) = &self.container_runtime {
       args.push_str(&format!("\t--container-runtime={}{end}", container_runtime));
     }
+    if !self.node_labels.is_empty() {
+      let labels = self
+        .node_labels
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<_>>()
+        .join(",");
+      args.push_str(&format!("\t--node-labels={labels}{end}"));
+    }
 
     // To ensure file content integrity
     if path.as_ref().is_file() {
@@ -82,11 +126,12 @@ mod tests {
   #[tokio::test]
   async fn it_creates_args() {
     let args = Args {
-      node_ip: "10.0.0.1".to_string(),
+      node_ip: Some("10.0.0.1".to_string()),
       pod_infra_container_image: "k8s.gcr.io/pause:3.1".to_string(),
       hostname_override: None,
       cloud_provider: "external".to_string(),
       container_runtime: Some("remote".to_string()),
+      node_labels: BTreeMap::new(),
     };
 
     // Write to file
@@ -100,6 +145,34 @@ mod tests {
     insta::assert_debug_snapshot!(buf);
   }
 
+  #[tokio::test]
+  async fn it_creates_args_with_node_labels() {
+    let args = Args {
+      node_ip: Some("10.0.0.1".to_string()),
+      pod_infra_container_image: "k8s.gcr.io/pause:3.1".to_string(),
+      hostname_override: None,
+      cloud_provider: "external".to_string(),
+      container_runtime: None,
+      node_labels: BTreeMap::from([
+        ("topology.k8s.aws/zone-id".to_string(), "usw2-az2".to_string()),
+        ("topology.kubernetes.io/zone".to_string(), "us-west-2a".to_string()),
+      ]),
+    };
+
+    // The file is replaced on write, so read back contents from the path
+    let file = NamedTempFile::new().unwrap();
+    args.write(file.path(), false).await.unwrap();
+    insta::assert_snapshot!(std::fs::read_to_string(file.path()).unwrap());
+  }
+
+  #[tokio::test]
+  async fn it_creates_standalone_service_dropin() {
+    // `ExecStart=` must reset the command of the base unit before it is redefined
+    let file = NamedTempFile::new().unwrap();
+    create_standalone_service_dropin(file.path(), false).await.unwrap();
+    insta::assert_snapshot!(std::fs::read_to_string(file.path()).unwrap());
+  }
+
   #[tokio::test]
   async fn it_creates_empty_extrargs() {
     let args = ExtraArgs::new(None);
@@ -129,4 +202,15 @@ mod tests {
     file.read_to_string(&mut buf).unwrap();
     insta::assert_debug_snapshot!(buf);
   }
+
+  #[test]
+  fn it_sets_node_ip() {
+    let contents =
+      "[Service]\nEnvironment='KUBELET_ARGS=--v=2 \\\n\t--node-ip=10.0.0.1 \\\n\t--cloud-provider=external'\n";
+
+    assert_eq!(
+      set_node_ip(contents, "10.0.0.2"),
+      "[Service]\nEnvironment='KUBELET_ARGS=--v=2 \\\n\t--node-ip=10.0.0.2 \\\n\t--cloud-provider=external'\n"
+    );
+  }
 }
@@ -1,15 +1,10 @@
-use std::{
-  collections::BTreeMap,
-  fs::{File, OpenOptions},
-  io::{BufReader, BufWriter},
-  net::IpAddr,
-  os::unix::fs::{chown, OpenOptionsExt},
-  path::Path,
-};
-
-use anyhow::Result;
+use std::{collections::BTreeMap, fs::File, io::BufReader, net::IpAddr, path::Path, str::FromStr, time::Duration};
+
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::{profile::Profile, utils};
+
 /// KubeletConfiguration contains the configuration for the Kubelet
 ///
 /// https://kubernetes.io/docs/tasks/administer-cluster/kubelet-config-file/
@@ -243,16 +238,17 @@ pub struct KubeletConfiguration {
   /// for no container. Rolling back the flag requires a reboot.
   /// The cgroupRoot must be specified if this field is not empty.
   #[serde(skip_serializing_if = "Option::is_none")]
-  cystem_cgroups: Option<String>,
+  system_cgroups: Option<String>,
 
   /// cgroupRoot is the root cgroup to use for pods. This is handled by the
   /// container runtime on a best effort basis.
+  #[serde(skip_serializing_if = "Option::is_none")]
   cgroup_root: Option<String>,
 
   /// cgroupsPerQOS enable QoS based CGroup hierarchy: top level CGroups for QoS classes
   /// and all Burstable and BestEffort Pods are brought up under their specific top level QoS CGroup.
   #[serde(rename = "cgroupsPerQOS", skip_serializing_if = "Option::is_none")]
-  cgroups_per_qos: Option<String>,
+  cgroups_per_qos: Option<bool>,
 
   /// cgroupDriver is the driver kubelet uses to manipulate CGroups on the host (cgroupfs or systemd).
   #[serde(skip_serializing_if = "Option::is_none")]
@@ -444,7 +440,7 @@ pub struct KubeletConfiguration {
   /// The value must be a non-negative integer.
   /// If 0, there is no limit on the number of Pods.
   #[serde(skip_serializing_if = "Option::is_none")]
-  pods_per_core: Option<i32>,
+  pub pods_per_core: Option<i32>,
 
   /// enableControllerAttachDetach enables the Attach/Detach controller to
   /// manage attachment/detachment of volumes scheduled to this node, and
@@ -607,7 +603,7 @@ pub struct KubeletConfiguration {
   /// shutdownGracePeriod specifies the total duration that the node should delay the
   /// shutdown and total grace period for pod termination during a node shutdown.
   #[serde(skip_serializing_if = "Option::is_none")]
-  shutdown_grace_period: Option<String>,
+  pub shutdown_grace_period: Option<String>,
 
   /// shutdownGracePeriodCriticalPods specifies the duration used to terminate critical
   /// pods during a node shutdown. This should be less than shutdownGracePeriod.
@@ -616,7 +612,7 @@ pub struct KubeletConfiguration {
   /// terminating normal pods, and the last 10 seconds would be reserved for terminating
   /// critical pods.
   #[serde(skip_serializing_if = "Option::is_none")]
-  shutdown_grace_period_critical_pods: Option<String>,
+  pub shutdown_grace_period_critical_pods: Option<String>,
 
   /// shutdownGracePeriodByPodPriority specifies the shutdown grace period for Pods based
   /// on their associated priority class value.
@@ -641,7 +637,7 @@ pub struct KubeletConfiguration {
   /// Requires the GracefulNodeShutdown feature gate to be enabled.
   /// This configuration must be empty if either ShutdownGracePeriod or ShutdownGracePeriodCriticalPods is set.
   #[serde(skip_serializing_if = "Option::is_none")]
-  shutdown_grace_period_by_pod_priority: Option<Vec<ShutdownGracePeriodByPodPriority>>,
+  pub shutdown_grace_period_by_pod_priority: Option<Vec<ShutdownGracePeriodByPodPriority>>,
 
   /// reservedMemory specifies a comma-separated list of memory reservations for NUMA nodes.
   /// The parameter makes sense only in the context of the memory manager feature.
@@ -697,7 +693,7 @@ pub struct KubeletConfiguration {
   /// Tracing specifies the versioned configuration for OpenTelemetry tracing clients.
   /// See https://kep.k8s.io/2832 for more details.
   #[serde(skip_serializing_if = "Option::is_none")]
-  tracing: Option<TracingConfiguration>,
+  pub tracing: Option<TracingConfiguration>,
 
   /// LocalStorageCapacityIsolation enables local ephemeral storage isolation feature. The default setting is true.
   /// This feature allows users to set request/limit for container's ephemeral storage and manage it in a similar way
@@ -721,7 +717,7 @@ pub struct KubeletConfiguration {
   /// Examples:'unix:///path/to/runtime.sock', 'npipe:////./pipe/runtime'.
   /// If not specified, the value in containerRuntimeEndpoint is used.
   #[serde(skip_serializing_if = "Option::is_none")]
-  image_service_endpoint: Option<String>,
+  pub image_service_endpoint: Option<String>,
 }
 
 impl KubeletConfiguration {
@@ -790,7 +786,7 @@ impl KubeletConfiguration {
   ///
   /// Only used when the cloud provider is external (< 1.27)
   pub fn get_provider_id(&self, availability_zone: &str, instance_id: &str) -> Result<String> {
-    Ok(format!("aws:///{availability_zone}/{instance_id}"))
+    Ok(crate::ec2::get_provider_id(availability_zone, instance_id))
   }
 
   pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
@@ -801,17 +797,29 @@ impl KubeletConfiguration {
     Ok(conf)
   }
 
-  pub fn write<P: AsRef<Path>>(&self, path: P, id: Option<u32>) -> Result<()> {
-    let file = OpenOptions::new()
-      .write(true)
-      .create(true)
-      .truncate(true)
-      .mode(0o644)
-      .open(&path)?;
-    let writer = BufWriter::new(file);
-
-    serde_json::to_writer_pretty(writer, self).map_err(anyhow::Error::from)?;
-    Ok(chown(&path, id, id)?)
+  /// Resources reserved for the Kubernetes system daemons (kubeReserved)
+  pub fn kube_reserved(&self) -> Option<&BTreeMap<String, String>> {
+    self.kube_reserved.as_ref()
+  }
+
+  /// Resources reserved for the OS system daemons (systemReserved)
+  pub fn system_reserved(&self) -> Option<&BTreeMap<String, String>> {
+    self.system_reserved.as_ref()
+  }
+
+  /// Hard eviction thresholds by signal name (evictionHard)
+  pub fn eviction_hard(&self) -> Option<&BTreeMap<String, String>> {
+    self.eviction_hard.as_ref()
+  }
+
+  /// Validate the config against the upstream schema before it is written for kubelet
+  pub fn validate(&self) -> Result<()> {
+    super::validate_kubelet_config(&serde_json::to_value(self)?)
+  }
+
+  pub async fn write<P: AsRef<Path>>(&self, path: P, chown: bool) -> Result<()> {
+    let contents = serde_json::to_string_pretty(self)?;
+    utils::write_file(contents.as_bytes(), path, Some(0o644), chown).await
   }
 }
 
@@ -829,6 +837,7 @@ pub enum HairpinMode {
   /// Neither of the above. If the kubelet is started in this hairpin mode
   /// and kube-proxy is running in iptables mode, hairpin packets will be
   /// dropped by the container bridge.
+  #[serde(rename = "none")]
   HairpinNone,
 }
 
@@ -883,6 +892,7 @@ pub struct MemorySwapConfiguration {
   /// swapBehavior configures swap memory available to container workloads. May be one of
   /// "", "LimitedSwap": workload combined memory and swap usage cannot exceed pod memory limit
   /// "UnlimitedSwap": workloads can use unlimited swap, up to the allocatable limit.
+  #[serde(skip_serializing_if = "Option::is_none")]
   swap_behavior: Option<String>,
 }
 
@@ -898,7 +908,7 @@ pub enum ResourceChangeDetectionStrategy {
 }
 
 // Specifies the shutdown grace period for Pods based on their associated priority class value
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ShutdownGracePeriodByPodPriority {
   /// priority is the priority value associated with the shutdown grace period
@@ -908,6 +918,90 @@ pub struct ShutdownGracePeriodByPodPriority {
   shutdown_grace_period_seconds: i64,
 }
 
+impl FromStr for ShutdownGracePeriodByPodPriority {
+  type Err = anyhow::Error;
+
+  /// Parse from `<priority>=<seconds>` (i.e. - `2000000000=10`)
+  fn from_str(s: &str) -> Result<Self> {
+    let Some((priority, seconds)) = s.split_once('=') else {
+      bail!("Invalid shutdown grace period by pod priority {s}; expected <priority>=<seconds>");
+    };
+
+    Ok(Self {
+      priority: priority.trim().parse()?,
+      shutdown_grace_period_seconds: seconds.trim().parse()?,
+    })
+  }
+}
+
+/// Disk usage thresholds (percent) between which kubelet garbage collects unused images
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImageGcPolicy {
+  low: i32,
+  high: i32,
+}
+
+impl ImageGcPolicy {
+  /// Percent of available disk below which pods are evicted for the policy
+  ///
+  /// Two thirds of the headroom above the high threshold, so that image garbage collection always runs before
+  /// pods are evicted for disk pressure (the kubelet default high threshold of 85 gives the default of 10%)
+  fn eviction_available_percent(&self) -> i32 {
+    (100 - self.high) * 2 / 3
+  }
+}
+
+impl FromStr for ImageGcPolicy {
+  type Err = anyhow::Error;
+
+  /// Parse from `<low>,<high>` (i.e. - `70,80`)
+  fn from_str(s: &str) -> Result<Self> {
+    let Some((low, high)) = s.split_once(',') else {
+      bail!("Invalid image GC policy {s}; expected <low>,<high>");
+    };
+    let policy = Self {
+      low: low.trim().parse()?,
+      high: high.trim().parse()?,
+    };
+
+    if policy.low < 0 || policy.low >= policy.high {
+      bail!("Invalid image GC policy {s}; the low threshold must be at least 0 and less than the high threshold");
+    }
+    if policy.eviction_available_percent() < 1 {
+      bail!("Invalid image GC policy {s}; the high threshold must leave room for the eviction threshold (at most 98)");
+    }
+
+    Ok(policy)
+  }
+}
+
+/// Parse a duration in the subset of the Go duration format used by kubelet (i.e. - `1h30m`, `45s`)
+pub fn parse_duration(s: &str) -> Result<Duration> {
+  let mut total = 0;
+  let mut digits = String::new();
+
+  for c in s.chars() {
+    match c {
+      '0'..='9' => digits.push(c),
+      'h' | 'm' | 's' if !digits.is_empty() => {
+        let unit = match c {
+          'h' => 3600,
+          'm' => 60,
+          _ => 1,
+        };
+        total += digits.parse::<u64>()? * unit;
+        digits.clear();
+      }
+      _ => bail!("Invalid duration {s}; expected a value such as 45s, 5m, or 1h30m"),
+    }
+  }
+  if s.is_empty() || !digits.is_empty() {
+    bail!("Invalid duration {s}; expected a value such as 45s, 5m, or 1h30m");
+  }
+
+  Ok(Duration::from_secs(total))
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Taint {
@@ -915,14 +1009,139 @@ pub struct Taint {
   key: String,
   /// Required. The taint value corresponding to the taint key.
   /// +optional
-  value: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  value: Option<String>,
   /// Required. The effect of the taint on pods
   /// that do not tolerate the taint.
   /// Valid effects are NoSchedule, PreferNoSchedule and NoExecute.
   effect: String,
   /// TimeAdded represents the time at which the taint was added.
   /// It is only written for NoExecute taints.
-  time_added: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  time_added: Option<String>,
+}
+
+/// Effects a taint may have on pods that do not tolerate it
+const TAINT_EFFECTS: &[&str] = &["NoSchedule", "PreferNoSchedule", "NoExecute"];
+
+/// Whether the name is a valid Kubernetes name segment (alphanumeric at both ends, with `-`, `_`, or `.` inside)
+fn is_name_segment(name: &str) -> bool {
+  let alphanumeric_ends =
+    name.starts_with(|c: char| c.is_ascii_alphanumeric()) && name.ends_with(|c: char| c.is_ascii_alphanumeric());
+
+  alphanumeric_ends && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+/// Whether the key is a valid qualified name with an optional DNS subdomain prefix (i.e. - `example.com/dedicated`)
+fn is_qualified_name(key: &str) -> bool {
+  let (prefix, name) = match key.split_once('/') {
+    Some((prefix, name)) => (Some(prefix), name),
+    None => (None, key),
+  };
+  let valid_prefix = prefix.is_none_or(|prefix| {
+    prefix.len() <= 253
+      && prefix
+        .split('.')
+        .all(|label| is_name_segment(label) && !label.contains('_'))
+  });
+
+  valid_prefix && name.len() <= 63 && is_name_segment(name)
+}
+
+impl FromStr for Taint {
+  type Err = anyhow::Error;
+
+  /// Parse from `<key>[=<value>]:<effect>` (i.e. - `dedicated=gpu:NoSchedule`)
+  fn from_str(s: &str) -> Result<Self> {
+    let Some((key_value, effect)) = s.rsplit_once(':') else {
+      bail!("Invalid taint {s}; expected <key>[=<value>]:<effect>");
+    };
+    let (key, value) = match key_value.split_once('=') {
+      Some((key, value)) => (key, Some(value)),
+      None => (key_value, None),
+    };
+
+    if !is_qualified_name(key) {
+      bail!("Invalid taint key {key}; expected an optional DNS subdomain prefix and a name of up to 63 characters");
+    }
+    let value = value.filter(|v| !v.is_empty());
+    if let Some(value) = value.filter(|v| v.len() > 63 || !is_name_segment(v)) {
+      bail!("Invalid taint value {value}; expected up to 63 alphanumeric characters, `-`, `_`, or `.`");
+    }
+    if !TAINT_EFFECTS.contains(&effect) {
+      bail!(
+        "Invalid taint effect {effect}; expected one of {}",
+        TAINT_EFFECTS.join(", ")
+      );
+    }
+
+    Ok(Self {
+      key: key.to_owned(),
+      value: value.map(String::from),
+      effect: effect.to_owned(),
+      time_added: None,
+    })
+  }
+}
+
+/// Prefixes of the `kubernetes.io` and `k8s.io` label namespaces that kubelet is allowed to set on its node
+const KUBELET_LABEL_PREFIXES: &[&str] = &["kubelet.kubernetes.io", "node.kubernetes.io"];
+
+/// Labels of the `kubernetes.io` and `k8s.io` namespaces that kubelet is allowed to set on its node
+const KUBELET_LABELS: &[&str] = &[
+  "beta.kubernetes.io/arch",
+  "beta.kubernetes.io/instance-type",
+  "beta.kubernetes.io/os",
+  "failure-domain.beta.kubernetes.io/region",
+  "failure-domain.beta.kubernetes.io/zone",
+  "kubernetes.io/arch",
+  "kubernetes.io/hostname",
+  "kubernetes.io/os",
+  "topology.kubernetes.io/region",
+  "topology.kubernetes.io/zone",
+];
+
+/// Label added to the node when it registers with the cluster
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeLabel {
+  pub key: String,
+  pub value: String,
+}
+
+impl FromStr for NodeLabel {
+  type Err = anyhow::Error;
+
+  /// Parse from `<key>=<value>` (i.e. - `team=data`)
+  fn from_str(s: &str) -> Result<Self> {
+    let Some((key, value)) = s.split_once('=') else {
+      bail!("Invalid node label {s}; expected <key>=<value>");
+    };
+
+    if !is_qualified_name(key) {
+      bail!("Invalid node label key {key}; expected [<dns-subdomain>/]<name> with a name of up to 63 characters");
+    }
+    if !value.is_empty() && (value.len() > 63 || !is_name_segment(value)) {
+      bail!("Invalid node label value {value}; expected up to 63 alphanumeric characters, `-`, `_`, or `.`");
+    }
+    // Kubelet refuses to start with labels of the Kubernetes namespaces that the NodeRestriction admission plugin
+    // does not allow it to set
+    let prefix = key.split_once('/').map(|(prefix, _)| prefix).unwrap_or_default();
+    let restricted = ["kubernetes.io", "k8s.io"]
+      .iter()
+      .any(|namespace| prefix == *namespace || prefix.ends_with(&format!(".{namespace}")));
+    let allowed = KUBELET_LABELS.contains(&key)
+      || KUBELET_LABEL_PREFIXES
+        .iter()
+        .any(|allowed| prefix == *allowed || prefix.ends_with(&format!(".{allowed}")));
+    if restricted && !allowed {
+      bail!("Invalid node label key {key}; kubelet is not allowed to set labels of the {prefix} namespace");
+    }
+
+    Ok(Self {
+      key: key.to_owned(),
+      value: value.to_owned(),
+    })
+  }
 }
 
 // MemoryReservation specifies the memory reservation of different types for each NUMA node
@@ -970,12 +1189,14 @@ pub struct TracingConfiguration {
   /// Endpoint of the collector this component will report traces to.
   /// The connection is insecure, and does not currently support TLS.
   /// Recommended is unset, and endpoint is the otlp grpc default, localhost:4317.
-  endpoint: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub endpoint: Option<String>,
 
   /// SamplingRatePerMillion is the number of samples to collect per million spans.
   /// Recommended is unset. If unset, sampler respects its parent span's sampling
   /// rate, but otherwise never samples.
-  sampling_rate_per_million: Option<i32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sampling_rate_per_million: Option<i32>,
 }
 
 /// Defines verbosity for one or more files which match a certain glob pattern
@@ -993,10 +1214,276 @@ pub struct VModuleItem {
   verbosity: u32,
 }
 
+impl KubeletConfiguration {
+  /// Apply the tuning profile, merging its settings over the current values
+  pub fn apply_profile(&mut self, profile: &Profile) {
+    let maps = [
+      (&mut self.kube_reserved, &profile.kube_reserved),
+      (&mut self.system_reserved, &profile.system_reserved),
+      (&mut self.eviction_hard, &profile.eviction_hard),
+      (&mut self.eviction_soft, &profile.eviction_soft),
+      (
+        &mut self.eviction_soft_grace_period,
+        &profile.eviction_soft_grace_period,
+      ),
+    ];
+    for (current, overrides) in maps {
+      if !overrides.is_empty() {
+        current
+          .get_or_insert_with(BTreeMap::new)
+          .extend(overrides.iter().map(|(k, v)| (k.to_owned(), v.to_owned())));
+      }
+    }
+
+    if let Some(serialize) = profile.serialize_image_pulls {
+      self.serialize_image_pulls = Some(serialize);
+    }
+    if let Some(max) = profile.max_parallel_image_pulls {
+      self.max_parallel_image_pulls = Some(max);
+    }
+  }
+
+  /// Set the taints added to the node when kubelet registers it with the cluster
+  pub fn set_register_with_taints(&mut self, taints: &[Taint]) {
+    if !taints.is_empty() {
+      self.register_with_taints = Some(taints.to_vec());
+    }
+  }
+
+  /// Authenticate requests to the kubelet server with client certificates signed by the certificate authority
+  pub fn set_client_ca_file(&mut self, path: &str) {
+    self.authentication.x509.client_ca_file = path.to_owned();
+  }
+
+  /// Run only the static pods in the directory, without serving the kubelet API used by an API server
+  pub fn set_standalone(&mut self, static_pod_path: &str) {
+    self.static_pod_path = Some(static_pod_path.to_owned());
+    self.enable_server = Some(false);
+  }
+
+  /// Restrict the kubelet server to the minimum TLS version and cipher suites
+  pub fn set_tls_policy(&mut self, min_version: &str, cipher_suites: &[&str]) {
+    self.tls_min_version = Some(min_version.to_owned());
+    self.tls_cipher_suites = Some(cipher_suites.iter().map(|s| s.to_string()).collect());
+  }
+
+  /// Whether the kubelet server is restricted to the minimum TLS version and a subset of the cipher suites
+  pub fn uses_tls_policy(&self, min_version: &str, cipher_suites: &[&str]) -> bool {
+    self.tls_min_version.as_deref() == Some(min_version)
+      && self
+        .tls_cipher_suites
+        .as_ref()
+        .is_some_and(|suites| !suites.is_empty() && suites.iter().all(|s| cipher_suites.contains(&s.as_str())))
+  }
+
+  /// Export kubelet traces to the OTLP gRPC collector endpoint (i.e. - `localhost:4317`)
+  pub fn set_tracing(&mut self, endpoint: &str, sampling_rate_per_million: Option<i32>) {
+    self.tracing = Some(TracingConfiguration {
+      endpoint: Some(endpoint.to_owned()),
+      sampling_rate_per_million,
+    });
+  }
+
+  /// Set the image garbage collection thresholds and the nodefs/imagefs hard eviction thresholds that follow them
+  pub fn set_image_gc_policy(&mut self, policy: &ImageGcPolicy) {
+    self.image_gc_low_threshold_percent = Some(policy.low);
+    self.image_gc_high_threshold_percent = Some(policy.high);
+
+    let available = format!("{}%", policy.eviction_available_percent());
+    let eviction_hard = self.eviction_hard.get_or_insert_with(BTreeMap::new);
+    eviction_hard.insert("nodefs.available".to_string(), available.to_owned());
+    eviction_hard.insert("imagefs.available".to_string(), available);
+  }
+
+  /// Set the graceful node shutdown grace periods, replacing the defaults
+  ///
+  /// The grace periods by pod priority cannot be combined with the total and critical pods grace periods
+  pub fn set_shutdown_grace_periods(
+    &mut self,
+    total: Option<&str>,
+    critical_pods: Option<&str>,
+    by_pod_priority: &[ShutdownGracePeriodByPodPriority],
+  ) -> Result<()> {
+    if !by_pod_priority.is_empty() {
+      if total.is_some() || critical_pods.is_some() {
+        bail!("Shutdown grace period by pod priority cannot be combined with the shutdown grace periods");
+      }
+      let mut by_pod_priority = by_pod_priority.to_vec();
+      by_pod_priority.sort_by_key(|p| std::cmp::Reverse(p.priority));
+
+      self.shutdown_grace_period = None;
+      self.shutdown_grace_period_critical_pods = None;
+      self.shutdown_grace_period_by_pod_priority = Some(by_pod_priority);
+      return Ok(());
+    }
+
+    if let Some(total) = total {
+      let duration = parse_duration(total)?;
+      self.shutdown_grace_period = Some(total.to_owned());
+
+      // The default critical pods period is a third of the default total, and is scaled the same when it would
+      // consume the whole of a shorter total
+      if critical_pods.is_none() {
+        if let Some(default) = &self.shutdown_grace_period_critical_pods {
+          if parse_duration(default)? >= duration {
+            self.shutdown_grace_period_critical_pods = Some(format!("{}s", duration.as_secs() / 3));
+          }
+        }
+      }
+    }
+    if let Some(critical_pods) = critical_pods {
+      parse_duration(critical_pods)?;
+      self.shutdown_grace_period_critical_pods = Some(critical_pods.to_owned());
+    }
+
+    if let (Some(total), Some(critical_pods)) = (&self.shutdown_grace_period, &self.shutdown_grace_period_critical_pods)
+    {
+      if parse_duration(critical_pods)? >= parse_duration(total)? {
+        bail!("Shutdown grace period for critical pods ({critical_pods}) must be less than the shutdown grace period ({total})");
+      }
+    }
+
+    Ok(())
+  }
+}
+
 #[cfg(test)]
 mod tests {
+  use rstest::*;
+
   use super::*;
 
+  #[rstest]
+  #[case("45s", Some(45))]
+  #[case("5m", Some(300))]
+  #[case("1h30m", Some(5400))]
+  #[case("30", None)]
+  #[case("m", None)]
+  #[case("", None)]
+  fn it_parses_durations(#[case] duration: &str, #[case] expected: Option<u64>) {
+    assert_eq!(parse_duration(duration).ok().map(|d| d.as_secs()), expected);
+  }
+
+  #[test]
+  fn it_applies_profile() {
+    let mut config = KubeletConfiguration::new(IpAddr::from([10, 100, 0, 10]), 893, 70);
+    let profile = Profile {
+      kube_reserved: BTreeMap::from([("ephemeral-storage".to_string(), "10Gi".to_string())]),
+      eviction_soft: BTreeMap::from([("memory.available".to_string(), "1Gi".to_string())]),
+      max_parallel_image_pulls: Some(5),
+      ..Profile::default()
+    };
+    config.apply_profile(&profile);
+
+    let kube_reserved = config.kube_reserved.unwrap();
+    assert_eq!(kube_reserved.get("ephemeral-storage").unwrap(), "10Gi");
+    assert_eq!(kube_reserved.get("memory").unwrap(), "893Mi");
+    assert_eq!(config.eviction_soft, Some(profile.eviction_soft));
+    assert_eq!(config.serialize_image_pulls, Some(false));
+    assert_eq!(config.max_parallel_image_pulls, Some(5));
+  }
+
+  #[test]
+  fn it_sets_shutdown_grace_periods() {
+    let mut config = KubeletConfiguration::new(IpAddr::from([10, 100, 0, 10]), 893, 70);
+
+    config.set_shutdown_grace_periods(Some("5m"), None, &[]).unwrap();
+    assert_eq!(config.shutdown_grace_period.as_deref(), Some("5m"));
+    assert_eq!(config.shutdown_grace_period_critical_pods.as_deref(), Some("15s"));
+
+    assert!(config
+      .set_shutdown_grace_periods(Some("10s"), Some("15s"), &[])
+      .is_err());
+
+    let by_pod_priority = ["0=30".parse().unwrap(), "2000000000=10".parse().unwrap()];
+    config.set_shutdown_grace_periods(None, None, &by_pod_priority).unwrap();
+    assert_eq!(config.shutdown_grace_period, None);
+    assert_eq!(config.shutdown_grace_period_critical_pods, None);
+    assert_eq!(
+      config.shutdown_grace_period_by_pod_priority.unwrap(),
+      vec![by_pod_priority[1].clone(), by_pod_priority[0].clone()]
+    );
+  }
+
+  #[rstest]
+  #[case("10s", "3s")]
+  #[case("15s", "5s")]
+  #[case("1s", "0s")]
+  #[case("16s", "15s")]
+  fn it_scales_critical_pods_shutdown_grace_period(#[case] total: &str, #[case] expected: &str) {
+    let mut config = KubeletConfiguration::new(IpAddr::from([10, 100, 0, 10]), 893, 70);
+
+    config.set_shutdown_grace_periods(Some(total), None, &[]).unwrap();
+    assert_eq!(config.shutdown_grace_period.as_deref(), Some(total));
+    assert_eq!(config.shutdown_grace_period_critical_pods.as_deref(), Some(expected));
+  }
+
+  #[rstest]
+  #[case("80,85", Some((80, 85, "10%")))]
+  #[case(" 50 , 70 ", Some((50, 70, "20%")))]
+  #[case("0,98", Some((0, 98, "1%")))]
+  #[case("85,80", None)]
+  #[case("80,80", None)]
+  #[case("90,99", None)]
+  #[case("-1,80", None)]
+  #[case("80", None)]
+  fn it_sets_image_gc_policy(#[case] policy: &str, #[case] expected: Option<(i32, i32, &str)>) {
+    let policy = policy.parse::<ImageGcPolicy>();
+    let Some((low, high, available)) = expected else {
+      assert!(policy.is_err());
+      return;
+    };
+
+    let mut config = KubeletConfiguration::new(IpAddr::from([10, 100, 0, 10]), 893, 70);
+    config.set_image_gc_policy(&policy.unwrap());
+    assert_eq!(config.image_gc_low_threshold_percent, Some(low));
+    assert_eq!(config.image_gc_high_threshold_percent, Some(high));
+    let eviction_hard = config.eviction_hard.unwrap();
+    assert_eq!(eviction_hard["nodefs.available"], available);
+    assert_eq!(eviction_hard["imagefs.available"], available);
+    assert_eq!(eviction_hard["memory.available"], "100Mi");
+  }
+
+  #[rstest]
+  #[case(
+    "dedicated=gpu:NoSchedule",
+    Some(r#"{"key":"dedicated","value":"gpu","effect":"NoSchedule"}"#)
+  )]
+  #[case(
+    "example.com/spot:PreferNoSchedule",
+    Some(r#"{"key":"example.com/spot","effect":"PreferNoSchedule"}"#)
+  )]
+  #[case(
+    "node.kubernetes.io/out-of-service=:NoExecute",
+    Some(r#"{"key":"node.kubernetes.io/out-of-service","effect":"NoExecute"}"#)
+  )]
+  #[case("dedicated=gpu", None)]
+  #[case("dedicated=gpu:NoRun", None)]
+  #[case("-dedicated=gpu:NoSchedule", None)]
+  #[case("Example_.com/dedicated:NoSchedule", None)]
+  #[case("dedicated=gpu/a100:NoSchedule", None)]
+  fn it_parses_taints(#[case] taint: &str, #[case] expected: Option<&str>) {
+    let taint = taint.parse::<Taint>().ok();
+
+    assert_eq!(taint.map(|t| serde_json::to_string(&t).unwrap()).as_deref(), expected);
+  }
+
+  #[rstest]
+  #[case("team=data", Some(("team", "data")))]
+  #[case("example.com/dedicated=", Some(("example.com/dedicated", "")))]
+  #[case("node.kubernetes.io/lifecycle=spot", Some(("node.kubernetes.io/lifecycle", "spot")))]
+  #[case("topology.kubernetes.io/zone=us-west-2a", Some(("topology.kubernetes.io/zone", "us-west-2a")))]
+  #[case("team", None)]
+  #[case("team=data/analytics", None)]
+  #[case("-team=data", None)]
+  #[case("node-role.kubernetes.io/worker=", None)]
+  #[case("k8s.io/team=data", None)]
+  fn it_parses_node_labels(#[case] label: &str, #[case] expected: Option<(&str, &str)>) {
+    let label = label.parse::<NodeLabel>().ok();
+
+    assert_eq!(label.as_ref().map(|l| (l.key.as_str(), l.value.as_str())), expected);
+  }
+
   #[test]
   fn it_serializes_kubelet_config() {
     let config = r#"{
@@ -1,14 +1,12 @@
-use std::{
-  fs::{File, OpenOptions},
-  io::{BufReader, BufWriter},
-  os::unix::fs::{self, OpenOptionsExt},
-  path::Path,
-};
+use std::{fs::File, io::BufReader, path::Path};
 
 use anyhow::Result;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 
+use super::VersionMatrix;
+use crate::utils;
+
 pub const CREDENTIAL_PROVIDER_CONFIG_PATH: &str = "/etc/eks/image-credential-provider/config.json";
 
 /// CredentialProviderConfig is the configuration containing information about each exec credential provider. Kubelet
@@ -77,10 +75,8 @@ struct ExecEnvVar {
 impl CredentialProviderConfig {
   pub fn new(kubelet_version: &Version) -> Result<Self> {
     // ecr-credential-provider only implements v1alpha1 prior to 1.27.1: https://github.com/kubernetes/cloud-provider-aws/pull/597
-    let api_version = match kubelet_version.lt(&Version::parse("1.27.0")?) {
-      true => "v1alpha1",
-      false => "v1",
-    };
+    let matrix = VersionMatrix::new(kubelet_version)?;
+    let api_version = matrix.credential_provider_api_version();
 
     Ok(CredentialProviderConfig {
       api_version: format!("kubelet.config.k8s.io/{api_version}"),
@@ -102,6 +98,25 @@ impl CredentialProviderConfig {
     })
   }
 
+  /// Set additional environment variables on the credential provider plugin(s)
+  pub fn set_env(&mut self, env: &[(String, String)]) {
+    if env.is_empty() {
+      return;
+    }
+
+    for provider in self.providers.iter_mut() {
+      provider.env = Some(
+        env
+          .iter()
+          .map(|(name, value)| ExecEnvVar {
+            name: name.to_owned(),
+            value: value.to_owned(),
+          })
+          .collect(),
+      );
+    }
+  }
+
   pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
@@ -110,21 +125,9 @@ impl CredentialProviderConfig {
     Ok(conf)
   }
 
-  pub fn write<P: AsRef<Path>>(&self, path: P, chown: bool) -> Result<()> {
-    let file = OpenOptions::new()
-      .write(true)
-      .create(true)
-      .truncate(true)
-      .mode(0o644)
-      .open(&path)?;
-    let writer = BufWriter::new(file);
-
-    serde_json::to_writer_pretty(writer, self).map_err(anyhow::Error::from)?;
-    if chown {
-      fs::chown(&path, Some(0), Some(0))?
-    }
-
-    Ok(())
+  pub async fn write<P: AsRef<Path>>(&self, path: P, chown: bool) -> Result<()> {
+    let contents = serde_json::to_string_pretty(self)?;
+    utils::write_file(contents.as_bytes(), path, Some(0o644), chown).await
   }
 }
 
@@ -164,8 +167,8 @@ mod tests {
     insta::assert_debug_snapshot!(serialized);
   }
 
-  #[test]
-  fn it_creates_v1alpha1() {
+  #[tokio::test]
+  async fn it_creates_v1alpha1() {
     let kubelet_version = Version::parse("1.26.0").unwrap();
     let new = CredentialProviderConfig::new(&kubelet_version).unwrap();
     insta::assert_debug_snapshot!(new);
@@ -176,7 +179,7 @@ mod tests {
     );
 
     let mut file = NamedTempFile::new().unwrap();
-    new.write(&file, false).unwrap();
+    new.write(&file, false).await.unwrap();
 
     // Seek to start
     file.seek(SeekFrom::Start(0)).unwrap();
@@ -187,8 +190,8 @@ mod tests {
     insta::assert_debug_snapshot!(buf);
   }
 
-  #[test]
-  fn it_creates_v1() {
+  #[tokio::test]
+  async fn it_creates_v1() {
     let kubelet_version = Version::parse("1.27.0").unwrap();
     let new = CredentialProviderConfig::new(&kubelet_version).unwrap();
     insta::assert_debug_snapshot!(new);
@@ -200,7 +203,7 @@ mod tests {
 
     // Write to file
     let mut file = NamedTempFile::new().unwrap();
-    new.write(&file, false).unwrap();
+    new.write(&file, false).await.unwrap();
     file.seek(SeekFrom::Start(0)).unwrap();
 
     // Read back contents written to file
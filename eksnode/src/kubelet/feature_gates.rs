@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use semver::Version;
+
+use super::matrix::Availability;
+use crate::Assets;
+
+/// Parse a feature gate in the form `Name=true|false`
+pub fn parse_feature_gate(s: &str) -> Result<(String, bool)> {
+  match s.split_once('=') {
+    Some((name, enabled)) if !name.is_empty() => Ok((name.to_owned(), enabled.parse()?)),
+    _ => bail!("Invalid feature gate {s}; expected Name=true|false"),
+  }
+}
+
+/// Validate the feature gates against the gates known to be available in the kubelet version
+pub fn validate_feature_gates(gates: &[(String, bool)], version: &Version) -> Result<()> {
+  let file = Assets::load("kubelet-feature-gates.yaml")?;
+  let known: BTreeMap<String, Availability> = serde_yaml::from_slice(file.as_ref())?;
+
+  for (name, _) in gates {
+    match known.get(name) {
+      Some(gate) if gate.is_available(version)? => {}
+      Some(gate) => bail!(
+        "Feature gate {name} is not available in kubelet {version} (available from {})",
+        gate.range()
+      ),
+      None => bail!("Unknown kubelet feature gate {name}"),
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use rstest::*;
+
+  use super::*;
+
+  #[test]
+  fn it_parses_feature_gates() {
+    assert_eq!(
+      parse_feature_gate("SidecarContainers=true").unwrap(),
+      ("SidecarContainers".to_owned(), true)
+    );
+    assert!(parse_feature_gate("SidecarContainers").is_err());
+    assert!(parse_feature_gate("SidecarContainers=yes").is_err());
+  }
+
+  #[rstest]
+  #[case("SidecarContainers", "1.29.0", true)]
+  #[case("SidecarContainers", "1.27.3", false)]
+  #[case("UserNamespacesStatelessPodsSupport", "1.27.0", true)]
+  #[case("UserNamespacesStatelessPodsSupport", "1.28.0", false)]
+  #[case("NotAFeatureGate", "1.29.0", false)]
+  fn it_validates_feature_gates(#[case] name: &str, #[case] version: &str, #[case] valid: bool) {
+    let gates = [(name.to_owned(), true)];
+    let result = validate_feature_gates(&gates, &Version::parse(version).unwrap());
+
+    assert_eq!(result.is_ok(), valid);
+  }
+}
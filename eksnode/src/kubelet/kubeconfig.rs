@@ -1,13 +1,78 @@
 use std::{
   collections::BTreeMap,
-  fs::{File, OpenOptions},
-  io::{BufReader, BufWriter},
-  os::unix::fs::{chown, OpenOptionsExt},
+  fs::File,
+  io::BufReader,
   path::{Path, PathBuf},
 };
 
-use anyhow::Result;
+use anyhow::{bail, Context as _, Result};
+use base64::Engine as _;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use tracing::debug;
+
+use crate::{pki, secret::Secret, utils};
+
+/// Kubeconfig kubelet uses to connect to the API server
+pub const KUBECONFIG_PATH: &str = "/var/lib/kubelet/kubeconfig";
+
+/// Context of the cluster the node is joined to
+pub const KUBECONFIG_CONTEXT: &str = "kubelet";
+
+/// AWS shared config file used by the exec credential plugin when credentials are sourced from a `credential_process`
+pub const CREDENTIAL_PROCESS_CONFIG_PATH: &str = "/etc/eksnode/aws/kubeconfig-credential-process";
+
+/// Optional settings for the exec credential plugin of the generated kubeconfig
+#[derive(Debug, Default)]
+pub struct ExecOptions {
+  /// Command that replaces the default exec credential plugin (aws-iam-authenticator)
+  pub command: Option<String>,
+
+  /// Arguments that replace the default arguments passed to the exec credential plugin
+  pub args: Option<Vec<String>>,
+
+  /// Additional environment variables exposed to the exec credential plugin
+  pub env: Vec<(String, String)>,
+
+  /// Text shown when the exec credential plugin executable is not present
+  pub install_hint: Option<String>,
+
+  /// Provide cluster information to the exec credential plugin through `KUBERNETES_EXEC_INFO`
+  pub provide_cluster_info: Option<bool>,
+}
+
+impl ExecOptions {
+  /// Source the exec credential plugin's AWS credentials from a `credential_process` helper
+  ///
+  /// The helper is referenced from an AWS shared config file at `path` (see [`write_credential_process_config`]),
+  /// and the plugin is pointed at that file through its environment. This allows wrapping helpers that do not
+  /// speak the ExecCredential protocol (i.e. - hybrid or proxy-auth setups) behind aws-iam-authenticator
+  pub fn with_credential_process(mut self, path: &str) -> Self {
+    self.env.push(("AWS_CONFIG_FILE".to_owned(), path.to_owned()));
+    self.env.push(("AWS_SDK_LOAD_CONFIG".to_owned(), "true".to_owned()));
+
+    self
+  }
+}
+
+/// Write the AWS shared config file that sources credentials from a `credential_process` helper
+pub async fn write_credential_process_config<P: AsRef<Path>>(
+  credential_process: &str,
+  path: P,
+  chown: bool,
+) -> Result<()> {
+  let contents = get_credential_process_config(credential_process);
+
+  if let Some(parent) = path.as_ref().parent() {
+    tokio::fs::create_dir_all(parent).await?;
+  }
+  utils::write_file(contents.as_bytes(), &path, Some(0o600), chown).await
+}
+
+/// Render the AWS shared config file that sources credentials from a `credential_process` helper
+fn get_credential_process_config(credential_process: &str) -> String {
+  format!("[default]\ncredential_process = {credential_process}\n")
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -57,9 +122,9 @@ impl KubeConfig {
           user: "kubelet".to_owned(),
           extensions: None,
         },
-        name: "kubelet".to_owned(),
+        name: KUBECONFIG_CONTEXT.to_owned(),
       }],
-      current_context: "kubelet".to_owned(),
+      current_context: KUBECONFIG_CONTEXT.to_owned(),
       users: vec![NamedAuthInfo {
         user: AuthInfo {
           client_certificate: None,
@@ -97,6 +162,122 @@ impl KubeConfig {
     })
   }
 
+  /// Add a cluster, along with a context and user of the same name, that kubelet can be switched to
+  ///
+  /// The current context is unchanged; see [`KubeConfig::use_context`]
+  pub fn add_cluster(&mut self, name: &str, server: &str, certificate_authority: &Path, region: &str) -> Result<()> {
+    if self.contexts.iter().any(|c| c.name == name) {
+      bail!("Context {name} already exists in the kubeconfig");
+    }
+
+    let mut added = KubeConfig::new(server, name, region)?;
+    for cluster in &mut added.clusters {
+      cluster.name = name.to_owned();
+      cluster.cluster.certificate_authority = Some(certificate_authority.to_path_buf());
+    }
+    for context in &mut added.contexts {
+      context.name = name.to_owned();
+      context.context.cluster = name.to_owned();
+      context.context.user = name.to_owned();
+    }
+    for user in &mut added.users {
+      user.name = name.to_owned();
+    }
+
+    self.clusters.append(&mut added.clusters);
+    self.contexts.append(&mut added.contexts);
+    self.users.append(&mut added.users);
+
+    Ok(())
+  }
+
+  /// Get the names of the contexts in the kubeconfig
+  pub fn contexts(&self) -> Vec<&str> {
+    self.contexts.iter().map(|c| c.name.as_str()).collect()
+  }
+
+  /// Set the current context, returning the certificate authority file of its cluster
+  pub fn use_context(&mut self, name: &str) -> Result<Option<PathBuf>> {
+    let Some(context) = self.contexts.iter().find(|c| c.name == name) else {
+      bail!(
+        "Context {name} not found in the kubeconfig; available contexts: {}",
+        self.contexts().join(", ")
+      );
+    };
+    let certificate_authority = self
+      .clusters
+      .iter()
+      .find(|c| c.name == context.context.cluster)
+      .and_then(|c| c.cluster.certificate_authority.to_owned());
+    self.current_context = name.to_owned();
+
+    Ok(certificate_authority)
+  }
+
+  /// Apply the optional exec credential plugin settings to the exec credential plugin(s)
+  pub fn set_exec_options(&mut self, options: &ExecOptions) {
+    for exec in self.users.iter_mut().filter_map(|u| u.user.exec.as_mut()) {
+      if let Some(command) = &options.command {
+        exec.command = command.to_owned();
+      }
+      if options.args.is_some() {
+        exec.args = options.args.to_owned();
+      }
+      if !options.env.is_empty() {
+        exec.env = Some(
+          options
+            .env
+            .iter()
+            .map(|(name, value)| EnvVar {
+              name: name.to_owned(),
+              value: value.to_owned(),
+            })
+            .collect(),
+        );
+      }
+      if options.install_hint.is_some() {
+        exec.install_hint = options.install_hint.to_owned();
+      }
+      if options.provide_cluster_info.is_some() {
+        exec.provide_cluster_info = options.provide_cluster_info;
+      }
+    }
+  }
+
+  /// Verify the exec credential plugin(s) referenced by the kubeconfig are installed and functional
+  ///
+  /// Each plugin is executed with `--version` and the reported version(s) are returned. Without this check,
+  /// a missing plugin is only surfaced later by kubelet crash-looping with an opaque authentication error
+  pub fn verify_exec_commands(&self) -> Result<Vec<String>> {
+    let mut versions = Vec::new();
+
+    for exec in self.users.iter().filter_map(|u| u.user.exec.as_ref()) {
+      let command = &exec.command;
+      if utils::find_command(command).is_none() {
+        bail!(
+          "Exec credential plugin {command} not found. Ensure it is installed on the AMI and executable by root, \
+           or that the kubeconfig references the correct path or a command on the PATH"
+        );
+      }
+
+      let output = utils::cmd_exec(command, vec!["--version"])?;
+      if output.status != 0 {
+        bail!(
+          "Exec credential plugin {command} failed to report its version (exit code {}): {}. \
+           Verify the binary is compatible with this instance architecture and is not corrupt",
+          output.status,
+          output.stderr.trim()
+        );
+      }
+
+      let version = output.stdout.trim().to_owned();
+      debug!("Exec credential plugin {command} version: {version}");
+      versions.push(version);
+    }
+
+    Ok(versions)
+  }
+
   pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
@@ -105,17 +286,121 @@ impl KubeConfig {
     Ok(conf)
   }
 
-  pub fn write<P: AsRef<Path>>(&self, path: P, id: Option<u32>) -> Result<()> {
-    let file = OpenOptions::new()
-      .write(true)
-      .create(true)
-      .truncate(true)
-      .mode(0o644)
-      .open(&path)?;
-    let writer = BufWriter::new(file);
-
-    serde_yaml::to_writer(writer, self).map_err(anyhow::Error::from)?;
-    Ok(chown(path, id, id)?)
+  pub async fn write<P: AsRef<Path>>(&self, path: P, chown: bool) -> Result<()> {
+    let contents = serde_yaml::to_string(self)?;
+    utils::write_file(contents.as_bytes(), path, Some(0o644), chown).await
+  }
+
+  /// Embed the PEM encoded CA in the clusters of the kubeconfig in place of the CA file
+  ///
+  /// Allows reaching the API server before `join-cluster` writes the CA file
+  pub fn set_certificate_authority_data(&mut self, pem: &[u8]) {
+    for cluster in &mut self.clusters {
+      cluster.cluster.certificate_authority = None;
+      cluster.cluster.certificate_authority_data = Some(Secret::new(pki::CA_BASE64.encode(pem)));
+    }
+  }
+
+  /// Get the server and credentials of the current context
+  ///
+  /// A token is obtained by running the exec credential plugin when the user does not provide one directly
+  pub(super) fn get_credentials(&self) -> Result<ContextCredentials> {
+    let Some(context) = self.contexts.iter().find(|c| c.name == self.current_context) else {
+      bail!("Current context {} not found in the kubeconfig", self.current_context);
+    };
+    let Some(cluster) = self.clusters.iter().find(|c| c.name == context.context.cluster) else {
+      bail!("Cluster {} not found in the kubeconfig", context.context.cluster);
+    };
+    let Some(user) = self.users.iter().find(|u| u.name == context.context.user) else {
+      bail!("User {} not found in the kubeconfig", context.context.user);
+    };
+    let cluster = &cluster.cluster;
+    let user = &user.user;
+
+    let certificate_authority = read_data(
+      cluster.certificate_authority_data.as_ref(),
+      cluster.certificate_authority.as_deref(),
+    )?;
+
+    let mut client_identity = match (
+      read_data(
+        user.client_certificate_data.as_ref(),
+        user.client_certificate.as_deref(),
+      )?,
+      read_data(user.client_key_data.as_ref(), user.client_key.as_deref())?,
+    ) {
+      (Some(certificate), Some(key)) => Some(Secret::new([certificate, key].concat())),
+      _ => None,
+    };
+
+    let mut token = match (&user.token, &user.token_file) {
+      (Some(token), _) => Some(token.to_owned()),
+      (None, Some(path)) => Some(Secret::new(
+        std::fs::read_to_string(path)
+          .with_context(|| format!("Unable to read token file {}", path.display()))?
+          .trim()
+          .to_owned(),
+      )),
+      (None, None) => None,
+    };
+
+    if let Some(exec) = user
+      .exec
+      .as_ref()
+      .filter(|_| token.is_none() && client_identity.is_none())
+    {
+      let credential = exec.get_credential()?;
+      token = credential.token;
+      client_identity = credential.client_identity;
+    }
+
+    Ok(ContextCredentials {
+      server: cluster.server.to_owned(),
+      certificate_authority,
+      insecure_skip_tls_verify: cluster.insecure_skip_tls_verify.unwrap_or_default(),
+      client_identity,
+      token,
+    })
+  }
+}
+
+/// Server and credentials of the current context of a kubeconfig
+pub(super) struct ContextCredentials {
+  /// Address of the API server
+  pub server: String,
+
+  /// PEM encoded certificate authority of the API server
+  pub certificate_authority: Option<Vec<u8>>,
+
+  /// Skip the verification of the API server certificate
+  pub insecure_skip_tls_verify: bool,
+
+  /// PEM encoded client certificate and key
+  pub client_identity: Option<Secret<Vec<u8>>>,
+
+  /// Bearer token
+  pub token: Option<Secret<String>>,
+}
+
+/// Credentials returned by an exec credential plugin
+#[derive(Debug, Default)]
+struct ExecCredential {
+  token: Option<Secret<String>>,
+  client_identity: Option<Secret<Vec<u8>>>,
+}
+
+/// Read the base64 encoded data of a kubeconfig field, or the file it references when the data is not set
+fn read_data(data: Option<&Secret<String>>, path: Option<&Path>) -> Result<Option<Vec<u8>>> {
+  match (data, path) {
+    (Some(data), _) => Ok(Some(
+      pki::CA_BASE64
+        .decode(data.expose().trim())
+        .context("Kubeconfig data is not valid base64")?,
+    )),
+    (None, Some(path)) => Ok(Some(
+      std::fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?,
+    )),
+    (None, None) => Ok(None),
   }
 }
 
@@ -152,7 +437,7 @@ struct Cluster {
   /// CertificateAuthorityData contains PEM-encoded certificate authority certificates.
   /// Overrides CertificateAuthority
   #[serde(skip_serializing_if = "Option::is_none")]
-  certificate_authority_data: Option<Vec<u8>>,
+  certificate_authority_data: Option<Secret<String>>,
 
   /// ProxyURL is the URL to the proxy to be used for all requests made by this client.
   ///
@@ -239,7 +524,7 @@ struct AuthInfo {
 
   /// ClientCertificateData contains PEM-encoded data from a client cert file for TLS. Overrides ClientCertificate
   #[serde(skip_serializing_if = "Option::is_none")]
-  client_certificate_data: Option<Vec<u8>>,
+  client_certificate_data: Option<Secret<String>>,
 
   /// ClientKey is the path to a client key file for TLS
   #[serde(skip_serializing_if = "Option::is_none")]
@@ -247,11 +532,11 @@ struct AuthInfo {
 
   /// ClientKeyData contains PEM-encoded data from a client key file for TLS. Overrides ClientKey
   #[serde(skip_serializing_if = "Option::is_none")]
-  client_key_data: Option<Vec<u8>>,
+  client_key_data: Option<Secret<String>>,
 
   /// Token is the bearer token for authentication to the kubernetes cluster
   #[serde(skip_serializing_if = "Option::is_none")]
-  token: Option<String>,
+  token: Option<Secret<String>>,
 
   /// TokenFile is a pointer to a file that contains a bearer token (as described above). If both Token and TokenFile
   /// are present, Token takes precedence
@@ -280,7 +565,7 @@ struct AuthInfo {
 
   /// Password is the password for basic authentication to the kubernetes cluster
   #[serde(skip_serializing_if = "Option::is_none")]
-  password: Option<String>,
+  password: Option<Secret<String>>,
 
   /// AuthProvider specifies a custom authentication plugin for the kubernetes cluster
   #[serde(skip_serializing_if = "Option::is_none")]
@@ -354,6 +639,60 @@ struct ExecConfig {
   interactive_mode: Option<ExecInteractiveMode>,
 }
 
+impl ExecConfig {
+  /// Run the exec credential plugin, returning the credential of its ExecCredential output
+  fn get_credential(&self) -> Result<ExecCredential> {
+    let api_version = self
+      .api_version
+      .as_deref()
+      .unwrap_or("client.authentication.k8s.io/v1beta1");
+    let mut env = vec![(
+      "KUBERNETES_EXEC_INFO".to_owned(),
+      json!({"apiVersion": api_version, "kind": "ExecCredential", "spec": {"interactive": false}}).to_string(),
+    )];
+    for var in self.env.iter().flatten() {
+      env.push((var.name.to_owned(), var.value.to_owned()));
+    }
+
+    let args = self.args.iter().flatten().map(String::as_str).collect();
+    let output = utils::cmd_exec_env(&self.command, args, &env)?;
+    if output.status != 0 {
+      bail!(
+        "Exec credential plugin {} failed (exit code {}): {}",
+        self.command,
+        output.status,
+        output.stderr.trim()
+      );
+    }
+
+    parse_exec_credential(&output.stdout).with_context(|| {
+      format!(
+        "Exec credential plugin {} returned an invalid ExecCredential",
+        self.command
+      )
+    })
+  }
+}
+
+fn parse_exec_credential(output: &str) -> Result<ExecCredential> {
+  let credential: JsonValue = serde_json::from_str(output)?;
+  let status = &credential["status"];
+  let field = |name: &str| status[name].as_str().filter(|v| !v.is_empty());
+
+  let credential = ExecCredential {
+    token: field("token").map(|token| Secret::new(token.to_owned())),
+    client_identity: match (field("clientCertificateData"), field("clientKeyData")) {
+      (Some(certificate), Some(key)) => Some(Secret::new(format!("{certificate}\n{key}").into_bytes())),
+      _ => None,
+    },
+  };
+  if credential.token.is_none() && credential.client_identity.is_none() {
+    bail!("ExecCredential status does not contain a token or client certificate");
+  }
+
+  Ok(credential)
+}
+
 /// ExecEnvVar is used for setting environment variables when executing an exec-based credential plugin
 #[derive(Debug, Serialize, Deserialize)]
 struct EnvVar {
@@ -424,14 +763,14 @@ mod tests {
     insta::assert_debug_snapshot!(serialized);
   }
 
-  #[test]
-  fn it_creates_kubeconfig() {
+  #[tokio::test]
+  async fn it_creates_kubeconfig() {
     let new = KubeConfig::new("http://localhost:8080", "example", "us-west-2").unwrap();
     insta::assert_debug_snapshot!(new);
 
     // Write to file
     let mut file = NamedTempFile::new().unwrap();
-    new.write(&file, None).unwrap();
+    new.write(&file, false).await.unwrap();
     file.seek(SeekFrom::Start(0)).unwrap();
 
     // Read back contents written to file
@@ -439,4 +778,168 @@ mod tests {
     file.read_to_string(&mut buf).unwrap();
     insta::assert_debug_snapshot!(buf);
   }
+
+  #[test]
+  fn it_switches_cluster_context() {
+    let mut config = KubeConfig::new("https://old.example.com", "old", "us-west-2").unwrap();
+    config
+      .add_cluster(
+        "new",
+        "https://new.example.com",
+        Path::new("/etc/kubernetes/pki/new-ca.crt"),
+        "us-west-2",
+      )
+      .unwrap();
+    assert_eq!(config.contexts(), vec!["kubelet", "new"]);
+    assert_eq!(config.current_context, "kubelet");
+    assert!(config
+      .add_cluster("new", "https://new.example.com", Path::new("/tmp/ca.crt"), "us-west-2")
+      .is_err());
+
+    assert_eq!(
+      config.use_context("new").unwrap(),
+      Some(PathBuf::from("/etc/kubernetes/pki/new-ca.crt"))
+    );
+    assert_eq!(config.current_context, "new");
+    assert_eq!(
+      config.users[1].user.exec.as_ref().unwrap().args.as_ref().unwrap()[2],
+      "new"
+    );
+    assert_eq!(
+      config.use_context("kubelet").unwrap(),
+      Some(PathBuf::from("/etc/kubernetes/pki/ca.crt"))
+    );
+    assert!(config.use_context("missing").is_err());
+  }
+
+  #[test]
+  fn it_fails_verification_for_missing_exec_command() {
+    let mut config = KubeConfig::new("http://localhost:8080", "example", "us-west-2").unwrap();
+    if let Some(exec) = config.users[0].user.exec.as_mut() {
+      exec.command = "/does/not/exist/aws-iam-authenticator".to_owned();
+    }
+
+    let result = config.verify_exec_commands();
+    assert!(result.unwrap_err().to_string().contains("not found"));
+  }
+
+  #[test]
+  fn it_verifies_exec_command_on_path() {
+    let mut config = KubeConfig::new("http://localhost:8080", "example", "us-west-2").unwrap();
+    if let Some(exec) = config.users[0].user.exec.as_mut() {
+      exec.command = "true".to_owned();
+    }
+
+    assert!(config.verify_exec_commands().is_ok());
+  }
+
+  #[test]
+  fn it_overrides_exec_command() {
+    let options = ExecOptions {
+      command: Some("/usr/local/bin/token-helper".to_owned()),
+      args: Some(vec!["--mfa".to_owned(), "--cluster=example".to_owned()]),
+      ..ExecOptions::default()
+    };
+
+    let mut config = KubeConfig::new("http://localhost:8080", "example", "us-west-2").unwrap();
+    config.set_exec_options(&options);
+
+    let exec = config.users[0].user.exec.as_ref().unwrap();
+    assert_eq!(exec.command, "/usr/local/bin/token-helper");
+    assert_eq!(
+      exec.args,
+      Some(vec!["--mfa".to_owned(), "--cluster=example".to_owned()])
+    );
+  }
+
+  #[tokio::test]
+  async fn it_sets_exec_options() {
+    let file = NamedTempFile::new().unwrap();
+    let options = ExecOptions {
+      command: None,
+      args: None,
+      env: vec![("HTTPS_PROXY".to_owned(), "http://proxy.example.com:3128".to_owned())],
+      install_hint: Some("Install aws-iam-authenticator to /usr/bin".to_owned()),
+      provide_cluster_info: Some(true),
+    }
+    .with_credential_process(&file.path().to_string_lossy());
+    write_credential_process_config("/usr/local/bin/credential-helper --profile node", file.path(), false)
+      .await
+      .unwrap();
+
+    let mut config = KubeConfig::new("http://localhost:8080", "example", "us-west-2").unwrap();
+    config.set_exec_options(&options);
+
+    let serialized = serde_yaml::to_string(&config).unwrap().replace(
+      &file.path().to_string_lossy().into_owned(),
+      "CREDENTIAL_PROCESS_CONFIG_PATH",
+    );
+    insta::assert_snapshot!(serialized);
+
+    let contents = std::fs::read_to_string(file.path()).unwrap();
+    assert_eq!(
+      contents,
+      "[default]\ncredential_process = /usr/local/bin/credential-helper --profile node\n"
+    );
+  }
+
+  #[test]
+  fn it_gets_credentials_from_exec_plugin() {
+    let config = r#"
+      apiVersion: v1
+      kind: Config
+      clusters:
+      - cluster:
+          certificate-authority-data: LS0tLS1CRUdJTiBDRVJUSUZJQ0FURS0tLS0tCg
+          server: https://example.gr7.us-west-2.eks.amazonaws.com
+        name: example
+      contexts:
+      - context:
+          cluster: example
+          user: example
+        name: example
+      current-context: example
+      users:
+      - name: example
+        user:
+          exec:
+            apiVersion: client.authentication.k8s.io/v1beta1
+            command: sh
+            args:
+              - -c
+              - echo "{\"kind\":\"ExecCredential\",\"status\":{\"token\":\"k8s-aws-v1.$CLUSTER\"}}"
+            env:
+              - name: CLUSTER
+                value: example
+    "#;
+
+    let config: KubeConfig = serde_yaml::from_str(config).unwrap();
+    let credentials = config.get_credentials().unwrap();
+    assert_eq!(credentials.server, "https://example.gr7.us-west-2.eks.amazonaws.com");
+    assert_eq!(
+      credentials.certificate_authority.as_deref(),
+      Some("-----BEGIN CERTIFICATE-----\n".as_bytes())
+    );
+    assert_eq!(
+      credentials.token.as_ref().map(|t| t.expose().as_str()),
+      Some("k8s-aws-v1.example")
+    );
+    assert!(credentials.client_identity.is_none());
+  }
+
+  #[test]
+  fn it_parses_exec_credential() {
+    let credential = parse_exec_credential(
+      r#"{"kind":"ExecCredential","status":{"clientCertificateData":"CERT","clientKeyData":"KEY"}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+      credential.client_identity.as_ref().map(|c| c.expose().as_slice()),
+      Some("CERT\nKEY".as_bytes())
+    );
+    assert!(credential.token.is_none());
+
+    assert!(parse_exec_credential(r#"{"kind":"ExecCredential","status":{}}"#).is_err());
+    assert!(parse_exec_credential("Unable to locate credentials").is_err());
+  }
 }
@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::Assets;
+
+/// Kubernetes minor versions in which a kubelet flag, configuration field, or feature gate is available
+#[derive(Debug, Deserialize)]
+pub(super) struct Availability {
+  /// First minor version where it is available (i.e. - `1.28`)
+  pub since: Option<String>,
+  /// First minor version where it has been removed
+  pub until: Option<String>,
+}
+
+impl Availability {
+  pub fn is_available(&self, version: &Version) -> Result<bool> {
+    let minor = (version.major, version.minor);
+    let since = self.since.as_deref().map(parse_minor).transpose()?;
+    let until = self.until.as_deref().map(parse_minor).transpose()?;
+
+    Ok(since.is_none_or(|since| minor >= since) && until.is_none_or(|until| minor < until))
+  }
+
+  /// Human readable range of minor versions (i.e. - `1.22 until 1.27`)
+  pub fn range(&self) -> String {
+    format!(
+      "{} until {}",
+      self.since.as_deref().unwrap_or("-"),
+      self.until.as_deref().unwrap_or("-")
+    )
+  }
+}
+
+fn parse_minor(version: &str) -> Result<(u64, u64)> {
+  match version.split_once('.') {
+    Some((major, minor)) => Ok((major.parse()?, minor.parse()?)),
+    None => bail!("Invalid Kubernetes minor version: {version}"),
+  }
+}
+
+/// Details of a Kubernetes minor version supported by `eksnode`
+///
+/// Generated into `kubernetes-versions.yaml` by `eksnode-gen update-k8s-matrix`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KubernetesVersion {
+  /// The latest <major>.<minor>.<patch> version provided by EKS
+  pub kubernetes_version: String,
+
+  /// Tag of the default sandbox (pause) image
+  pub pause_tag: String,
+
+  /// Version of the kubelet credential provider API implemented by ecr-credential-provider (i.e. - `v1`)
+  pub credential_provider_api_version: String,
+
+  /// Kubelet flags that have been removed in or before the version
+  #[serde(default)]
+  pub removed_kubelet_flags: Vec<String>,
+}
+
+/// Get the Kubernetes versions supported by `eksnode`, keyed by minor version (i.e. - `1.29`)
+pub fn get_kubernetes_versions() -> Result<BTreeMap<String, KubernetesVersion>> {
+  let file = Assets::load("kubernetes-versions.yaml")?;
+
+  Ok(serde_yaml::from_slice(file.as_ref())?)
+}
+
+/// The kubelet flags, configuration fields, and Kubernetes version details that apply to a kubelet version
+///
+/// Sourced from `kubelet-versions.yaml` and `kubernetes-versions.yaml` so that supporting a new Kubernetes version
+/// is a change to the data rather than to the builders of the kubelet arguments and configuration
+#[derive(Debug)]
+pub struct VersionMatrix {
+  version: Version,
+  entries: BTreeMap<String, Availability>,
+  /// Whether the minor version is listed in `kubernetes-versions.yaml`
+  supported: bool,
+  /// Details of the minor version, or of the closest listed version when it is not supported
+  kubernetes: KubernetesVersion,
+}
+
+impl VersionMatrix {
+  pub fn new(version: &Version) -> Result<Self> {
+    let file = Assets::load("kubelet-versions.yaml")?;
+
+    let minor = (version.major, version.minor);
+    let versions = get_kubernetes_versions()?
+      .into_iter()
+      .map(|(k, v)| Ok((parse_minor(&k)?, v)))
+      .collect::<Result<BTreeMap<_, _>>>()?;
+    let Some((closest, kubernetes)) = versions.range(..=minor).next_back().or(versions.first_key_value()) else {
+      bail!("No Kubernetes versions found in kubernetes-versions.yaml");
+    };
+
+    Ok(Self {
+      version: version.to_owned(),
+      entries: serde_yaml::from_slice(file.as_ref())?,
+      supported: *closest == minor,
+      kubernetes: kubernetes.to_owned(),
+    })
+  }
+
+  /// Whether the Kubernetes minor version of kubelet is supported by `eksnode`
+  pub fn is_supported_version(&self) -> bool {
+    self.supported
+  }
+
+  /// Tag of the default sandbox (pause) image
+  pub fn pause_tag(&self) -> &str {
+    &self.kubernetes.pause_tag
+  }
+
+  /// Version of the kubelet credential provider API (i.e. - `v1`)
+  pub fn credential_provider_api_version(&self) -> &str {
+    &self.kubernetes.credential_provider_api_version
+  }
+
+  /// Kubelet flags in the arguments that have been removed from the kubelet version
+  pub fn removed_flags<'a>(&self, args: &'a str) -> Vec<&'a str> {
+    args
+      .split_whitespace()
+      .filter(|arg| {
+        let flag = arg.split_once('=').map_or(*arg, |(flag, _)| flag);
+        self
+          .kubernetes
+          .removed_kubelet_flags
+          .iter()
+          .any(|removed| removed == flag)
+      })
+      .collect()
+  }
+
+  fn get(&self, name: &str) -> Result<&Availability> {
+    match self.entries.get(name) {
+      Some(availability) => Ok(availability),
+      None => bail!(
+        "Unable to determine whether kubelet {} supports {name}, which is missing from kubelet-versions.yaml",
+        self.version
+      ),
+    }
+  }
+
+  /// Whether the flag or configuration field applies to the kubelet version
+  pub fn supports(&self, name: &str) -> Result<bool> {
+    self.get(name)?.is_available(&self.version)
+  }
+
+  /// Whether a flag or configuration field requested by the user is supported, warning when it is not
+  pub fn supports_requested(&self, name: &str) -> Result<bool> {
+    let supported = self.supports(name)?;
+    if !supported {
+      warn!(
+        "{name} is not supported by kubelet {} (supported {}) and will be ignored",
+        self.version,
+        self.get(name)?.range()
+      );
+    }
+
+    Ok(supported)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rstest::*;
+
+  use super::*;
+
+  #[rstest]
+  #[case("kubeAPIQPS", "1.21.14", false)]
+  #[case("kubeAPIQPS", "1.22.0", true)]
+  #[case("kubeAPIQPS", "1.27.0", false)]
+  #[case("providerID", "1.25.16", false)]
+  #[case("providerID", "1.30.6", true)]
+  #[case("--container-runtime", "1.26.15", true)]
+  #[case("--container-runtime", "1.27.0", false)]
+  fn it_supports_versions(#[case] name: &str, #[case] version: &str, #[case] expected: bool) {
+    let matrix = VersionMatrix::new(&Version::parse(version).unwrap()).unwrap();
+
+    assert_eq!(matrix.supports(name).unwrap(), expected);
+  }
+
+  #[test]
+  fn it_fails_unknown_entries() {
+    let matrix = VersionMatrix::new(&Version::parse("1.29.3").unwrap()).unwrap();
+
+    let err = matrix.supports("--unknown-flag").unwrap_err();
+    assert!(
+      err.to_string().contains("kubelet 1.29.3 supports --unknown-flag"),
+      "{err}"
+    );
+  }
+
+  #[rstest]
+  #[case("1.26.15", true, "v1alpha1")]
+  #[case("1.29.3", true, "v1")]
+  #[case("1.23.17", false, "v1alpha1")]
+  #[case("1.99.0", false, "v1")]
+  fn it_gets_kubernetes_version(#[case] version: &str, #[case] supported: bool, #[case] api_version: &str) {
+    let matrix = VersionMatrix::new(&Version::parse(version).unwrap()).unwrap();
+
+    assert_eq!(matrix.is_supported_version(), supported);
+    assert_eq!(matrix.credential_provider_api_version(), api_version);
+    assert_eq!(matrix.pause_tag(), "3.8");
+  }
+
+  #[test]
+  fn it_finds_removed_flags() {
+    let matrix = VersionMatrix::new(&Version::parse("1.29.3").unwrap()).unwrap();
+    let args = "--node-labels=team=a --container-runtime=remote --network-plugin cni --max-pods=20";
+
+    assert_eq!(
+      matrix.removed_flags(args),
+      vec!["--container-runtime=remote", "--network-plugin"]
+    );
+  }
+
+  #[test]
+  fn it_parses_matrix() {
+    let matrix = VersionMatrix::new(&Version::parse("1.30.0").unwrap()).unwrap();
+
+    for (name, availability) in &matrix.entries {
+      assert!(availability.is_available(&matrix.version).is_ok(), "{name}");
+    }
+  }
+}
@@ -1,21 +1,106 @@
+mod api;
 mod args;
 mod config;
 mod credential;
+mod feature_gates;
+mod health;
 mod kubeconfig;
+mod matrix;
+mod schema;
 
-use anyhow::Result;
-pub use args::{Args, ExtraArgs, ARGS_PATH, EXTRA_ARGS_PATH};
-pub use config::KubeletConfiguration;
+use std::{path::Path, sync::OnceLock, time::Duration};
+
+use anyhow::{Context, Result};
+pub use api::ApiClient;
+pub use args::{
+  create_standalone_service_dropin, set_node_ip, Args, ExtraArgs, ARGS_PATH, EXTRA_ARGS_PATH, STANDALONE_DROPIN_PATH,
+};
+pub use config::{ImageGcPolicy, KubeletConfiguration, NodeLabel, ShutdownGracePeriodByPodPriority, Taint};
 pub use credential::{CredentialProviderConfig, CREDENTIAL_PROVIDER_CONFIG_PATH};
-pub use kubeconfig::KubeConfig;
+pub use feature_gates::{parse_feature_gate, validate_feature_gates};
+pub use health::{check_healthz, get_metric_issues};
+pub use kubeconfig::{
+  write_credential_process_config, ExecOptions, KubeConfig, CREDENTIAL_PROCESS_CONFIG_PATH, KUBECONFIG_CONTEXT,
+  KUBECONFIG_PATH,
+};
+pub use matrix::{get_kubernetes_versions, KubernetesVersion, VersionMatrix};
+pub use schema::validate_kubelet_config;
 use semver::Version;
-use tracing::debug;
+use tracing::{debug, warn};
 
 use crate::utils;
 
-pub fn get_kubelet_version() -> Result<Version> {
-  let cmd = utils::cmd_exec("kubelet", vec!["--version"])?;
+/// Kubelet config written by `join-cluster` and passed to kubelet with `--config`
+pub const KUBELET_CONFIG_PATH: &str = "/etc/kubernetes/kubelet/kubelet-config.json";
+
+/// Client certificate kubelet obtains through TLS bootstrapping, present once the node has registered
+pub const CLIENT_CERT_PATH: &str = "/var/lib/kubelet/pki/kubelet-client-current.pem";
+
+/// Directory of the static pods run by kubelet, created when the AMI is provisioned
+pub const STATIC_POD_PATH: &str = "/etc/kubernetes/manifests";
+
+/// Kubelet version recorded at AMI build time, used when `kubelet --version` fails or does not respond in time
+pub const KUBELET_VERSION_PATH: &str = "/etc/eksnode/kubelet-version";
+
+/// Maximum time to wait on `kubelet --version` before falling back to the version file
+const KUBELET_VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Kubelet version is resolved once and reused for the life of the process
+static KUBELET_VERSION: OnceLock<Version> = OnceLock::new();
+
+/// Get the version of the kubelet installed on the host
+///
+/// The version is sourced from `kubelet --version`, falling back to the version file written at AMI build time
+pub async fn get_kubelet_version() -> Result<Version> {
+  if let Some(version) = KUBELET_VERSION.get() {
+    return Ok(version.clone());
+  }
+
+  let version = match exec_kubelet_version().await {
+    Ok(version) => version,
+    Err(e) => {
+      warn!("Unable to get kubelet version from kubelet, falling back to {KUBELET_VERSION_PATH}: {e}");
+      read_kubelet_version(KUBELET_VERSION_PATH)?
+    }
+  };
+
+  Ok(KUBELET_VERSION.get_or_init(|| version).clone())
+}
+
+async fn exec_kubelet_version() -> Result<Version> {
+  let cmd = utils::cmd_exec_timeout("kubelet", vec!["--version"], KUBELET_VERSION_TIMEOUT).await?;
   debug!("kubelet version: {}", cmd.stdout);
 
   utils::get_semver(&cmd.stdout)
 }
+
+/// Read the kubelet version from a file containing the output of `kubelet --version` (i.e. - `Kubernetes v1.29.0`)
+fn read_kubelet_version<P: AsRef<Path>>(path: P) -> Result<Version> {
+  let contents = std::fs::read_to_string(&path)
+    .with_context(|| format!("Unable to read kubelet version file {}", path.as_ref().display()))?;
+
+  utils::get_semver(contents.trim())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+
+  use tempfile::NamedTempFile;
+
+  use super::*;
+
+  #[test]
+  fn it_reads_kubelet_version_file() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "Kubernetes v1.29.3-eks-ae9a62a").unwrap();
+
+    let version = read_kubelet_version(file.path()).unwrap();
+    assert_eq!(version, Version::parse("1.29.3").unwrap());
+  }
+
+  #[test]
+  fn it_fails_on_missing_kubelet_version_file() {
+    assert!(read_kubelet_version("/does/not/exist/kubelet-version").is_err());
+  }
+}
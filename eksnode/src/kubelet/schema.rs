@@ -0,0 +1,171 @@
+use anyhow::{bail, Context, Result};
+use regex_lite::Regex;
+use serde_json::Value;
+
+use crate::Assets;
+
+/// Validate a kubelet config against the upstream `KubeletConfiguration` (`kubelet.config.k8s.io/v1beta1`) schema
+///
+/// Catches misspelled fields and values kubelet would reject on start, reporting every issue found at once
+pub fn validate_kubelet_config(config: &Value) -> Result<()> {
+  let file = Assets::load("kubelet-config-schema.json")?;
+  let schema: Value = serde_json::from_slice(file.as_ref())?;
+
+  let mut issues = Vec::new();
+  validate(&schema, &schema, config, "", &mut issues)?;
+  issues.sort();
+  if !issues.is_empty() {
+    bail!(
+      "Kubelet config does not match the upstream schema:\n  {}",
+      issues.join("\n  ")
+    );
+  }
+
+  Ok(())
+}
+
+/// Validate the value against the subset of JSON Schema keywords used by the embedded schema
+fn validate(root: &Value, schema: &Value, value: &Value, path: &str, issues: &mut Vec<String>) -> Result<()> {
+  if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+    let definition = reference
+      .strip_prefix("#/")
+      .and_then(|pointer| root.pointer(&format!("/{pointer}")))
+      .with_context(|| format!("Unknown schema reference {reference}"))?;
+    return validate(root, definition, value, path, issues);
+  }
+
+  let location = if path.is_empty() { "<root>" } else { path };
+
+  if let Some(types) = schema.get("type") {
+    let types = match types {
+      Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+      types => vec![types.as_str().unwrap_or_default()],
+    };
+    if !types.iter().any(|t| is_type(value, t)) {
+      issues.push(format!("{location}: expected {}, found {value}", types.join(" or ")));
+      return Ok(());
+    }
+  }
+
+  if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+    if !allowed.contains(value) {
+      let allowed = allowed.iter().map(Value::to_string).collect::<Vec<_>>();
+      issues.push(format!("{location}: {value} is not one of {}", allowed.join(", ")));
+    }
+  }
+
+  if let Some(number) = value.as_f64() {
+    if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+      if number < minimum {
+        issues.push(format!("{location}: {value} is less than the minimum of {minimum}"));
+      }
+    }
+    if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+      if number > maximum {
+        issues.push(format!("{location}: {value} is greater than the maximum of {maximum}"));
+      }
+    }
+  }
+
+  if let (Some(pattern), Some(s)) = (schema.get("pattern").and_then(Value::as_str), value.as_str()) {
+    if !Regex::new(pattern)?.is_match(s) {
+      issues.push(format!("{location}: {value} does not match the pattern {pattern}"));
+    }
+  }
+
+  if let Some(required) = schema.get("required").and_then(Value::as_array) {
+    for field in required.iter().filter_map(Value::as_str) {
+      if value.get(field).is_none() {
+        issues.push(format!("{location}: missing required field {field}"));
+      }
+    }
+  }
+
+  if let Some(object) = value.as_object() {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (key, item) in object {
+      let item_path = if path.is_empty() {
+        key.to_owned()
+      } else {
+        format!("{path}.{key}")
+      };
+      match (properties.and_then(|p| p.get(key)), schema.get("additionalProperties")) {
+        (Some(property), _) => validate(root, property, item, &item_path, issues)?,
+        (None, Some(Value::Bool(false))) => issues.push(format!("{item_path}: unknown field")),
+        (None, Some(additional @ Value::Object(_))) => validate(root, additional, item, &item_path, issues)?,
+        (None, _) => {}
+      }
+    }
+  }
+
+  if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+    for (i, item) in array.iter().enumerate() {
+      validate(root, items, item, &format!("{path}[{i}]"), issues)?;
+    }
+  }
+
+  Ok(())
+}
+
+fn is_type(value: &Value, name: &str) -> bool {
+  match name {
+    "object" => value.is_object(),
+    "array" => value.is_array(),
+    "string" => value.is_string(),
+    "boolean" => value.is_boolean(),
+    "integer" => value.is_i64() || value.is_u64(),
+    "number" => value.is_number(),
+    "null" => value.is_null(),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn it_validates_kubelet_config() {
+    let config = json!({
+      "kind": "KubeletConfiguration",
+      "apiVersion": "kubelet.config.k8s.io/v1beta1",
+      "authentication": { "anonymous": { "enabled": false }, "webhook": { "cacheTTL": "2m0s", "enabled": true } },
+      "clusterDNS": ["172.20.0.10"],
+      "hairpinMode": "hairpin-veth",
+      "evictionHard": { "memory.available": "100Mi" },
+      "featureGates": { "RotateKubeletServerCertificate": true },
+      "shutdownGracePeriod": "45s",
+    });
+    validate_kubelet_config(&config).unwrap();
+  }
+
+  #[test]
+  fn it_rejects_invalid_kubelet_config() {
+    let config = json!({
+      "kind": "KubeletConfiguration",
+      "apiVersion": "kubelet.config.k8s.io/v1beta1",
+      "cystemCgroups": "/system",
+      "cgroupsPerQOS": "true",
+      "hairpinMode": "hairpin-none",
+      "authentication": { "webhook": { "cacheTTL": "2 minutes" } },
+      "clusterDNS": [10],
+      "imageGCHighThresholdPercent": 101,
+    });
+
+    let err = validate_kubelet_config(&config).unwrap_err().to_string();
+    assert_eq!(
+      err.lines().skip(1).map(str::trim).collect::<Vec<_>>(),
+      vec![
+        r#"authentication.webhook.cacheTTL: "2 minutes" does not match the pattern "#.to_owned()
+          + r"^(0|([0-9]+(\.[0-9]+)?(ns|us|µs|ms|s|m|h))+)$",
+        "cgroupsPerQOS: expected boolean, found \"true\"".to_owned(),
+        "clusterDNS[0]: expected string, found 10".to_owned(),
+        "cystemCgroups: unknown field".to_owned(),
+        r#"hairpinMode: "hairpin-none" is not one of "promiscuous-bridge", "hairpin-veth", "none""#.to_owned(),
+        "imageGCHighThresholdPercent: 101 is greater than the maximum of 100".to_owned(),
+      ]
+    );
+  }
+}
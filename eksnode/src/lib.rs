@@ -1,18 +1,52 @@
+//! Bootstrap Amazon EKS nodes
+//!
+//! Used by the `eksnode` command line interface, and embeddable by provisioners that join nodes to a cluster
+//! through [`NodeJoiner`] in place of running the binary
+
+pub mod adopt;
+pub mod aws;
+pub mod cis;
 pub mod cli;
 pub mod commands;
 pub mod containerd;
+pub mod disks;
 pub mod ec2;
 pub mod ecr;
 pub mod eks;
+pub mod events;
+pub mod fips;
 pub mod gpu;
+pub mod hybrid;
+pub mod instance_store;
 pub mod kubelet;
+pub mod log_export;
+pub mod network;
+pub mod node;
+pub mod nodeconfig;
+pub mod pki;
+pub mod preflight;
+pub mod profile;
 pub mod resource;
+pub mod sbom;
+pub mod secret;
+pub mod securityhub;
+pub mod ssm;
+pub mod state;
+pub mod systemd;
+pub mod telemetry;
+pub mod timing;
 pub mod utils;
 
+use std::{borrow::Cow, fmt, io::ErrorKind, path::Path};
+
+use anyhow::{bail, Result};
 use clap::ValueEnum;
 pub use cli::{Cli, Commands};
+pub use commands::join::JoinClusterInput;
+pub use node::NodeJoiner;
 use rust_embed::RustEmbed;
 use serde::{Deserialize, Serialize};
+use tracing::info;
 
 /// Embeds the contents of the `files/` directory into the binary
 ///
@@ -21,6 +55,35 @@ use serde::{Deserialize, Serialize};
 #[folder = "files/"]
 pub struct Assets;
 
+/// Directory checked for a file before the embedded contents (i.e. - `/etc/eksnode/overrides/ec2-instances.yaml`)
+///
+/// Allows operators to patch the static data and templates in the field without rebuilding the binary
+pub const ASSET_OVERRIDES_DIR: &str = "/etc/eksnode/overrides";
+
+impl Assets {
+  /// Get the contents of the file, from the override directory when present, otherwise the embedded contents
+  pub fn load(name: &str) -> Result<Cow<'static, [u8]>> {
+    Self::load_from(Path::new(ASSET_OVERRIDES_DIR), name)
+  }
+
+  fn load_from(dir: &Path, name: &str) -> Result<Cow<'static, [u8]>> {
+    let path = dir.join(name);
+    match std::fs::read(&path) {
+      Ok(contents) => {
+        info!("Using {} in place of the embedded {name}", path.display());
+        return Ok(Cow::Owned(contents));
+      }
+      Err(e) if e.kind() == ErrorKind::NotFound => {}
+      Err(e) => bail!("Unable to read override {}: {e}", path.display()),
+    }
+
+    match Self::get(name) {
+      Some(file) => Ok(file.data),
+      None => bail!("Unable to find embedded file {name}"),
+    }
+  }
+}
+
 #[derive(Copy, Clone, Debug, ValueEnum, Serialize, Deserialize)]
 pub enum IpvFamily {
   Ipv4,
@@ -32,3 +95,93 @@ impl Default for IpvFamily {
     Self::Ipv4
   }
 }
+
+/// CPU architecture of the node
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Architecture {
+  X86_64,
+  Aarch64,
+}
+
+impl Architecture {
+  /// Detect the architecture of the running node from the kernel's reported machine hardware name
+  pub fn detect() -> Result<Self> {
+    let output = utils::cmd_exec("uname", vec!["-m"])?;
+    if output.status != 0 {
+      bail!("Unable to detect node architecture: {}", output.stderr.trim());
+    }
+
+    Self::from_machine(output.stdout.trim())
+  }
+
+  /// Parse the architecture from the machine hardware name (i.e. - `uname -m`)
+  pub fn from_machine(machine: &str) -> Result<Self> {
+    match machine {
+      "x86_64" | "amd64" => Ok(Self::X86_64),
+      "aarch64" | "arm64" => Ok(Self::Aarch64),
+      _ => bail!("Unsupported node architecture: {machine}"),
+    }
+  }
+
+  /// The architecture name used by OCI images and Go (i.e. - `amd64`)
+  pub fn oci_arch(&self) -> &'static str {
+    match self {
+      Self::X86_64 => "amd64",
+      Self::Aarch64 => "arm64",
+    }
+  }
+
+  /// The OCI platform used when pulling container images for this architecture
+  pub fn platform(&self) -> &'static str {
+    match self {
+      Self::X86_64 => "linux/amd64",
+      Self::Aarch64 => "linux/arm64",
+    }
+  }
+}
+
+impl fmt::Display for Architecture {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::X86_64 => write!(f, "x86_64"),
+      Self::Aarch64 => write!(f, "aarch64"),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rstest::*;
+
+  use super::*;
+
+  #[rstest]
+  #[case("x86_64", Architecture::X86_64, "linux/amd64")]
+  #[case("amd64", Architecture::X86_64, "linux/amd64")]
+  #[case("aarch64", Architecture::Aarch64, "linux/arm64")]
+  #[case("arm64", Architecture::Aarch64, "linux/arm64")]
+  fn it_parses_architecture(#[case] machine: &str, #[case] expected: Architecture, #[case] platform: &str) {
+    let arch = Architecture::from_machine(machine).unwrap();
+    assert_eq!(arch, expected);
+    assert_eq!(arch.platform(), platform);
+  }
+
+  #[test]
+  fn it_rejects_unsupported_architecture() {
+    assert!(Architecture::from_machine("riscv64").is_err());
+  }
+
+  #[test]
+  fn it_loads_asset_override() {
+    let dir = tempfile::tempdir().unwrap();
+    let embedded = Assets::load_from(dir.path(), "provision/runtime.slice").unwrap();
+    assert!(embedded.starts_with(b"[Unit]"));
+
+    std::fs::create_dir_all(dir.path().join("provision")).unwrap();
+    std::fs::write(dir.path().join("provision/runtime.slice"), "[Slice]\n").unwrap();
+    let overridden = Assets::load_from(dir.path(), "provision/runtime.slice").unwrap();
+    assert_eq!(overridden.as_ref(), b"[Slice]\n");
+
+    assert!(Assets::load_from(dir.path(), "missing.yaml").is_err());
+  }
+}
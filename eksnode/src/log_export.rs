@@ -0,0 +1,358 @@
+//! Export of the bootstrap logs to CloudWatch Logs
+//!
+//! Nodes that fail to join are often terminated by their autoscaling group before anyone can read their logs. With
+//! `--log-export cloudwatch:<log-group>` the log of eksnode, and optionally the kubelet log of the current boot, is
+//! shipped once the join finishes or fails so that the node still leaves diagnosable traces
+
+use std::{
+  io,
+  str::FromStr,
+  sync::{Mutex, PoisonError},
+  time::SystemTime,
+};
+
+use anyhow::{bail, Result};
+use aws_sdk_cloudwatchlogs::{types::InputLogEvent, Client};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tracing::{debug, info, Subscriber};
+use tracing_subscriber::{fmt, registry::LookupSpan, Layer};
+
+use crate::{aws, events, utils};
+
+const CREATE_LOG_STREAM: &str = "logs:CreateLogStream";
+const PUT_LOG_EVENTS: &str = "logs:PutLogEvents";
+
+/// Maximum number of events accepted by a single PutLogEvents request
+const PUT_LOG_EVENTS_MAX_EVENTS: usize = 10_000;
+
+/// Maximum size of a PutLogEvents request, counting each message plus 26 bytes per event
+const PUT_LOG_EVENTS_MAX_BYTES: usize = 1_048_576;
+
+/// Bytes counted against the request size for each event in addition to its message
+const EVENT_OVERHEAD_BYTES: usize = 26;
+
+/// Maximum size of a single event, including the overhead
+const MAX_EVENT_BYTES: usize = 262_144;
+
+/// Maximum time between the first and last event of a PutLogEvents request, in milliseconds
+const PUT_LOG_EVENTS_MAX_SPAN_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Age beyond which PutLogEvents rejects an event, in milliseconds
+const MAX_EVENT_AGE_MILLIS: i64 = 14 * 24 * 60 * 60 * 1000;
+
+/// Log lines of eksnode captured for export, in the order they were emitted
+static CAPTURED: Mutex<Vec<LogEvent>> = Mutex::new(Vec::new());
+
+/// Destination the bootstrap logs are exported to
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogExport {
+  /// Log group of CloudWatch Logs, which must already exist
+  CloudWatch { log_group: String },
+}
+
+impl FromStr for LogExport {
+  type Err = anyhow::Error;
+
+  /// Parse from `cloudwatch:<log-group>` (i.e. - `cloudwatch:/eks/example/bootstrap`)
+  fn from_str(s: &str) -> Result<Self> {
+    let Some(("cloudwatch", log_group)) = s.split_once(':') else {
+      bail!("Invalid log export {s}; expected cloudwatch:<log-group>");
+    };
+    let valid = |c: char| c.is_ascii_alphanumeric() || "_-/.#".contains(c);
+    if log_group.is_empty() || log_group.len() > 512 || !log_group.chars().all(valid) {
+      bail!("Invalid CloudWatch Logs log group {log_group:?}");
+    }
+
+    Ok(Self::CloudWatch {
+      log_group: log_group.to_owned(),
+    })
+  }
+}
+
+/// Event of a CloudWatch Logs log stream, in the shape PutLogEvents accepts
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEvent {
+  /// Milliseconds since the Unix epoch
+  pub timestamp: i64,
+  pub message: String,
+}
+
+impl LogEvent {
+  /// Messages larger than CloudWatch Logs accepts are truncated
+  fn new(timestamp: i64, message: &str) -> Self {
+    let mut end = message.len().min(MAX_EVENT_BYTES - EVENT_OVERHEAD_BYTES);
+    while !message.is_char_boundary(end) {
+      end -= 1;
+    }
+
+    Self {
+      timestamp,
+      message: message[..end].to_owned(),
+    }
+  }
+
+  fn size(&self) -> usize {
+    self.message.len() + EVENT_OVERHEAD_BYTES
+  }
+}
+
+/// Writer appending each formatted log line to the captured events
+struct CaptureWriter;
+
+impl io::Write for CaptureWriter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let message = String::from_utf8_lossy(buf);
+    let message = message.trim_end();
+    if !message.is_empty() {
+      let event = LogEvent::new(now_millis(), message);
+      CAPTURED.lock().unwrap_or_else(PoisonError::into_inner).push(event);
+    }
+
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Layer capturing the log of eksnode for export, alongside the log target the user selected
+///
+/// Captures the events at the same level as the log target; CloudWatch Logs records the time of each event
+pub fn capture_layer<S>() -> impl Layer<S>
+where
+  S: Subscriber + for<'a> LookupSpan<'a>,
+{
+  fmt::layer()
+    .without_time()
+    .with_ansi(false)
+    .with_writer(|| CaptureWriter)
+}
+
+fn now_millis() -> i64 {
+  SystemTime::now()
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .map_or(0, |d| d.as_millis() as i64)
+}
+
+/// Export the captured log of eksnode, and the kubelet log when `kubelet` is set, to the destination
+///
+/// The logs are written to the `<name>/eksnode` and `<name>/kubelet` streams, which are created when they do not
+/// already exist. Events older than CloudWatch Logs accepts are dropped
+pub async fn export(destination: &LogExport, name: &str, region: Option<&str>, kubelet: bool) -> Result<()> {
+  let LogExport::CloudWatch { log_group } = destination;
+  let client = aws::get_logs_client(region).await;
+
+  let captured = CAPTURED.lock().unwrap_or_else(PoisonError::into_inner).to_owned();
+  let mut streams = vec![(format!("{name}/eksnode"), captured)];
+  if kubelet {
+    streams.push((format!("{name}/kubelet"), get_kubelet_events()?));
+  }
+
+  for (stream, events) in streams {
+    let events = get_exportable_events(events, now_millis());
+    create_log_stream(&client, log_group, &stream).await?;
+    for batch in get_batches(&events) {
+      put_log_events(&client, log_group, &stream, batch).await?;
+    }
+    info!(
+      "Exported {} log event(s) to CloudWatch Logs {log_group}:{stream}",
+      events.len()
+    );
+  }
+
+  Ok(())
+}
+
+/// Get the entries of the kubelet unit logged to the journal since boot
+fn get_kubelet_events() -> Result<Vec<LogEvent>> {
+  let output = utils::cmd_exec(
+    "journalctl",
+    vec!["--unit", "kubelet", "--boot", "--output", "json", "--no-pager"],
+  )?;
+  if output.status != 0 {
+    bail!(
+      "Unable to read the kubelet log from the journal: {}",
+      output.stderr.trim()
+    );
+  }
+
+  Ok(parse_journal(&output.stdout))
+}
+
+/// Parse the entries of `journalctl --output json`, skipping any entry without a timestamp or a text message
+fn parse_journal(output: &str) -> Vec<LogEvent> {
+  output
+    .lines()
+    .filter_map(|line| serde_json::from_str::<JsonValue>(line).ok())
+    .filter_map(|entry| {
+      // Microseconds since the Unix epoch
+      let timestamp = entry["__REALTIME_TIMESTAMP"].as_str()?.parse::<i64>().ok()? / 1000;
+      Some(LogEvent::new(timestamp, entry["MESSAGE"].as_str()?))
+    })
+    .collect()
+}
+
+/// Sort the events chronologically, as PutLogEvents requires, dropping those too old to be accepted
+fn get_exportable_events(mut events: Vec<LogEvent>, now: i64) -> Vec<LogEvent> {
+  let count = events.len();
+  events.retain(|event| now - event.timestamp < MAX_EVENT_AGE_MILLIS);
+  if events.len() < count {
+    debug!("Dropped {} log event(s) older than 14 days", count - events.len());
+  }
+  events.sort_by_key(|event| event.timestamp);
+
+  events
+}
+
+/// Split the chronologically sorted events into batches within the limits of a PutLogEvents request
+fn get_batches(events: &[LogEvent]) -> Vec<&[LogEvent]> {
+  let mut batches = Vec::new();
+  let (mut start, mut size) = (0, 0);
+  for (i, event) in events.iter().enumerate() {
+    if i - start == PUT_LOG_EVENTS_MAX_EVENTS
+      || size + event.size() > PUT_LOG_EVENTS_MAX_BYTES
+      || (i > start && event.timestamp - events[start].timestamp > PUT_LOG_EVENTS_MAX_SPAN_MILLIS)
+    {
+      batches.push(&events[start..i]);
+      (start, size) = (i, 0);
+    }
+    size += event.size();
+  }
+  if start < events.len() {
+    batches.push(&events[start..]);
+  }
+
+  batches
+}
+
+async fn create_log_stream(client: &Client, log_group: &str, stream: &str) -> Result<()> {
+  let result = client
+    .create_log_stream()
+    .log_group_name(log_group)
+    .log_stream_name(stream)
+    .send()
+    .await;
+
+  match result {
+    Ok(_) => Ok(()),
+    // The stream already exists when the node is joined again
+    Err(e)
+      if e
+        .as_service_error()
+        .is_some_and(|e| e.is_resource_already_exists_exception()) =>
+    {
+      Ok(())
+    }
+    Err(e) => {
+      events::record_api_failure(CREATE_LOG_STREAM, &e);
+      Err(e.into())
+    }
+  }
+}
+
+async fn put_log_events(client: &Client, log_group: &str, stream: &str, events: &[LogEvent]) -> Result<()> {
+  let log_events = events
+    .iter()
+    .map(|event| {
+      InputLogEvent::builder()
+        .timestamp(event.timestamp)
+        .message(&event.message)
+        .build()
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+
+  client
+    .put_log_events()
+    .log_group_name(log_group)
+    .log_stream_name(stream)
+    .set_log_events(Some(log_events))
+    .send()
+    .await
+    .inspect_err(|e| events::record_api_failure(PUT_LOG_EVENTS, e))?;
+  debug!("Put {} log event(s) to {log_group}:{stream}", events.len());
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_parses_log_export() {
+    assert_eq!(
+      "cloudwatch:/eks/example/bootstrap".parse::<LogExport>().unwrap(),
+      LogExport::CloudWatch {
+        log_group: "/eks/example/bootstrap".to_owned()
+      }
+    );
+    assert!("cloudwatch:".parse::<LogExport>().is_err());
+    assert!("cloudwatch:bootstrap logs".parse::<LogExport>().is_err());
+    assert!("s3:example".parse::<LogExport>().is_err());
+    assert!("/eks/example/bootstrap".parse::<LogExport>().is_err());
+  }
+
+  #[test]
+  fn it_parses_journal() {
+    let output = r#"{"__REALTIME_TIMESTAMP":"1704164645123456","MESSAGE":"Starting kubelet"}
+{"__REALTIME_TIMESTAMP":"1704164645200000","MESSAGE":[98,105,110]}
+{"MESSAGE":"No timestamp"}
+-- No entries --
+"#;
+    assert_eq!(
+      parse_journal(output),
+      vec![LogEvent::new(1704164645123, "Starting kubelet")]
+    );
+  }
+
+  #[test]
+  fn it_gets_batches() {
+    assert!(get_batches(&[]).is_empty());
+
+    let events = vec![LogEvent::new(0, "ok"); PUT_LOG_EVENTS_MAX_EVENTS + 1];
+    let batches = get_batches(&events);
+    assert_eq!(
+      batches.iter().map(|b| b.len()).collect::<Vec<_>>(),
+      vec![PUT_LOG_EVENTS_MAX_EVENTS, 1]
+    );
+
+    // Messages are truncated to the maximum event size, of which 4 fill a request
+    let large = LogEvent::new(0, &"a".repeat(MAX_EVENT_BYTES));
+    assert_eq!(large.size(), MAX_EVENT_BYTES);
+    let events = vec![large; 5];
+    assert_eq!(
+      get_batches(&events).iter().map(|b| b.len()).collect::<Vec<_>>(),
+      vec![4, 1]
+    );
+
+    // The events of a request cannot span more than 24 hours
+    let events = [
+      0,
+      1000,
+      PUT_LOG_EVENTS_MAX_SPAN_MILLIS,
+      PUT_LOG_EVENTS_MAX_SPAN_MILLIS + 1,
+    ]
+    .map(|timestamp| LogEvent::new(timestamp, "ok"));
+    assert_eq!(
+      get_batches(&events).iter().map(|b| b.len()).collect::<Vec<_>>(),
+      vec![3, 1]
+    );
+  }
+
+  #[test]
+  fn it_gets_exportable_events() {
+    let now = MAX_EVENT_AGE_MILLIS + 5000;
+    let events = vec![
+      LogEvent::new(now - 1000, "recent"),
+      LogEvent::new(0, "expired"),
+      LogEvent::new(now - 2000, "older"),
+    ];
+
+    assert_eq!(
+      get_exportable_events(events, now),
+      vec![LogEvent::new(now - 2000, "older"), LogEvent::new(now - 1000, "recent")]
+    );
+  }
+}
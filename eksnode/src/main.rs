@@ -1,26 +1,92 @@
+use std::time::Instant;
+
 use anyhow::Result;
-use clap::Parser;
-use eksnode::{Cli, Commands};
+use clap::{CommandFactory, FromArgMatches};
+use eksnode::{
+  cli::LogTarget,
+  events::{self, EventKind},
+  log_export,
+  telemetry::{self, UsageRecord},
+  Cli, Commands,
+};
 use tracing_log::AsTrace;
-use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::{layer::SubscriberExt, FmtSubscriber, Registry};
 
 #[cfg(not(tarpaulin_include))]
 #[tokio::main]
 async fn main() -> Result<()> {
-  let cli = Cli::parse();
-  let subscriber = FmtSubscriber::builder()
-    .with_max_level(cli.verbose.log_level_filter().as_trace())
-    .without_time()
-    .with_ansi(!cli.no_color)
-    .finish();
-  tracing::subscriber::set_global_default(subscriber).expect("Setting default subscriber failed");
+  let matches = Cli::command().get_matches();
+  let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+  let level = cli.verbose.log_level_filter().as_trace();
+  // The log of join-cluster is also captured in case --log-export is set, directly or through --config-file
+  let capture = matches!(cli.command, Commands::JoinCluster(_));
+  match cli.log_target {
+    LogTarget::Stderr => {
+      let subscriber = FmtSubscriber::builder()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .without_time()
+        .with_ansi(!cli.no_color)
+        .finish()
+        .with(capture.then(log_export::capture_layer));
+      tracing::subscriber::set_global_default(subscriber).expect("Setting default subscriber failed");
+    }
+    LogTarget::Journald => {
+      let journald = tracing_journald::layer()?.with_field_prefix(None);
+      let subscriber = Registry::default()
+        .with(journald)
+        .with(level)
+        .with(capture.then(log_export::capture_layer));
+      tracing::subscriber::set_global_default(subscriber).expect("Setting default subscriber failed");
+    }
+  }
 
-  match &cli.command {
+  eksnode::aws::configure(cli.aws)?;
+  eksnode::utils::configure_path_maps(cli.path_map)?;
+
+  let command = cli.command.name();
+  // Querying the event log, status, or reservations is not itself an action on the node
+  if !matches!(
+    cli.command,
+    Commands::Events(_) | Commands::Reservations(_) | Commands::Status(_)
+  ) {
+    events::init(command);
+  }
+
+  let started = Instant::now();
+  let result = match cli.command {
     Commands::CalculateMaxPods(maxpods) => maxpods.result().await,
     Commands::Debug(debug) => debug.debug().await,
+    Commands::EcrCredentialRefresh(credential) => credential.refresh().await,
+    Commands::Events(events) => events.query().await,
     Commands::GetVersions(versions) => versions.get_versions().await,
     Commands::PullImage(image) => image.pull().await,
-    Commands::JoinCluster(node) => node.join_node_to_cluster().await,
+    Commands::JoinCluster(node) => {
+      // Inputs set on the command line take precedence over those of --config-file
+      let explicit = matches
+        .subcommand_matches(command)
+        .map(eksnode::cli::get_explicit_args)
+        .unwrap_or_default();
+      async move { node.merge_config_file(&explicit)?.join_node_to_cluster().await }.await
+    }
+    Commands::ProvisionAmi(provision) => provision.provision().await,
+    Commands::Reconcile(reconcile) => reconcile.reconcile().await,
+    Commands::Render(render) => render.render().await,
+    Commands::Reservations(reservations) => reservations.report().await,
+    Commands::Status(status) => status.status().await,
+    Commands::SwitchCluster(switch) => switch.switch().await,
+    Commands::ValidateConfig(config) => config.validate().await,
     Commands::ValidateNode(validate) => validate.validate().await,
+    Commands::VerifyAccelerators(accelerators) => accelerators.verify().await,
+    Commands::VerifyArtifacts(artifacts) => artifacts.verify().await,
+  };
+  if let Err(e) = &result {
+    events::record(EventKind::CommandFailed, command, Some(format!("{e:#}")));
+  }
+  if cli.telemetry.is_enabled(command) {
+    let record = UsageRecord::new(command, started.elapsed(), result.is_ok()).await;
+    telemetry::record(&cli.telemetry, &record).await;
   }
+
+  result
 }
@@ -0,0 +1,331 @@
+use std::{
+  collections::BTreeMap,
+  fmt,
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::utils;
+
+/// Directory containing the systemd-networkd configuration
+const NETWORKD_CONFIG_DIR: &str = "/etc/systemd/network";
+
+/// MTU supported for traffic within a region (jumbo frames)
+pub const IN_REGION_MTU: u32 = 9001;
+
+/// MTU supported for traffic leaving the region (i.e. - internet, inter-region VPC peering, VPN)
+pub const CROSS_REGION_MTU: u32 = 1500;
+
+/// Network sysctls scaled to the size of the instance, written at join
+pub const NETWORK_SYSCTL_PATH: &str = "/etc/sysctl.d/99-eksnode-network.conf";
+
+/// Kernel modules loaded at boot so that the network sysctls can be applied by systemd-sysctl, written at join
+pub const NETWORK_MODULES_PATH: &str = "/etc/modules-load.d/eksnode-network.conf";
+
+/// Connection tracking entries per vCPU; twice the kube-proxy default to absorb high connection churn
+///
+/// The table is sized from the vCPUs rather than the memory of the instance, as kube-proxy does. Entries are only
+/// allocated as connections are tracked and only the hash buckets (8 bytes each) are allocated up front, so the upper
+/// bound keeps a full table of the largest instances within ~1.2 GiB while the smallest instances reserve ~256 KiB
+const CONNTRACK_MAX_PER_CPU: i64 = 65536;
+
+/// Bounds of the connection tracking table size, from the kube-proxy minimum to a table of ~1.2 GiB
+const CONNTRACK_MAX_RANGE: (i64, i64) = (131072, 4194304);
+
+/// vCPUs from which the ephemeral port range is widened; smaller instances keep the kernel default range
+const WIDE_PORT_RANGE_MIN_CPUS: i32 = 8;
+
+/// MTU to configure on the primary interface
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InterfaceMtu {
+  /// Select the MTU based on whether the cluster is in the same region as the node
+  Auto,
+  /// Explicit MTU in bytes
+  Bytes(u32),
+}
+
+impl FromStr for InterfaceMtu {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "auto" => Ok(Self::Auto),
+      _ => {
+        let mtu = s
+          .parse::<u32>()
+          .map_err(|_| anyhow!("Invalid MTU {s}; expected `auto` or a number of bytes"))?;
+        if !(576..=9001).contains(&mtu) {
+          bail!("Invalid MTU {mtu}; must be between 576 and 9001 bytes");
+        }
+        Ok(Self::Bytes(mtu))
+      }
+    }
+  }
+}
+
+impl fmt::Display for InterfaceMtu {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Auto => write!(f, "auto"),
+      Self::Bytes(mtu) => write!(f, "{mtu}"),
+    }
+  }
+}
+
+impl InterfaceMtu {
+  /// Resolve the MTU in bytes for the node and cluster regions
+  pub fn resolve(&self, node_region: &str, cluster_region: Option<&str>) -> u32 {
+    match self {
+      Self::Bytes(mtu) => *mtu,
+      Self::Auto => match cluster_region {
+        Some(cluster_region) if cluster_region != node_region => CROSS_REGION_MTU,
+        _ => IN_REGION_MTU,
+      },
+    }
+  }
+}
+
+/// Get the region of the cluster from its API server endpoint (i.e. - `https://<id>.gr7.<region>.eks.amazonaws.com`)
+pub fn get_endpoint_region(endpoint: &str) -> Option<String> {
+  let host = endpoint.split("://").last()?.split(['/', ':']).next()?;
+  let labels = host.split('.').collect::<Vec<&str>>();
+  let eks = labels.iter().position(|l| *l == "eks")?;
+
+  eks.checked_sub(1).map(|i| labels[i].to_owned())
+}
+
+/// Get the name of the network interface with the given MAC address
+fn get_interface_name<P: AsRef<Path>>(mac_address: &str, sys_class_net: P) -> Result<String> {
+  for entry in std::fs::read_dir(sys_class_net)? {
+    let entry = entry?;
+    let address = std::fs::read_to_string(entry.path().join("address")).unwrap_or_default();
+    if address.trim().eq_ignore_ascii_case(mac_address) {
+      return Ok(entry.file_name().to_string_lossy().into_owned());
+    }
+  }
+
+  bail!("Network interface with MAC address {mac_address} not found")
+}
+
+/// Render the systemd-networkd drop-in that sets the MTU on the interface
+fn get_mtu_dropin(mtu: u32) -> String {
+  format!("[Link]\nMTUBytes={mtu}\n")
+}
+
+/// Path of the systemd-networkd drop-in for the interface
+///
+/// amazon-ec2-net-utils generates `70-<interface>.network` for each ENI
+fn get_mtu_dropin_path(interface: &str) -> PathBuf {
+  PathBuf::from(NETWORKD_CONFIG_DIR)
+    .join(format!("70-{interface}.network.d"))
+    .join("10-eksnode-mtu.conf")
+}
+
+/// Configure the MTU of the primary interface
+///
+/// The MTU is persisted through a systemd-networkd drop-in and applied to the running interface
+pub async fn configure_interface_mtu(mac_address: &str, mtu: u32) -> Result<()> {
+  let interface = get_interface_name(mac_address, "/sys/class/net")?;
+  info!("Setting MTU of {interface} to {mtu}");
+
+  let path = get_mtu_dropin_path(&interface);
+  if let Some(parent) = path.parent() {
+    tokio::fs::create_dir_all(parent).await?;
+  }
+  utils::write_file(get_mtu_dropin(mtu).as_bytes(), &path, Some(0o644), true).await?;
+
+  let output = utils::cmd_exec("ip", vec!["link", "set", "dev", &interface, "mtu", &mtu.to_string()])?;
+  if output.status != 0 {
+    bail!("Failed to set MTU of {interface} to {mtu}: {}", output.stderr.trim());
+  }
+
+  Ok(())
+}
+
+/// Parse a sysctl in the form `<setting>=<value>` (i.e. - `net.ipv4.tcp_fin_timeout=30`)
+pub fn parse_sysctl(s: &str) -> Result<(String, String)> {
+  match s.split_once('=') {
+    Some((setting, value)) if !setting.trim().is_empty() && !value.trim().is_empty() => {
+      Ok((setting.trim().to_owned(), value.trim().to_owned()))
+    }
+    _ => bail!("Invalid sysctl {s}; expected <setting>=<value>"),
+  }
+}
+
+/// Get the connection tracking and ephemeral port sysctls for the number of vCPUs, with the overrides applied last
+///
+/// kube-proxy sizes `nf_conntrack_max` from its own `conntrack.maxPerCore` when it starts; set it to 0 for the value
+/// written here to be kept
+pub fn get_network_sysctls(cpus: i32, overrides: &[(String, String)]) -> BTreeMap<String, String> {
+  let (min, max) = CONNTRACK_MAX_RANGE;
+  // The hash table is sized from an overridden table size unless it is also overridden
+  let conntrack_max = overrides
+    .iter()
+    .rev()
+    .find(|(setting, _)| setting == "net.netfilter.nf_conntrack_max")
+    .and_then(|(_, value)| value.parse::<i64>().ok())
+    .unwrap_or_else(|| (i64::from(cpus) * CONNTRACK_MAX_PER_CPU).clamp(min, max));
+
+  let mut sysctls = BTreeMap::from([
+    ("net.netfilter.nf_conntrack_max".to_owned(), conntrack_max.to_string()),
+    // Hash table of 4 entries per bucket, as sized by kube-proxy
+    (
+      "net.netfilter.nf_conntrack_buckets".to_owned(),
+      (conntrack_max / 4).to_string(),
+    ),
+  ]);
+  if cpus >= WIDE_PORT_RANGE_MIN_CPUS {
+    sysctls.insert("net.ipv4.ip_local_port_range".to_owned(), "10240 65535".to_owned());
+    // Keep the NodePort range out of the ephemeral ports
+    sysctls.insert("net.ipv4.ip_local_reserved_ports".to_owned(), "30000-32767".to_owned());
+  }
+  sysctls.extend(overrides.iter().cloned());
+
+  sysctls
+}
+
+/// Render the sysctls as a sysctl.d file
+fn render_sysctls(sysctls: &BTreeMap<String, String>) -> String {
+  sysctls
+    .iter()
+    .map(|(setting, value)| format!("{setting} = {value}\n"))
+    .collect()
+}
+
+/// Write the network sysctls so that they are applied by systemd-sysctl on boot
+pub async fn write_sysctls<P: AsRef<Path>>(sysctls: &BTreeMap<String, String>, path: P, chown: bool) -> Result<()> {
+  utils::write_file(render_sysctls(sysctls).as_bytes(), path, Some(0o644), chown).await
+}
+
+/// Write the kernel modules the network sysctls depend on so that they are loaded before systemd-sysctl runs on boot
+///
+/// The `net.netfilter` settings do not exist until nf_conntrack is loaded, which otherwise only happens once
+/// kube-proxy programs its first rule
+pub async fn write_modules<P: AsRef<Path>>(path: P, chown: bool) -> Result<()> {
+  utils::write_file(b"nf_conntrack\n", path, Some(0o644), chown).await
+}
+
+/// Apply the sysctls written to the file to the running host
+///
+/// The `net.netfilter` settings only exist once nf_conntrack is loaded, so the module is loaded first. Settings the
+/// kernel does not support are logged rather than failing the join
+pub fn apply_sysctls(path: &str) {
+  if let Err(e) = utils::cmd_exec("modprobe", vec!["nf_conntrack"]) {
+    warn!("Unable to load nf_conntrack: {e}");
+  }
+  match utils::cmd_exec("sysctl", vec!["-e", "-p", path]) {
+    Ok(output) if output.status == 0 => info!("Applied network sysctls from {path}"),
+    Ok(output) => warn!("Unable to apply network sysctls from {path}: {}", output.stderr.trim()),
+    Err(e) => warn!("Unable to apply network sysctls from {path}: {e}"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rstest::*;
+
+  use super::*;
+
+  #[rstest]
+  #[case("auto", InterfaceMtu::Auto)]
+  #[case("9001", InterfaceMtu::Bytes(9001))]
+  #[case("1500", InterfaceMtu::Bytes(1500))]
+  fn it_parses_interface_mtu(#[case] input: &str, #[case] expected: InterfaceMtu) {
+    assert_eq!(input.parse::<InterfaceMtu>().unwrap(), expected);
+  }
+
+  #[rstest]
+  #[case("jumbo")]
+  #[case("9216")]
+  #[case("100")]
+  fn it_rejects_invalid_interface_mtu(#[case] input: &str) {
+    assert!(input.parse::<InterfaceMtu>().is_err());
+  }
+
+  #[rstest]
+  #[case(InterfaceMtu::Auto, "us-west-2", Some("us-west-2"), IN_REGION_MTU)]
+  #[case(InterfaceMtu::Auto, "us-west-2", Some("us-east-1"), CROSS_REGION_MTU)]
+  #[case(InterfaceMtu::Auto, "us-west-2", None, IN_REGION_MTU)]
+  #[case(InterfaceMtu::Bytes(1450), "us-west-2", Some("us-west-2"), 1450)]
+  fn it_resolves_interface_mtu(
+    #[case] mtu: InterfaceMtu,
+    #[case] node_region: &str,
+    #[case] cluster_region: Option<&str>,
+    #[case] expected: u32,
+  ) {
+    assert_eq!(mtu.resolve(node_region, cluster_region), expected);
+  }
+
+  #[rstest]
+  #[case(
+    "https://0123456789ABCDEF.gr7.us-west-2.eks.amazonaws.com",
+    Some("us-west-2".to_owned())
+  )]
+  #[case(
+    "https://0123456789ABCDEF.yl4.cn-north-1.eks.amazonaws.com.cn",
+    Some("cn-north-1".to_owned())
+  )]
+  #[case("https://10.0.0.10:443", None)]
+  fn it_gets_endpoint_region(#[case] endpoint: &str, #[case] expected: Option<String>) {
+    assert_eq!(get_endpoint_region(endpoint), expected);
+  }
+
+  #[test]
+  fn it_gets_interface_name() {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, mac) in [("lo", "00:00:00:00:00:00"), ("ens5", "0a:1b:2c:3d:4e:5f")] {
+      std::fs::create_dir(dir.path().join(name)).unwrap();
+      std::fs::write(dir.path().join(name).join("address"), format!("{mac}\n")).unwrap();
+    }
+
+    assert_eq!(get_interface_name("0a:1b:2c:3d:4e:5f", dir.path()).unwrap(), "ens5");
+    assert!(get_interface_name("0a:00:00:00:00:01", dir.path()).is_err());
+  }
+
+  #[rstest]
+  #[case(2, &[], "net.netfilter.nf_conntrack_buckets = 32768\nnet.netfilter.nf_conntrack_max = 131072\n")]
+  #[case(
+    16,
+    &[],
+    "net.ipv4.ip_local_port_range = 10240 65535\nnet.ipv4.ip_local_reserved_ports = 30000-32767\n\
+     net.netfilter.nf_conntrack_buckets = 262144\nnet.netfilter.nf_conntrack_max = 1048576\n"
+  )]
+  #[case(
+    192,
+    &[("net.netfilter.nf_conntrack_max", "2097152"), ("net.ipv4.tcp_fin_timeout", "30")],
+    "net.ipv4.ip_local_port_range = 10240 65535\nnet.ipv4.ip_local_reserved_ports = 30000-32767\n\
+     net.ipv4.tcp_fin_timeout = 30\nnet.netfilter.nf_conntrack_buckets = 524288\n\
+     net.netfilter.nf_conntrack_max = 2097152\n"
+  )]
+  fn it_gets_network_sysctls(#[case] cpus: i32, #[case] overrides: &[(&str, &str)], #[case] expected: &str) {
+    let overrides = overrides
+      .iter()
+      .map(|(setting, value)| (setting.to_string(), value.to_string()))
+      .collect::<Vec<_>>();
+
+    assert_eq!(render_sysctls(&get_network_sysctls(cpus, &overrides)), expected);
+  }
+
+  #[test]
+  fn it_parses_sysctls() {
+    assert_eq!(
+      parse_sysctl("net.ipv4.ip_local_port_range = 1024 65535").unwrap(),
+      ("net.ipv4.ip_local_port_range".to_owned(), "1024 65535".to_owned())
+    );
+    assert!(parse_sysctl("net.ipv4.tcp_fin_timeout").is_err());
+    assert!(parse_sysctl("=30").is_err());
+  }
+
+  #[test]
+  fn it_renders_mtu_dropin() {
+    assert_eq!(get_mtu_dropin(9001), "[Link]\nMTUBytes=9001\n");
+    assert_eq!(
+      get_mtu_dropin_path("ens5"),
+      PathBuf::from("/etc/systemd/network/70-ens5.network.d/10-eksnode-mtu.conf")
+    );
+  }
+}
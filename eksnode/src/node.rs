@@ -0,0 +1,207 @@
+use std::{net::IpAddr, path::PathBuf};
+
+use anyhow::{bail, Result};
+use ipnet::IpNet;
+
+use crate::{commands::join::JoinClusterInput, containerd, ec2, hybrid, kubelet, profile};
+
+/// Joins the node to an EKS cluster when `eksnode` is embedded as a library
+///
+/// Provides the same behavior as `eksnode join-cluster` without parsing command line arguments. Inputs not covered
+/// by the builder methods can be set by constructing the `JoinClusterInput` directly and converting it:
+///
+/// ```no_run
+/// # async fn join() -> anyhow::Result<()> {
+/// eksnode::NodeJoiner::new("example")
+///   .max_pods(58)
+///   .node_taint("dedicated=gpu:NoSchedule".parse()?)
+///   .join()
+///   .await
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct NodeJoiner {
+  input: JoinClusterInput,
+}
+
+impl NodeJoiner {
+  /// Create a joiner for the named cluster, with the remaining inputs set to the `join-cluster` defaults
+  pub fn new(cluster_name: impl Into<String>) -> Self {
+    Self {
+      input: JoinClusterInput {
+        cluster_name: cluster_name.into(),
+        use_max_pods: true,
+        ..JoinClusterInput::default()
+      },
+    }
+  }
+
+  /// The cluster API server endpoint and base64 encoded CA, bypassing the call to describe the cluster
+  pub fn cluster_endpoint(mut self, endpoint: impl Into<String>, b64_ca: impl Into<String>) -> Self {
+    self.input.apiserver_endpoint = Some(endpoint.into());
+    self.input.b64_cluster_ca = Some(b64_ca.into());
+    self
+  }
+
+  /// The IPv4 or IPv6 CIDR range of the cluster services
+  pub fn service_cidr(mut self, cidr: IpNet) -> Self {
+    self.input.service_cidr = Some(cidr);
+    self
+  }
+
+  /// Overrides the IP address used for DNS queries within the cluster
+  pub fn cluster_dns_ip(mut self, ip: IpAddr) -> Self {
+    self.input.cluster_dns_ip = Some(ip);
+    self
+  }
+
+  /// Host index of the cluster DNS service within the service CIDR, used when the cluster DNS IP is derived
+  pub fn cluster_dns_host_index(mut self, host_index: u32) -> Self {
+    self.input.cluster_dns_host_index = Some(host_index);
+    self
+  }
+
+  /// The source of the AWS credentials used by the node
+  pub fn credential_provider(mut self, provider: hybrid::CredentialProvider) -> Self {
+    self.input.credential_provider = provider;
+    self
+  }
+
+  /// The AWS region of the cluster; required for hybrid nodes
+  pub fn region(mut self, region: impl Into<String>) -> Self {
+    self.input.region = Some(region.into());
+    self
+  }
+
+  /// The name of the node object; required for hybrid nodes
+  pub fn node_name(mut self, name: impl Into<String>) -> Self {
+    self.input.node_name = Some(name.into());
+    self
+  }
+
+  /// The source of the name of the node object on EC2 instances, when no node name is given
+  pub fn node_name_strategy(mut self, strategy: ec2::NodeNameStrategy) -> Self {
+    self.input.node_name_strategy = strategy;
+    self
+  }
+
+  /// Overrides the maximum number of pods that can run on the node
+  pub fn max_pods(mut self, max_pods: i32) -> Self {
+    self.input.max_pods = Some(max_pods);
+    self
+  }
+
+  /// Extra arguments to add to the kubelet
+  pub fn kubelet_extra_args(mut self, args: impl Into<String>) -> Self {
+    self.input.kubelet_extra_args = Some(args.into());
+    self
+  }
+
+  /// Adds a taint to the node when it registers with the cluster
+  pub fn node_taint(mut self, taint: kubelet::Taint) -> Self {
+    self.input.node_taint.push(taint);
+    self
+  }
+
+  /// Adds a label to the node when it registers with the cluster
+  pub fn node_label(mut self, label: kubelet::NodeLabel) -> Self {
+    self.input.node_labels.push(label);
+    self
+  }
+
+  /// The pause container image, optionally per architecture
+  pub fn pause_container_image(mut self, image: containerd::PauseImage) -> Self {
+    self.input.pause_container_image = Some(image);
+    self
+  }
+
+  /// Tuning profile applied to the kubelet and containerd
+  pub fn profile(mut self, profile: profile::ProfileName) -> Self {
+    self.input.profile = profile;
+    self
+  }
+
+  /// Skip verifying the node IAM role permissions and GPU compatibility before joining
+  pub fn skip_preflight(mut self, skip: bool) -> Self {
+    self.input.skip_preflight = skip;
+    self
+  }
+
+  /// Take over a node already configured by bootstrap.sh or nodeadm instead of refusing to join
+  pub fn adopt(mut self, adopt: bool) -> Self {
+    self.input.adopt = adopt;
+    self
+  }
+
+  /// The inputs used to join the node
+  pub fn input(&self) -> &JoinClusterInput {
+    &self.input
+  }
+
+  /// Validate the inputs without calling AWS or reading from the filesystem
+  ///
+  /// Returns the list of issues found, which is empty when the inputs are valid
+  pub fn validate(&self) -> Vec<String> {
+    self.input.validate_config()
+  }
+
+  /// Generate the node configuration files under `output_dir`, in a tree mirroring `/`
+  ///
+  /// Cluster and instance details are still discovered, but the host is not changed and no services are started
+  pub async fn render(mut self, output_dir: impl Into<PathBuf>) -> Result<()> {
+    self.input.dry_run = true;
+    self.input.output_dir = Some(output_dir.into());
+    self.run().await
+  }
+
+  /// Configure the node and start containerd and kubelet to join the cluster
+  pub async fn join(mut self) -> Result<()> {
+    self.input.dry_run = false;
+    self.input.output_dir = None;
+    self.run().await
+  }
+
+  async fn run(&mut self) -> Result<()> {
+    let issues = self.validate();
+    if !issues.is_empty() {
+      bail!("Invalid join inputs:\n  {}", issues.join("\n  "));
+    }
+
+    self.input.join_node_to_cluster().await
+  }
+}
+
+impl From<JoinClusterInput> for NodeJoiner {
+  fn from(input: JoinClusterInput) -> Self {
+    Self { input }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_builds_join_input() {
+    let joiner = NodeJoiner::new("example")
+      .cluster_endpoint("https://example.eks.amazonaws.com", "Y2VydA==")
+      .max_pods(58)
+      .node_taint("dedicated=gpu:NoSchedule".parse().unwrap())
+      .node_label("team=data".parse().unwrap());
+
+    let input = joiner.input();
+    assert_eq!(input.cluster_name, "example");
+    assert_eq!(input.max_pods, Some(58));
+    assert!(input.use_max_pods);
+    assert_eq!(input.node_taint.len(), 1);
+    assert_eq!(input.node_labels.len(), 1);
+    assert!(joiner.validate().is_empty());
+  }
+
+  #[test]
+  fn it_reports_invalid_join_input() {
+    let joiner = NodeJoiner::new("example").max_pods(0);
+
+    assert_eq!(joiner.validate(), vec!["max_pods must be greater than 0: 0"]);
+  }
+}
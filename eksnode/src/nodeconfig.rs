@@ -0,0 +1,144 @@
+//! Versioned NodeConfig documents declaring the `join-cluster` inputs
+//!
+//! Modeled on the `nodeadm` NodeConfig; the spec holds the `join-cluster` inputs by their field names (i.e. -
+//! `cluster_name: example`), the same as the plain config files that `validate-config` and `reconcile` accept
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+
+use crate::commands::join::JoinClusterInput;
+
+/// API version of the NodeConfig documents supported
+pub const API_VERSION: &str = "eksnode.clowdhaus.com/v1alpha1";
+
+pub const KIND: &str = "NodeConfig";
+
+/// Versioned document of the `join-cluster` inputs
+///
+/// ```yaml
+/// apiVersion: eksnode.clowdhaus.com/v1alpha1
+/// kind: NodeConfig
+/// spec:
+///   cluster_name: example
+///   max_pods: 58
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct NodeConfig {
+  pub api_version: String,
+  pub kind: String,
+  #[serde(default)]
+  pub spec: Value,
+}
+
+impl NodeConfig {
+  pub fn new(input: &JoinClusterInput) -> Result<Self> {
+    Ok(Self {
+      api_version: API_VERSION.to_owned(),
+      kind: KIND.to_owned(),
+      spec: serde_yaml::to_value(input)?,
+    })
+  }
+}
+
+/// Get the mapping of `join-cluster` inputs from a config file, which is either a NodeConfig or the plain mapping
+pub fn get_spec(contents: &str) -> Result<Mapping> {
+  let value: Value = serde_yaml::from_str(contents)?;
+  let spec = match value.as_mapping().is_some_and(|fields| fields.contains_key("kind")) {
+    true => {
+      let config: NodeConfig = serde_yaml::from_value(value)?;
+      if config.kind != KIND {
+        bail!("Unsupported kind {}; expected {KIND}", config.kind);
+      }
+      if config.api_version != API_VERSION {
+        bail!("Unsupported apiVersion {}; expected {API_VERSION}", config.api_version);
+      }
+      config.spec
+    }
+    false => value,
+  };
+
+  match spec {
+    Value::Mapping(fields) => Ok(fields),
+    Value::Null => Ok(Mapping::new()),
+    _ => bail!("Config must be a mapping of join-cluster inputs"),
+  }
+}
+
+/// Get the fields of the spec that are not `join-cluster` inputs, since they are otherwise silently ignored
+pub fn get_unknown_fields(spec: &Mapping) -> Result<Vec<String>> {
+  let known = serde_yaml::to_value(JoinClusterInput::default())?;
+
+  Ok(
+    spec
+      .keys()
+      .filter(|key| !known.as_mapping().is_some_and(|known| known.contains_key(*key)))
+      .map(|key| key.as_str().unwrap_or_default().to_owned())
+      .collect(),
+  )
+}
+
+/// Merge the spec over the command line inputs, except for the inputs set explicitly on the command line or through
+/// the environment which take precedence
+///
+/// Inputs in neither keep the `join-cluster` defaults
+pub fn merge(spec: &Mapping, input: &JoinClusterInput, explicit: &[String]) -> Result<JoinClusterInput> {
+  let Value::Mapping(mut fields) = serde_yaml::to_value(input)? else {
+    bail!("join-cluster inputs must serialize to a mapping");
+  };
+  for (key, value) in spec {
+    if !explicit.iter().any(|id| key.as_str() == Some(id.as_str())) {
+      fields.insert(key.to_owned(), value.to_owned());
+    }
+  }
+
+  Ok(serde_yaml::from_value(Value::Mapping(fields))?)
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::{CommandFactory, FromArgMatches, Parser};
+
+  use super::*;
+  use crate::cli;
+
+  #[derive(Parser)]
+  struct JoinCluster {
+    #[command(flatten)]
+    input: JoinClusterInput,
+  }
+
+  #[test]
+  fn it_gets_spec() {
+    let spec = get_spec(&format!(
+      "apiVersion: {API_VERSION}\nkind: {KIND}\nspec:\n  cluster_name: example\n"
+    ))
+    .unwrap();
+    assert_eq!(spec, get_spec("cluster_name: example").unwrap());
+    assert!(get_spec(&format!("apiVersion: {API_VERSION}\nkind: {KIND}\n"))
+      .unwrap()
+      .is_empty());
+
+    assert!(get_spec("apiVersion: node.eks.aws/v1alpha1\nkind: NodeConfig\nspec: {}\n").is_err());
+    assert!(get_spec(&format!("apiVersion: {API_VERSION}\nkind: Node\nspec: {{}}\n")).is_err());
+    assert!(get_spec("- cluster_name").is_err());
+
+    let spec = get_spec("cluster_name: example\ncluster_nmae: typo\n").unwrap();
+    assert_eq!(get_unknown_fields(&spec).unwrap(), vec!["cluster_nmae"]);
+  }
+
+  #[test]
+  fn it_merges_command_line_over_spec() {
+    let spec = get_spec("cluster_name: file\nmax_pods: 58\nsgpp_enabled: true\n").unwrap();
+    let matches = JoinCluster::command().get_matches_from(["join-cluster", "--max-pods", "110"]);
+    let input = JoinCluster::from_arg_matches(&matches).unwrap().input;
+
+    let merged = merge(&spec, &input, &cli::get_explicit_args(&matches)).unwrap();
+    assert_eq!(merged.cluster_name, "file");
+    assert_eq!(merged.max_pods, Some(110));
+    assert!(merged.sgpp_enabled);
+    // Defaults of the command line are kept rather than those of the serialized inputs
+    assert!(merged.use_max_pods);
+  }
+}
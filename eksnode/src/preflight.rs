@@ -0,0 +1,254 @@
+use std::{
+  fmt,
+  net::UdpSocket,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+use aws_sdk_ec2::error::ProvideErrorMetadata;
+use tracing::{info, warn};
+
+use crate::{
+  aws,
+  events::{self, EventKind},
+};
+
+/// IAM permissions required by the node role to join the cluster
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Permission {
+  /// Required to discover the cluster endpoint and CA when not provided
+  EksDescribeCluster,
+  /// Required by the ECR credential provider and to pull the sandbox image
+  EcrGetAuthorizationToken,
+  /// Required to look up the private DNS name used as the node name
+  Ec2DescribeInstances,
+}
+
+impl fmt::Display for Permission {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::EksDescribeCluster => write!(f, "eks:DescribeCluster"),
+      Self::EcrGetAuthorizationToken => write!(f, "ecr:GetAuthorizationToken"),
+      Self::Ec2DescribeInstances => write!(f, "ec2:DescribeInstances"),
+    }
+  }
+}
+
+/// Error codes returned by AWS services when the caller lacks permission for the action
+fn is_access_denied(code: Option<&str>) -> bool {
+  matches!(
+    code,
+    Some("AccessDenied" | "AccessDeniedException" | "UnauthorizedOperation" | "UnauthorizedException")
+  )
+}
+
+/// Exercise the API call that requires the permission, returning the error code on failure
+async fn check(permission: Permission, cluster_name: &str, instance_id: Option<&str>) -> Result<Option<String>> {
+  let code = match permission {
+    Permission::EksDescribeCluster => {
+      let client = aws::get_eks_client().await;
+      match client.describe_cluster().name(cluster_name).send().await {
+        Ok(_) => None,
+        Err(e) => Some(e.into_service_error().code().unwrap_or("Unknown").to_owned()),
+      }
+    }
+    Permission::EcrGetAuthorizationToken => {
+      let client = aws::get_ecr_client().await;
+      match client.get_authorization_token().send().await {
+        Ok(_) => None,
+        Err(e) => Some(e.into_service_error().code().unwrap_or("Unknown").to_owned()),
+      }
+    }
+    Permission::Ec2DescribeInstances => {
+      let client = aws::get_ec2_client().await;
+      match client
+        .describe_instances()
+        .set_instance_ids(instance_id.map(|id| vec![id.to_owned()]))
+        .send()
+        .await
+      {
+        Ok(_) => None,
+        Err(e) => Some(e.into_service_error().code().unwrap_or("Unknown").to_owned()),
+      }
+    }
+  };
+
+  Ok(code)
+}
+
+/// Verify the node role has the permissions required to join the cluster
+///
+/// Each permission is exercised with the minimal API call that requires it. All calls are attempted so that every
+/// missing permission is reported at once, rather than failing midway through join with a generic SDK error.
+/// Failures other than access denied (i.e. - throttling, networking) are logged and left to surface during join
+pub async fn check_iam_permissions(
+  permissions: &[Permission],
+  cluster_name: &str,
+  instance_id: Option<&str>,
+) -> Result<()> {
+  let mut missing = Vec::new();
+
+  for permission in permissions {
+    let code = check(*permission, cluster_name, instance_id).await?;
+    if let Some(code) = &code {
+      events::record(EventKind::ApiCallFailed, &permission.to_string(), Some(code.to_owned()));
+    }
+    match code {
+      None => info!("Preflight: {permission} allowed"),
+      Some(code) if is_access_denied(Some(&code)) => missing.push(permission.to_string()),
+      Some(code) => warn!("Preflight: unable to verify {permission}: {code}"),
+    }
+  }
+
+  if !missing.is_empty() {
+    bail!(
+      "The node IAM role is missing the following permission(s): {}. Update the role's policies \
+       (i.e. - AmazonEKSWorkerNodePolicy, AmazonEC2ContainerRegistryReadOnly) and retry",
+      missing.join(", ")
+    );
+  }
+
+  Ok(())
+}
+
+/// Amazon Time Sync Service NTP server, reachable from all EC2 instances
+pub const TIME_SYNC_SERVER: &str = "169.254.169.123:123";
+
+/// Default maximum clock offset in milliseconds
+///
+/// Amazon Time Sync keeps synchronized instances well within this; a larger offset means chrony is not
+/// synchronizing, which breaks signed requests, tokens, and certificate validation once it grows
+pub const DEFAULT_MAX_CLOCK_SKEW_MS: u64 = 1000;
+
+/// Seconds between the NTP epoch (1900) and the Unix epoch (1970)
+const NTP_UNIX_EPOCH_OFFSET: f64 = 2_208_988_800.0;
+
+/// Size of an NTP packet without extensions
+const NTP_PACKET_SIZE: usize = 48;
+
+/// Seconds since the Unix epoch of an NTP timestamp (32 bit seconds and 32 bit fraction)
+fn parse_ntp_timestamp(bytes: &[u8]) -> f64 {
+  let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+  let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+
+  f64::from(seconds) + f64::from(fraction) / 2f64.powi(32) - NTP_UNIX_EPOCH_OFFSET
+}
+
+/// Calculate the offset of the local clock from the server in milliseconds, positive when the local clock is behind
+///
+/// `sent` and `received` are the local Unix times in seconds when the request was sent and the response received
+fn parse_clock_offset(response: &[u8], sent: f64, received: f64) -> Result<i64> {
+  if response.len() < NTP_PACKET_SIZE {
+    bail!("Invalid NTP response of {} bytes", response.len());
+  }
+  // Mode 4 is a server response; stratum 0 is a kiss-o'-death response (i.e. - rate limited)
+  if response[0] & 0x7 != 4 || response[1] == 0 {
+    bail!(
+      "Unexpected NTP response (mode {}, stratum {})",
+      response[0] & 0x7,
+      response[1]
+    );
+  }
+
+  let server_received = parse_ntp_timestamp(&response[32..40]);
+  let server_sent = parse_ntp_timestamp(&response[40..48]);
+  let offset = ((server_received - sent) + (server_sent - received)) / 2.0;
+
+  Ok((offset * 1000.0).round() as i64)
+}
+
+fn unix_now() -> Result<f64> {
+  Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64())
+}
+
+/// Measure the offset of the local clock from the NTP server (i.e. - `169.254.169.123:123`) in milliseconds
+pub fn get_clock_offset(server: &str) -> Result<i64> {
+  let socket = UdpSocket::bind("0.0.0.0:0")?;
+  socket.set_read_timeout(Some(Duration::from_secs(2)))?;
+  socket.connect(server)?;
+
+  // LI 0 (no warning), version 4, mode 3 (client)
+  let mut request = [0u8; NTP_PACKET_SIZE];
+  request[0] = 0x23;
+
+  let sent = unix_now()?;
+  socket.send(&request)?;
+  let mut response = [0u8; NTP_PACKET_SIZE];
+  let len = socket.recv(&mut response)?;
+  let received = unix_now()?;
+
+  parse_clock_offset(&response[..len], sent, received)
+}
+
+/// Verify the clock offset measured from Amazon Time Sync is within the maximum
+pub fn verify_clock_offset(offset_ms: i64, max_skew_ms: u64) -> Result<()> {
+  if offset_ms.unsigned_abs() > max_skew_ms {
+    bail!(
+      "The clock is {offset_ms}ms off from Amazon Time Sync ({TIME_SYNC_SERVER}), exceeding the maximum of \
+       {max_skew_ms}ms. Verify chrony is running and synchronizing (i.e. - `chronyc tracking`)"
+    );
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use rstest::*;
+
+  use super::*;
+
+  #[rstest]
+  #[case(Some("AccessDeniedException"), true)]
+  #[case(Some("UnauthorizedOperation"), true)]
+  #[case(Some("AccessDenied"), true)]
+  #[case(Some("ThrottlingException"), false)]
+  #[case(Some("ResourceNotFoundException"), false)]
+  #[case(None, false)]
+  fn it_detects_access_denied(#[case] code: Option<&str>, #[case] expected: bool) {
+    assert_eq!(is_access_denied(code), expected);
+  }
+
+  #[test]
+  fn it_displays_permission_actions() {
+    assert_eq!(Permission::EksDescribeCluster.to_string(), "eks:DescribeCluster");
+    assert_eq!(
+      Permission::EcrGetAuthorizationToken.to_string(),
+      "ecr:GetAuthorizationToken"
+    );
+    assert_eq!(Permission::Ec2DescribeInstances.to_string(), "ec2:DescribeInstances");
+  }
+
+  #[test]
+  fn it_parses_clock_offset() {
+    // Server timestamps of 2024-01-01T00:00:01.5Z
+    let seconds = (1_704_067_201u64 + 2_208_988_800).to_be_bytes();
+    let fraction = (1u32 << 31).to_be_bytes();
+    let mut response = [0u8; NTP_PACKET_SIZE];
+    response[0] = 0x24;
+    response[1] = 1;
+    for start in [32, 40] {
+      response[start..start + 4].copy_from_slice(&seconds[4..]);
+      response[start + 4..start + 8].copy_from_slice(&fraction);
+    }
+
+    // Local clock 2.25s behind with a 0.5s round trip
+    let sent = 1_704_067_199.0;
+    assert_eq!(parse_clock_offset(&response, sent, sent + 0.5).unwrap(), 2250);
+
+    response[1] = 0;
+    assert!(parse_clock_offset(&response, sent, sent + 0.5).is_err());
+    assert!(parse_clock_offset(&response[..16], sent, sent + 0.5).is_err());
+  }
+
+  #[rstest]
+  #[case(250, true)]
+  #[case(-1000, true)]
+  #[case(-1001, false)]
+  fn it_verifies_clock_offset(#[case] offset_ms: i64, #[case] expected: bool) {
+    assert_eq!(
+      verify_clock_offset(offset_ms, DEFAULT_MAX_CLOCK_SKEW_MS).is_ok(),
+      expected
+    );
+  }
+}
@@ -0,0 +1,145 @@
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::Assets;
+
+/// Tuning profile applied to the kubelet and containerd configuration at join
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProfileName {
+  /// Generated defaults without additional tuning
+  #[default]
+  Default,
+  /// Accelerated instances running workloads with large images
+  Gpu,
+  /// Nodes running a large number of small pods
+  HighDensity,
+  /// Memory bound workloads
+  MemoryOptimized,
+}
+
+impl fmt::Display for ProfileName {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Default => write!(f, "default"),
+      Self::Gpu => write!(f, "gpu"),
+      Self::HighDensity => write!(f, "high-density"),
+      Self::MemoryOptimized => write!(f, "memory-optimized"),
+    }
+  }
+}
+
+/// Kubelet and containerd settings of a tuning profile
+///
+/// Kubelet settings are merged over the generated defaults; unset settings are left unchanged
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Profile {
+  #[serde(default)]
+  pub kube_reserved: BTreeMap<String, String>,
+  #[serde(default)]
+  pub system_reserved: BTreeMap<String, String>,
+  #[serde(default)]
+  pub eviction_hard: BTreeMap<String, String>,
+  #[serde(default)]
+  pub eviction_soft: BTreeMap<String, String>,
+  #[serde(default)]
+  pub eviction_soft_grace_period: BTreeMap<String, String>,
+  pub serialize_image_pulls: Option<bool>,
+  pub max_parallel_image_pulls: Option<i32>,
+  /// Merged into the containerd CRI plugin configuration
+  pub containerd: Option<JsonValue>,
+}
+
+impl Profile {
+  fn validate(&self, name: &str) -> Result<()> {
+    if self.max_parallel_image_pulls.is_some() && self.serialize_image_pulls != Some(false) {
+      bail!("Profile {name} sets maxParallelImagePulls which requires serializeImagePulls: false");
+    }
+    if let Some(key) = self
+      .eviction_soft
+      .keys()
+      .find(|key| !self.eviction_soft_grace_period.contains_key(*key))
+    {
+      bail!("Profile {name} sets evictionSoft {key} without a corresponding evictionSoftGracePeriod");
+    }
+    if self.containerd.as_ref().is_some_and(|c| !c.is_object()) {
+      bail!("Profile {name} containerd settings must be a mapping");
+    }
+
+    Ok(())
+  }
+}
+
+fn parse_profiles(contents: &[u8]) -> Result<BTreeMap<String, Profile>> {
+  Ok(serde_yaml::from_slice::<Option<_>>(contents)?.unwrap_or_default())
+}
+
+/// Get the tuning profile from the embedded profiles, replaced by the profile of the same name in the override file
+pub fn get_profile(name: ProfileName, override_path: Option<&Path>) -> Result<Profile> {
+  let file = Assets::load("profiles.yaml")?;
+  let mut profiles = parse_profiles(file.as_ref())?;
+
+  if let Some(path) = override_path {
+    let contents = std::fs::read(path)?;
+    profiles.extend(parse_profiles(&contents)?);
+  }
+
+  let name = name.to_string();
+  let profile = profiles
+    .remove(&name)
+    .ok_or_else(|| anyhow!("Profile {name} is not defined"))?;
+  profile.validate(&name)?;
+
+  Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+  use rstest::*;
+
+  use super::*;
+
+  #[rstest]
+  #[case(ProfileName::Default)]
+  #[case(ProfileName::Gpu)]
+  #[case(ProfileName::HighDensity)]
+  #[case(ProfileName::MemoryOptimized)]
+  fn it_gets_embedded_profiles(#[case] name: ProfileName) {
+    let profile = get_profile(name, None).unwrap();
+    insta::assert_debug_snapshot!(format!("profile-{name}"), profile);
+  }
+
+  #[test]
+  fn it_overrides_profiles() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "gpu:\n  serializeImagePulls: true\n").unwrap();
+
+    let profile = get_profile(ProfileName::Gpu, Some(file.path())).unwrap();
+    assert_eq!(
+      profile,
+      Profile {
+        serialize_image_pulls: Some(true),
+        ..Profile::default()
+      }
+    );
+  }
+
+  #[test]
+  fn it_rejects_invalid_profiles() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+
+    std::fs::write(file.path(), "gpu:\n  maxParallelImagePulls: 5\n").unwrap();
+    assert!(get_profile(ProfileName::Gpu, Some(file.path())).is_err());
+
+    std::fs::write(file.path(), "gpu:\n  evictionSoft:\n    memory.available: 1Gi\n").unwrap();
+    assert!(get_profile(ProfileName::Gpu, Some(file.path())).is_err());
+
+    std::fs::write(file.path(), "gpu:\n  unknownField: true\n").unwrap();
+    assert!(get_profile(ProfileName::Gpu, Some(file.path())).is_err());
+  }
+}
@@ -57,6 +57,17 @@ pub fn calculate_eni_max_pods(num_enis: i32, ipv4_addrs: i32, enable_prefix_del:
   num_enis * ((ipv4_addrs - 1) * modifier) + 2
 }
 
+/// Number of ENIs that provide pod IPs when security groups for pods (`ENABLE_POD_ENI`) is enabled
+///
+/// The VPC CNI attaches a trunk ENI to Nitro instances for the branch ENIs of pods with security groups; the trunk
+/// ENI does not provide pod IPs. Pods using branch ENIs still count towards max pods
+pub fn sgpp_available_enis(num_enis: i32, hypervisor: &str) -> i32 {
+  match hypervisor {
+    "nitro" => (num_enis - 1).max(1),
+    _ => num_enis,
+  }
+}
+
 /// Evaluate if the CNI version supports prefix delegation
 ///
 /// https://docs.aws.amazon.com/eks/latest/userguide/cni-increase-ip-addresses.html
@@ -124,6 +135,14 @@ mod tests {
     assert_eq!(expected, result);
   }
 
+  #[rstest]
+  #[case(3, "nitro", 2)]
+  #[case(1, "nitro", 1)]
+  #[case(3, "xen", 3)]
+  fn sgpp_available_enis_test(#[case] num_enis: i32, #[case] hypervisor: &str, #[case] expected: i32) {
+    assert_eq!(sgpp_available_enis(num_enis, hypervisor), expected);
+  }
+
   #[rstest]
   #[case("1.8.0", false)]
   #[case("1.9.0", true)]
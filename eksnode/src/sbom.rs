@@ -0,0 +1,187 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+/// Software bill of materials (SBOM) document format
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum SbomFormat {
+  /// CycloneDX 1.5 JSON
+  Cyclonedx,
+  /// SPDX 2.3 JSON
+  Spdx,
+}
+
+/// The kind of software component installed on the node
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComponentKind {
+  /// Package installed by the system package manager
+  Rpm,
+  /// Binary installed outside of the package manager (i.e. - kubelet)
+  Binary,
+}
+
+/// Software component included in the SBOM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Component {
+  pub name: String,
+  pub version: String,
+  pub kind: ComponentKind,
+}
+
+impl Component {
+  /// Package URL identifying the component
+  ///
+  /// https://github.com/package-url/purl-spec
+  pub fn purl(&self, distro: &str) -> String {
+    match self.kind {
+      ComponentKind::Rpm => format!("pkg:rpm/{distro}/{}@{}", self.name, self.version),
+      ComponentKind::Binary => format!("pkg:generic/{}@{}", self.name, self.version),
+    }
+  }
+}
+
+/// Details of the SBOM document that are not derived from the components
+#[derive(Debug)]
+pub struct Document<'a> {
+  /// Unique ID of the document (UUID)
+  pub id: &'a str,
+  /// RFC 3339 creation timestamp
+  pub timestamp: &'a str,
+  /// Distribution ID used as the RPM package URL namespace (i.e. - `amzn`)
+  pub distro: &'a str,
+}
+
+/// Render the components as a CycloneDX 1.5 JSON document
+///
+/// https://cyclonedx.org/docs/1.5/json/
+pub fn cyclonedx(doc: &Document, components: &[Component]) -> JsonValue {
+  let components = components
+    .iter()
+    .map(|c| {
+      let purl = c.purl(doc.distro);
+      json!({
+        "type": match c.kind {
+          ComponentKind::Rpm => "library",
+          ComponentKind::Binary => "application",
+        },
+        "bom-ref": purl,
+        "name": c.name,
+        "version": c.version,
+        "purl": purl,
+      })
+    })
+    .collect::<Vec<_>>();
+
+  json!({
+    "bomFormat": "CycloneDX",
+    "specVersion": "1.5",
+    "serialNumber": format!("urn:uuid:{}", doc.id),
+    "version": 1,
+    "metadata": {
+      "timestamp": doc.timestamp,
+      "tools": {
+        "components": [{
+          "type": "application",
+          "name": env!("CARGO_PKG_NAME"),
+          "version": env!("CARGO_PKG_VERSION"),
+        }]
+      },
+      "component": {
+        "type": "operating-system",
+        "name": doc.distro,
+      }
+    },
+    "components": components,
+  })
+}
+
+/// Render the components as an SPDX 2.3 JSON document
+///
+/// https://spdx.github.io/spdx-spec/v2.3/
+pub fn spdx(doc: &Document, components: &[Component]) -> JsonValue {
+  let packages = components
+    .iter()
+    .enumerate()
+    .map(|(i, c)| {
+      json!({
+        "name": c.name,
+        "SPDXID": format!("SPDXRef-Package-{i}"),
+        "versionInfo": c.version,
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": false,
+        "externalRefs": [{
+          "referenceCategory": "PACKAGE-MANAGER",
+          "referenceType": "purl",
+          "referenceLocator": c.purl(doc.distro),
+        }]
+      })
+    })
+    .collect::<Vec<_>>();
+
+  let relationships = (0..components.len())
+    .map(|i| {
+      json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": format!("SPDXRef-Package-{i}"),
+      })
+    })
+    .collect::<Vec<_>>();
+
+  json!({
+    "spdxVersion": "SPDX-2.3",
+    "dataLicense": "CC0-1.0",
+    "SPDXID": "SPDXRef-DOCUMENT",
+    "name": format!("{}-node", doc.distro),
+    "documentNamespace": format!("https://spdx.org/spdxdocs/{}-{}", env!("CARGO_PKG_NAME"), doc.id),
+    "creationInfo": {
+      "created": doc.timestamp,
+      "creators": [format!("Tool: {}-{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))],
+    },
+    "packages": packages,
+    "relationships": relationships,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn components() -> Vec<Component> {
+    vec![
+      Component {
+        name: "containerd".to_owned(),
+        version: "1.7.11-1.amzn2023.0.1".to_owned(),
+        kind: ComponentKind::Rpm,
+      },
+      Component {
+        name: "kubelet".to_owned(),
+        version: "1.29.3".to_owned(),
+        kind: ComponentKind::Binary,
+      },
+    ]
+  }
+
+  const DOC: Document = Document {
+    id: "5f0c6b6e-2a4f-4c1e-9a6e-3e2b7f1d0c11",
+    timestamp: "2024-01-01T00:00:00Z",
+    distro: "amzn",
+  };
+
+  /// Redact the eksnode version so the snapshots do not change on release
+  fn render(doc: JsonValue) -> String {
+    serde_json::to_string_pretty(&doc)
+      .unwrap()
+      .replace(env!("CARGO_PKG_VERSION"), "[version]")
+  }
+
+  #[test]
+  fn it_renders_cyclonedx() {
+    insta::assert_snapshot!(render(cyclonedx(&DOC, &components())));
+  }
+
+  #[test]
+  fn it_renders_spdx() {
+    insta::assert_snapshot!(render(spdx(&DOC, &components())));
+  }
+}
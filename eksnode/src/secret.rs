@@ -0,0 +1,67 @@
+//! Sensitive values that are redacted when formatted
+//!
+//! Cluster CA data, ECR tokens, and kubeconfig credentials are wrapped in [`Secret`] so that debug level tracing and
+//! debug bundles never contain them; the value is only available through [`Secret::expose`]
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Text shown in place of a sensitive value
+const REDACTED: &str = "[REDACTED]";
+
+/// Value whose `Debug` and `Display` output is redacted
+///
+/// Serialized as the inner value, since the files written for kubelet require the actual contents
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+  pub fn new(value: T) -> Self {
+    Self(value)
+  }
+
+  /// Get the sensitive value; avoid passing it to logging or formatting macros
+  pub fn expose(&self) -> &T {
+    &self.0
+  }
+
+  pub fn into_inner(self) -> T {
+    self.0
+  }
+}
+
+impl<T> From<T> for Secret<T> {
+  fn from(value: T) -> Self {
+    Self(value)
+  }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(REDACTED)
+  }
+}
+
+impl<T> fmt::Display for Secret<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(REDACTED)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_redacts_secret() {
+    let secret = Secret::new("QVdTOnRva2Vu".to_owned());
+
+    assert_eq!(format!("{secret:?}"), REDACTED);
+    assert_eq!(format!("{secret}"), REDACTED);
+    assert_eq!(format!("{:?}", Some(&secret)), "Some([REDACTED])");
+    assert_eq!(secret.expose(), "QVdTOnRva2Vu");
+    assert_eq!(serde_json::to_string(&secret).unwrap(), "\"QVdTOnRva2Vu\"");
+  }
+}
@@ -0,0 +1,195 @@
+use anyhow::{bail, Result};
+use aws_sdk_securityhub::types::{
+  AwsSecurityFinding, Compliance, ComplianceStatus, Partition, RecordState, Resource, Severity as FindingSeverity,
+  SeverityLabel,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+
+use crate::{aws, ec2::InstanceIdentity, events};
+
+const BATCH_IMPORT_FINDINGS: &str = "securityhub:BatchImportFindings";
+
+/// Version of the AWS Security Finding Format (ASFF) the findings are written in
+const ASFF_SCHEMA_VERSION: &str = "2018-10-08";
+
+/// Finding type under which the node benchmark checks are reported
+const FINDING_TYPE: &str =
+  "Software and Configuration Checks/Industry and Regulatory Standards/CIS Host Hardening Benchmarks";
+
+/// Maximum number of findings accepted by a single BatchImportFindings request
+const BATCH_IMPORT_MAX_FINDINGS: usize = 100;
+
+/// Severity of a failed check
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Severity {
+  Informational,
+  Low,
+  Medium,
+  High,
+  Critical,
+}
+
+impl Severity {
+  fn label(&self) -> SeverityLabel {
+    match self {
+      Self::Informational => SeverityLabel::Informational,
+      Self::Low => SeverityLabel::Low,
+      Self::Medium => SeverityLabel::Medium,
+      Self::High => SeverityLabel::High,
+      Self::Critical => SeverityLabel::Critical,
+    }
+  }
+}
+
+/// Result of a check performed on the node
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+  /// Identifier of the check, unique within the generator (i.e. - `/etc/containerd/config.toml`)
+  pub check: String,
+  pub title: String,
+  pub description: String,
+  pub severity: Severity,
+  pub passed: bool,
+}
+
+/// Convert the findings to the AWS Security Finding Format with the instance as the affected resource
+///
+/// The finding IDs are derived from the instance and check so that subsequent imports update the existing findings
+pub fn to_asff(
+  findings: &[Finding],
+  identity: &InstanceIdentity,
+  generator: &str,
+  timestamp: &str,
+) -> Result<Vec<AwsSecurityFinding>> {
+  let partition = identity.partition();
+  let instance_arn = identity.arn();
+  let product_arn = format!(
+    "arn:{partition}:securityhub:{}:{}:product/{}/default",
+    identity.region, identity.account_id, identity.account_id
+  );
+
+  findings
+    .iter()
+    .map(|finding| {
+      // Passed checks are reported as informational so that they do not contribute to the severity of the resource
+      let (severity, status) = match finding.passed {
+        true => (Severity::Informational, ComplianceStatus::Passed),
+        false => (finding.severity, ComplianceStatus::Failed),
+      };
+      let resource = Resource::builder()
+        .r#type("AwsEc2Instance")
+        .id(&instance_arn)
+        .partition(Partition::from(partition))
+        .region(&identity.region)
+        .build();
+
+      Ok(
+        AwsSecurityFinding::builder()
+          .schema_version(ASFF_SCHEMA_VERSION)
+          .id(format!(
+            "{instance_arn}/{generator}/{}",
+            finding.check.trim_start_matches('/')
+          ))
+          .product_arn(&product_arn)
+          .generator_id(format!("eksnode/{generator}"))
+          .aws_account_id(&identity.account_id)
+          .types(FINDING_TYPE)
+          .created_at(timestamp)
+          .updated_at(timestamp)
+          .severity(FindingSeverity::builder().label(severity.label()).build())
+          .title(&finding.title)
+          .description(&finding.description)
+          .resources(resource)
+          .compliance(Compliance::builder().status(status).build())
+          .record_state(RecordState::Active)
+          .build(),
+      )
+    })
+    .collect()
+}
+
+/// Submit the ASFF findings to Security Hub in the region, in batches of up to 100
+pub async fn import_findings(findings: &[AwsSecurityFinding], region: &str) -> Result<()> {
+  let client = aws::get_securityhub_client(region).await;
+
+  let mut failed = 0;
+  for batch in findings.chunks(BATCH_IMPORT_MAX_FINDINGS) {
+    let output = client
+      .batch_import_findings()
+      .set_findings(Some(batch.to_vec()))
+      .send()
+      .await
+      .inspect_err(|e| events::record_api_failure(BATCH_IMPORT_FINDINGS, e))?;
+
+    debug!(
+      "Imported {} finding(s) into Security Hub",
+      output.success_count().unwrap_or_default()
+    );
+    for finding in output.failed_findings() {
+      error!(
+        "Security Hub rejected finding {}: {}",
+        finding.id().unwrap_or_default(),
+        finding.error_message().unwrap_or_default()
+      );
+    }
+    failed += output.failed_count().unwrap_or_default();
+  }
+
+  if failed > 0 {
+    bail!("{failed} finding(s) were not imported into Security Hub");
+  }
+
+  info!("Imported {} finding(s) into Security Hub", findings.len());
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_converts_findings_to_asff() {
+    let identity = InstanceIdentity {
+      account_id: "111122223333".to_owned(),
+      instance_id: "i-0e46d9575664f45bd".to_owned(),
+      region: "us-west-2".to_owned(),
+    };
+    let findings = [
+      Finding {
+        check: "/etc/containerd/config.toml".to_owned(),
+        title: "/etc/containerd/config.toml permissions and ownership".to_owned(),
+        description: "Incorrect mode: 100666".to_owned(),
+        severity: Severity::Medium,
+        passed: false,
+      },
+      Finding {
+        check: "containerd-cri".to_owned(),
+        title: "containerd CRI plugin health".to_owned(),
+        description: "The CRI plugin is healthy".to_owned(),
+        severity: Severity::High,
+        passed: true,
+      },
+    ];
+
+    let asff = to_asff(&findings, &identity, "validate-node", "2024-01-01T00:00:00Z").unwrap();
+    let instance_arn = "arn:aws:ec2:us-west-2:111122223333:instance/i-0e46d9575664f45bd";
+    assert_eq!(
+      asff[0].id(),
+      Some(format!("{instance_arn}/validate-node/etc/containerd/config.toml").as_str())
+    );
+    assert_eq!(
+      asff[0].product_arn(),
+      Some("arn:aws:securityhub:us-west-2:111122223333:product/111122223333/default")
+    );
+    assert_eq!(asff[0].resources()[0].id(), Some(instance_arn));
+    assert_eq!(asff[0].resources()[0].partition(), Some(&Partition::Aws));
+    let label = |finding: &AwsSecurityFinding| finding.severity().and_then(|s| s.label()).cloned();
+    let status = |finding: &AwsSecurityFinding| finding.compliance().and_then(|c| c.status()).cloned();
+    assert_eq!(label(&asff[0]), Some(SeverityLabel::Medium));
+    assert_eq!(status(&asff[0]), Some(ComplianceStatus::Failed));
+    assert_eq!(label(&asff[1]), Some(SeverityLabel::Informational));
+    assert_eq!(status(&asff[1]), Some(ComplianceStatus::Passed));
+  }
+}
@@ -0,0 +1,124 @@
+use anyhow::Result;
+use ipnet::IpNet;
+use tracing::{debug, warn};
+
+use crate::{
+  aws::{self, ResponseError},
+  events,
+  secret::Secret,
+};
+
+const GET_PARAMETERS_BY_PATH: &str = "ssm:GetParametersByPath";
+
+/// Node bootstrap parameters stored in SSM Parameter Store under a common path (i.e. - `/eks/<cluster>/bootstrap`)
+///
+/// Parameter names match the `join-cluster` arguments they provide:
+///
+/// - `<path>/apiserver-endpoint`
+/// - `<path>/b64-cluster-ca`
+/// - `<path>/service-cidr`
+/// - `<path>/kubelet-extra-args`
+#[derive(Debug, Default, PartialEq)]
+pub struct BootstrapParameters {
+  pub apiserver_endpoint: Option<String>,
+  pub b64_cluster_ca: Option<Secret<String>>,
+  pub service_cidr: Option<IpNet>,
+  /// May hold credentials of kubelet flags, such as a bootstrap token
+  pub kubelet_extra_args: Option<Secret<String>>,
+}
+
+/// Parse the parameters under the path, given as (name, value) pairs, into the bootstrap parameters
+fn parse_bootstrap_parameters(path: &str, parameters: Vec<(String, String)>) -> Result<BootstrapParameters> {
+  let mut params = BootstrapParameters::default();
+
+  for (full_name, value) in parameters {
+    let name = full_name.strip_prefix(path.trim_end_matches('/')).unwrap_or(&full_name);
+    match name.trim_start_matches('/') {
+      "apiserver-endpoint" => params.apiserver_endpoint = Some(value),
+      "b64-cluster-ca" => params.b64_cluster_ca = Some(Secret::new(value)),
+      "service-cidr" => params.service_cidr = Some(value.trim().parse()?),
+      "kubelet-extra-args" => params.kubelet_extra_args = Some(Secret::new(value)),
+      _ => warn!("Ignoring unknown bootstrap parameter {full_name}"),
+    }
+  }
+
+  Ok(params)
+}
+
+/// Get the node bootstrap parameters stored under the path in SSM Parameter Store
+///
+/// SecureString parameters are decrypted; only the names of the parameters are logged
+pub async fn get_bootstrap_parameters(path: &str, region: &str) -> Result<BootstrapParameters> {
+  let client = aws::get_ssm_client(region).await;
+
+  let mut parameters = Vec::new();
+  let mut next_token = None;
+  loop {
+    let response = client
+      .get_parameters_by_path()
+      .path(path)
+      .with_decryption(true)
+      .set_next_token(next_token)
+      .send()
+      .await
+      .inspect_err(|e| events::record_api_failure(GET_PARAMETERS_BY_PATH, e))?;
+
+    for parameter in response.parameters() {
+      let name = parameter
+        .name()
+        .ok_or(ResponseError::missing(GET_PARAMETERS_BY_PATH, "name"))?;
+      let value = parameter
+        .value()
+        .ok_or(ResponseError::missing(GET_PARAMETERS_BY_PATH, "value"))?;
+      parameters.push((name.to_owned(), value.to_owned()));
+    }
+    next_token = response.next_token;
+    if next_token.is_none() {
+      break;
+    }
+  }
+
+  let names = parameters.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+  debug!("Bootstrap parameters from {path}: {}", names.join(", "));
+
+  parse_bootstrap_parameters(path, parameters)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_parses_bootstrap_parameters() {
+    let parameters = [
+      ("apiserver-endpoint", "https://ABC.gr7.us-west-2.eks.amazonaws.com"),
+      ("b64-cluster-ca", "LS0tLS1CRUdJTg=="),
+      ("service-cidr", "172.20.0.0/16"),
+      ("kubelet-extra-args", "--node-labels=team=a"),
+      ("unknown", "ignored"),
+    ]
+    .map(|(name, value)| (format!("/eks/example/bootstrap/{name}"), value.to_owned()));
+
+    let params = parse_bootstrap_parameters("/eks/example/bootstrap/", parameters.to_vec()).unwrap();
+    assert_eq!(
+      params,
+      BootstrapParameters {
+        apiserver_endpoint: Some("https://ABC.gr7.us-west-2.eks.amazonaws.com".to_owned()),
+        b64_cluster_ca: Some(Secret::new("LS0tLS1CRUdJTg==".to_owned())),
+        service_cidr: Some("172.20.0.0/16".parse().unwrap()),
+        kubelet_extra_args: Some(Secret::new("--node-labels=team=a".to_owned())),
+      }
+    );
+    assert!(!format!("{params:?}").contains("LS0tLS1CRUdJTg=="));
+  }
+
+  #[test]
+  fn it_rejects_invalid_service_cidr() {
+    let parameters = vec![(
+      "/eks/example/bootstrap/service-cidr".to_owned(),
+      "172.20.0.0".to_owned(),
+    )];
+
+    assert!(parse_bootstrap_parameters("/eks/example/bootstrap", parameters).is_err());
+  }
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// File where the settings the node was joined with are recorded for later validation
+pub const NODE_STATE_PATH: &str = "/etc/eksnode/state.json";
+
+/// Settings the node was joined to the cluster with
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeState {
+  /// Kubelet TLS was restricted to FIPS approved settings and the host crypto policy was verified
+  pub fips_enabled: bool,
+  /// Cluster the node was joined to
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub cluster_name: Option<String>,
+  /// EC2 instance the node was joined on; not set for hybrid nodes
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub instance_id: Option<String>,
+  /// IP address kubelet was configured to advertise
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub node_ip: Option<String>,
+  /// SHA256 digest of the join-cluster inputs
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub input_digest: Option<String>,
+}
+
+impl NodeState {
+  pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let contents = std::fs::read(path)?;
+
+    Ok(serde_json::from_slice(&contents)?)
+  }
+
+  pub async fn write<P: AsRef<Path>>(&self, path: P, chown: bool) -> Result<()> {
+    let contents = serde_json::to_string_pretty(self)?;
+    utils::write_file(contents.as_bytes(), path, Some(0o644), chown).await
+  }
+}
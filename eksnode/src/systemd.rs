@@ -0,0 +1,140 @@
+use std::{
+  env,
+  ffi::OsStr,
+  os::{linux::net::SocketAddrExt, unix::net::UnixDatagram},
+  time::Duration,
+};
+
+use anyhow::Result;
+use tracing::debug;
+
+use crate::{
+  events::{self, EventKind},
+  utils,
+};
+
+/// Socket provided by systemd to services with `Type=notify` for state updates
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+
+/// Send a state update (i.e. - `READY=1`) to the service manager
+///
+/// Does nothing when not run by systemd as a notify service. Returns whether the update was sent
+pub fn notify(state: &str) -> Result<bool> {
+  let Some(path) = env::var_os(NOTIFY_SOCKET) else {
+    return Ok(false);
+  };
+
+  notify_socket(&path, state)?;
+  Ok(true)
+}
+
+/// Send a state update to the socket at the path, or in the abstract namespace when prefixed with `@`
+fn notify_socket(path: &OsStr, state: &str) -> Result<()> {
+  let socket = UnixDatagram::unbound()?;
+  let path = path.to_string_lossy();
+  match path.strip_prefix('@') {
+    // Abstract namespace socket
+    Some(name) => {
+      let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+      socket.send_to_addr(state.as_bytes(), &addr)?;
+    }
+    None => {
+      socket.send_to(state.as_bytes(), path.as_ref())?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Notify the service manager, logging rather than failing when the update cannot be sent
+fn try_notify(state: &str) {
+  if let Err(e) = notify(state) {
+    debug!("Unable to send {state:?} to systemd: {e}");
+  }
+}
+
+/// Notify the service manager that startup is complete
+pub fn notify_ready() {
+  try_notify("READY=1");
+}
+
+/// Update the status shown by `systemctl status`
+pub fn notify_status(status: &str) {
+  try_notify(&format!("STATUS={status}"));
+}
+
+/// Reset the service watchdog timer
+pub fn notify_watchdog() {
+  try_notify("WATCHDOG=1");
+}
+
+/// Interval within which the service must ping the watchdog, when enabled with `WatchdogSec=`
+pub fn watchdog_interval() -> Option<Duration> {
+  // The watchdog applies to the main process of the service only
+  if let Ok(pid) = env::var("WATCHDOG_PID") {
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+      return None;
+    }
+  }
+
+  parse_watchdog_usec(&env::var("WATCHDOG_USEC").ok()?)
+}
+
+fn parse_watchdog_usec(usec: &str) -> Option<Duration> {
+  match usec.parse::<u64>() {
+    Ok(usec) if usec > 0 => Some(Duration::from_micros(usec)),
+    _ => None,
+  }
+}
+
+/// Run `systemctl` with the arguments (i.e. - `restart kubelet`), recording the action in the event log
+pub fn systemctl(args: Vec<&str>) -> Result<utils::CmdResult> {
+  let result = utils::cmd_exec("systemctl", args.to_owned())?;
+  let detail = (result.status != 0).then(|| format!("exit code {}: {}", result.status, result.stderr.trim()));
+  events::record(EventKind::UnitChanged, &args.join(" "), detail);
+
+  Ok(result)
+}
+
+/// Sleep for the duration, pinging the watchdog at half its interval so that the service is not restarted
+pub async fn sleep_with_watchdog(duration: Duration) {
+  let Some(interval) = watchdog_interval() else {
+    tokio::time::sleep(duration).await;
+    return;
+  };
+
+  let deadline = tokio::time::Instant::now() + duration;
+  loop {
+    notify_watchdog();
+    let now = tokio::time::Instant::now();
+    if now >= deadline {
+      return;
+    }
+    tokio::time::sleep((interval / 2).min(deadline - now)).await;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_parses_watchdog_usec() {
+    assert_eq!(parse_watchdog_usec("30000000"), Some(Duration::from_secs(30)));
+    assert_eq!(parse_watchdog_usec("0"), None);
+    assert_eq!(parse_watchdog_usec("invalid"), None);
+  }
+
+  #[test]
+  fn it_notifies_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notify.sock");
+    let listener = UnixDatagram::bind(&path).unwrap();
+
+    notify_socket(path.as_os_str(), "READY=1").unwrap();
+
+    let mut buf = [0; 64];
+    let len = listener.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"READY=1");
+  }
+}
@@ -0,0 +1,155 @@
+//! Opt-in usage statistics of the commands run on the node
+//!
+//! Each command records its name, duration, outcome, and the Kubernetes version of the node, without any identifiers
+//! of the node, cluster, or account. AMI maintainers enable it to measure the reliability of bootstrapping across the
+//! fleets they manage, optionally sending the records to an HTTP endpoint they collect them at
+
+use std::{path::Path, time::Duration};
+
+use anyhow::{bail, Result};
+use clap::Args;
+use hyper::{header, Body, Client, Method, Request};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{events, kubelet};
+
+/// File the usage records are appended to
+pub const TELEMETRY_PATH: &str = "/var/lib/eksnode/telemetry.jsonl";
+
+/// Size at which the usage records are rotated to `<path>.1`, replacing the records previously rotated
+const MAX_TELEMETRY_BYTES: u64 = 1024 * 1024;
+
+/// Time allowed to send a record to the endpoint
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Usage statistics settings; nothing is recorded unless --telemetry is set
+#[derive(Args, Clone, Debug, Default)]
+pub struct TelemetryConfig {
+  /// Record the command, its duration, its outcome, and the Kubernetes version to /var/lib/eksnode/telemetry.jsonl
+  ///
+  /// No identifiers of the node, cluster, or account are recorded
+  #[arg(long, env = "EKSNODE_TELEMETRY", global = true)]
+  pub telemetry: bool,
+
+  /// HTTP endpoint the usage records are also sent to as JSON, such as a collector run by the AMI maintainer
+  #[arg(long, env = "EKSNODE_TELEMETRY_ENDPOINT", global = true, requires = "telemetry")]
+  pub telemetry_endpoint: Option<String>,
+
+  /// Commands that usage is not recorded for (i.e. - `ecr-credential-refresh`)
+  #[arg(long, env = "EKSNODE_TELEMETRY_EXCLUDE", global = true, requires = "telemetry")]
+  pub telemetry_exclude: Vec<String>,
+}
+
+impl TelemetryConfig {
+  /// Usage is recorded when telemetry is enabled and the command is not excluded
+  pub fn is_enabled(&self, command: &str) -> bool {
+    self.telemetry && !self.telemetry_exclude.iter().any(|c| c == command)
+  }
+}
+
+/// Anonymous record of a command run on the node
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageRecord {
+  /// RFC 3339 time the command finished
+  pub timestamp: String,
+  /// eksnode command that was run (i.e. - `join-cluster`)
+  pub command: String,
+  pub duration_ms: u128,
+  pub success: bool,
+  /// Version of the kubelet installed on the node, when available
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub kubernetes_version: Option<String>,
+  pub eksnode_version: String,
+}
+
+impl UsageRecord {
+  pub async fn new(command: &str, duration: Duration, success: bool) -> Self {
+    Self {
+      timestamp: events::now(),
+      command: command.to_owned(),
+      duration_ms: duration.as_millis(),
+      success,
+      kubernetes_version: kubelet::get_kubelet_version().await.ok().map(|v| v.to_string()),
+      eksnode_version: env!("CARGO_PKG_VERSION").to_owned(),
+    }
+  }
+}
+
+/// Record the usage of the command to the local file and the endpoint, when configured
+///
+/// The file is rotated once it reaches 1 MiB. Failures are logged rather than returned so that telemetry never fails
+/// the command it records
+pub async fn record(config: &TelemetryConfig, record: &UsageRecord) {
+  if let Err(e) = events::append(record, Path::new(TELEMETRY_PATH), MAX_TELEMETRY_BYTES) {
+    debug!("Unable to record usage to {TELEMETRY_PATH}: {e}");
+  }
+  if let Some(endpoint) = &config.telemetry_endpoint {
+    if let Err(e) = send(record, endpoint).await {
+      debug!("Unable to send usage to {endpoint}: {e}");
+    }
+  }
+}
+
+async fn send(record: &UsageRecord, endpoint: &str) -> Result<()> {
+  let connector = hyper_rustls::HttpsConnectorBuilder::new()
+    .with_native_roots()
+    .https_or_http()
+    .enable_http1()
+    .build();
+  let request = Request::builder()
+    .method(Method::POST)
+    .uri(endpoint)
+    .header(header::CONTENT_TYPE, "application/json")
+    .body(Body::from(serde_json::to_string(record)?))?;
+
+  let client = Client::builder().build::<_, Body>(connector);
+  let response = tokio::time::timeout(SEND_TIMEOUT, client.request(request)).await??;
+  if !response.status().is_success() {
+    bail!("Endpoint responded with {}", response.status());
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_appends_usage_records() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("telemetry.jsonl");
+    let record = UsageRecord {
+      timestamp: "2024-01-02T03:04:05Z".to_owned(),
+      command: "join-cluster".to_owned(),
+      duration_ms: 4200,
+      success: false,
+      kubernetes_version: Some("1.29.0".to_owned()),
+      eksnode_version: "0.1.0".to_owned(),
+    };
+
+    events::append(&record, &path, MAX_TELEMETRY_BYTES).unwrap();
+    events::append(&record, &path, MAX_TELEMETRY_BYTES).unwrap();
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+      contents.lines().next().unwrap(),
+      r#"{"timestamp":"2024-01-02T03:04:05Z","command":"join-cluster","duration_ms":4200,"success":false,"#.to_owned()
+        + r#""kubernetes_version":"1.29.0","eksnode_version":"0.1.0"}"#
+    );
+    assert_eq!(contents.lines().count(), 2);
+  }
+
+  #[test]
+  fn it_excludes_commands() {
+    let config = TelemetryConfig {
+      telemetry: true,
+      telemetry_endpoint: None,
+      telemetry_exclude: vec!["ecr-credential-refresh".to_owned()],
+    };
+    assert!(config.is_enabled("join-cluster"));
+    assert!(!config.is_enabled("ecr-credential-refresh"));
+    assert!(!TelemetryConfig::default().is_enabled("join-cluster"));
+  }
+}
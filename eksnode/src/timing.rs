@@ -0,0 +1,192 @@
+use std::{path::Path, time::Instant};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tabled::{Table, Tabled};
+
+use crate::{systemd, utils};
+
+/// File where the durations of the join phases are written
+///
+/// Written under /var/log so that it is included in the archive created by `eksnode debug --create-log-archive`
+pub const JOIN_TIMINGS_PATH: &str = "/var/log/eksnode/join-timings.json";
+
+/// Progress output of the join phases, in addition to the logged output
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Progress {
+  /// Progress is only logged
+  #[default]
+  None,
+  /// A single line marker is printed to stdout as each phase starts and when the join ends
+  ///
+  /// Suited to the EC2 serial console and cloud-init logs, where the verbose log output is hard to follow
+  Plain,
+}
+
+/// Wall-clock duration of a phase of joining the node to the cluster
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct PhaseTiming {
+  pub phase: String,
+  #[tabled(rename = "duration (ms)")]
+  pub duration_ms: u128,
+}
+
+/// Records the duration of sequential phases; starting a phase ends the previous one
+///
+/// A phase that is started again, such as a second preflight check, adds to its existing duration
+#[derive(Debug, Default)]
+pub struct PhaseTimer {
+  timings: Vec<PhaseTiming>,
+  current: Option<(&'static str, Instant)>,
+  /// The most recently started phase, which is where a failed join stopped
+  last: Option<&'static str>,
+  steps: usize,
+  started: Option<Instant>,
+  progress: Progress,
+}
+
+impl PhaseTimer {
+  pub fn new(progress: Progress) -> Self {
+    Self {
+      progress,
+      ..Self::default()
+    }
+  }
+
+  /// End the current phase and start the named phase
+  ///
+  /// The phase is also reported as the status of the unit when run by systemd
+  pub fn start(&mut self, phase: &'static str) {
+    systemd::notify_status(phase);
+    let now = Instant::now();
+    if self.progress == Progress::Plain {
+      println!("{}", self.start_marker(phase, now));
+    }
+    self.start_at(phase, now);
+  }
+
+  /// Print the outcome of the join when plain progress output is enabled
+  pub fn print_result(&self, error: Option<&anyhow::Error>) {
+    if self.progress == Progress::Plain {
+      println!("{}", self.result_marker(error, Instant::now()));
+    }
+  }
+
+  fn elapsed_secs(&self, now: Instant) -> f64 {
+    self
+      .started
+      .map_or(0.0, |started| now.duration_since(started).as_secs_f64())
+  }
+
+  fn start_marker(&self, phase: &str, now: Instant) -> String {
+    format!(
+      "eksnode: [{}] {phase} (+{:.1}s)",
+      self.steps + 1,
+      self.elapsed_secs(now)
+    )
+  }
+
+  fn result_marker(&self, error: Option<&anyhow::Error>, now: Instant) -> String {
+    let elapsed = self.elapsed_secs(now);
+    match error {
+      None => format!("eksnode: join succeeded in {elapsed:.1}s"),
+      Some(e) => format!(
+        "eksnode: join failed during {} after {elapsed:.1}s: {e}",
+        self.last.unwrap_or("startup")
+      ),
+    }
+  }
+
+  /// End the current phase, returning the durations of all recorded phases
+  pub fn finish(&mut self) -> &[PhaseTiming] {
+    self.finish_at(Instant::now());
+    &self.timings
+  }
+
+  fn start_at(&mut self, phase: &'static str, now: Instant) {
+    self.finish_at(now);
+    self.started.get_or_insert(now);
+    self.current = Some((phase, now));
+    self.last = Some(phase);
+    self.steps += 1;
+  }
+
+  fn finish_at(&mut self, now: Instant) {
+    let Some((phase, started)) = self.current.take() else {
+      return;
+    };
+
+    let duration_ms = now.duration_since(started).as_millis();
+    match self.timings.iter_mut().find(|t| t.phase == phase) {
+      Some(timing) => timing.duration_ms += duration_ms,
+      None => self.timings.push(PhaseTiming {
+        phase: phase.to_owned(),
+        duration_ms,
+      }),
+    }
+  }
+
+  /// Render the recorded phases as a table, with the total duration as the last row
+  pub fn summary(&self) -> String {
+    let total = PhaseTiming {
+      phase: "total".to_owned(),
+      duration_ms: self.timings.iter().map(|t| t.duration_ms).sum(),
+    };
+
+    Table::new(self.timings.iter().chain([&total])).to_string()
+  }
+
+  /// Write the recorded phases as JSON
+  pub async fn write<P: AsRef<Path>>(&self, path: P, chown: bool) -> Result<()> {
+    let contents = serde_json::to_string_pretty(&self.timings)?;
+    utils::write_file(contents.as_bytes(), path, Some(0o644), chown).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+
+  #[test]
+  fn it_records_phase_durations() {
+    let start = Instant::now();
+    let mut timer = PhaseTimer::default();
+    timer.start_at("imds", start);
+    timer.start_at("preflight", start + Duration::from_millis(20));
+    timer.start_at("discovery", start + Duration::from_millis(50));
+    timer.start_at("preflight", start + Duration::from_millis(450));
+    timer.finish_at(start + Duration::from_millis(500));
+
+    assert_eq!(
+      timer
+        .timings
+        .iter()
+        .map(|t| (t.phase.as_str(), t.duration_ms))
+        .collect::<Vec<_>>(),
+      vec![("imds", 20), ("preflight", 80), ("discovery", 400)]
+    );
+    assert!(timer.summary().contains("total"));
+  }
+
+  #[test]
+  fn it_formats_progress_markers() {
+    let start = Instant::now();
+    let mut timer = PhaseTimer::new(Progress::Plain);
+    assert_eq!(timer.start_marker("imds", start), "eksnode: [1] imds (+0.0s)");
+    timer.start_at("imds", start);
+    let now = start + Duration::from_millis(1300);
+    assert_eq!(timer.start_marker("preflight", now), "eksnode: [2] preflight (+1.3s)");
+    timer.start_at("preflight", now);
+
+    let now = start + Duration::from_millis(3000);
+    assert_eq!(timer.result_marker(None, now), "eksnode: join succeeded in 3.0s");
+    assert_eq!(
+      timer.result_marker(Some(&anyhow::anyhow!("missing eks:DescribeCluster")), now),
+      "eksnode: join failed during preflight after 3.0s: missing eks:DescribeCluster"
+    );
+  }
+}
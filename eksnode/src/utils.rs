@@ -1,10 +1,22 @@
-use std::{os::unix::fs, path::Path};
+use std::{
+  env,
+  ffi::OsStr,
+  io,
+  os::unix::fs,
+  path::{Path, PathBuf},
+  process::{Command, Stdio},
+  sync::OnceLock,
+  time::Duration,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use regex_lite::Regex;
 use semver::Version;
+use sha2::{Digest, Sha256};
 use tokio::{fs::OpenOptions, io::AsyncWriteExt};
 
+use crate::events::{self, EventKind};
+
 /// Extract the semantic version from the version string provided
 pub fn get_semver(ver: &str) -> Result<Version> {
   let re = Regex::new(r"v?(\d+\.\d+\.\d+)(-.*)?")?;
@@ -28,7 +40,12 @@ pub struct CmdResult {
 }
 
 pub fn cmd_exec(cmd: &str, args: Vec<&str>) -> Result<CmdResult> {
-  let output = std::process::Command::new(cmd).args(args).output();
+  cmd_exec_env(cmd, args, &[])
+}
+
+/// Execute a command with the environment variables set in addition to those inherited from this process
+pub fn cmd_exec_env(cmd: &str, args: Vec<&str>, env: &[(String, String)]) -> Result<CmdResult> {
+  let output = Command::new(cmd).args(args).envs(env.iter().cloned()).output();
 
   match output {
     Ok(output) => Ok(CmdResult {
@@ -40,8 +57,99 @@ pub fn cmd_exec(cmd: &str, args: Vec<&str>) -> Result<CmdResult> {
   }
 }
 
+/// Execute a command, killing it and returning an error if it does not complete within the timeout
+///
+/// The output is read while the command runs so that a command writing more than the pipe buffer does not block
+pub async fn cmd_exec_timeout(cmd: &str, args: Vec<&str>, timeout: Duration) -> Result<CmdResult> {
+  let child = tokio::process::Command::new(cmd)
+    .args(args)
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .kill_on_drop(true)
+    .spawn()
+    .map_err(|e| anyhow!("Error executing command {cmd}: {e}"))?;
+
+  // The child is killed when the timeout drops its future
+  let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+    Ok(output) => output?,
+    Err(_) => bail!("Command {cmd} did not complete within {}s", timeout.as_secs()),
+  };
+  Ok(CmdResult {
+    stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+    stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    status: output.status.code().unwrap_or(1),
+  })
+}
+
+/// Directory on an immutable root filesystem whose files are written to a writable directory instead
+///
+/// The directory is replaced with a symlink or bind mount to the writable directory when the AMI is provisioned
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathMap {
+  /// Absolute path the files are read from on the node (i.e. - `/etc/kubernetes`)
+  pub from: PathBuf,
+  /// Absolute path of the writable directory the files are written to (i.e. - `/var/lib/eksnode/etc/kubernetes`)
+  pub to: PathBuf,
+}
+
+/// Parse a path mapping in the format `<from>=<to>` (i.e. - `/etc/kubernetes=/var/lib/eksnode/etc/kubernetes`)
+pub fn parse_path_map(s: &str) -> Result<PathMap> {
+  let Some((from, to)) = s.split_once('=') else {
+    bail!("Invalid path mapping {s}; expected <from>=<to>");
+  };
+  let (from, to) = (
+    PathBuf::from(from.trim_end_matches('/')),
+    PathBuf::from(to.trim_end_matches('/')),
+  );
+  if !from.is_absolute() || !to.is_absolute() || from.parent().is_none() || to.parent().is_none() {
+    bail!("Invalid path mapping {s}; both paths must be absolute and not the root directory");
+  }
+  if from.starts_with(&to) || to.starts_with(&from) {
+    bail!("Invalid path mapping {s}; the paths must not contain one another");
+  }
+
+  Ok(PathMap { from, to })
+}
+
+/// Path mappings applied to the files written by eksnode, set once by [`configure_path_maps`]
+static PATH_MAPS: OnceLock<Vec<PathMap>> = OnceLock::new();
+
+/// Set the path mappings applied to the files written afterwards
+pub fn configure_path_maps(maps: Vec<PathMap>) -> Result<()> {
+  PATH_MAPS
+    .set(maps)
+    .map_err(|_| anyhow!("Path mappings have already been set"))
+}
+
+/// Get the configured path mappings
+pub fn path_maps() -> &'static [PathMap] {
+  PATH_MAPS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// Resolve the path a file is written to on the node, applying the longest matching path mapping
+pub fn map_path<P: AsRef<Path>>(path: P) -> PathBuf {
+  map_path_with(path_maps(), path.as_ref())
+}
+
+fn map_path_with(maps: &[PathMap], path: &Path) -> PathBuf {
+  maps
+    .iter()
+    .filter_map(|map| path.strip_prefix(&map.from).ok().map(|rest| (map, rest)))
+    .max_by_key(|(map, _)| map.from.components().count())
+    .map_or_else(|| path.to_path_buf(), |(map, rest)| map.to.join(rest))
+}
+
 /// Write a file to disk, setting the file mode and owner (gid/uid)
+///
+/// Files under a mapped directory are written to its writable directory (see [`PathMap`])
 pub async fn write_file<P: AsRef<Path>>(contents: &[u8], path: P, mode: Option<u32>, chown: bool) -> Result<()> {
+  let path = map_path(&path);
+  if path_maps().iter().any(|map| path.starts_with(&map.to)) {
+    if let Some(parent) = path.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+  }
+
   let mut file = OpenOptions::new()
     .write(true)
     .create(true)
@@ -53,16 +161,142 @@ pub async fn write_file<P: AsRef<Path>>(contents: &[u8], path: P, mode: Option<u
   file.flush().await?;
 
   if chown {
-    fs::chown(&path, Some(0), Some(0))?
+    fs::chown(&path, Some(0), Some(0))?;
+    // Ownership is only changed when writing to the host
+    events::record(EventKind::FileWritten, &path.to_string_lossy(), None);
   }
 
   Ok(())
 }
 
+/// Resolve an absolute path on the node relative to a root directory (i.e. - an AMI being provisioned)
+pub fn rooted<P: AsRef<Path>>(root: P, path: &str) -> PathBuf {
+  root.as_ref().join(path.trim_start_matches('/'))
+}
+
+/// Resolve a command to the file executed for it, searching `PATH` for bare command names
+pub fn find_command(command: &str) -> Option<PathBuf> {
+  find_command_in(command, env::var_os("PATH").as_deref())
+}
+
+fn find_command_in(command: &str, paths: Option<&OsStr>) -> Option<PathBuf> {
+  if command.contains('/') {
+    let path = PathBuf::from(command);
+    return path.is_file().then_some(path);
+  }
+
+  env::split_paths(paths?)
+    .map(|dir| dir.join(command))
+    .find(|path| path.is_file())
+}
+
+/// Compute the hex encoded SHA256 digest of a file
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String> {
+  let mut file = std::fs::File::open(&path)?;
+  let mut hasher = Sha256::new();
+  io::copy(&mut file, &mut hasher)?;
+
+  Ok(format!("{:x}", hasher.finalize()))
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
 
+  #[test]
+  fn it_resolves_rooted_paths() {
+    assert_eq!(rooted("/", "/etc/eks"), PathBuf::from("/etc/eks"));
+    assert_eq!(rooted("/mnt/ami", "/etc/eks"), PathBuf::from("/mnt/ami/etc/eks"));
+  }
+
+  #[test]
+  fn it_maps_paths() {
+    let maps = vec![
+      parse_path_map("/etc=/var/lib/eksnode/etc").unwrap(),
+      parse_path_map("/etc/kubernetes/=/var/lib/kubernetes-config").unwrap(),
+    ];
+
+    assert_eq!(
+      map_path_with(&maps, Path::new("/etc/kubernetes/kubelet/kubelet-config.json")),
+      PathBuf::from("/var/lib/kubernetes-config/kubelet/kubelet-config.json")
+    );
+    assert_eq!(
+      map_path_with(&maps, Path::new("/etc/containerd/config.toml")),
+      PathBuf::from("/var/lib/eksnode/etc/containerd/config.toml")
+    );
+    assert_eq!(
+      map_path_with(&maps, Path::new("/etcd/config")),
+      PathBuf::from("/etcd/config")
+    );
+  }
+
+  #[test]
+  fn it_rejects_invalid_path_maps() {
+    for map in ["/etc", "etc=/var/lib/etc", "/=/var/lib/root", "/var=/var/lib/etc"] {
+      assert!(parse_path_map(map).is_err(), "{map}");
+    }
+  }
+
+  #[test]
+  fn it_finds_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let command = dir.path().join("aws-iam-authenticator");
+    std::fs::write(&command, "").unwrap();
+    let paths = env::join_paths(["/does/not/exist", &dir.path().to_string_lossy()]).unwrap();
+
+    assert_eq!(
+      find_command_in("aws-iam-authenticator", Some(&paths)),
+      Some(command.to_owned())
+    );
+    assert_eq!(
+      find_command_in(&command.to_string_lossy(), None),
+      Some(command.to_owned())
+    );
+    assert_eq!(find_command_in("aws-iam-authenticator", None), None);
+    assert_eq!(find_command_in("./aws-iam-authenticator", Some(&paths)), None);
+  }
+
+  #[test]
+  fn it_computes_sha256_of_file() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "eksnode").unwrap();
+
+    assert_eq!(
+      sha256_file(file.path()).unwrap(),
+      "0f0be138463a248b7f256afeccea397ab8c4b405c90eb1b84a68be9ce7bca2d7"
+    );
+  }
+
+  #[tokio::test]
+  async fn it_times_out_cmd_exec() {
+    let result = cmd_exec_timeout("sleep", vec!["5"], Duration::from_millis(100)).await;
+    assert!(result.is_err());
+  }
+
+  #[tokio::test]
+  async fn it_completes_cmd_exec_within_timeout() {
+    let result = cmd_exec_timeout("echo", vec!["v1.29.0"], Duration::from_secs(5))
+      .await
+      .unwrap();
+    assert_eq!(result.status, 0);
+    assert_eq!(result.stdout.trim(), "v1.29.0");
+  }
+
+  #[tokio::test]
+  async fn it_reads_cmd_exec_output_larger_than_pipe_buffer() {
+    let result = cmd_exec_timeout("head", vec!["-c", "1048576", "/dev/zero"], Duration::from_secs(5))
+      .await
+      .unwrap();
+    assert_eq!(result.stdout.len(), 1_048_576);
+  }
+
+  #[test]
+  fn it_sets_cmd_exec_env() {
+    let env = vec![("AWS_PROFILE".to_owned(), "eksnode-ecr".to_owned())];
+    let result = cmd_exec_env("sh", vec!["-c", "echo $AWS_PROFILE"], &env).unwrap();
+    assert_eq!(result.stdout.trim(), "eksnode-ecr");
+  }
+
   #[test]
   fn it_gets_semver_bare() {
     let expected = Version::parse("1.20.4").unwrap();